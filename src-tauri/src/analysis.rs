@@ -0,0 +1,482 @@
+//! QuestScribe - Document Analysis
+//!
+//! Read-only checks over entities, markers, and document content that surface
+//! problems the state engine would otherwise silently work around.
+
+use crate::chapters::Chapter;
+use crate::engine;
+use crate::knowledge;
+use crate::mentions;
+use crate::positions;
+use crate::stats;
+use crate::state::{ChangeType, Entity, Marker};
+use crate::visibility::VisibilityFilters;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Why a marker is considered orphaned
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrphanReason {
+    MissingEntity,   // entity_id doesn't match any entity
+    OutOfRange,      // position is past the end of the document
+    MissingFromText, // no marker node with this ID exists in the content
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedMarker {
+    pub marker_id: String,
+    pub entity_id: String,
+    pub position: usize,
+    pub reasons: Vec<OrphanReason>,
+}
+
+/// Find markers that reference a missing entity or no longer fit the document
+///
+/// Content checks (range and embedded node) only run when the ProseMirror
+/// document is provided. Results are sorted by position.
+pub fn find_orphaned_markers(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    doc: Option<&serde_json::Value>,
+) -> Vec<OrphanedMarker> {
+    let doc_size = doc.map(positions::content_size);
+    let embedded = doc.map(positions::marker_node_positions);
+
+    let mut orphans: Vec<OrphanedMarker> = markers
+        .values()
+        .filter_map(|marker| {
+            let mut reasons = Vec::new();
+
+            if !entities.contains_key(&marker.entity_id) {
+                reasons.push(OrphanReason::MissingEntity);
+            }
+            if doc_size.is_some_and(|size| marker.position > size) {
+                reasons.push(OrphanReason::OutOfRange);
+            }
+            if embedded.as_ref().is_some_and(|nodes| !nodes.contains_key(&marker.id)) {
+                reasons.push(OrphanReason::MissingFromText);
+            }
+
+            if reasons.is_empty() {
+                None
+            } else {
+                Some(OrphanedMarker {
+                    marker_id: marker.id.clone(),
+                    entity_id: marker.entity_id.clone(),
+                    position: marker.position,
+                    reasons,
+                })
+            }
+        })
+        .collect();
+
+    orphans.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.marker_id.cmp(&b.marker_id)));
+    orphans
+}
+
+/// Marker counts for one segment of the document
+#[derive(Debug, Clone, Serialize)]
+pub struct DensityBucket {
+    pub start: usize, // First position in the segment
+    pub end: usize,   // One past the last position
+    pub marker_count: usize,
+    pub change_count: usize, // Field changes across those markers
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkerDensity {
+    pub document_size: usize,
+    pub max_marker_count: usize, // For normalizing heatmap colors
+    pub buckets: Vec<DensityBucket>,
+}
+
+/// Count markers per equal-width document segment, optionally filtered by entity and tag
+/// and by the document's visibility filters
+pub fn marker_density(
+    markers: &HashMap<String, Marker>,
+    bucket_count: usize,
+    document_size: usize,
+    entity_id: Option<&str>,
+    tag: Option<&str>,
+    visibility: Option<&VisibilityFilters>,
+) -> MarkerDensity {
+    let bucket_count = bucket_count.max(1);
+    let document_size = document_size.max(1);
+
+    let mut buckets: Vec<DensityBucket> = (0..bucket_count)
+        .map(|i| DensityBucket {
+            start: i * document_size / bucket_count,
+            end: (i + 1) * document_size / bucket_count,
+            marker_count: 0,
+            change_count: 0,
+        })
+        .collect();
+
+    for marker in markers.values() {
+        if entity_id.is_some_and(|id| marker.entity_id != id) {
+            continue;
+        }
+        if tag.is_some_and(|t| !marker.tags.iter().any(|mt| mt == t)) {
+            continue;
+        }
+        if visibility.is_some_and(|v| !v.is_marker_visible(marker)) {
+            continue;
+        }
+
+        let index = (marker.position.min(document_size - 1) * bucket_count / document_size).min(bucket_count - 1);
+        buckets[index].marker_count += 1;
+        buckets[index].change_count += marker.changes.len();
+    }
+
+    MarkerDensity {
+        document_size,
+        max_marker_count: buckets.iter().map(|b| b.marker_count).max().unwrap_or(0),
+        buckets,
+    }
+}
+
+/// Why a field shows up in the Chekhov's gun report
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChekhovReason {
+    NeverReferenced,            // Set by a single marker and never touched again
+    RemovedWithoutIntroduction, // Removed before anything set it
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChekhovItem {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub field: String,
+    pub reason: ChekhovReason,
+    pub marker_id: String, // Marker that sets (or removes) the field
+    pub position: usize,
+}
+
+// Whether two field paths overlap ("inventory" and "inventory.sword" do)
+fn paths_overlap(a: &str, b: &str) -> bool {
+    a == b || a.starts_with(&format!("{}.", b)) || b.starts_with(&format!("{}.", a))
+}
+
+// Whether a text block after `position` names the field (its last path segment)
+fn named_later(blocks: &[(usize, usize, String)], field: &str, position: usize) -> bool {
+    let name = field.rsplit('.').next().unwrap_or(field).to_lowercase();
+    // Very short names would match inside ordinary words
+    name.chars().count() >= 3 && blocks.iter().any(|(start, _, text)| *start > position && text.contains(&name))
+}
+
+/// Find planted-but-unfired setups: fields set once and never referenced again, and
+/// fields removed without ever being introduced
+///
+/// A field counts as referenced when another marker of the same entity touches it
+/// (or a parent or child path), or, when the ProseMirror document is provided, when a
+/// later paragraph names it. Learned facts are left to the knowledge queries. Results
+/// are sorted by position.
+pub fn chekhov_report(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    doc: Option<&serde_json::Value>,
+) -> Vec<ChekhovItem> {
+    let blocks: Vec<(usize, usize, String)> = doc
+        .map(mentions::text_blocks)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(start, end, text)| (start, end, text.to_lowercase()))
+        .collect();
+    let mut items = Vec::new();
+
+    for entity in entities.values() {
+        let mut entity_markers: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity.id).collect();
+        entity_markers.sort_by(|a, b| engine::compare_markers(a, b));
+
+        let item = |field: &str, reason, marker: &Marker| ChekhovItem {
+            entity_id: entity.id.clone(),
+            entity_name: entity.name.clone(),
+            field: field.to_string(),
+            reason,
+            marker_id: marker.id.clone(),
+            position: marker.position,
+        };
+
+        let mut introduced: Vec<&str> = Vec::new();
+        for (index, marker) in entity_markers.iter().enumerate() {
+            for change in marker.changes.iter().filter(|c| c.change_type != ChangeType::Learn) {
+                let field = change.field_name.as_str();
+
+                if change.change_type == ChangeType::Remove {
+                    if !introduced.iter().any(|p| paths_overlap(p, field)) {
+                        items.push(item(field, ChekhovReason::RemovedWithoutIntroduction, marker));
+                    }
+                    continue;
+                }
+                introduced.push(field);
+
+                let touched_elsewhere = entity_markers
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .any(|(_, m)| m.changes.iter().any(|c| paths_overlap(&c.field_name, field)));
+                let already_reported = items
+                    .iter()
+                    .any(|i: &ChekhovItem| i.marker_id == marker.id && i.field == field);
+
+                if !touched_elsewhere && !already_reported && !named_later(&blocks, field, marker.position) {
+                    items.push(item(field, ChekhovReason::NeverReferenced, marker));
+                }
+            }
+        }
+    }
+
+    items.sort_by(|a, b| {
+        a.position
+            .cmp(&b.position)
+            .then_with(|| a.entity_name.cmp(&b.entity_name))
+            .then_with(|| a.field.cmp(&b.field))
+    });
+    items
+}
+
+/// An entity that looks unused
+#[derive(Debug, Clone, Serialize)]
+pub struct UnusedEntity {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub marker_count: usize,
+    pub mention_count: Option<usize>, // Times the name appears in the text; None without content
+    pub last_touched: Option<i64>,    // Latest marker or field change (Unix seconds); None if never
+}
+
+/// Find entities with no markers, or (when the ProseMirror document is provided) whose
+/// name never appears in the text
+///
+/// Sorted with the longest-untouched entities first, so pruning candidates come up top.
+pub fn find_unused_entities(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    doc: Option<&serde_json::Value>,
+) -> Vec<UnusedEntity> {
+    let mentions = doc.map(|d| mentions::find_mentions(d, entities));
+
+    let mut unused: Vec<UnusedEntity> = entities
+        .values()
+        .filter_map(|entity| {
+            let entity_markers: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity.id).collect();
+            let mention_count = mentions
+                .as_ref()
+                .map(|found| found.iter().filter(|m| m.entity_id == entity.id).count());
+
+            if !entity_markers.is_empty() && mention_count != Some(0) {
+                return None;
+            }
+
+            let last_touched = entity_markers
+                .iter()
+                .map(|m| m.modified_at)
+                .chain(entity.field_metadata.values().map(|f| f.last_modified))
+                .max();
+
+            Some(UnusedEntity {
+                entity_id: entity.id.clone(),
+                entity_name: entity.name.clone(),
+                marker_count: entity_markers.len(),
+                mention_count,
+                last_touched,
+            })
+        })
+        .collect();
+
+    unused.sort_by(|a, b| a.last_touched.cmp(&b.last_touched).then_with(|| a.entity_name.cmp(&b.entity_name)));
+    unused
+}
+
+/// Default distance, in positions, within which identical markers count as duplicates
+pub const DUPLICATE_DISTANCE: usize = 3;
+
+/// Markers that repeat an earlier marker (same entity, same changes, close by)
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateMarkers {
+    pub entity_id: String,
+    pub kept_marker_id: String, // The first marker of the run
+    pub duplicate_marker_ids: Vec<String>,
+    pub positions: Vec<usize>, // Of the kept marker, then of each duplicate
+}
+
+// Changes compared as (field, type, value), in order
+fn same_changes(a: &Marker, b: &Marker) -> bool {
+    a.changes.len() == b.changes.len()
+        && a.changes.iter().zip(&b.changes).all(|(x, y)| {
+            x.field_name == y.field_name && x.change_type == y.change_type && x.value == y.value
+        })
+}
+
+/// Find runs of identical markers, typically left by a double click
+///
+/// A marker is a duplicate when it has the same entity and the same changes as
+/// the previous marker of the run and sits within `max_distance` positions of it.
+/// Markers without changes are never duplicates. Results are sorted by position.
+pub fn find_duplicate_markers(markers: &HashMap<String, Marker>, max_distance: usize) -> Vec<DuplicateMarkers> {
+    let mut ordered: Vec<&Marker> = markers.values().filter(|m| !m.changes.is_empty()).collect();
+    ordered.sort_by(|a, b| engine::compare_markers(a, b));
+
+    let mut groups: Vec<DuplicateMarkers> = Vec::new();
+    // Last marker of each entity's current run, by group index
+    let mut open_runs: HashMap<&str, (usize, &Marker)> = HashMap::new();
+
+    for marker in ordered {
+        let run = open_runs
+            .get(marker.entity_id.as_str())
+            .filter(|(_, last)| marker.position - last.position <= max_distance && same_changes(last, marker))
+            .map(|(index, _)| *index);
+
+        match run {
+            Some(index) => {
+                groups[index].duplicate_marker_ids.push(marker.id.clone());
+                groups[index].positions.push(marker.position);
+                open_runs.insert(&marker.entity_id, (index, marker));
+            }
+            None => {
+                groups.push(DuplicateMarkers {
+                    entity_id: marker.entity_id.clone(),
+                    kept_marker_id: marker.id.clone(),
+                    duplicate_marker_ids: Vec::new(),
+                    positions: vec![marker.position],
+                });
+                open_runs.insert(&marker.entity_id, (groups.len() - 1, marker));
+            }
+        }
+    }
+
+    groups.retain(|g| !g.duplicate_marker_ids.is_empty());
+    groups
+}
+
+/// How one of an entity's fields is used across its markers
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldUsage {
+    pub field: String,
+    pub marker_count: usize, // Markers with a change to the field
+    pub first_position: Option<usize>,
+    pub last_position: Option<usize>,
+    pub in_final_state: bool, // Whether the field has a value at the end of the document
+}
+
+/// Usage of each of the entity's fields (and any field its markers change), sorted by field
+///
+/// Fields with no markers, or that end the document removed, are candidates for cleanup.
+pub fn field_usage(entity: &Entity, markers: &HashMap<String, Marker>) -> Vec<FieldUsage> {
+    let mut own: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity.id).collect();
+    own.sort_by(|a, b| engine::compare_markers(a, b));
+    let final_state = engine::compute_state_with_defaults(own.iter().copied(), &engine::field_defaults(entity));
+
+    let mut fields: Vec<String> = entity.fields.clone();
+    for change in own.iter().flat_map(|m| m.changes.iter()) {
+        let path = knowledge::change_path(change);
+        if !fields.contains(&path) {
+            fields.push(path);
+        }
+    }
+    fields.sort();
+
+    fields
+        .into_iter()
+        .map(|field| {
+            let touching: Vec<&&Marker> = own
+                .iter()
+                .filter(|m| m.changes.iter().any(|c| knowledge::change_path(c) == field))
+                .collect();
+
+            FieldUsage {
+                marker_count: touching.len(),
+                first_position: touching.first().map(|m| m.position),
+                last_position: touching.last().map(|m| m.position),
+                in_final_state: engine::get_nested_value(&final_state, &field).is_some(),
+                field,
+            }
+        })
+        .collect()
+}
+
+/// Default length, in words, of a stretch without an entity that counts as a gap
+/// (about ten chapters of a typical novel)
+pub const GAP_MIN_WORDS: usize = 25_000;
+
+/// A stretch of the document where an entity has no markers and isn't mentioned
+#[derive(Debug, Clone, Serialize)]
+pub struct StateGap {
+    pub start: usize, // Last appearance before the gap
+    pub end: usize,   // Next appearance, or the document size for a trailing gap
+    pub word_count: usize,
+    pub chapters: Vec<String>, // Titles of the chapters the gap overlaps
+    pub trailing: bool, // The entity never appears again
+}
+
+/// Find stretches of at least `min_words` words where an entity has no markers and no
+/// mentions, in document order
+///
+/// Counting starts at the entity's first appearance, so characters introduced late
+/// aren't reported; a gap running to the end of the document is marked `trailing`.
+pub fn find_state_gaps(
+    entity: &Entity,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    doc: &serde_json::Value,
+    chapters: &[Chapter],
+    min_words: usize,
+) -> Vec<StateGap> {
+    let mut appearances: Vec<usize> = markers
+        .values()
+        .filter(|m| m.entity_id == entity.id)
+        .map(|m| m.position)
+        .chain(
+            mentions::find_mentions(doc, entities)
+                .into_iter()
+                .filter(|m| m.entity_id == entity.id)
+                .map(|m| m.position),
+        )
+        .collect();
+    appearances.sort_unstable();
+    appearances.dedup();
+    let Some(&last) = appearances.last() else {
+        return Vec::new();
+    };
+
+    let document_size = positions::content_size(doc);
+    let blocks: Vec<(usize, usize, usize)> = mentions::text_blocks(doc)
+        .into_iter()
+        .map(|(start, end, text)| (start, end, stats::count_words(&text)))
+        .collect();
+    // Words in blocks that lie entirely between two positions
+    let words_between = |from: usize, to: usize| -> usize {
+        blocks
+            .iter()
+            .filter(|(start, end, _)| *start > from && *end <= to)
+            .map(|(_, _, words)| words)
+            .sum()
+    };
+
+    let stretches = appearances
+        .windows(2)
+        .map(|pair| (pair[0], pair[1], false))
+        .chain(std::iter::once((last, document_size.max(last), true)));
+
+    stretches
+        .filter_map(|(start, end, trailing)| {
+            let word_count = words_between(start, end);
+            if word_count < min_words.max(1) {
+                return None;
+            }
+            Some(StateGap {
+                start,
+                end,
+                word_count,
+                chapters: chapters
+                    .iter()
+                    .filter(|c| c.start < end && c.end > start)
+                    .map(|c| c.title.clone())
+                    .collect(),
+                trailing,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,124 @@
+//! QuestScribe - Character Arc Outlines
+//!
+//! Turns one entity's markers into a chapter-by-chapter Markdown outline of
+//! their trajectory, for synopses and pitch documents. Each chapter with
+//! markers for the entity lists its milestones (marker descriptions and level
+//! changes), its other key changes, and the status effects gained or lost there.
+//!
+//! Key changes compare the entity's state at the chapter's start with its state
+//! at the chapter's end, like recaps, so changes that cancel out within a
+//! chapter are left out. Chapters without markers for the entity are skipped.
+
+use crate::chapters::Chapter;
+use crate::engine;
+use crate::gantt;
+use crate::recap;
+use crate::state::{Entity, Marker};
+use std::collections::{BTreeSet, HashMap};
+
+// "before → after", with a missing side shown as new or removed
+fn describe_change(field: &str, before: Option<&String>, after: Option<&String>) -> String {
+    match (before, after) {
+        (Some(old), Some(new)) => format!("{}: {} → {}", field, old, new),
+        (None, Some(new)) => format!("{}: {} (new)", field, new),
+        (Some(old), None) => format!("{}: removed (was {})", field, old),
+        (None, None) => field.to_string(),
+    }
+}
+
+fn push_list(out: &mut String, heading: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    out.push_str(&format!("**{}**\n\n", heading));
+    for item in items {
+        out.push_str(&format!("- {}\n", item));
+    }
+    out.push('\n');
+}
+
+/// Render an entity's arc outline as Markdown
+pub fn generate_arc_outline(entity: &Entity, markers: &HashMap<String, Marker>, chapters: &[Chapter]) -> String {
+    let entity_markers = gantt::entity_markers(markers, &entity.id);
+    let defaults = engine::field_defaults(entity);
+    let state_before = |position: usize| {
+        recap::flat_values(&engine::compute_state_with_defaults(
+            entity_markers.iter().copied().filter(|m| m.position < position),
+            &defaults,
+        ))
+    };
+
+    // Open spans run to the end, so they never count as lost
+    let spans = gantt::status_spans(&entity_markers, usize::MAX);
+    let status_fields: BTreeSet<&str> = spans.iter().map(|(field, _, _)| field.as_str()).collect();
+
+    let mut out = format!("# {}: Arc Outline\n\n", entity.name);
+    let mut chapter_count = 0;
+
+    for chapter in chapters {
+        let in_chapter: Vec<&Marker> = entity_markers
+            .iter()
+            .copied()
+            .filter(|m| m.position >= chapter.start && m.position < chapter.end)
+            .collect();
+        if in_chapter.is_empty() {
+            continue;
+        }
+        chapter_count += 1;
+
+        let mut milestones: Vec<String> = in_chapter
+            .iter()
+            .map(|m| m.description.trim())
+            .filter(|d| !d.is_empty())
+            .map(str::to_string)
+            .collect();
+        let mut key_changes = Vec::new();
+
+        let before = state_before(chapter.start);
+        let after = state_before(chapter.end);
+        let fields: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        for field in fields {
+            let (old, new) = (before.get(field), after.get(field));
+            if old == new || status_fields.contains(field.as_str()) {
+                continue;
+            }
+            let line = describe_change(field, old, new);
+            if recap::is_level_field(field) {
+                milestones.push(line);
+            } else {
+                key_changes.push(line);
+            }
+        }
+
+        let in_range = |position: usize| position >= chapter.start && position < chapter.end;
+        let gained: Vec<String> = spans
+            .iter()
+            .filter(|(_, start, _)| in_range(*start))
+            .map(|(field, _, _)| field.clone())
+            .collect();
+        let lost: Vec<String> = spans
+            .iter()
+            .filter(|(_, _, end)| in_range(*end))
+            .map(|(field, _, _)| field.clone())
+            .collect();
+
+        out.push_str(&format!("## {}\n\n", chapter.title));
+        push_list(&mut out, "Milestones", &milestones);
+        push_list(&mut out, "Key changes", &key_changes);
+        push_list(&mut out, "Status effects gained", &gained);
+        push_list(&mut out, "Status effects lost", &lost);
+    }
+
+    if chapter_count == 0 {
+        out.push_str("No markers for this entity.\n");
+        return out;
+    }
+
+    let end_state = recap::flat_values(&engine::compute_state_with_defaults(entity_markers.iter().copied(), &defaults));
+    out.push_str("## Where the arc ends\n\n");
+    for (field, value) in &end_state {
+        out.push_str(&format!("- {}: {}\n", field, value));
+    }
+
+    out
+}
@@ -0,0 +1,154 @@
+//! QuestScribe - Emotional Arcs
+//!
+//! An arc field (field type `arc`) tracks a character's emotional state on a
+//! fixed scale from -5 to +5, alongside their hard stats: despair to elation,
+//! distrust to trust, and so on. Absolute values outside the scale are rejected
+//! when a marker is saved. The engine clamps relative changes as it applies them,
+//! so "+3" at +4 gives +5, and a following "-2" gives +3.
+
+use crate::engine;
+use crate::state::{ChangeType, Entity, FieldChange, FieldType, Marker};
+use serde::Serialize;
+use std::collections::HashMap;
+
+pub const ARC_MIN: f64 = -5.0;
+pub const ARC_MAX: f64 = 5.0;
+
+/// One sample of an arc
+#[derive(Debug, Clone, Serialize)]
+pub struct ArcPoint {
+    pub position: usize,
+    pub value: f64,
+    pub description: Option<String>, // Description of the marker that set the value, if sampled at a change
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmotionalArc {
+    pub field: String,
+    pub min: f64,
+    pub max: f64,
+    pub points: Vec<ArcPoint>,
+}
+
+/// Whether a field of the entity is declared as an arc
+pub fn is_arc_field(entity: &Entity, field: &str) -> bool {
+    entity
+        .field_metadata
+        .get(field)
+        .is_some_and(|meta| meta.field_type == Some(FieldType::Arc))
+}
+
+/// Check changes to the entity's arc fields: values must be numbers, and absolute values on the scale
+pub fn validate_changes(entity: &Entity, changes: &[FieldChange]) -> Result<(), String> {
+    for change in changes.iter().filter(|c| is_arc_field(entity, &c.field_name)) {
+        if change.change_type != ChangeType::Absolute && change.change_type != ChangeType::Relative {
+            continue;
+        }
+
+        let value = change
+            .value
+            .parse::<f64>()
+            .map_err(|_| format!("Arc field \"{}\" needs a number, not \"{}\"", change.field_name, change.value))?;
+        if change.change_type == ChangeType::Absolute && !(ARC_MIN..=ARC_MAX).contains(&value) {
+            return Err(format!(
+                "Arc field \"{}\" must be between {} and +{}",
+                change.field_name, ARC_MIN, ARC_MAX
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// An arc value clamped to the scale
+pub fn clamp(value: f64) -> f64 {
+    value.clamp(ARC_MIN, ARC_MAX)
+}
+
+/// A value on a labeled text scale, e.g. "-2  -5 ───●─┼───── +5"
+pub fn render_scale(value: f64) -> String {
+    let value = clamp(value);
+    let slot = |v: f64| (v - ARC_MIN).round() as usize;
+
+    let scale: String = (0..=slot(ARC_MAX))
+        .map(|i| {
+            if i == slot(value) {
+                '●'
+            } else if i == slot(0.0) {
+                '┼'
+            } else {
+                '─'
+            }
+        })
+        .collect();
+
+    format!("{:+}  {} {} +{}", value, ARC_MIN, scale, ARC_MAX)
+}
+
+fn sample(markers: &HashMap<String, Marker>, entity: &Entity, field: &str, position: usize) -> Option<f64> {
+    let state = engine::entity_state_with_defaults(markers, entity, position);
+    engine::get_nested_value(&state, field).and_then(|v| v.as_f64()).map(clamp)
+}
+
+/// Sample each of the entity's arc fields (or just `field`)
+///
+/// Without `positions`, there's a point after every marker that changes the field,
+/// labeled with the marker's description; otherwise a point at each given position
+/// where the field has a value.
+pub fn emotional_arcs(
+    entity: &Entity,
+    markers: &HashMap<String, Marker>,
+    field: Option<&str>,
+    positions: Option<&[usize]>,
+) -> Vec<EmotionalArc> {
+    let mut fields: Vec<&String> = entity
+        .fields
+        .iter()
+        .filter(|f| is_arc_field(entity, f))
+        .filter(|f| field.is_none_or(|wanted| wanted == f.as_str()))
+        .collect();
+    fields.sort();
+
+    fields
+        .into_iter()
+        .map(|field| {
+            let points = match positions {
+                Some(positions) => positions
+                    .iter()
+                    .filter_map(|&position| {
+                        sample(markers, entity, field, position).map(|value| ArcPoint {
+                            position,
+                            value,
+                            description: None,
+                        })
+                    })
+                    .collect(),
+                None => {
+                    let mut changing: Vec<&Marker> = markers
+                        .values()
+                        .filter(|m| m.entity_id == entity.id && !m.todo && m.changes.iter().any(|c| c.field_name == *field))
+                        .collect();
+                    changing.sort_by(|a, b| engine::compare_markers(a, b));
+
+                    changing
+                        .into_iter()
+                        .filter_map(|marker| {
+                            sample(markers, entity, field, marker.position).map(|value| ArcPoint {
+                                position: marker.position,
+                                value,
+                                description: (!marker.description.is_empty()).then(|| marker.description.clone()),
+                            })
+                        })
+                        .collect()
+                }
+            };
+
+            EmotionalArc {
+                field: field.clone(),
+                min: ARC_MIN,
+                max: ARC_MAX,
+                points,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,160 @@
+//! QuestScribe - Atomic Command Batches
+//!
+//! Runs a sequence of entity/marker mutations as one unit: if any command fails,
+//! every change made by the earlier commands is rolled back, so a multi-step
+//! feature (e.g., "create entity + insert its first marker + recolor") never
+//! leaves half-applied state behind.
+//!
+//! Later commands can refer to something an earlier command created with "$N",
+//! where N is the index of that command (e.g., `"entity_id": "$0"`).
+
+use crate::mutations::{self, EntityUpdate, MarkerUpdate, MutationContext, NewEntity, NewMarker};
+use crate::state::{Entity, Marker};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// One mutation in a batch, tagged by command name (e.g., `{"command": "delete_marker", "marker_id": "..."}`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum BatchCommand {
+    CreateEntity(NewEntity),
+    UpdateEntity(EntityUpdate),
+    DeleteEntity { entity_id: String },
+    DeleteFieldCompletely { entity_id: String, field_name: String },
+    MoveFieldSubtree { entity_id: String, old_prefix: String, new_prefix: String },
+    InsertMarker(NewMarker),
+    UpdateMarker(MarkerUpdate),
+    DeleteMarker { marker_id: String },
+    SetMarkerTags { marker_id: String, tags: Vec<String> },
+    SetMarkerStoryTime { marker_id: String, story_time: Option<f64> },
+}
+
+impl BatchCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            BatchCommand::CreateEntity(_) => "create_entity",
+            BatchCommand::UpdateEntity(_) => "update_entity",
+            BatchCommand::DeleteEntity { .. } => "delete_entity",
+            BatchCommand::DeleteFieldCompletely { .. } => "delete_field_completely",
+            BatchCommand::MoveFieldSubtree { .. } => "move_field_subtree",
+            BatchCommand::InsertMarker(_) => "insert_marker",
+            BatchCommand::UpdateMarker(_) => "update_marker",
+            BatchCommand::DeleteMarker { .. } => "delete_marker",
+            BatchCommand::SetMarkerTags { .. } => "set_marker_tags",
+            BatchCommand::SetMarkerStoryTime { .. } => "set_marker_story_time",
+        }
+    }
+}
+
+// Replace a "$N" reference with the ID created by command N
+fn resolve(id: String, created: &[Option<String>]) -> Result<String, String> {
+    let Some(index) = id.strip_prefix('$').and_then(|n| n.parse::<usize>().ok()) else {
+        return Ok(id);
+    };
+
+    created
+        .get(index)
+        .cloned()
+        .flatten()
+        .ok_or_else(|| format!("Reference {} doesn't point to an earlier command that created something", id))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<serde_json::Value, String> {
+    serde_json::to_value(value).map_err(|e| format!("Failed to serialize result: {}", e))
+}
+
+// Run one command, returning its result and the ID of anything it created
+fn run_command(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    command: BatchCommand,
+    created: &[Option<String>],
+) -> Result<(serde_json::Value, Option<String>), String> {
+    match command {
+        BatchCommand::CreateEntity(new_entity) => {
+            let entity = mutations::create_entity(entities, context, new_entity)?;
+            Ok((to_json(&entity)?, Some(entity.id)))
+        }
+        BatchCommand::UpdateEntity(mut update) => {
+            update.entity_id = resolve(update.entity_id, created)?;
+            let entity = mutations::update_entity(entities, markers, update)?;
+            Ok((to_json(&entity)?, None))
+        }
+        BatchCommand::DeleteEntity { entity_id } => {
+            mutations::delete_entity(entities, markers, &resolve(entity_id, created)?)?;
+            Ok((serde_json::Value::Null, None))
+        }
+        BatchCommand::DeleteFieldCompletely { entity_id, field_name } => {
+            mutations::delete_field_completely(entities, markers, &resolve(entity_id, created)?, &field_name)?;
+            Ok((serde_json::Value::Null, None))
+        }
+        BatchCommand::MoveFieldSubtree { entity_id, old_prefix, new_prefix } => {
+            let entity = mutations::move_field_subtree(
+                entities,
+                markers,
+                &resolve(entity_id, created)?,
+                &old_prefix,
+                &new_prefix,
+            )?;
+            Ok((to_json(&entity)?, None))
+        }
+        BatchCommand::InsertMarker(mut new_marker) => {
+            new_marker.entity_id = resolve(new_marker.entity_id, created)?;
+            let marker = mutations::insert_marker(entities, markers, context, new_marker)?;
+            Ok((to_json(&marker)?, Some(marker.id)))
+        }
+        BatchCommand::UpdateMarker(mut update) => {
+            update.marker_id = resolve(update.marker_id, created)?;
+            update.entity_id = update.entity_id.map(|id| resolve(id, created)).transpose()?;
+            let marker = mutations::update_marker(entities, markers, context, update)?;
+            Ok((to_json(&marker)?, None))
+        }
+        BatchCommand::DeleteMarker { marker_id } => {
+            mutations::delete_marker(entities, markers, context, &resolve(marker_id, created)?)?;
+            Ok((serde_json::Value::Null, None))
+        }
+        BatchCommand::SetMarkerTags { marker_id, tags } => {
+            let marker = mutations::set_marker_tags(markers, &resolve(marker_id, created)?, tags)?;
+            Ok((to_json(&marker)?, None))
+        }
+        BatchCommand::SetMarkerStoryTime { marker_id, story_time } => {
+            let marker = mutations::set_marker_story_time(markers, &resolve(marker_id, created)?, story_time)?;
+            Ok((to_json(&marker)?, None))
+        }
+    }
+}
+
+/// Run every command in order, or none of them
+///
+/// Returns each command's result (the entity or marker it returned, or null).
+/// On failure, entities and markers are restored to their state before the batch.
+pub fn run_batch(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    commands: Vec<BatchCommand>,
+) -> Result<Vec<serde_json::Value>, String> {
+    let entities_before = entities.clone();
+    let markers_before = markers.clone();
+
+    let mut results = Vec::with_capacity(commands.len());
+    let mut created: Vec<Option<String>> = Vec::with_capacity(commands.len());
+
+    for (index, command) in commands.into_iter().enumerate() {
+        let name = command.name();
+        match run_command(entities, markers, context, command, &created) {
+            Ok((result, created_id)) => {
+                results.push(result);
+                created.push(created_id);
+            }
+            Err(e) => {
+                *entities = entities_before;
+                *markers = markers_before;
+                return Err(format!("Batch command {} ({}) failed: {}", index, name, e));
+            }
+        }
+    }
+
+    Ok(results)
+}
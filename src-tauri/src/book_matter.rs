@@ -0,0 +1,156 @@
+//! QuestScribe - Front and Back Matter for Export
+//!
+//! Blocks exports wrap around the manuscript so it reads like a finished book:
+//!
+//! - Front matter: a title page (title, then "by" the author) and a copyright
+//!   page, each on a page of its own (the manuscript starts a new page too).
+//! - Back matter: a character appendix listing every character, and a glossary
+//!   listing every place, each with its description as of the end of the
+//!   exported part (from the entity's "description" field, or the field set
+//!   in the document's matter settings).
+//!
+//! The settings live in the document's preferences (see preferences.rs). Back
+//! matter comes after the endnotes and character sheets; redacted exports only
+//! list the entities the redaction keeps.
+
+use crate::engine;
+use crate::i18n;
+use crate::recap;
+use crate::state::{Entity, EntityKind, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const DEFAULT_DESCRIPTION_FIELD: &str = "description";
+
+/// Which front and back matter exports include
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookMatter {
+    pub title_page: bool,
+    pub author: Option<String>, // Shown on the title page
+    pub copyright: Option<String>, // Copyright page text, one paragraph per line; None = no page
+    pub character_appendix: bool,
+    pub glossary: bool,
+    pub description_field: Option<String>, // Field the appendix and glossary read; None = "description"
+}
+
+impl BookMatter {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.author.as_ref().is_some_and(|a| a.trim().is_empty()) {
+            return Err("Author cannot be empty".to_string());
+        }
+        if self.description_field.as_ref().is_some_and(|f| f.trim().is_empty()) {
+            return Err("Description field cannot be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MatterStyle {
+    Title, // The book title on the title page
+    Heading, // Section heading (appendix, glossary)
+    Centered, // Title page and copyright lines
+    Entry, // A term in bold, then its description
+}
+
+/// One paragraph of front or back matter
+pub struct MatterBlock {
+    pub style: MatterStyle,
+    pub term: Option<String>, // Entry term (entity name)
+    pub text: String,
+    pub page_break: bool, // Starts a new page
+}
+
+fn block(style: MatterStyle, text: String, page_break: bool) -> MatterBlock {
+    MatterBlock { style, term: None, text, page_break }
+}
+
+/// Blocks before the manuscript
+pub fn front_matter(matter: &BookMatter, title: &str, locale: &str) -> Vec<MatterBlock> {
+    let mut blocks = Vec::new();
+
+    if matter.title_page {
+        blocks.push(block(MatterStyle::Title, title.to_string(), false));
+        if let Some(author) = &matter.author {
+            blocks.push(block(
+                MatterStyle::Centered,
+                i18n::tr(locale, "matter.by_author", &[("author", author.trim())]),
+                false,
+            ));
+        }
+    }
+
+    if let Some(copyright) = &matter.copyright {
+        let lines: Vec<&str> = copyright.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        for (index, line) in lines.iter().enumerate() {
+            // Only the first line starts the page (and only if something precedes it)
+            let page_break = index == 0 && !blocks.is_empty();
+            blocks.push(block(MatterStyle::Centered, line.to_string(), page_break));
+        }
+    }
+
+    blocks
+}
+
+// One entry per entity of a kind, in name order, described as of a position
+fn entries(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    kind: EntityKind,
+    field: &str,
+    position: usize,
+) -> Vec<MatterBlock> {
+    let mut sorted: Vec<&Entity> = entities.values().filter(|e| e.kind == kind).collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+
+    sorted
+        .into_iter()
+        .map(|entity| {
+            let mut state = engine::entity_state_with_defaults(markers, entity, position);
+            engine::fill_defaults(&mut state, entity);
+            let description = recap::flat_values(&state)
+                .into_iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(field))
+                .map(|(_, value)| value)
+                .unwrap_or_default();
+
+            MatterBlock {
+                style: MatterStyle::Entry,
+                term: Some(entity.name.clone()),
+                text: description,
+                page_break: false,
+            }
+        })
+        .collect()
+}
+
+/// Blocks after the manuscript, describing entities as of a position (usize::MAX for the end)
+pub fn back_matter(
+    matter: &BookMatter,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    position: usize,
+    locale: &str,
+) -> Vec<MatterBlock> {
+    let field = matter.description_field.as_deref().unwrap_or(DEFAULT_DESCRIPTION_FIELD).trim();
+    let sections = [
+        (matter.character_appendix, EntityKind::Character, "matter.characters_heading"),
+        (matter.glossary, EntityKind::Location, "matter.glossary_heading"),
+    ];
+
+    let mut blocks = Vec::new();
+    for (enabled, kind, heading) in sections {
+        if !enabled {
+            continue;
+        }
+        let section = entries(entities, markers, kind, field, position);
+        if section.is_empty() {
+            continue;
+        }
+        blocks.push(block(MatterStyle::Heading, i18n::tr(locale, heading, &[]), true));
+        blocks.extend(section);
+    }
+
+    blocks
+}
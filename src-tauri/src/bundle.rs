@@ -0,0 +1,359 @@
+//! QuestScribe - Campaign Bundle Export
+//!
+//! Writes a whole campaign as a zip of JSON files, a stable interchange format
+//! for third-party tools (virtual tabletops, wikis, character builders). The
+//! layout is documented in the README.md inside every bundle; any breaking
+//! change to it must bump `FORMAT_VERSION`.
+//!
+//! ```text
+//! manifest.json       format name/version, source document, export time, file list
+//! entities.json       every entity with its fields and final state
+//! timelines.json      per entity, its markers in story order with the state after each
+//! relationships.json  graph of entities referencing or appearing alongside each other
+//! chapters.json       per chapter, what changed for whom (see recap.rs)
+//! ```
+
+use crate::chapters::{self, Chapter};
+use crate::engine::{self, EntityState};
+use crate::recap::{self, EntityRecap};
+use crate::state::{Entity, FieldChange, Marker};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Seek, Write};
+
+/// Identifies bundles written by QuestScribe
+pub const FORMAT_NAME: &str = "questscribe-campaign";
+
+/// Incremented on breaking changes to the bundle layout
+pub const FORMAT_VERSION: u32 = 1;
+
+const README: &str = "\
+# QuestScribe campaign bundle
+
+Exported by QuestScribe. All files are UTF-8 JSON. Positions are ProseMirror
+document positions; chapters are delimited by level-1 headings. IDs are stable
+across exports of the same document.
+
+## manifest.json
+- `format`: always \"questscribe-campaign\"
+- `format_version`: layout version (breaking changes increment it)
+- `generator`: app name and version
+- `exported_at`: Unix time in seconds
+- `language`: document language code, or null
+- `files`: the JSON files in this bundle
+
+## entities.json
+Array of `{id, name, color, fields, final_state}`. `fields` lists field paths
+such as \"stats.HP\"; `final_state` is the state after the last marker, nested by
+path segment (`{\"stats\": {\"HP\": 35}}`).
+
+## timelines.json
+Object keyed by entity ID. Each value is an array, in story order, of
+`{marker_id, position, chapter, changes, description, tags, state}`.
+`changes` entries are `{field_name, change_type, value}` where `change_type` is
+\"absolute\", \"relative\" or \"remove\"; `state` is the entity state after the
+marker. `chapter` is the chapter index, or null when no chapters were exported.
+
+## relationships.json
+`{nodes, edges}`. `nodes` are entity IDs. Each edge is
+`{source, target, kind, fields, chapters}`:
+- kind \"reference\": a field of `source` was set to `target`'s name; `fields`
+  lists those fields
+- kind \"co_appearance\": both entities have markers in the chapters listed in
+  `chapters` (source and target in ID order)
+
+## chapters.json
+Array of `{index, title, start, end, summary, entities}`. `summary` is a short
+prose recap; `entities` lists per-entity changes (`level_changes`,
+`items_gained`, `items_lost`, `notable_changes`). Empty when the bundle was
+exported without document content.
+";
+
+#[derive(Debug, Clone, Serialize)]
+struct Manifest {
+    format: &'static str,
+    format_version: u32,
+    generator: String,
+    exported_at: i64,
+    language: Option<String>,
+    files: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BundleEntity {
+    id: String,
+    name: String,
+    color: String,
+    fields: Vec<String>,
+    final_state: EntityState,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TimelineEntry {
+    marker_id: String,
+    position: usize,
+    chapter: Option<usize>,
+    changes: Vec<FieldChange>,
+    description: String,
+    tags: Vec<String>,
+    state: EntityState,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EdgeKind {
+    Reference,
+    CoAppearance,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Edge {
+    source: String,
+    target: String,
+    kind: EdgeKind,
+    fields: Vec<String>,
+    chapters: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RelationshipGraph {
+    nodes: Vec<String>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ChapterSummary {
+    index: usize,
+    title: String,
+    start: usize,
+    end: usize,
+    summary: String,
+    entities: Vec<EntityRecap>,
+}
+
+/// Everything the bundle is built from
+pub struct CampaignSource<'a> {
+    pub entities: &'a HashMap<String, Entity>,
+    pub markers: &'a HashMap<String, Marker>,
+    pub content: Option<&'a serde_json::Value>, // None = no chapter data
+    pub language: Option<String>,
+    pub locale: &'a str,
+    pub untitled_chapter: &'a str,
+    pub now: i64,
+}
+
+fn chapter_at(chapters: &[Chapter], position: usize) -> Option<usize> {
+    chapters
+        .iter()
+        .find(|c| position >= c.start && position < c.end)
+        .or(chapters.last().filter(|c| position >= c.end))
+        .map(|c| c.index)
+}
+
+// Entities sorted by name, so bundle files diff cleanly between exports
+fn sorted_entities(entities: &HashMap<String, Entity>) -> Vec<&Entity> {
+    let mut sorted: Vec<&Entity> = entities.values().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    sorted
+}
+
+// An entity's markers in application order, leaving out TODO reminders
+fn entity_markers<'a>(markers: &'a HashMap<String, Marker>, entity_id: &str) -> Vec<&'a Marker> {
+    let mut list: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity_id && !m.todo).collect();
+    list.sort_by(|a, b| engine::compare_markers(a, b));
+    list
+}
+
+fn build_entities(source: &CampaignSource) -> Vec<BundleEntity> {
+    sorted_entities(source.entities)
+        .into_iter()
+        .map(|entity| BundleEntity {
+            id: entity.id.clone(),
+            name: entity.name.clone(),
+            color: entity.color.clone(),
+            fields: entity.fields.clone(),
+            final_state: engine::compute_state_with_defaults(
+                entity_markers(source.markers, &entity.id),
+                &engine::field_defaults(entity),
+            ),
+        })
+        .collect()
+}
+
+fn build_timelines(source: &CampaignSource, chapters: &[Chapter]) -> BTreeMap<String, Vec<TimelineEntry>> {
+    let mut timelines = BTreeMap::new();
+
+    for entity in source.entities.values() {
+        let defaults = engine::field_defaults(entity);
+        let mut state = EntityState::new();
+        let entries = entity_markers(source.markers, &entity.id)
+            .into_iter()
+            .map(|marker| {
+                for change in &marker.changes {
+                    engine::apply_change(&mut state, change, &defaults);
+                }
+                TimelineEntry {
+                    marker_id: marker.id.clone(),
+                    position: marker.position,
+                    chapter: chapter_at(chapters, marker.position),
+                    changes: marker.changes.clone(),
+                    description: marker.description.clone(),
+                    tags: marker.tags.clone(),
+                    state: state.clone(),
+                }
+            })
+            .collect();
+
+        timelines.insert(entity.id.clone(), entries);
+    }
+
+    timelines
+}
+
+fn build_relationships(source: &CampaignSource, chapters: &[Chapter]) -> RelationshipGraph {
+    let entities = sorted_entities(source.entities);
+    let by_name: HashMap<String, &str> = entities
+        .iter()
+        .map(|e| (e.name.trim().to_lowercase(), e.id.as_str()))
+        .collect();
+
+    // Reference edges: a field value that names another entity (e.g., "companion" = "Mira")
+    let mut references: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for marker in source.markers.values() {
+        for change in &marker.changes {
+            let Some(target) = by_name.get(&change.value.trim().to_lowercase()) else {
+                continue;
+            };
+            if *target == marker.entity_id || !source.entities.contains_key(&marker.entity_id) {
+                continue;
+            }
+            let fields = references
+                .entry((marker.entity_id.clone(), target.to_string()))
+                .or_default();
+            if !fields.contains(&change.field_name) {
+                fields.push(change.field_name.clone());
+            }
+        }
+    }
+
+    let mut edges: Vec<Edge> = references
+        .into_iter()
+        .map(|((source, target), mut fields)| {
+            fields.sort();
+            Edge { source, target, kind: EdgeKind::Reference, fields, chapters: Vec::new() }
+        })
+        .collect();
+
+    // Co-appearance edges: both entities have markers in the same chapters
+    let mut appearances: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for marker in source.markers.values() {
+        if let Some(chapter) = chapter_at(chapters, marker.position) {
+            let list = appearances.entry(marker.entity_id.as_str()).or_default();
+            if !list.contains(&chapter) {
+                list.push(chapter);
+            }
+        }
+    }
+
+    let ids: Vec<&str> = appearances
+        .keys()
+        .copied()
+        .filter(|id| source.entities.contains_key(*id))
+        .collect();
+    for (i, a) in ids.iter().enumerate() {
+        for b in &ids[i + 1..] {
+            let mut shared: Vec<usize> = appearances[a]
+                .iter()
+                .filter(|c| appearances[b].contains(c))
+                .copied()
+                .collect();
+            if shared.is_empty() {
+                continue;
+            }
+            shared.sort();
+            edges.push(Edge {
+                source: a.to_string(),
+                target: b.to_string(),
+                kind: EdgeKind::CoAppearance,
+                fields: Vec::new(),
+                chapters: shared,
+            });
+        }
+    }
+
+    RelationshipGraph {
+        nodes: entities.iter().map(|e| e.id.clone()).collect(),
+        edges,
+    }
+}
+
+fn build_chapter_summaries(source: &CampaignSource, chapters: &[Chapter]) -> Vec<ChapterSummary> {
+    chapters
+        .iter()
+        .map(|chapter| {
+            let recap = recap::generate_recap(
+                source.entities,
+                source.markers,
+                chapter.start,
+                chapter.end,
+                source.locale,
+            );
+            ChapterSummary {
+                index: chapter.index,
+                title: chapter.title.clone(),
+                start: chapter.start,
+                end: chapter.end,
+                summary: recap.prose,
+                entities: recap.entities,
+            }
+        })
+        .collect()
+}
+
+fn add_json<W: Write + Seek, T: Serialize>(
+    zip: &mut zip::ZipWriter<W>,
+    name: &str,
+    value: &T,
+) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+    add_file(zip, name, json.as_bytes())
+}
+
+fn add_file<W: Write + Seek>(zip: &mut zip::ZipWriter<W>, name: &str, data: &[u8]) -> Result<(), String> {
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    zip.start_file(name, options)
+        .map_err(|e| format!("Failed to add {} to bundle: {}", name, e))?;
+    zip.write_all(data)
+        .map_err(|e| format!("Failed to add {} to bundle: {}", name, e))
+}
+
+/// Write the campaign bundle to `writer`
+pub fn write_bundle<W: Write + Seek>(writer: W, source: &CampaignSource) -> Result<(), String> {
+    let chapters = source
+        .content
+        .map(|doc| chapters::chapters_from_content(doc, source.untitled_chapter))
+        .unwrap_or_default();
+
+    let manifest = Manifest {
+        format: FORMAT_NAME,
+        format_version: FORMAT_VERSION,
+        generator: format!("QuestScribe {}", env!("CARGO_PKG_VERSION")),
+        exported_at: source.now,
+        language: source.language.clone(),
+        files: vec!["entities.json", "timelines.json", "relationships.json", "chapters.json"],
+    };
+
+    let mut zip = zip::ZipWriter::new(writer);
+    add_json(&mut zip, "manifest.json", &manifest)?;
+    add_file(&mut zip, "README.md", README.as_bytes())?;
+    add_json(&mut zip, "entities.json", &build_entities(source))?;
+    add_json(&mut zip, "timelines.json", &build_timelines(source, &chapters))?;
+    add_json(&mut zip, "relationships.json", &build_relationships(source, &chapters))?;
+    add_json(&mut zip, "chapters.json", &build_chapter_summaries(source, &chapters))?;
+
+    zip.finish()
+        .map_err(|e| format!("Failed to finish bundle: {}", e))?;
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+//! QuestScribe - Custom Change Types
+//!
+//! Besides the built-in change types (absolute, relative, remove, learn), a
+//! document can define its own, declaratively: a name, how many arguments a
+//! change takes, and a reducer formula (see formula.rs) that computes the
+//! field's new value from `current` and the arguments `$1`, `$2`, ... A karma
+//! meter, for instance, is `karma` with one argument and the reducer
+//! `clamp(current + $1, -100, 100)`.
+//!
+//! A change's arguments are its value, separated by spaces or commas ("3, 6").
+//! `current` is the field's number (or its default, or 0).
+//!
+//! Each custom change carries its type's reducer, so the state engine needs
+//! only the markers, and markers copied to another document (clipboard, entity
+//! packs) keep computing the same values. Redefining a type updates the changes
+//! that use it. Reducers are parsed once and the parsed formulas cached, since
+//! the engine applies them for every marker in every state computation.
+
+use crate::formula::Formula;
+use crate::state::{ChangeType, CustomChange, FieldChange, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Most arguments a custom change type can take
+const MAX_ARITY: usize = 9;
+/// Names taken by the built-in change types
+const BUILT_IN: &[&str] = &["absolute", "relative", "remove", "learn", "custom"];
+/// Most parsed reducers kept around; past this the cache starts over
+const MAX_CACHED: usize = 256;
+
+// Parsed reducers by source
+static PARSED: Mutex<Option<HashMap<String, Arc<Formula>>>> = Mutex::new(None);
+
+// A reducer's parsed formula, parsing it only the first time it's seen
+fn parsed(reducer: &str) -> Result<Arc<Formula>, String> {
+    let mut cache = PARSED.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if let Some(formula) = cache.get(reducer) {
+        return Ok(Arc::clone(formula));
+    }
+
+    let formula = Arc::new(Formula::parse(reducer)?);
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(reducer.to_string(), Arc::clone(&formula));
+    Ok(formula)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomChangeType {
+    pub name: String, // e.g., "karma"
+    pub arity: usize, // Arguments a change takes
+    pub reducer: String, // Formula over `current` and $1..$arity
+    #[serde(default)]
+    pub description: String,
+}
+
+impl CustomChangeType {
+    pub fn validate(&self) -> Result<(), String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err("Change type name cannot be empty".to_string());
+        }
+        if BUILT_IN.contains(&name.to_lowercase().as_str()) {
+            return Err(format!("\"{}\" is a built-in change type", name));
+        }
+        if self.arity > MAX_ARITY {
+            return Err(format!("A change type takes at most {} arguments", MAX_ARITY));
+        }
+
+        let formula = Formula::parse(&self.reducer)?;
+        for variable in formula.variables() {
+            let known = variable == "current"
+                || variable
+                    .strip_prefix('$')
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .is_some_and(|n| (1..=self.arity).contains(&n));
+            if !known {
+                return Err(format!("Reducer of \"{}\" uses unknown variable \"{}\"", name, variable));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check a document's change types: each valid, names unique
+pub fn validate_all(types: &[CustomChangeType]) -> Result<(), String> {
+    for (index, change_type) in types.iter().enumerate() {
+        change_type.validate()?;
+        if types[..index].iter().any(|t| t.name.trim() == change_type.name.trim()) {
+            return Err(format!("Change type \"{}\" is defined twice", change_type.name.trim()));
+        }
+    }
+    Ok(())
+}
+
+/// A change's arguments: its value split at spaces and commas
+pub fn arguments(value: &str) -> Vec<&str> {
+    value.split(|c: char| c.is_whitespace() || c == ',').filter(|a| !a.is_empty()).collect()
+}
+
+/// The field's new value after a custom change, from its current number
+pub fn apply(current: f64, change: &FieldChange) -> Result<f64, String> {
+    let custom = change.custom.as_ref().ok_or("Custom change has no change type")?;
+    let formula = parsed(&custom.reducer)?;
+
+    let mut variables = HashMap::from([("current".to_string(), current)]);
+    for (index, argument) in arguments(&change.value).into_iter().enumerate() {
+        let number = argument
+            .parse::<f64>()
+            .map_err(|_| format!("Argument \"{}\" of a {} change is not a number", argument, custom.name))?;
+        variables.insert(format!("${}", index + 1), number);
+    }
+
+    formula.evaluate(&variables)
+}
+
+/// Fill in the reducers of custom changes from the document's definitions
+///
+/// Fails on an unknown change type or the wrong number of arguments.
+pub fn resolve_changes(types: &[CustomChangeType], changes: &mut [FieldChange]) -> Result<(), String> {
+    for change in changes.iter_mut().filter(|c| c.change_type == ChangeType::Custom) {
+        let name = change.custom.as_ref().map(|c| c.name.trim()).unwrap_or("");
+        let definition = types
+            .iter()
+            .find(|t| t.name.trim() == name)
+            .ok_or_else(|| format!("Unknown change type \"{}\"", name))?;
+
+        let count = arguments(&change.value).len();
+        if count != definition.arity {
+            return Err(format!(
+                "A {} change takes {} argument(s), not {}",
+                definition.name, definition.arity, count
+            ));
+        }
+        change.custom = Some(CustomChange {
+            name: definition.name.trim().to_string(),
+            reducer: definition.reducer.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Bring the reducers of existing changes up to date with redefined types
+///
+/// Changes whose type was removed keep the reducer they have. Returns how many
+/// markers changed.
+pub fn redefine(markers: &mut HashMap<String, Marker>, types: &[CustomChangeType]) -> usize {
+    let mut updated = 0;
+    for marker in markers.values_mut() {
+        let mut changed = false;
+        for custom in marker.changes.iter_mut().filter_map(|c| c.custom.as_mut()) {
+            if let Some(definition) = types.iter().find(|t| t.name.trim() == custom.name) {
+                if custom.reducer != definition.reducer {
+                    custom.reducer = definition.reducer.clone();
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            updated += 1;
+        }
+    }
+    updated
+}
@@ -0,0 +1,75 @@
+//! QuestScribe - Chapter Import
+//!
+//! Assembling a book from per-chapter files: a file (in any format importers.rs
+//! reads) is appended to the end of the stored document as a new chapter. The
+//! document's chapter pattern, or heading promotion (see chapters.rs), first
+//! looks for the file's chapter title; a file that still doesn't open with a
+//! chapter heading gets one, titled with the file's name.
+//!
+//! The chapter's markers can come in a sidecar file next to it: a marker CSV
+//! (see marker_csv.rs) named after the chapter file, with the `.markers.csv`
+//! extension (`chapter-03.md` and `chapter-03.markers.csv`). Its positions and
+//! chapters refer to the chapter file on its own, and are offset to where the
+//! chapter lands in the document.
+
+use crate::chapters::{self, Chapter};
+use crate::positions;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Extension of a chapter's marker file, replacing the chapter file's own
+const SIDECAR_EXTENSION: &str = "markers.csv";
+
+/// An imported chapter file, ready to append
+pub struct ChapterFile {
+    pub blocks: Vec<Value>,
+    pub heading_size: usize, // Size of the heading added before the file's content (0 if none)
+    pub chapters: Vec<Chapter>, // The file's own chapters, for its sidecar's chapter locations
+    pub size: usize, // Size of the file's own content
+}
+
+/// Path of the marker file that goes with a chapter file
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    path.with_extension(SIDECAR_EXTENSION)
+}
+
+/// Turn an imported document into a chapter, opening with a level-1 heading
+pub fn prepare(mut imported: Value, pattern: Option<&Regex>, title: &str, untitled: &str) -> ChapterFile {
+    chapters::detect_chapters(&mut imported, pattern);
+    let size = positions::content_size(&imported);
+    let file_chapters = chapters::chapters_from_content(&imported, untitled);
+
+    let mut blocks = match imported.get_mut("content").and_then(|c| c.as_array_mut()) {
+        Some(content) => std::mem::take(content),
+        None => Vec::new(),
+    };
+
+    let mut heading_size = 0;
+    if blocks.first().and_then(chapters::heading_level) != Some(1) {
+        let heading = json!({
+            "type": "heading",
+            "attrs": { "level": 1 },
+            "content": [{ "type": "text", "text": title }],
+        });
+        heading_size = positions::node_size(&heading);
+        blocks.insert(0, heading);
+    }
+
+    ChapterFile {
+        blocks,
+        heading_size,
+        chapters: file_chapters,
+        size,
+    }
+}
+
+/// Append a chapter's blocks to the end of a document; returns the position it starts at
+pub fn append(doc: &mut Value, blocks: Vec<Value>) -> usize {
+    let start = positions::content_size(doc);
+    match doc.get_mut("content").and_then(|c| c.as_array_mut()) {
+        Some(content) => content.extend(blocks),
+        None => doc["content"] = Value::Array(blocks),
+    }
+    start
+}
@@ -0,0 +1,241 @@
+//! QuestScribe - Chapter Structure
+//!
+//! Chapters are delimited by level-1 headings, which is what the editor's
+//! "Insert Chapter Break" creates. Text before the first heading forms an
+//! untitled leading section. Chapter ranges use document positions (see positions.rs).
+//!
+//! A marker can be pinned to a heading instead of a text position: its position
+//! is then taken from wherever the heading is whenever the content is synced, so
+//! structural markers ("start of Chapter 12") survive rewrites of the prose.
+
+use crate::entity_refs;
+use crate::positions;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Chapter {
+    pub index: usize,
+    pub title: String,
+    pub start: usize, // Position of the heading (or 0 for the leading section)
+    pub end: usize,   // Start of the next chapter, or the document size
+}
+
+/// Which end of a chapter a chapter-scoped state query looks at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChapterEdge {
+    Start, // The chapter's heading: the state going into the chapter
+    #[default]
+    End, // The chapter's last position: the state after everything in it
+}
+
+/// Part of a document to export: a position range or a list of chapters
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DocumentRange {
+    Positions { start: usize, end: usize },
+    Chapters { chapters: Vec<usize> }, // Chapter indices, as in `chapters_from_content`
+}
+
+/// A marker's anchor at the start of a heading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeadingPin {
+    pub heading: String, // Heading title (e.g., "Chapter 12"), matched ignoring case and surrounding space
+}
+
+// Collect the plain text of a node's inline content
+pub fn node_text(node: &serde_json::Value) -> String {
+    let mut text = String::new();
+    if let Some(content) = node.get("content").and_then(|c| c.as_array()) {
+        for child in content {
+            if let Some(t) = child.get("text").and_then(|t| t.as_str()) {
+                text.push_str(t);
+            } else if child.get("type").and_then(|t| t.as_str()) == Some("hard_break") {
+                text.push(' ');
+            } else if entity_refs::mention_entity_id(child).is_some() {
+                text.push_str(entity_refs::mention_label(child));
+            } else {
+                text.push_str(&node_text(child));
+            }
+        }
+    }
+    text
+}
+
+/// Level of a heading node (None for other nodes)
+pub fn heading_level(node: &serde_json::Value) -> Option<u64> {
+    if node.get("type").and_then(|t| t.as_str()) != Some("heading") {
+        return None;
+    }
+    Some(node.get("attrs").and_then(|a| a.get("level")).and_then(|l| l.as_u64()).unwrap_or(1))
+}
+
+// Turn a paragraph or heading into a level-1 heading, keeping its other attributes
+fn make_chapter_heading(node: &mut serde_json::Value) {
+    node["type"] = serde_json::json!("heading");
+    match node.get_mut("attrs").and_then(|a| a.as_object_mut()) {
+        Some(attrs) => {
+            attrs.insert("level".to_string(), serde_json::json!(1));
+        }
+        None => node["attrs"] = serde_json::json!({ "level": 1 }),
+    }
+}
+
+/// Turn a manuscript's chapter titles into level-1 headings; returns how many nodes changed
+///
+/// With a `pattern`, top-level paragraphs and headings whose text starts with a
+/// match (e.g., `Chapter \d+`) become chapter headings. Without one, a document
+/// with no level-1 headings has its shallowest headings promoted, which is how
+/// manuscripts pasted from other tools often mark their chapters. Converted nodes
+/// keep their size, so marker positions are unaffected.
+pub fn detect_chapters(doc: &mut serde_json::Value, pattern: Option<&Regex>) -> usize {
+    let Some(children) = doc.get_mut("content").and_then(|c| c.as_array_mut()) else {
+        return 0;
+    };
+
+    let promote_level = match pattern {
+        Some(_) => None,
+        None => {
+            let levels: Vec<u64> = children.iter().filter_map(heading_level).collect();
+            if levels.contains(&1) {
+                return 0;
+            }
+            levels.into_iter().min()
+        }
+    };
+
+    let mut changed = 0;
+    for child in children.iter_mut() {
+        let level = heading_level(child);
+        let is_title = match pattern {
+            Some(regex) => {
+                let is_textblock = level.is_some() || child.get("type").and_then(|t| t.as_str()) == Some("paragraph");
+                let text = node_text(child);
+                is_textblock && regex.find(text.trim()).is_some_and(|m| m.start() == 0)
+            }
+            None => level.is_some() && level == promote_level,
+        };
+
+        if is_title && level != Some(1) {
+            make_chapter_heading(child);
+            changed += 1;
+        }
+    }
+
+    changed
+}
+
+/// Position of the pinned heading: the first top-level heading (of any level) with the title
+pub fn resolve_pin(doc: &serde_json::Value, pin: &HeadingPin) -> Option<usize> {
+    let title = pin.heading.trim().to_lowercase();
+    let mut pos = 0;
+    for child in doc.get("content").and_then(|c| c.as_array())? {
+        if heading_level(child).is_some() && node_text(child).trim().to_lowercase() == title {
+            return Some(pos);
+        }
+        pos += positions::node_size(child);
+    }
+    None
+}
+
+/// Position a chapter-scoped query resolves to (chapter indices as in `chapters_from_content`)
+pub fn chapter_position(doc: &serde_json::Value, index: usize, edge: ChapterEdge, untitled: &str) -> Result<usize, String> {
+    let chapter = chapters_from_content(doc, untitled)
+        .into_iter()
+        .nth(index)
+        .ok_or_else(|| format!("Chapter not found: {}", index))?;
+
+    Ok(match edge {
+        ChapterEdge::Start => chapter.start,
+        // Ends are exclusive: a marker at the end belongs to the next chapter
+        ChapterEdge::End => chapter.end.saturating_sub(1).max(chapter.start),
+    })
+}
+
+/// Position spans a range covers, sorted and within the document
+pub fn range_spans(doc: &serde_json::Value, range: &DocumentRange, untitled: &str) -> Result<Vec<(usize, usize)>, String> {
+    match range {
+        DocumentRange::Positions { start, end } => {
+            if start >= end {
+                return Err("Range start must be before its end".to_string());
+            }
+            Ok(vec![(*start, (*end).min(positions::content_size(doc)))])
+        }
+        DocumentRange::Chapters { chapters } => {
+            if chapters.is_empty() {
+                return Err("No chapters selected".to_string());
+            }
+            let all = chapters_from_content(doc, untitled);
+            let mut spans = chapters
+                .iter()
+                .map(|index| {
+                    all.get(*index)
+                        .map(|c| (c.start, c.end))
+                        .ok_or_else(|| format!("Chapter not found: {}", index))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            spans.sort();
+            spans.dedup();
+            Ok(spans)
+        }
+    }
+}
+
+/// Copy of the document with only the top-level nodes that overlap the spans
+///
+/// Whole blocks are kept, so a range starting mid-paragraph includes that paragraph.
+pub fn slice_content(doc: &serde_json::Value, spans: &[(usize, usize)]) -> serde_json::Value {
+    let mut sliced = doc.clone();
+    if let Some(children) = doc.get("content").and_then(|c| c.as_array()) {
+        let mut pos = 0;
+        let mut kept = Vec::new();
+        for child in children {
+            let size = positions::node_size(child);
+            if spans.iter().any(|(start, end)| pos < *end && pos + size > *start) {
+                kept.push(child.clone());
+            }
+            pos += size;
+        }
+        sliced["content"] = serde_json::Value::Array(kept);
+    }
+    sliced
+}
+
+/// Split a ProseMirror document into chapters at its top-level level-1 headings
+///
+/// `untitled` names the leading section before the first heading (and the whole
+/// document when it has no headings at all).
+pub fn chapters_from_content(doc: &serde_json::Value, untitled: &str) -> Vec<Chapter> {
+    let doc_size = positions::content_size(doc);
+    let mut starts: Vec<(usize, String)> = Vec::new();
+
+    let mut pos = 0;
+    if let Some(children) = doc.get("content").and_then(|c| c.as_array()) {
+        for child in children {
+            if heading_level(child) == Some(1) {
+                starts.push((pos, node_text(child).trim().to_string()));
+            } else if starts.is_empty() && pos == 0 {
+                // Content before the first heading
+                starts.push((0, untitled.to_string()));
+            }
+
+            pos += positions::node_size(child);
+        }
+    }
+
+    if starts.is_empty() {
+        starts.push((0, untitled.to_string()));
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, (start, title))| Chapter {
+            index,
+            title: title.clone(),
+            start: *start,
+            end: starts.get(index + 1).map(|(next, _)| *next).unwrap_or(doc_size),
+        })
+        .collect()
+}
@@ -0,0 +1,109 @@
+//! QuestScribe - Chronological Ordering
+//!
+//! Markers are normally applied in narrative order (document position). Stories
+//! told out of order need a second ordering: a marker can carry a `story_time`,
+//! the in-world moment its changes happen, in hours from any fixed point the
+//! author picks (e.g., the opening scene). Only the order of the values matters
+//! for state computation.
+//!
+//! Markers without a story time inherit the story time of the closest timed
+//! marker before them in the document (of any entity), so an author only times
+//! the first marker of a flashback and the first marker after it. Markers before
+//! any timed marker come first. Ties are broken by document position.
+
+use crate::engine::{self, EntityState};
+use crate::state::{Entity, Marker};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Which ordering state is computed in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateOrder {
+    #[default]
+    Narrative,     // Document position
+    Chronological, // In-world time
+}
+
+// Markers in document order, leaving out open TODOs
+fn narrative_order(markers: &HashMap<String, Marker>) -> Vec<&Marker> {
+    let mut ordered: Vec<&Marker> = markers.values().filter(|m| !m.todo).collect();
+    ordered.sort_by(|a, b| engine::compare_markers(a, b));
+    ordered
+}
+
+/// Effective story time of every marker (explicit or inherited), keyed by marker ID
+pub fn story_times(markers: &HashMap<String, Marker>) -> HashMap<String, f64> {
+    let mut current = f64::NEG_INFINITY;
+
+    narrative_order(markers)
+        .into_iter()
+        .map(|marker| {
+            if let Some(time) = marker.story_time {
+                current = time;
+            }
+            (marker.id.clone(), current)
+        })
+        .collect()
+}
+
+/// Story time at a document position: that of the last timed marker at or before it
+pub fn story_time_at(markers: &HashMap<String, Marker>, position: usize) -> f64 {
+    narrative_order(markers)
+        .into_iter()
+        .filter(|m| m.position <= position)
+        .filter_map(|m| m.story_time)
+        .next_back()
+        .unwrap_or(f64::NEG_INFINITY)
+}
+
+// Compare (story time, position) pairs
+fn compare_moments(a: (f64, usize), b: (f64, usize)) -> Ordering {
+    a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1))
+}
+
+/// An entity's state at an in-world moment, replaying its markers in story-time order
+///
+/// Markers at exactly `story_time` are included up to `position`, so a query from
+/// inside a flashback sees the flashback's earlier changes but not its later ones.
+pub fn chronological_state_at(
+    markers: &HashMap<String, Marker>,
+    entity: &Entity,
+    story_time: f64,
+    position: usize,
+) -> EntityState {
+    let times = story_times(markers);
+    let moment = |m: &Marker| (times.get(&m.id).copied().unwrap_or(f64::NEG_INFINITY), m.position);
+
+    let mut relevant: Vec<&Marker> = markers
+        .values()
+        .filter(|m| m.entity_id == entity.id && !m.todo)
+        .filter(|m| compare_moments(moment(m), (story_time, position)) != Ordering::Greater)
+        .collect();
+    relevant.sort_by(|a, b| compare_moments(moment(a), moment(b)).then_with(|| engine::compare_markers(a, b)));
+
+    let defaults = engine::field_defaults(entity);
+    let mut state = EntityState::new();
+    for marker in relevant {
+        for change in &marker.changes {
+            engine::apply_change(&mut state, change, &defaults);
+        }
+    }
+    state
+}
+
+/// An entity's state at a document position, in either ordering
+pub fn entity_state_at(
+    markers: &HashMap<String, Marker>,
+    entity: &Entity,
+    position: usize,
+    order: StateOrder,
+) -> EntityState {
+    match order {
+        StateOrder::Narrative => engine::entity_state_with_defaults(markers, entity, position),
+        StateOrder::Chronological => {
+            chronological_state_at(markers, entity, story_time_at(markers, position), position)
+        }
+    }
+}
@@ -0,0 +1,809 @@
+//! QuestScribe - Clipboard Conversion
+//!
+//! Converts rich text pasted from Word, Google Docs, LibreOffice and browsers
+//! (HTML or RTF) into ProseMirror JSON for the editor's schema, so a pasted
+//! chapter keeps its headings, paragraphs, bold and italic text, links, block
+//! quotes and line breaks.
+//!
+//! The schema has no list nodes, so list items become paragraphs that start with
+//! "• " or their number ("1. "), indented two spaces per nesting level. Everything
+//! else (tables, images, fonts, colors) is reduced to its text.
+
+use crate::markdown;
+use serde_json::{json, Value};
+
+/// Convert pasted HTML or RTF (detected by its `{\rtf` header) to a ProseMirror document
+pub fn convert(html_or_rtf: &str) -> Value {
+    let input = html_or_rtf.trim_start_matches('\u{feff}');
+    if input.trim_start().starts_with("{\\rtf") {
+        convert_rtf(input)
+    } else {
+        convert_html(input)
+    }
+}
+
+// ===== Document building =====
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Marks {
+    bold: bool,
+    italic: bool,
+    code: bool,
+}
+
+#[derive(Default)]
+struct Textblock {
+    level: Option<u64>, // Heading level; None for a paragraph
+    code: bool,         // Code block (no marks)
+    inline: Vec<Value>,
+}
+
+// Collects blocks, one container per open blockquote
+struct DocBuilder {
+    containers: Vec<Vec<Value>>,
+    current: Option<Textblock>,
+    bullet: Option<String>, // List item prefix, written before the next text
+}
+
+impl DocBuilder {
+    fn new() -> Self {
+        DocBuilder {
+            containers: vec![Vec::new()],
+            current: None,
+            bullet: None,
+        }
+    }
+
+    fn start_block(&mut self, level: Option<u64>, code: bool) {
+        self.end_block();
+        self.current = Some(Textblock {
+            level,
+            code,
+            inline: Vec::new(),
+        });
+    }
+
+    // Finish the open textblock; `keep_empty` keeps it even without content (RTF's blank lines)
+    fn finish_block(&mut self, keep_empty: bool) {
+        let Some(mut block) = self.current.take() else {
+            return;
+        };
+
+        // Trailing whitespace left by HTML whitespace collapsing
+        if !block.code {
+            if let Some(Value::String(text)) = block.inline.last_mut().and_then(|n| n.get_mut("text")) {
+                let trimmed = text.trim_end_matches(' ').to_string();
+                if trimmed.is_empty() {
+                    block.inline.pop();
+                } else {
+                    *text = trimmed;
+                }
+            }
+        }
+
+        if block.inline.is_empty() && !keep_empty {
+            return;
+        }
+
+        let mut node = match (block.level, block.code) {
+            (_, true) => json!({ "type": "code_block" }),
+            (Some(level), _) => json!({ "type": "heading", "attrs": { "level": level } }),
+            (None, _) => json!({ "type": "paragraph" }),
+        };
+        if !block.inline.is_empty() {
+            node["content"] = Value::Array(block.inline);
+        }
+        self.push_block(node);
+    }
+
+    fn end_block(&mut self) {
+        self.finish_block(false);
+    }
+
+    fn push_block(&mut self, node: Value) {
+        if let Some(container) = self.containers.last_mut() {
+            container.push(node);
+        }
+    }
+
+    fn open_quote(&mut self) {
+        self.end_block();
+        self.containers.push(Vec::new());
+    }
+
+    fn close_quote(&mut self) {
+        self.end_block();
+        if self.containers.len() > 1 {
+            let blocks = self.containers.pop().unwrap_or_default();
+            if !blocks.is_empty() {
+                self.push_block(json!({ "type": "blockquote", "content": blocks }));
+            }
+        }
+    }
+
+    fn current_block(&mut self) -> &mut Textblock {
+        self.current.get_or_insert_with(Textblock::default)
+    }
+
+    // Whether the open textblock ends in whitespace (or has nothing yet)
+    fn at_space(&self) -> bool {
+        match self.current.as_ref().and_then(|b| b.inline.last()) {
+            Some(node) => node
+                .get("text")
+                .and_then(|t| t.as_str())
+                .is_none_or(|t| t.ends_with([' ', '\n', '\t'])),
+            None => true,
+        }
+    }
+
+    fn text(&mut self, text: &str, marks: Marks, link: Option<&str>) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(bullet) = self.bullet.take() {
+            self.text(&bullet, Marks::default(), None);
+        }
+
+        let block = self.current_block();
+        let mut mark_list = Vec::new();
+        if !block.code {
+            if marks.bold {
+                mark_list.push(json!({ "type": "strong" }));
+            }
+            if marks.italic {
+                mark_list.push(json!({ "type": "em" }));
+            }
+            if marks.code {
+                mark_list.push(json!({ "type": "code" }));
+            }
+            if let Some(href) = link {
+                mark_list.push(json!({ "type": "link", "attrs": { "href": href, "title": null } }));
+            }
+        }
+
+        // Extend the previous text node when its marks are the same
+        if let Some(last) = block.inline.last_mut() {
+            let same_marks = last.get("marks").and_then(|m| m.as_array()).map(|m| m.as_slice()).unwrap_or(&[])
+                == mark_list.as_slice();
+            if let (true, Some(Value::String(existing))) = (same_marks, last.get_mut("text")) {
+                existing.push_str(text);
+                return;
+            }
+        }
+
+        let mut node = json!({ "type": "text", "text": text });
+        if !mark_list.is_empty() {
+            node["marks"] = Value::Array(mark_list);
+        }
+        block.inline.push(node);
+    }
+
+    fn hard_break(&mut self) {
+        let block = self.current_block();
+        if block.code {
+            block.inline.push(json!({ "type": "text", "text": "\n" }));
+        } else {
+            block.inline.push(json!({ "type": "hard_break" }));
+        }
+    }
+
+    fn horizontal_rule(&mut self) {
+        self.end_block();
+        self.push_block(json!({ "type": "horizontal_rule" }));
+    }
+
+    fn finish(mut self) -> Value {
+        self.end_block();
+        while self.containers.len() > 1 {
+            self.close_quote();
+        }
+
+        let mut blocks = self.containers.pop().unwrap_or_default();
+        if blocks.is_empty() {
+            blocks.push(json!({ "type": "paragraph" }));
+        }
+        json!({ "type": "doc", "content": blocks })
+    }
+}
+
+// ===== HTML =====
+
+// Elements whose content is never shown
+const HIDDEN_ELEMENTS: &[&str] = &["head", "style", "script", "title", "template", "xml", "noscript"];
+// Elements that never have a closing tag
+const VOID_ELEMENTS: &[&str] = &["br", "hr", "img", "meta", "link", "input", "col", "area", "base", "wbr"];
+// Elements that start and end a paragraph of their own
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p", "div", "section", "article", "header", "footer", "li", "dt", "dd", "tr", "caption", "figcaption", "address",
+];
+
+struct Tag {
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    attrs: Vec<(String, String)>,
+}
+
+impl Tag {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    // Value of one CSS property in the style attribute, lowercased
+    fn style(&self, property: &str) -> Option<String> {
+        self.attr("style")?.split(';').find_map(|declaration| {
+            let (key, value) = declaration.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(property).then(|| value.trim().to_lowercase())
+        })
+    }
+}
+
+// What an open element changed, undone when it closes
+#[derive(Default)]
+struct OpenElement {
+    name: String,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    code: bool,
+    link: Option<String>,
+    list: Option<ListState>,
+    block: bool,
+    quote: bool,
+    pre: bool,
+}
+
+struct ListState {
+    ordered: bool,
+    next: u64,
+}
+
+// Parse the tag starting after '<'; returns the tag and the index after '>'
+fn parse_tag(chars: &[char], start: usize) -> Option<(Tag, usize)> {
+    let mut i = start;
+    let closing = chars.get(i) == Some(&'/');
+    if closing {
+        i += 1;
+    }
+
+    let name_start = i;
+    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == ':' || chars[i] == '-') {
+        i += 1;
+    }
+    if i == name_start {
+        return None;
+    }
+    let name: String = chars[name_start..i].iter().collect::<String>().to_lowercase();
+
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        match chars.get(i) {
+            None => return None,
+            Some('>') => return Some((Tag { name, closing, self_closing, attrs }, i + 1)),
+            Some('/') => {
+                self_closing = true;
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let key_start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '=' | '>' | '/') {
+            i += 1;
+        }
+        let key: String = chars[key_start..i].iter().collect::<String>().to_lowercase();
+        if key.is_empty() {
+            i += 1;
+            continue;
+        }
+
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        let mut value = String::new();
+        if chars.get(i) == Some(&'=') {
+            i += 1;
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            match chars.get(i) {
+                Some(&quote) if quote == '"' || quote == '\'' => {
+                    i += 1;
+                    while i < chars.len() && chars[i] != quote {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                _ => {
+                    while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '>' {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                }
+            }
+        }
+        attrs.push((key, decode_entities(&value)));
+    }
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "bull" => '•',
+        "middot" => '·',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "shy" => '\u{ad}',
+        _ => return None,
+    })
+}
+
+// Replace character references (&amp;, &#8212;, &#x2014;); unknown ones are left as written
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest[1..].find(';').filter(|&end| end <= 10).and_then(|end| {
+            let name = &rest[1..end + 1];
+            let ch = match name.strip_prefix('#') {
+                Some(number) => match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                    None => number.parse::<u32>().ok().and_then(char::from_u32),
+                },
+                None => named_entity(name),
+            };
+            ch.map(|ch| (ch, end + 2))
+        });
+
+        match decoded {
+            Some((ch, length)) => {
+                out.push(ch);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn heading_level(name: &str) -> Option<u64> {
+    let level = name.strip_prefix('h')?.parse::<u64>().ok()?;
+    (1..=6).contains(&level).then_some(level)
+}
+
+fn is_bold_weight(weight: &str) -> bool {
+    weight.starts_with("bold") || weight.parse::<u32>().is_ok_and(|w| w >= 600)
+}
+
+// Index just past the next case-insensitive occurrence of `needle` at or after `from`
+fn find_after(chars: &[char], from: usize, needle: &str) -> usize {
+    let needle: Vec<char> = needle.chars().collect();
+    (from..chars.len())
+        .find(|&i| {
+            chars.len() - i >= needle.len()
+                && chars[i..i + needle.len()].iter().zip(&needle).all(|(a, b)| a.eq_ignore_ascii_case(b))
+        })
+        .map(|i| i + needle.len())
+        .unwrap_or(chars.len())
+}
+
+fn convert_html(html: &str) -> Value {
+    let chars: Vec<char> = html.chars().collect();
+    let mut builder = DocBuilder::new();
+    let mut stack: Vec<OpenElement> = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    // Current inline formatting, from the innermost element that sets it
+    let marks = |stack: &[OpenElement]| Marks {
+        bold: stack.iter().rev().find_map(|e| e.bold).unwrap_or(false),
+        italic: stack.iter().rev().find_map(|e| e.italic).unwrap_or(false),
+        code: stack.iter().any(|e| e.code),
+    };
+    let link = |stack: &[OpenElement]| stack.iter().rev().find_map(|e| e.link.clone());
+    let in_pre = |stack: &[OpenElement]| stack.iter().any(|e| e.pre);
+
+    // Flush collected text into the open block, collapsing whitespace outside <pre>
+    let flush = |text: &mut String, builder: &mut DocBuilder, stack: &[OpenElement]| {
+        if text.is_empty() {
+            return;
+        }
+        let decoded = decode_entities(text);
+        text.clear();
+
+        let content = if in_pre(stack) {
+            decoded
+        } else {
+            let mut collapsed = String::new();
+            let mut space = builder.at_space();
+            for ch in decoded.chars() {
+                if ch.is_whitespace() && ch != '\u{a0}' {
+                    if !space {
+                        collapsed.push(' ');
+                    }
+                    space = true;
+                } else {
+                    collapsed.push(ch);
+                    space = false;
+                }
+            }
+            collapsed
+        };
+        if !content.is_empty() {
+            builder.text(&content, marks(stack), link(stack).as_deref());
+        }
+    };
+
+    while i < chars.len() {
+        if chars[i] != '<' {
+            text.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        // Comments and declarations. Word marks list bullets with <![if !supportLists]>...<![endif]>.
+        if chars[i..].starts_with(&['<', '!', '-', '-']) {
+            i = find_after(&chars, i, "-->");
+            continue;
+        }
+        if chars.get(i + 1) == Some(&'!') || chars.get(i + 1) == Some(&'?') {
+            let end = find_after(&chars, i, ">");
+            let declaration: String = chars[i..end].iter().collect::<String>().to_lowercase();
+            i = end;
+            if declaration.contains("supportlists") && !declaration.contains("endif") {
+                flush(&mut text, &mut builder, &stack);
+                i = find_after(&chars, i, "<![endif]>");
+                builder.bullet = Some("• ".to_string());
+            }
+            continue;
+        }
+
+        let Some((tag, end)) = parse_tag(&chars, i + 1) else {
+            text.push('<');
+            i += 1;
+            continue;
+        };
+        i = end;
+        flush(&mut text, &mut builder, &stack);
+
+        if tag.closing {
+            // Close up to the matching element, tolerating unclosed ones inside it
+            let Some(index) = stack.iter().rposition(|e| e.name == tag.name) else {
+                continue;
+            };
+            for element in stack.drain(index..).rev() {
+                if element.block || element.pre || heading_level(&element.name).is_some() {
+                    builder.end_block();
+                }
+                if element.quote {
+                    builder.close_quote();
+                }
+            }
+            continue;
+        }
+
+        if HIDDEN_ELEMENTS.contains(&tag.name.as_str()) {
+            if !tag.self_closing {
+                i = find_after(&chars, i, &format!("</{}", tag.name));
+                i = find_after(&chars, i, ">");
+            }
+            continue;
+        }
+
+        match tag.name.as_str() {
+            "br" => {
+                builder.hard_break();
+                continue;
+            }
+            "hr" => {
+                builder.horizontal_rule();
+                continue;
+            }
+            name if VOID_ELEMENTS.contains(&name) => continue,
+            _ => {}
+        }
+        if tag.self_closing {
+            continue;
+        }
+
+        let mut element = OpenElement {
+            name: tag.name.clone(),
+            ..Default::default()
+        };
+
+        match tag.name.as_str() {
+            "b" | "strong" => element.bold = Some(true),
+            "i" | "em" | "cite" => element.italic = Some(true),
+            "code" | "tt" | "kbd" | "samp" => element.code = true,
+            // Attribute values are already entity-decoded; only web and mail links are kept
+            "a" => element.link = tag.attr("href").and_then(|h| markdown::safe_link(h.trim())).map(str::to_string),
+            "ul" | "ol" => {
+                builder.end_block();
+                element.list = Some(ListState {
+                    ordered: tag.name == "ol",
+                    next: tag.attr("start").and_then(|s| s.parse().ok()).unwrap_or(1),
+                });
+            }
+            "blockquote" => {
+                builder.open_quote();
+                element.quote = true;
+            }
+            "pre" => {
+                builder.start_block(None, true);
+                element.pre = true;
+            }
+            name => {
+                if let Some(level) = heading_level(name) {
+                    builder.start_block(Some(level), false);
+                } else if BLOCK_ELEMENTS.contains(&name) {
+                    builder.start_block(None, false);
+                    element.block = true;
+                }
+            }
+        }
+
+        // Inline styles, as Google Docs writes them (its outer <b> carries font-weight:normal)
+        if let Some(weight) = tag.style("font-weight") {
+            element.bold = Some(is_bold_weight(&weight));
+        }
+        if let Some(style) = tag.style("font-style") {
+            element.italic = Some(style == "italic" || style == "oblique");
+        }
+
+        if tag.name == "li" {
+            let depth = stack.iter().filter(|e| e.list.is_some()).count();
+            let bullet = match stack.iter_mut().rev().find_map(|e| e.list.as_mut()) {
+                Some(list) if list.ordered => {
+                    list.next += 1;
+                    format!("{}. ", list.next - 1)
+                }
+                _ => "• ".to_string(),
+            };
+            builder.bullet = Some(format!("{}{}", "  ".repeat(depth.saturating_sub(1)), bullet));
+        }
+
+        stack.push(element);
+    }
+
+    flush(&mut text, &mut builder, &stack);
+    builder.finish()
+}
+
+// ===== RTF =====
+
+// Destination groups whose text isn't document content
+const RTF_SKIPPED_DESTINATIONS: &[&str] = &[
+    "fonttbl", "colortbl", "stylesheet", "info", "pict", "object", "header", "headerl", "headerr", "headerf",
+    "footer", "footerl", "footerr", "footerf", "fldinst", "listtable", "listoverridetable", "rsidtbl",
+    "generator", "xmlnstbl", "themedata", "colorschememapping", "datastore", "latentstyles", "filetbl",
+    "revtbl", "pgdsctbl", "footnote", "annotation", "shp", "nonshppict",
+];
+
+#[derive(Clone, Copy)]
+struct RtfGroup {
+    marks: Marks,
+    skip: bool,
+    unicode_skip: usize, // Fallback characters after \uN (\ucN)
+}
+
+// Windows-1252 characters in 0x80..0x9F; other bytes map to the same code point
+fn cp1252(byte: u8) -> char {
+    const HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+        '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+    ];
+    match byte {
+        0x80..=0x9f => HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+fn convert_rtf(rtf: &str) -> Value {
+    let chars: Vec<char> = rtf.chars().collect();
+    let mut builder = DocBuilder::new();
+    let mut group = RtfGroup {
+        marks: Marks::default(),
+        skip: false,
+        unicode_skip: 1,
+    };
+    let mut groups: Vec<RtfGroup> = Vec::new();
+    let mut heading: Option<u64> = None;
+    let mut pending_skip = 0; // Fallback characters still to drop after a \uN
+    let mut text = String::new();
+    let mut i = 0;
+
+    let flush = |text: &mut String, builder: &mut DocBuilder, group: &RtfGroup, heading: Option<u64>| {
+        if text.is_empty() {
+            return;
+        }
+        if builder.current.is_none() {
+            builder.start_block(heading, false);
+        }
+        builder.text(text, group.marks, None);
+        text.clear();
+    };
+
+    while i < chars.len() {
+        let ch = chars[i];
+        match ch {
+            '{' => {
+                flush(&mut text, &mut builder, &group, heading);
+                groups.push(group);
+                pending_skip = 0;
+                i += 1;
+            }
+            '}' => {
+                flush(&mut text, &mut builder, &group, heading);
+                group = groups.pop().unwrap_or(group);
+                pending_skip = 0;
+                i += 1;
+            }
+            '\r' | '\n' => i += 1,
+            '\\' => {
+                i += 1;
+                let Some(&next) = chars.get(i) else {
+                    break;
+                };
+
+                if !next.is_ascii_alphabetic() {
+                    i += 1;
+                    let symbol = match next {
+                        '\\' | '{' | '}' => Some(next),
+                        '~' => Some('\u{a0}'),
+                        '_' => Some('\u{2011}'),
+                        '\'' => {
+                            let hex: String = chars.iter().skip(i).take(2).collect();
+                            i += hex.len();
+                            u8::from_str_radix(&hex, 16).ok().map(cp1252)
+                        }
+                        '*' => {
+                            // Optional destination this reader doesn't know
+                            group.skip = true;
+                            None
+                        }
+                        '\r' | '\n' => {
+                            // Same as \par
+                            flush(&mut text, &mut builder, &group, heading);
+                            if builder.current.is_none() {
+                                builder.start_block(heading, false);
+                            }
+                            builder.finish_block(true);
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(symbol) = symbol {
+                        if pending_skip > 0 {
+                            pending_skip -= 1;
+                        } else if !group.skip {
+                            text.push(symbol);
+                        }
+                    }
+                    continue;
+                }
+
+                // Control word: letters, an optional signed number, an optional space delimiter
+                let word_start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let word: String = chars[word_start..i].iter().collect();
+                let number_start = i;
+                if chars.get(i) == Some(&'-') {
+                    i += 1;
+                }
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let parameter: Option<i64> = chars[number_start..i].iter().collect::<String>().parse().ok();
+                if chars.get(i) == Some(&' ') {
+                    i += 1;
+                }
+
+                if RTF_SKIPPED_DESTINATIONS.contains(&word.as_str()) {
+                    group.skip = true;
+                    continue;
+                }
+                if group.skip {
+                    continue;
+                }
+
+                let literal = match word.as_str() {
+                    "tab" => Some('\t'),
+                    "emdash" => Some('—'),
+                    "endash" => Some('–'),
+                    "lquote" => Some('‘'),
+                    "rquote" => Some('’'),
+                    "ldblquote" => Some('“'),
+                    "rdblquote" => Some('”'),
+                    "bullet" => Some('•'),
+                    "emspace" | "enspace" | "qmspace" | "cell" => Some(' '),
+                    "u" => {
+                        pending_skip = group.unicode_skip;
+                        parameter
+                            .map(|n| if n < 0 { n + 65536 } else { n })
+                            .and_then(|n| char::from_u32(n as u32))
+                    }
+                    _ => None,
+                };
+                if let Some(literal) = literal {
+                    text.push(literal);
+                    continue;
+                }
+
+                let on = parameter != Some(0);
+                let marks_before = group.marks;
+                match word.as_str() {
+                    "b" => group.marks.bold = on,
+                    "i" => group.marks.italic = on,
+                    "plain" => group.marks = Marks::default(),
+                    "uc" => group.unicode_skip = parameter.unwrap_or(1).max(0) as usize,
+                    "par" | "sect" | "page" | "row" => {
+                        flush(&mut text, &mut builder, &group, heading);
+                        if builder.current.is_none() {
+                            builder.start_block(heading, false);
+                        }
+                        builder.finish_block(true);
+                    }
+                    "line" => {
+                        flush(&mut text, &mut builder, &group, heading);
+                        if builder.current.is_none() {
+                            builder.start_block(heading, false);
+                        }
+                        builder.hard_break();
+                    }
+                    "pard" => heading = None,
+                    "outlinelevel" => heading = parameter.filter(|l| (0..6).contains(l)).map(|l| l as u64 + 1),
+                    _ => {}
+                }
+                if group.marks != marks_before && !text.is_empty() {
+                    // Text so far keeps the formatting it was written with
+                    let current = group.marks;
+                    group.marks = marks_before;
+                    flush(&mut text, &mut builder, &group, heading);
+                    group.marks = current;
+                }
+            }
+            _ => {
+                i += 1;
+                if pending_skip > 0 {
+                    pending_skip -= 1;
+                } else if !group.skip {
+                    text.push(ch);
+                }
+            }
+        }
+    }
+
+    flush(&mut text, &mut builder, &group, heading);
+    builder.finish()
+}
@@ -0,0 +1,138 @@
+//! QuestScribe - Colors
+//!
+//! Entity and marker colors are stored as "#RRGGBB". Input may also be "#RGB"
+//! or a CSS color name ("teal", "crimson"); either is normalized before it's
+//! stored, so the frontend and exports only ever see one form.
+//!
+//! New entities without a color get one from a palette of distinct colors,
+//! skipping colors other entities already use, so a cast of twelve characters
+//! doesn't end up all gold.
+//!
+//! Named palettes (see settings.rs) recolor every entity at once. The bundled
+//! ones are the default palette, a colorblind-safe one (Okabe-Ito), and one
+//! that reads well on a dark background.
+
+use std::collections::BTreeMap;
+
+/// Colors handed out to new entities, in order (the first is the default entity color)
+pub const PALETTE: &[&str] = &[
+    "#FFD700", "#E6194B", "#3CB44B", "#4363D8", "#F58231", "#911EB4",
+    "#42D4F4", "#F032E6", "#BFEF45", "#469990", "#9A6324", "#800000",
+    "#808000", "#000075", "#FABED4", "#DCBEFF",
+];
+
+// Okabe-Ito, distinguishable with the common forms of color blindness
+const COLORBLIND_SAFE: &[&str] = &[
+    "#E69F00", "#56B4E9", "#009E73", "#F0E442", "#0072B2", "#D55E00", "#CC79A7", "#999999",
+];
+
+// Light, saturated colors for dark themes
+const DARK_MODE: &[&str] = &[
+    "#FFD166", "#EF476F", "#06D6A0", "#4CC9F0", "#F78C6B", "#B388EB", "#90E0EF", "#F4A261",
+    "#C5F277", "#FF8FAB",
+];
+
+/// The bundled palettes, by name
+pub fn builtin_palettes() -> BTreeMap<String, Vec<String>> {
+    [("Default", PALETTE), ("Colorblind Safe", COLORBLIND_SAFE), ("Dark Mode", DARK_MODE)]
+        .into_iter()
+        .map(|(name, colors)| (name.to_string(), colors.iter().map(|c| c.to_string()).collect()))
+        .collect()
+}
+
+// CSS color names accepted as input
+const NAMED_COLORS: &[(&str, &str)] = &[
+    ("black", "#000000"),
+    ("white", "#FFFFFF"),
+    ("gray", "#808080"),
+    ("grey", "#808080"),
+    ("silver", "#C0C0C0"),
+    ("red", "#FF0000"),
+    ("maroon", "#800000"),
+    ("crimson", "#DC143C"),
+    ("orange", "#FFA500"),
+    ("gold", "#FFD700"),
+    ("yellow", "#FFFF00"),
+    ("olive", "#808000"),
+    ("lime", "#00FF00"),
+    ("green", "#008000"),
+    ("teal", "#008080"),
+    ("cyan", "#00FFFF"),
+    ("aqua", "#00FFFF"),
+    ("blue", "#0000FF"),
+    ("navy", "#000080"),
+    ("indigo", "#4B0082"),
+    ("purple", "#800080"),
+    ("violet", "#EE82EE"),
+    ("magenta", "#FF00FF"),
+    ("fuchsia", "#FF00FF"),
+    ("pink", "#FFC0CB"),
+    ("brown", "#A52A2A"),
+    ("chocolate", "#D2691E"),
+    ("tan", "#D2B48C"),
+    ("coral", "#FF7F50"),
+    ("salmon", "#FA8072"),
+    ("khaki", "#F0E68C"),
+    ("turquoise", "#40E0D0"),
+];
+
+/// Accept "#RGB" and "#RRGGBB"
+pub fn is_hex_color(color: &str) -> bool {
+    color
+        .strip_prefix('#')
+        .is_some_and(|hex| (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Normalize a hex color or CSS color name to "#RRGGBB"
+pub fn parse_color(input: &str) -> Result<String, String> {
+    let color = input.trim();
+    if is_hex_color(color) {
+        let hex = color[1..].to_uppercase();
+        return Ok(if hex.len() == 3 {
+            format!("#{}", hex.chars().flat_map(|c| [c, c]).collect::<String>())
+        } else {
+            format!("#{}", hex)
+        });
+    }
+
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(color))
+        .map(|(_, hex)| hex.to_string())
+        .ok_or_else(|| format!("Invalid color: {}", input))
+}
+
+/// Normalize a palette's colors, rejecting empty palettes and repeated colors
+pub fn parse_palette(colors: &[String]) -> Result<Vec<String>, String> {
+    if colors.is_empty() {
+        return Err("A palette needs at least one color".to_string());
+    }
+
+    let mut parsed: Vec<String> = Vec::with_capacity(colors.len());
+    for color in colors {
+        let color = parse_color(color)?;
+        if parsed.contains(&color) {
+            return Err(format!("Color listed twice: {}", color));
+        }
+        parsed.push(color);
+    }
+    Ok(parsed)
+}
+
+/// Pick a color for a new entity: the preferred color, else the first palette
+/// color nobody uses yet, else the least used palette color
+pub fn distinct_color<'a>(preferred: &str, used: impl IntoIterator<Item = &'a str>) -> String {
+    let used: Vec<String> = used.into_iter().filter_map(|c| parse_color(c).ok()).collect();
+    let count = |color: &str| used.iter().filter(|u| u.as_str() == color).count();
+
+    let preferred = parse_color(preferred).unwrap_or_else(|_| PALETTE[0].to_string());
+    if count(&preferred) == 0 {
+        return preferred;
+    }
+
+    PALETTE
+        .iter()
+        .min_by_key(|color| count(color))
+        .map(|color| color.to_string())
+        .unwrap_or(preferred)
+}
@@ -0,0 +1,555 @@
+//! QuestScribe - Backend Document Content
+//!
+//! Each session keeps the canonical ProseMirror document, so commands that need
+//! the text (saving, exporting, statistics, ...) don't have to be sent all of it
+//! on every call. The frontend sends the whole document once (when loading or
+//! with `set_content`) and afterwards only the steps of each transaction, in
+//! ProseMirror's `Step.toJSON()` form:
+//!
+//! - `replace` and `replaceAround`: content replaced by a slice
+//! - `addMark` and `removeMark`: marks added to or removed from text
+//! - `attr` and `docAttr`: a node's (or the document's) attribute set
+//!
+//! Steps are applied to a flat token view of the document that mirrors
+//! ProseMirror's position counting (see positions.rs): an open and a close token
+//! per node, one token per character of text, one per leaf node. A replacement
+//! is then a splice of tokens, and the tree is rebuilt once per batch of steps.
+//!
+//! Search and replace (`replace_text`) runs on the same view, so each replacement
+//! comes with the position edit that keeps markers in place. So does
+//! `insert_marker_nodes`, which places the nodes of markers created in the backend
+//! (imports) in the text.
+
+use crate::engine;
+use crate::positions::{self, TextEdit};
+use crate::state::Marker;
+use regex::RegexBuilder;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone)]
+enum Token {
+    Open(Value), // Node without its content
+    Close,
+    Char(char, Vec<Value>), // Character of text with its marks
+    Leaf(Value),
+}
+
+impl Token {
+    // Positions the token takes up (characters count their UTF-16 length)
+    fn size(&self) -> usize {
+        match self {
+            Token::Char(ch, _) => ch.len_utf16(),
+            _ => 1,
+        }
+    }
+}
+
+/// A document with steps applied, and the same changes as position edits (for markers)
+pub struct AppliedSteps {
+    pub doc: Value,
+    pub edits: Vec<TextEdit>,
+}
+
+/// How `replace_text` matches
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ReplaceOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,         // `find` is a regular expression, and the replacement may use $1, ${name}
+    pub from: Option<usize>, // Only replace within from..to (e.g., the selection)
+    pub to: Option<usize>,
+}
+
+/// A document after search and replace
+pub struct Replaced {
+    pub doc: Value,
+    pub edits: Vec<TextEdit>, // One per replacement, in order
+    pub count: usize,
+}
+
+fn node_kind(node: &Value) -> &str {
+    node.get("type").and_then(|t| t.as_str()).unwrap_or("")
+}
+
+fn flatten(nodes: &[Value], tokens: &mut Vec<Token>) {
+    for node in nodes {
+        let kind = node_kind(node);
+        if kind == "text" {
+            let marks = node.get("marks").and_then(|m| m.as_array()).cloned().unwrap_or_default();
+            let text = node.get("text").and_then(|t| t.as_str()).unwrap_or("");
+            tokens.extend(text.chars().map(|ch| Token::Char(ch, marks.clone())));
+        } else if positions::LEAF_NODE_TYPES.contains(&kind) {
+            tokens.push(Token::Leaf(node.clone()));
+        } else {
+            let mut open = node.clone();
+            if let Some(obj) = open.as_object_mut() {
+                obj.remove("content");
+            }
+            tokens.push(Token::Open(open));
+            if let Some(children) = node.get("content").and_then(|c| c.as_array()) {
+                flatten(children, tokens);
+            }
+            tokens.push(Token::Close);
+        }
+    }
+}
+
+fn content_tokens(node: &Value) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    if let Some(children) = node.get("content").and_then(|c| c.as_array()) {
+        flatten(children, &mut tokens);
+    }
+    tokens
+}
+
+// Rebuild nodes from tokens, merging runs of characters with the same marks into text nodes
+fn build(tokens: Vec<Token>) -> Result<Vec<Value>, String> {
+    fn flush(text: &mut Option<(String, Vec<Value>)>, children: &mut Vec<Value>) {
+        if let Some((text, marks)) = text.take() {
+            let mut node = json!({ "type": "text", "text": text });
+            if !marks.is_empty() {
+                node["marks"] = Value::Array(marks);
+            }
+            children.push(node);
+        }
+    }
+
+    let mut stack: Vec<(Value, Vec<Value>)> = vec![(Value::Null, Vec::new())];
+    let mut text: Option<(String, Vec<Value>)> = None;
+
+    for token in tokens {
+        match token {
+            Token::Char(ch, marks) => match &mut text {
+                Some((run, run_marks)) if *run_marks == marks => run.push(ch),
+                _ => {
+                    flush(&mut text, &mut stack.last_mut().unwrap().1);
+                    text = Some((ch.to_string(), marks));
+                }
+            },
+            Token::Open(node) => {
+                flush(&mut text, &mut stack.last_mut().unwrap().1);
+                stack.push((node, Vec::new()));
+            }
+            Token::Close => {
+                flush(&mut text, &mut stack.last_mut().unwrap().1);
+                if stack.len() == 1 {
+                    return Err("Steps don't fit the document: a node is closed that was never opened".to_string());
+                }
+                let (mut node, children) = stack.pop().unwrap();
+                if !children.is_empty() {
+                    node["content"] = Value::Array(children);
+                }
+                stack.last_mut().unwrap().1.push(node);
+            }
+            Token::Leaf(node) => {
+                flush(&mut text, &mut stack.last_mut().unwrap().1);
+                stack.last_mut().unwrap().1.push(node);
+            }
+        }
+    }
+    flush(&mut text, &mut stack.last_mut().unwrap().1);
+
+    if stack.len() != 1 {
+        return Err("Steps don't fit the document: a node is left open".to_string());
+    }
+    Ok(stack.pop().unwrap().1)
+}
+
+// Token index at a position
+fn token_index(tokens: &[Token], pos: usize) -> Result<usize, String> {
+    let mut at = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        if at == pos {
+            return Ok(index);
+        }
+        at += token.size();
+        if at > pos {
+            return Err(format!("Position {} is inside a character", pos));
+        }
+    }
+    if at == pos {
+        return Ok(tokens.len());
+    }
+    Err(format!("Position {} is outside the document", pos))
+}
+
+/// Text between two positions in the same paragraph or heading
+///
+/// Fails when the range crosses a node boundary or a leaf node (such as a marker).
+pub fn text_between(doc: &Value, from: usize, to: usize) -> Result<String, String> {
+    let tokens = content_tokens(doc);
+    let (start, end) = (token_index(&tokens, from)?, token_index(&tokens, to)?);
+    if start > end {
+        return Err(format!("Invalid range: {}..{}", from, to));
+    }
+
+    // The node the range starts in
+    let mut open = Vec::new();
+    for token in &tokens[..start] {
+        match token {
+            Token::Open(node) => open.push(node_kind(node)),
+            Token::Close => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    if !matches!(open.last(), Some(&("paragraph" | "heading"))) {
+        return Err(format!("Position {} is not in a paragraph or heading", from));
+    }
+
+    tokens[start..end]
+        .iter()
+        .map(|token| match token {
+            Token::Char(ch, _) => Ok(*ch),
+            _ => Err("Range covers more than text".to_string()),
+        })
+        .collect()
+}
+
+fn tokens_size(tokens: &[Token]) -> usize {
+    tokens.iter().map(Token::size).sum()
+}
+
+fn number(step: &Value, name: &str) -> Result<usize, String> {
+    step.get(name)
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .ok_or_else(|| format!("Step is missing \"{}\"", name))
+}
+
+// A slice's tokens without its open ends, split at `insert` (a position in the slice's content)
+fn slice_tokens(slice: Option<&Value>, insert: Option<usize>) -> Result<(Vec<Token>, Vec<Token>), String> {
+    let Some(slice) = slice else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+    let open_start = slice.get("openStart").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let open_end = slice.get("openEnd").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+
+    let mut left = content_tokens(slice);
+    let mut right = match insert {
+        Some(insert) => {
+            let index = token_index(&left, insert)?;
+            left.split_off(index)
+        }
+        None => Vec::new(),
+    };
+
+    if left.len() < open_start || !left[..open_start].iter().all(|t| matches!(t, Token::Open(_))) {
+        return Err("Slice doesn't match its openStart".to_string());
+    }
+    left.drain(..open_start);
+
+    let closing = if insert.is_some() { &mut right } else { &mut left };
+    let keep = closing.len().checked_sub(open_end).ok_or("Slice doesn't match its openEnd")?;
+    if !closing[keep..].iter().all(|t| matches!(t, Token::Close)) {
+        return Err("Slice doesn't match its openEnd".to_string());
+    }
+    closing.truncate(keep);
+
+    Ok((left, right))
+}
+
+// Whether a mark in a set is the one a step names (by type, and by attributes when given)
+fn same_mark(mark: &Value, target: &Value) -> bool {
+    mark.get("type") == target.get("type") && target.get("attrs").is_none_or(|attrs| mark.get("attrs") == Some(attrs))
+}
+
+// Characters in from..to, as token indices
+fn char_range(tokens: &[Token], step: &Value) -> Result<std::ops::Range<usize>, String> {
+    let from = token_index(tokens, number(step, "from")?)?;
+    let to = token_index(tokens, number(step, "to")?)?;
+    if from > to {
+        return Err("Step range ends before it starts".to_string());
+    }
+    Ok(from..to)
+}
+
+fn apply_step(tokens: &mut Vec<Token>, doc_node: &mut Value, step: &Value, edits: &mut Vec<TextEdit>) -> Result<(), String> {
+    let step_type = step.get("stepType").and_then(|t| t.as_str()).unwrap_or("");
+
+    match step_type {
+        "replace" => {
+            let (from, to) = (number(step, "from")?, number(step, "to")?);
+            let range = token_index(tokens, from)?..token_index(tokens, to)?;
+            if range.start > range.end {
+                return Err("Step range ends before it starts".to_string());
+            }
+            let (inserted, _) = slice_tokens(step.get("slice"), None)?;
+            edits.push(TextEdit { from, to, inserted_len: tokens_size(&inserted) });
+            tokens.splice(range, inserted);
+        }
+        "replaceAround" => {
+            let (from, to) = (number(step, "from")?, number(step, "to")?);
+            let (gap_from, gap_to) = (number(step, "gapFrom")?, number(step, "gapTo")?);
+            if !(from <= gap_from && gap_from <= gap_to && gap_to <= to) {
+                return Err("Step gap is outside its range".to_string());
+            }
+            let (before, after) = slice_tokens(step.get("slice"), Some(number(step, "insert")?))?;
+            let (before_len, after_len) = (tokens_size(&before), tokens_size(&after));
+
+            let index = |pos| token_index(tokens, pos);
+            let (from_index, gap_from_index, gap_to_index, to_index) = (index(from)?, index(gap_from)?, index(gap_to)?, index(to)?);
+            let gap: Vec<Token> = tokens[gap_from_index..gap_to_index].to_vec();
+            let replacement: Vec<Token> = before.into_iter().chain(gap).chain(after).collect();
+            tokens.splice(from_index..to_index, replacement);
+
+            // The same change as two edits around the kept gap
+            edits.push(TextEdit { from, to: gap_from, inserted_len: before_len });
+            let after_gap = from + before_len + (gap_to - gap_from);
+            edits.push(TextEdit { from: after_gap, to: after_gap + (to - gap_to), inserted_len: after_len });
+        }
+        "addMark" => {
+            let mark = step.get("mark").ok_or("Step is missing \"mark\"")?;
+            let range = char_range(tokens, step)?;
+            for token in &mut tokens[range] {
+                if let Token::Char(_, marks) = token {
+                    marks.retain(|m| m.get("type") != mark.get("type"));
+                    marks.push(mark.clone());
+                }
+            }
+        }
+        "removeMark" => {
+            let mark = step.get("mark").ok_or("Step is missing \"mark\"")?;
+            let range = char_range(tokens, step)?;
+            for token in &mut tokens[range] {
+                if let Token::Char(_, marks) = token {
+                    marks.retain(|m| !same_mark(m, mark));
+                }
+            }
+        }
+        "attr" => {
+            let index = token_index(tokens, number(step, "pos")?)?;
+            let attr = step.get("attr").and_then(|a| a.as_str()).ok_or("Step is missing \"attr\"")?;
+            let value = step.get("value").cloned().unwrap_or(Value::Null);
+            match tokens.get_mut(index) {
+                Some(Token::Open(node)) | Some(Token::Leaf(node)) => set_attr(node, attr, value),
+                _ => return Err("No node at the step's position".to_string()),
+            }
+        }
+        "docAttr" => {
+            let attr = step.get("attr").and_then(|a| a.as_str()).ok_or("Step is missing \"attr\"")?;
+            set_attr(doc_node, attr, step.get("value").cloned().unwrap_or(Value::Null));
+        }
+        other => return Err(format!("Unsupported step type: {}", other)),
+    }
+
+    Ok(())
+}
+
+fn set_attr(node: &mut Value, attr: &str, value: Value) {
+    if let Some(obj) = node.as_object_mut() {
+        if let Some(attrs) = obj.entry("attrs".to_string()).or_insert_with(|| json!({})).as_object_mut() {
+            attrs.insert(attr.to_string(), value);
+        }
+    }
+}
+
+/// Apply a transaction's steps, in order; nothing changes if any step fails
+pub fn apply_steps(doc: &Value, steps: &[Value]) -> Result<AppliedSteps, String> {
+    let mut tokens = content_tokens(doc);
+    let mut edits = Vec::new();
+
+    // The doc node itself (for docAttr steps), its content rebuilt at the end
+    let mut new_doc = doc.clone();
+    if let Some(obj) = new_doc.as_object_mut() {
+        obj.remove("content");
+    }
+
+    for (index, step) in steps.iter().enumerate() {
+        apply_step(&mut tokens, &mut new_doc, step, &mut edits).map_err(|e| format!("Step {}: {}", index + 1, e))?;
+    }
+
+    new_doc["content"] = Value::Array(build(tokens)?);
+
+    Ok(AppliedSteps { doc: new_doc, edits })
+}
+
+/// The node that shows a marker in the editor (attributes as in the frontend's schema)
+pub fn marker_node(marker: &Marker) -> Value {
+    json!({
+        "type": "marker",
+        "attrs": {
+            "id": marker.id,
+            "entityId": marker.entity_id,
+            "changes": marker.changes,
+            "visual": marker.visual,
+            "description": marker.description,
+            "createdAt": marker.created_at,
+            "modifiedAt": marker.modified_at,
+        },
+    })
+}
+
+// Token index where an inline node for `pos` can go: `pos` itself when it's in a paragraph
+// or heading, else the start of the next one (or the end of the last one before it)
+fn inline_index(tokens: &[Token], pos: usize) -> Result<Option<usize>, String> {
+    let index = token_index(tokens, pos)?;
+    let is_textblock = |node: &Value| matches!(node_kind(node), "paragraph" | "heading");
+
+    let mut open = Vec::new();
+    for token in &tokens[..index] {
+        match token {
+            Token::Open(node) => open.push(is_textblock(node)),
+            Token::Close => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    if open.last() == Some(&true) {
+        return Ok(Some(index));
+    }
+
+    let next = tokens[index..]
+        .iter()
+        .position(|t| matches!(t, Token::Open(node) if is_textblock(node)))
+        .map(|offset| index + offset + 1);
+    Ok(next.or_else(|| {
+        // The end of the last paragraph or heading closed before `pos`
+        let mut open = Vec::new();
+        let mut last_end = None;
+        for (at, token) in tokens[..index].iter().enumerate() {
+            match token {
+                Token::Open(node) => open.push(is_textblock(node)),
+                Token::Close => {
+                    if open.pop() == Some(true) {
+                        last_end = Some(at);
+                    }
+                }
+                _ => {}
+            }
+        }
+        last_end
+    }))
+}
+
+/// Insert nodes for markers that aren't in the text yet, at their positions
+///
+/// A position between blocks moves into the next paragraph or heading. Markers
+/// at the same position keep their application order. Markers that can't be
+/// placed (a document without any paragraph) are left out; the returned edits
+/// say how everything after each node shifted.
+pub fn insert_marker_nodes(doc: &Value, markers: &[&Marker]) -> Result<AppliedSteps, String> {
+    let mut tokens = content_tokens(doc);
+
+    let mut ordered: Vec<&Marker> = markers.to_vec();
+    ordered.sort_by(|a, b| engine::compare_markers(a, b));
+    let mut placed: Vec<(usize, &Marker)> = Vec::new();
+    for marker in ordered {
+        if let Some(index) = inline_index(&tokens, marker.position)? {
+            placed.push((index, marker));
+        }
+    }
+
+    // Last first, so each edit is in the coordinates the previous one left behind
+    let mut edits = Vec::with_capacity(placed.len());
+    for (index, marker) in placed.into_iter().rev() {
+        let pos = tokens_size(&tokens[..index]);
+        tokens.insert(index, Token::Leaf(marker_node(marker)));
+        edits.push(TextEdit { from: pos, to: pos, inserted_len: 1 });
+    }
+
+    let mut new_doc = doc.clone();
+    new_doc["content"] = Value::Array(build(tokens)?);
+
+    Ok(AppliedSteps { doc: new_doc, edits })
+}
+
+/// Replace every match of `find` in the document's text
+///
+/// Matches never span blocks or leaf nodes, so marker nodes are never replaced
+/// away. The replacement takes the marks of the first character it replaces.
+pub fn replace_text(doc: &Value, find: &str, replace: &str, options: &ReplaceOptions) -> Result<Replaced, String> {
+    if find.is_empty() {
+        return Err("Nothing to find".to_string());
+    }
+    let mut pattern = if options.regex { find.to_string() } else { regex::escape(find) };
+    if options.whole_word {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid search pattern: {}", e))?;
+
+    let tokens = content_tokens(doc);
+    let mut replaced = Vec::with_capacity(tokens.len());
+    let mut edits = Vec::new();
+    let mut delta: isize = 0; // How far the replacements so far have moved later positions
+    let mut pos = 0;
+    let mut index = 0;
+
+    while index < tokens.len() {
+        if !matches!(tokens[index], Token::Char(..)) {
+            pos += tokens[index].size();
+            replaced.push(tokens[index].clone());
+            index += 1;
+            continue;
+        }
+
+        // A run of text, up to the next node boundary or leaf
+        let end = tokens[index..]
+            .iter()
+            .position(|t| !matches!(t, Token::Char(..)))
+            .map_or(tokens.len(), |n| index + n);
+        let run = &tokens[index..end];
+
+        let mut text = String::new();
+        let mut byte_starts = Vec::with_capacity(run.len());
+        let mut offsets = vec![pos]; // Position of each character, then of the run's end
+        for token in run {
+            if let Token::Char(ch, _) = token {
+                byte_starts.push(text.len());
+                text.push(*ch);
+                offsets.push(offsets.last().unwrap() + ch.len_utf16());
+            }
+        }
+        let char_index = |byte: usize| byte_starts.partition_point(|start| *start < byte);
+
+        let mut copied = 0;
+        for captures in regex.captures_iter(&text) {
+            let Some(found) = captures.get(0).filter(|m| m.start() < m.end()) else {
+                continue;
+            };
+            let (first, last) = (char_index(found.start()), char_index(found.end()));
+            let (from, to) = (offsets[first], offsets[last]);
+            if options.from.is_some_and(|f| from < f) || options.to.is_some_and(|t| to > t) {
+                continue;
+            }
+
+            let mut replacement = String::new();
+            if options.regex {
+                captures.expand(replace, &mut replacement);
+            } else {
+                replacement.push_str(replace);
+            }
+            let marks = match &run[first] {
+                Token::Char(_, marks) => marks.clone(),
+                _ => Vec::new(),
+            };
+            let inserted: Vec<Token> = replacement.chars().map(|ch| Token::Char(ch, marks.clone())).collect();
+            let inserted_len = tokens_size(&inserted);
+
+            let shifted_from = (from as isize + delta) as usize;
+            edits.push(TextEdit { from: shifted_from, to: shifted_from + (to - from), inserted_len });
+            delta += inserted_len as isize - (to - from) as isize;
+
+            replaced.extend_from_slice(&run[copied..first]);
+            replaced.extend(inserted);
+            copied = last;
+        }
+        replaced.extend_from_slice(&run[copied..]);
+
+        pos = *offsets.last().unwrap();
+        index = end;
+    }
+
+    let count = edits.len();
+    let mut new_doc = doc.clone();
+    new_doc["content"] = Value::Array(build(replaced)?);
+
+    Ok(Replaced { doc: new_doc, edits, count })
+}
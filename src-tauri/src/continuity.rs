@@ -0,0 +1,346 @@
+//! QuestScribe - Continuity Checker
+//!
+//! Rules that compare what the text says with what the markers track, and
+//! report likely continuity errors as warnings for the author to review.
+//! Messages are in the document's language (see i18n.rs).
+//!
+//! # Rules
+//!
+//! - **Co-location**: a scene's setting is the location most recently named in
+//!   the current chapter. A character named in that scene whose tracked location
+//!   (see locations.rs) is somewhere else is flagged, once per chapter, character,
+//!   and setting. Characters with no tracked location are never flagged.
+//! - **Premature knowledge**: a paragraph names a character together with a fact
+//!   (see knowledge.rs) that the character only learns later. Only facts the
+//!   character learns at some point are considered, so bystanders aren't
+//!   flagged; each character/fact pair is reported once.
+//! - **Implausible travel**: a move between two locations with a recorded
+//!   distance happens faster than the document's maximum travel speed, judged by
+//!   the story times of the markers involved (see chronology.rs). Moves without
+//!   a distance or story times, or that go back in story time (flashbacks), are
+//!   skipped.
+//! - **Open TODO**: a TODO marker is still in the document. Run before export,
+//!   so reminders like "decide how much gold here" don't ship unresolved.
+//! - **Name spelling**: a word is a near miss of an entity's name or alias
+//!   ("Katnis" for "Katniss"; see name_check.rs).
+//! - **Descriptor drift**: a sentence contradicts an entity's tracked pronouns,
+//!   eye color, hair or height (see descriptors.rs).
+
+use crate::chapters::Chapter;
+use crate::chronology;
+use crate::descriptors;
+use crate::i18n;
+use crate::knowledge;
+use crate::locations;
+use crate::mentions::{self, Mention};
+use crate::name_check;
+use crate::state::{Entity, EntityKind, Marker};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContinuityRule {
+    CoLocation,
+    PrematureKnowledge,
+    ImplausibleTravel,
+    OpenTodo,
+    NameSpelling,
+    DescriptorDrift,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContinuityIssue {
+    pub rule: ContinuityRule,
+    pub severity: Severity,
+    pub position: usize,
+    pub entity_id: Option<String>,
+    pub message: String,
+}
+
+/// Limits for the implausible travel rule
+pub struct TravelLimit {
+    pub max_speed: f64, // Distance units per story hour
+    pub unit: &'static str,
+}
+
+fn chapter_index(chapters: &[Chapter], position: usize) -> usize {
+    chapters
+        .iter()
+        .rev()
+        .find(|c| position >= c.start)
+        .map(|c| c.index)
+        .unwrap_or(0)
+}
+
+fn co_location_issues(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    mentions: &[Mention],
+    chapters: &[Chapter],
+    locale: &str,
+) -> Vec<ContinuityIssue> {
+    let mut issues = Vec::new();
+    let mut reported = HashSet::new();
+    let mut current_chapter = None;
+    let mut setting: Option<&Entity> = None;
+
+    for mention in mentions {
+        let Some(entity) = entities.get(&mention.entity_id) else {
+            continue;
+        };
+
+        // A new chapter starts without a known setting
+        let chapter = chapter_index(chapters, mention.position);
+        if current_chapter != Some(chapter) {
+            current_chapter = Some(chapter);
+            setting = None;
+        }
+
+        if entity.kind == EntityKind::Location {
+            setting = Some(entity);
+            continue;
+        }
+
+        let Some(scene) = setting else {
+            continue;
+        };
+        let Some((tracked, since)) = locations::location_at(entities, markers, &entity.id, mention.position) else {
+            continue;
+        };
+        if tracked.id == scene.id || !reported.insert((chapter, entity.id.clone(), scene.id.clone())) {
+            continue;
+        }
+
+        issues.push(ContinuityIssue {
+            rule: ContinuityRule::CoLocation,
+            severity: Severity::Warning,
+            position: mention.position,
+            entity_id: Some(entity.id.clone()),
+            message: i18n::tr(
+                locale,
+                "continuity.co_location",
+                &[
+                    ("name", &entity.name),
+                    ("scene", &scene.name),
+                    ("tracked", &tracked.name),
+                    ("since", &since.to_string()),
+                ],
+            ),
+        });
+    }
+
+    issues
+}
+
+fn premature_knowledge_issues(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    mentions: &[Mention],
+    doc: &serde_json::Value,
+    locale: &str,
+) -> Vec<ContinuityIssue> {
+    let learned = knowledge::first_learned(markers);
+    if learned.is_empty() {
+        return Vec::new();
+    }
+
+    let mut issues = Vec::new();
+    let mut reported = HashSet::new();
+
+    for (start, end, text) in mentions::text_blocks(doc) {
+        let text = text.to_lowercase();
+        for mention in mentions.iter().filter(|m| m.position >= start && m.position < end) {
+            let Some(entity) = entities.get(&mention.entity_id) else {
+                continue;
+            };
+
+            for ((entity_id, fact), learned_at) in &learned {
+                if *entity_id != entity.id || mention.position >= *learned_at {
+                    continue;
+                }
+                // Very short facts would match inside ordinary words
+                if fact.chars().count() < 3 || !text.contains(&fact.to_lowercase()) {
+                    continue;
+                }
+                if !reported.insert((entity_id.clone(), fact.clone())) {
+                    continue;
+                }
+
+                issues.push(ContinuityIssue {
+                    rule: ContinuityRule::PrematureKnowledge,
+                    severity: Severity::Warning,
+                    position: mention.position,
+                    entity_id: Some(entity.id.clone()),
+                    message: i18n::tr(
+                        locale,
+                        "continuity.premature_knowledge",
+                        &[("name", &entity.name), ("fact", fact), ("learned_at", &learned_at.to_string())],
+                    ),
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+fn travel_issues(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    limit: &TravelLimit,
+    locale: &str,
+) -> Vec<ContinuityIssue> {
+    let times = chronology::story_times(markers);
+    let mut issues = Vec::new();
+
+    for entity in entities.values() {
+        let log = locations::travel_log(entities, markers, &entity.id);
+
+        // The previous move brought the entity to where this one starts
+        for pair in log.windows(2) {
+            let (departure, arrival) = (&pair[0], &pair[1]);
+            let (Some(from), Some(to)) = (&arrival.from, &arrival.to) else {
+                continue;
+            };
+            let Some(distance) = locations::distance(entities, &from.id, &to.id).filter(|d| *d > 0.0) else {
+                continue;
+            };
+            let (Some(start), Some(end)) = (times.get(&departure.marker_id), times.get(&arrival.marker_id)) else {
+                continue;
+            };
+            let hours = end - start;
+            if !hours.is_finite() || hours < 0.0 || distance / hours <= limit.max_speed {
+                continue;
+            }
+
+            issues.push(ContinuityIssue {
+                rule: ContinuityRule::ImplausibleTravel,
+                severity: Severity::Warning,
+                position: arrival.position,
+                entity_id: Some(entity.id.clone()),
+                message: i18n::tr(
+                    locale,
+                    "continuity.travel",
+                    &[
+                        ("name", &entity.name),
+                        ("distance", &distance.to_string()),
+                        ("unit", limit.unit),
+                        ("from", &from.name),
+                        ("to", &to.name),
+                        ("hours", &hours.to_string()),
+                        ("max_speed", &limit.max_speed.to_string()),
+                    ],
+                ),
+            });
+        }
+    }
+
+    issues
+}
+
+// TODO markers still in the document, one issue each
+fn todo_issues(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    locale: &str,
+) -> Vec<ContinuityIssue> {
+    markers
+        .values()
+        .filter(|m| m.todo)
+        .map(|marker| {
+            let name = entities.get(&marker.entity_id).map(|e| e.name.as_str()).unwrap_or(&marker.entity_id);
+            let note = match marker.description.trim() {
+                "" => i18n::tr(locale, "continuity.no_description", &[]),
+                text => text.to_string(),
+            };
+            ContinuityIssue {
+                rule: ContinuityRule::OpenTodo,
+                severity: Severity::Warning,
+                position: marker.position,
+                entity_id: Some(marker.entity_id.clone()),
+                message: i18n::tr(locale, "continuity.open_todo", &[("name", name), ("note", &note)]),
+            }
+        })
+        .collect()
+}
+
+fn spelling_issues(
+    doc: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    locale: &str,
+) -> Vec<ContinuityIssue> {
+    name_check::find_misspellings(doc, entities, markers)
+        .into_iter()
+        .map(|miss| ContinuityIssue {
+            rule: ContinuityRule::NameSpelling,
+            severity: Severity::Warning,
+            position: miss.position,
+            message: i18n::tr(
+                locale,
+                "continuity.misspelling",
+                &[("found", &miss.found), ("expected", &miss.expected)],
+            ),
+            entity_id: Some(miss.entity_id),
+        })
+        .collect()
+}
+
+fn descriptor_issues(
+    doc: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    locale: &str,
+) -> Vec<ContinuityIssue> {
+    descriptors::scan_descriptors(doc, entities, markers)
+        .into_iter()
+        .map(|mismatch| {
+            let name = entities.get(&mismatch.entity_id).map(|e| e.name.as_str()).unwrap_or(&mismatch.entity_id);
+            ContinuityIssue {
+                rule: ContinuityRule::DescriptorDrift,
+                severity: Severity::Warning,
+                position: mismatch.position,
+                message: i18n::tr(
+                    locale,
+                    "continuity.descriptor",
+                    &[
+                        ("found", &mismatch.found),
+                        ("name", name),
+                        ("descriptor", &i18n::tr(locale, mismatch.descriptor.label_key(), &[])),
+                        ("tracked", &mismatch.tracked),
+                    ],
+                ),
+                entity_id: Some(mismatch.entity_id),
+            }
+        })
+        .collect()
+}
+
+/// Run every continuity rule, returning issues in document order
+pub fn check_continuity(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    doc: &serde_json::Value,
+    chapters: &[Chapter],
+    travel: &TravelLimit,
+    locale: &str,
+) -> Vec<ContinuityIssue> {
+    let mentions = mentions::find_mentions(doc, entities);
+
+    let mut issues = co_location_issues(entities, markers, &mentions, chapters, locale);
+    issues.extend(premature_knowledge_issues(entities, markers, &mentions, doc, locale));
+    issues.extend(travel_issues(entities, markers, travel, locale));
+    issues.extend(todo_issues(entities, markers, locale));
+    issues.extend(spelling_issues(doc, entities, markers, locale));
+    issues.extend(descriptor_issues(doc, entities, markers, locale));
+
+    issues.sort_by_key(|issue| issue.position);
+    issues
+}
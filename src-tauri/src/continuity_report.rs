@@ -0,0 +1,285 @@
+//! QuestScribe - Continuity Report
+//!
+//! Runs every validator (the continuity checker, orphaned marker detection, the
+//! Chekhov's gun report, unused entities, and unresolved plot threads) and
+//! renders the findings as a Markdown or HTML document an author can read
+//! through before sending a manuscript out. Each finding carries its document
+//! position, its chapter, and an excerpt of the surrounding text. The report
+//! is written in the document's language (see i18n.rs).
+
+use crate::analysis::{self, ChekhovReason, OrphanReason};
+use crate::chapters::Chapter;
+use crate::continuity::{self, TravelLimit};
+use crate::i18n;
+use crate::mentions;
+use crate::plot_threads::{self, PlotThread, ThreadStatus};
+use crate::positions;
+use crate::state::{Entity, Marker};
+use std::collections::HashMap;
+
+// Characters of context shown before and after a finding's position
+const EXCERPT_BEFORE: usize = 40;
+const EXCERPT_AFTER: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => Err(format!("Unknown report format: {}", format)),
+        }
+    }
+}
+
+/// One finding
+pub struct ReportEntry {
+    pub position: Option<usize>, // None for findings that aren't tied to a place (e.g., unused entities)
+    pub chapter: Option<String>,
+    pub message: String,
+    pub excerpt: Option<String>,
+}
+
+/// The findings of one validator
+pub struct ReportSection {
+    pub title: String,
+    pub entries: Vec<ReportEntry>,
+}
+
+// Text around a position, cut at word boundaries where possible.
+// Positions also count inline nodes such as markers, so the centre is approximate.
+fn excerpt(blocks: &[(usize, usize, String)], position: usize) -> Option<String> {
+    let (start, _, text) = blocks.iter().find(|(start, end, _)| position >= *start && position < *end)?;
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return None;
+    }
+
+    // Block content starts one position after the block's opening token
+    let offset = positions::utf16_to_char_index(text, position.saturating_sub(start + 1));
+    let mut from = offset.saturating_sub(EXCERPT_BEFORE);
+    let mut to = (offset + EXCERPT_AFTER).min(chars.len());
+    if from > 0 {
+        from = (from..offset).find(|&i| chars[i - 1].is_whitespace()).unwrap_or(from);
+    }
+    if to < chars.len() {
+        to = (offset..to).rev().find(|&i| chars[i].is_whitespace()).unwrap_or(to);
+    }
+
+    let mut snippet: String = chars[from..to].iter().collect::<String>().trim().to_string();
+    if from > 0 {
+        snippet.insert(0, '…');
+    }
+    if to < chars.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+fn chapter_title(chapters: &[Chapter], position: usize) -> Option<String> {
+    chapters
+        .iter()
+        .rev()
+        .find(|c| position >= c.start)
+        .map(|c| c.title.clone())
+}
+
+fn entity_name<'a>(entities: &'a HashMap<String, Entity>, entity_id: &'a str) -> &'a str {
+    entities.get(entity_id).map(|e| e.name.as_str()).unwrap_or(entity_id)
+}
+
+fn orphan_reason(reason: &OrphanReason) -> &'static str {
+    match reason {
+        OrphanReason::MissingEntity => "report.orphan.missing_entity",
+        OrphanReason::OutOfRange => "report.orphan.out_of_range",
+        OrphanReason::MissingFromText => "report.orphan.missing_from_text",
+    }
+}
+
+fn status_name(status: ThreadStatus) -> &'static str {
+    match status {
+        ThreadStatus::Open => "report.thread.open",
+        ThreadStatus::Developed => "report.thread.developed",
+        ThreadStatus::Resolved => "report.thread.resolved",
+    }
+}
+
+/// Run every validator over the document
+pub fn build_report(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    threads: &[PlotThread],
+    doc: &serde_json::Value,
+    chapters: &[Chapter],
+    travel: &TravelLimit,
+    locale: &str,
+) -> Vec<ReportSection> {
+    let blocks = mentions::text_blocks(doc);
+    let located = |position: usize, message: String| ReportEntry {
+        position: Some(position),
+        chapter: chapter_title(chapters, position),
+        message,
+        excerpt: excerpt(&blocks, position),
+    };
+
+    let continuity_entries = continuity::check_continuity(entities, markers, doc, chapters, travel, locale)
+        .into_iter()
+        .map(|issue| located(issue.position, issue.message))
+        .collect();
+
+    let orphan_entries = analysis::find_orphaned_markers(entities, markers, Some(doc))
+        .into_iter()
+        .map(|orphan| {
+            let reasons: Vec<String> = orphan.reasons.iter().map(|r| i18n::tr(locale, orphan_reason(r), &[])).collect();
+            let message = i18n::tr(
+                locale,
+                "report.orphaned",
+                &[("name", entity_name(entities, &orphan.entity_id)), ("reasons", &reasons.join("; "))],
+            );
+            located(orphan.position, message)
+        })
+        .collect();
+
+    let chekhov_entries = analysis::chekhov_report(entities, markers, Some(doc))
+        .into_iter()
+        .map(|item| {
+            let key = match item.reason {
+                ChekhovReason::NeverReferenced => "report.chekhov.never_referenced",
+                ChekhovReason::RemovedWithoutIntroduction => "report.chekhov.removed_without_introduction",
+            };
+            let message = i18n::tr(locale, key, &[("name", &item.entity_name), ("field", &item.field)]);
+            located(item.position, message)
+        })
+        .collect();
+
+    let unused_entries = analysis::find_unused_entities(entities, markers, Some(doc))
+        .into_iter()
+        .map(|unused| {
+            let key = if unused.marker_count == 0 { "report.unused.no_markers" } else { "report.unused.never_mentioned" };
+            let message = i18n::tr(locale, key, &[("name", &unused.entity_name)]);
+            ReportEntry {
+                position: None,
+                chapter: None,
+                message,
+                excerpt: None,
+            }
+        })
+        .collect();
+
+    let thread_entries = plot_threads::unresolved_threads(threads, markers, usize::MAX)
+        .into_iter()
+        .map(|thread| {
+            let status = i18n::tr(locale, status_name(thread.status), &[]);
+            let message = i18n::tr(locale, "report.thread.unresolved", &[("name", &thread.name), ("status", &status)]);
+            located(thread.since_position, message)
+        })
+        .collect();
+
+    let section = |key: &str, entries: Vec<ReportEntry>| ReportSection { title: i18n::tr(locale, key, &[]), entries };
+    vec![
+        section("report.section.continuity", continuity_entries),
+        section("report.section.orphaned", orphan_entries),
+        section("report.section.chekhov", chekhov_entries),
+        section("report.section.unused", unused_entries),
+        section("report.section.threads", thread_entries),
+    ]
+}
+
+fn total_findings(sections: &[ReportSection]) -> usize {
+    sections.iter().map(|s| s.entries.len()).sum()
+}
+
+// "Position 120 (Chapter 2)"
+fn location_label(entry: &ReportEntry, locale: &str) -> Option<String> {
+    let position = entry.position?.to_string();
+    Some(match &entry.chapter {
+        Some(chapter) => i18n::tr(locale, "report.position_in_chapter", &[("position", &position), ("chapter", chapter)]),
+        None => i18n::tr(locale, "report.position", &[("position", &position)]),
+    })
+}
+
+fn findings_label(sections: &[ReportSection], locale: &str) -> String {
+    i18n::tr(locale, "report.findings", &[("count", &total_findings(sections).to_string())])
+}
+
+/// Render the report as Markdown
+pub fn render_markdown(sections: &[ReportSection], locale: &str) -> String {
+    let mut out = format!(
+        "# {}\n\n{}\n",
+        i18n::tr(locale, "report.continuity_title", &[]),
+        findings_label(sections, locale)
+    );
+
+    for section in sections {
+        out.push_str(&format!("\n## {} ({})\n\n", section.title, section.entries.len()));
+        if section.entries.is_empty() {
+            out.push_str(&format!("{}\n", i18n::tr(locale, "report.no_issues", &[])));
+            continue;
+        }
+
+        for entry in &section.entries {
+            match location_label(entry, locale) {
+                Some(label) => out.push_str(&format!("- **{}**: {}\n", label, entry.message)),
+                None => out.push_str(&format!("- {}\n", entry.message)),
+            }
+            if let Some(excerpt) = &entry.excerpt {
+                out.push_str(&format!("  > {}\n", excerpt));
+            }
+        }
+    }
+
+    out
+}
+
+/// Escape text for HTML (and SVG) output
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render the report as a standalone HTML page
+pub fn render_html(sections: &[ReportSection], locale: &str) -> String {
+    let title = escape_html(&i18n::tr(locale, "report.continuity_title", &[]));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\nbody {{ font-family: sans-serif; max-width: 50em; margin: 2em auto; line-height: 1.5; }}\n\
+         .location {{ font-weight: bold; }}\n\
+         blockquote {{ color: #555; border-left: 3px solid #ccc; margin: 0.3em 0 0.8em; padding-left: 0.8em; }}\n\
+         </style>\n</head>\n<body>\n",
+        escape_html(i18n::normalize_locale(locale).unwrap_or(i18n::DEFAULT_LOCALE)),
+        title
+    );
+    out.push_str(&format!("<h1>{}</h1>\n<p>{}</p>\n", title, escape_html(&findings_label(sections, locale))));
+
+    for section in sections {
+        out.push_str(&format!("<h2>{} ({})</h2>\n", escape_html(&section.title), section.entries.len()));
+        if section.entries.is_empty() {
+            out.push_str(&format!("<p>{}</p>\n", escape_html(&i18n::tr(locale, "report.no_issues", &[]))));
+            continue;
+        }
+
+        out.push_str("<ul>\n");
+        for entry in &section.entries {
+            out.push_str("<li>");
+            if let Some(label) = location_label(entry, locale) {
+                out.push_str(&format!("<span class=\"location\">{}</span>: ", escape_html(&label)));
+            }
+            out.push_str(&escape_html(&entry.message));
+            if let Some(excerpt) = &entry.excerpt {
+                out.push_str(&format!("<blockquote>{}</blockquote>", escape_html(excerpt)));
+            }
+            out.push_str("</li>\n");
+        }
+        out.push_str("</ul>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
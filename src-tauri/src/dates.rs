@@ -0,0 +1,68 @@
+//! QuestScribe - Date Helpers
+//!
+//! Timestamps are Unix seconds throughout the backend. Calendar days are needed
+//! for streaks, daily goals, and history charts; since the backend has no time
+//! zone database, callers pass the user's UTC offset (from the frontend) and
+//! days are computed from that.
+//!
+//! Every timestamp the backend records comes from `now()`, so a fixed clock
+//! (see ids.rs for the replay mode that sets it) makes saved documents
+//! reproducible.
+
+use std::sync::Mutex;
+
+static FIXED_CLOCK: Mutex<Option<i64>> = Mutex::new(None);
+
+/// Current Unix timestamp in seconds (the fixed time, if one is set)
+pub fn now() -> i64 {
+    if let Some(fixed) = *FIXED_CLOCK.lock().unwrap() {
+        return fixed;
+    }
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// The fixed time, if one is set
+pub fn fixed_clock() -> Option<i64> {
+    *FIXED_CLOCK.lock().unwrap()
+}
+
+/// Make `now()` return the given time (None = the system clock)
+pub fn set_fixed_clock(timestamp: Option<i64>) {
+    *FIXED_CLOCK.lock().unwrap() = timestamp;
+}
+
+/// Day number (days since 1970-01-01) of a timestamp in the given UTC offset
+pub fn day_number(timestamp: i64, utc_offset_minutes: i32) -> i64 {
+    (timestamp + utc_offset_minutes as i64 * 60).div_euclid(86_400)
+}
+
+/// Format a day number as an ISO date ("2024-03-09")
+pub fn format_day(day: i64) -> String {
+    // Civil-from-days algorithm (Howard Hinnant), valid for the proleptic Gregorian calendar
+    let z = day + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Format a timestamp as an ISO date and time in UTC ("2024-03-09T14:30:00Z")
+pub fn format_utc(timestamp: i64) -> String {
+    let seconds = timestamp.rem_euclid(86_400);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_day(day_number(timestamp, 0)),
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
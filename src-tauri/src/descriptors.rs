@@ -0,0 +1,322 @@
+//! QuestScribe - Descriptor Tracking
+//!
+//! Descriptors are the fields readers notice when they drift: pronouns, eye
+//! color, hair, height. They're ordinary fields under "descriptors." (so
+//! markers can change them, say when a character dyes their hair), declared on
+//! an entity in one step with `add_descriptor_fields`.
+//!
+//! The scan reads every sentence that names exactly one entity and compares
+//! what it says about that entity with the entity's tracked values at that
+//! point:
+//!
+//! - **Pronouns**: the first possessive or reflexive pronoun after the name
+//!   ("Katniss drew his bow") must belong to the tracked set ("she/her").
+//!   Subject and object pronouns, and "her" (both object and possessive),
+//!   often refer to someone else in the sentence, so they aren't checked.
+//! - **Eyes** and **hair**: a color word right before "eyes"/"eyed" or
+//!   "hair"/"haired" ("grey eyes", "red-haired") must appear in the tracked
+//!   value.
+//! - **Height**: a height word right before the name or up to three words after
+//!   it ("tall Gale", "Gale was tall") must not contradict the tracked value
+//!   (tall vs. short).
+//!
+//! Descriptors without a tracked value, or with a value the scan can't read
+//! (eyes "heterochromatic"), are skipped. Sentences are read within one text
+//! run, so a sentence split by formatting or a marker is read in parts.
+
+use crate::engine;
+use crate::mentions;
+use crate::mutations;
+use crate::positions;
+use crate::state::{Entity, FieldType, Marker};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Descriptor {
+    Pronouns,
+    EyeColor,
+    Hair,
+    Height,
+}
+
+impl Descriptor {
+    pub const ALL: [Descriptor; 4] = [Descriptor::Pronouns, Descriptor::EyeColor, Descriptor::Hair, Descriptor::Height];
+
+    /// Field path of the descriptor
+    pub fn field(self) -> &'static str {
+        match self {
+            Descriptor::Pronouns => "descriptors.pronouns",
+            Descriptor::EyeColor => "descriptors.eye_color",
+            Descriptor::Hair => "descriptors.hair",
+            Descriptor::Height => "descriptors.height",
+        }
+    }
+
+    /// Message key of the descriptor's name (see i18n.rs)
+    pub fn label_key(self) -> &'static str {
+        match self {
+            Descriptor::Pronouns => "descriptor.pronouns",
+            Descriptor::EyeColor => "descriptor.eye_color",
+            Descriptor::Hair => "descriptor.hair",
+            Descriptor::Height => "descriptor.height",
+        }
+    }
+}
+
+/// Text that contradicts an entity's tracked descriptor
+#[derive(Debug, Clone, Serialize)]
+pub struct DescriptorMismatch {
+    pub entity_id: String,
+    pub position: usize, // Document position of the word that contradicts the value
+    pub descriptor: Descriptor,
+    pub found: String,
+    pub tracked: String,
+}
+
+const PRONOUN_SETS: &[&[&str]] = &[
+    &["he", "him", "his", "himself"],
+    &["she", "her", "hers", "herself"],
+    &["they", "them", "their", "theirs", "themselves", "themself"],
+];
+
+// Pronouns that almost always refer back to the sentence's subject
+const CHECKED_PRONOUNS: &[&str] = &["his", "himself", "hers", "herself", "their", "theirs", "themselves", "themself"];
+
+const EYE_COLORS: &[&str] = &[
+    "blue", "green", "brown", "hazel", "gray", "grey", "amber", "black", "violet", "gold", "golden", "silver",
+];
+const EYE_WORDS: &[&str] = &["eye", "eyes", "eyed"];
+
+const HAIR_COLORS: &[&str] = &[
+    "blond", "blonde", "brown", "brunette", "black", "red", "auburn", "ginger", "gray", "grey", "white",
+    "silver", "golden", "gold", "chestnut", "copper",
+];
+const HAIR_WORDS: &[&str] = &["hair", "haired"];
+
+const TALL_WORDS: &[&str] = &["tall", "towering", "lanky", "gangly"];
+const SHORT_WORDS: &[&str] = &["short", "petite", "diminutive", "tiny"];
+const HEIGHT_WINDOW: usize = 3; // Words after the name a height word may be
+
+// One spelling per color ("grey" is "gray")
+fn canonical(word: &str) -> &str {
+    match word {
+        "grey" => "gray",
+        "blonde" => "blond",
+        "golden" => "gold",
+        "ginger" => "red",
+        other => other,
+    }
+}
+
+// Lowercase words of a text as (char index, word)
+fn words(text: &str) -> Vec<(usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut list = Vec::new();
+    let mut start = None;
+    for (index, c) in chars.iter().chain(std::iter::once(&' ')).enumerate() {
+        match (c.is_alphabetic(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(from)) => {
+                list.push((from, chars[from..index].iter().collect::<String>().to_lowercase()));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    list
+}
+
+// Sentences of a text as char ranges
+fn sentences(text: &str) -> Vec<(usize, usize)> {
+    let mut list = Vec::new();
+    let mut start = 0;
+    let count = text.chars().count();
+    for (index, c) in text.chars().enumerate() {
+        if matches!(c, '.' | '!' | '?' | '\n') {
+            list.push((start, index + 1));
+            start = index + 1;
+        }
+    }
+    if start < count {
+        list.push((start, count));
+    }
+    list
+}
+
+// Words of the tracked value
+fn value_words(value: &str) -> Vec<String> {
+    words(value).into_iter().map(|(_, word)| canonical(&word).to_string()).collect()
+}
+
+// Pronouns the tracked value allows: every set it names, plus its own words
+fn allowed_pronouns(value: &str) -> Vec<String> {
+    let tracked = value_words(value);
+    let mut allowed = tracked.clone();
+    for set in PRONOUN_SETS {
+        if set.iter().any(|p| tracked.iter().any(|t| t == p)) {
+            allowed.extend(set.iter().map(|p| p.to_string()));
+        }
+    }
+    allowed
+}
+
+// Which side of the height words a value or word is on (Some(true) = tall)
+fn height_class(words: &[String]) -> Option<bool> {
+    let tall = words.iter().any(|w| TALL_WORDS.contains(&w.as_str()));
+    let short = words.iter().any(|w| SHORT_WORDS.contains(&w.as_str()));
+    match (tall, short) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    }
+}
+
+// Check one sentence for the entity named at `name_at` (an index into `words`)
+fn check_sentence(
+    words: &[(usize, String)],
+    name_at: usize,
+    name_words: usize,
+    tracked: &HashMap<Descriptor, String>,
+) -> Vec<(Descriptor, usize, String)> {
+    let mut found = Vec::new();
+    let after = &words[(name_at + name_words).min(words.len())..];
+
+    if let Some(value) = tracked.get(&Descriptor::Pronouns) {
+        let allowed = allowed_pronouns(value);
+        if let Some((at, word)) = after.iter().find(|(_, w)| CHECKED_PRONOUNS.contains(&w.as_str())) {
+            if !allowed.contains(word) {
+                found.push((Descriptor::Pronouns, *at, word.clone()));
+            }
+        }
+    }
+
+    for (descriptor, colors, nouns) in [
+        (Descriptor::EyeColor, EYE_COLORS, EYE_WORDS),
+        (Descriptor::Hair, HAIR_COLORS, HAIR_WORDS),
+    ] {
+        let Some(value) = tracked.get(&descriptor) else {
+            continue;
+        };
+        let known = value_words(value);
+        if !known.iter().any(|w| colors.iter().any(|c| canonical(c) == w)) {
+            continue;
+        }
+        for pair in after.windows(2) {
+            let (at, color) = &pair[0];
+            if colors.contains(&color.as_str())
+                && nouns.contains(&pair[1].1.as_str())
+                && !known.iter().any(|w| w == canonical(color))
+            {
+                found.push((descriptor, *at, color.clone()));
+            }
+        }
+    }
+
+    if let Some(tracked_class) = tracked.get(&Descriptor::Height).and_then(|v| height_class(&value_words(v))) {
+        let before = name_at.checked_sub(1).map(|i| &words[i]);
+        for (at, word) in before.into_iter().chain(after.iter().take(HEIGHT_WINDOW)) {
+            if height_class(std::slice::from_ref(word)).is_some_and(|class| class != tracked_class) {
+                found.push((Descriptor::Height, *at, word.clone()));
+            }
+        }
+    }
+
+    found
+}
+
+// Tracked descriptor values of an entity at a position
+fn tracked_values(entity: &Entity, markers: &HashMap<String, Marker>, position: usize) -> HashMap<Descriptor, String> {
+    let mut state = engine::entity_state_with_defaults(markers, entity, position);
+    engine::fill_defaults(&mut state, entity);
+
+    Descriptor::ALL
+        .into_iter()
+        .filter_map(|descriptor| {
+            let value = engine::get_nested_value(&state, descriptor.field())?;
+            let text = value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string());
+            let text = text.trim().to_string();
+            (!text.is_empty()).then_some((descriptor, text))
+        })
+        .collect()
+}
+
+/// Declare the descriptor fields on an entity (as text fields)
+pub fn add_descriptor_fields(entities: &mut HashMap<String, Entity>, entity_id: &str) -> Result<Entity, String> {
+    let mut entity = None;
+    for descriptor in Descriptor::ALL {
+        let field = descriptor.field();
+        let declared = entities
+            .get(entity_id)
+            .ok_or("Entity not found")?
+            .field_metadata
+            .get(field)
+            .and_then(|m| m.field_type);
+        entity = Some(mutations::set_field_type(entities, entity_id, field, declared.or(Some(FieldType::Text)))?);
+    }
+    entity.ok_or_else(|| "Entity not found".to_string())
+}
+
+/// Find text that contradicts the entities' tracked descriptors, in document order
+pub fn scan_descriptors(
+    doc: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+) -> Vec<DescriptorMismatch> {
+    let mentions = mentions::find_mentions(doc, entities);
+    let mut found = Vec::new();
+
+    positions::for_each_node(doc, |node, pos| {
+        let Some(text) = node.get("text").and_then(|t| t.as_str()) else {
+            return;
+        };
+        let end = pos + text.encode_utf16().count();
+        let here: Vec<_> = mentions.iter().filter(|m| m.position >= pos && m.position < end).collect();
+        if here.is_empty() {
+            return;
+        }
+
+        let text_words = words(text);
+        for (from, to) in sentences(text) {
+            let in_sentence: Vec<_> = here
+                .iter()
+                .filter(|m| (from..to).contains(&positions::utf16_to_char_index(text, m.position - pos)))
+                .collect();
+            let Some(first) = in_sentence.first() else {
+                continue;
+            };
+            if in_sentence.iter().any(|m| m.entity_id != first.entity_id) {
+                continue;
+            }
+            let Some(entity) = entities.get(&first.entity_id) else {
+                continue;
+            };
+            let tracked = tracked_values(entity, markers, first.position);
+            if tracked.is_empty() {
+                continue;
+            }
+
+            let sentence: Vec<(usize, String)> =
+                text_words.iter().filter(|(at, _)| (from..to).contains(at)).cloned().collect();
+            let name_char = positions::utf16_to_char_index(text, first.position - pos);
+            let Some(name_at) = sentence.iter().position(|(at, _)| *at == name_char) else {
+                continue;
+            };
+            let name_words = words(&entity.name).len().max(1);
+
+            for (descriptor, at, word) in check_sentence(&sentence, name_at, name_words, &tracked) {
+                found.push(DescriptorMismatch {
+                    entity_id: entity.id.clone(),
+                    position: pos + positions::char_index_to_utf16(text, at),
+                    descriptor,
+                    found: word,
+                    tracked: tracked[&descriptor].clone(),
+                });
+            }
+        }
+    });
+
+    found.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.entity_id.cmp(&b.entity_id)));
+    found
+}
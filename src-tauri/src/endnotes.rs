@@ -0,0 +1,122 @@
+//! QuestScribe - Marker Endnotes for Export
+//!
+//! In endnote mode, exports replace each marker in the text with a note number and
+//! list the notes after the manuscript, so a printed copy carries its progression
+//! data for editors who work on paper. Each note names the entity, lists the
+//! marker's field changes with the values they result in, and ends with the
+//! marker's description.
+//!
+//! Notes are numbered in document order. Only markers whose node appears in the
+//! exported content get a note.
+
+use crate::engine;
+use crate::i18n;
+use crate::knowledge;
+use crate::markdown;
+use crate::positions;
+use crate::state::{ChangeType, Entity, FieldChange, Marker};
+use std::collections::HashMap;
+
+/// One numbered note
+pub struct Endnote {
+    pub number: usize,
+    pub text: String,
+}
+
+/// Endnotes for a document, with the note number of each marker
+pub struct Endnotes {
+    pub numbers: HashMap<String, usize>, // Marker ID -> note number
+    pub notes: Vec<Endnote>,
+}
+
+// Show whole numbers without the ".0" the engine's f64 values carry
+fn format_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Number(n) => match n.as_f64() {
+            Some(num) if num.fract() == 0.0 && num.abs() < 1e15 => format!("{}", num as i64),
+            _ => n.to_string(),
+        },
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// A change as written in the note, e.g. "stats.HP +10 (now 35)" or "Level = 5"
+fn describe_change(change: &FieldChange, state: &engine::EntityState, locale: &str) -> String {
+    match change.change_type {
+        ChangeType::Absolute => format!("{} = {}", change.field_name, change.value),
+        ChangeType::Remove => i18n::tr(locale, "endnote.removed", &[("field", &change.field_name)]),
+        ChangeType::Learn => {
+            let fact = knowledge::learned_fact(change).unwrap_or(&change.field_name);
+            i18n::tr(locale, "endnote.learned", &[("fact", fact)])
+        }
+        ChangeType::Relative | ChangeType::Custom => {
+            let described = match &change.custom {
+                Some(custom) => format!("{} {} {}", change.field_name, custom.name, change.value),
+                None if change.value.starts_with('-') || change.value.starts_with('+') => {
+                    format!("{} {}", change.field_name, change.value)
+                }
+                None => format!("{} +{}", change.field_name, change.value),
+            };
+
+            match engine::get_nested_value(state, &change.field_name) {
+                Some(value) => {
+                    let result = i18n::tr(locale, "endnote.result", &[("value", &format_value(value))]);
+                    format!("{} ({})", described, result)
+                }
+                None => described,
+            }
+        }
+    }
+}
+
+/// Build the notes for the markers embedded in `doc_json`
+///
+/// `markers` may be a filtered set (e.g., after spoiler redaction); marker nodes
+/// without an entry get no note.
+pub fn build_endnotes(
+    doc_json: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    locale: &str,
+) -> Endnotes {
+    let mut placed: Vec<(usize, &Marker)> = positions::marker_node_positions(doc_json)
+        .into_iter()
+        .filter_map(|(id, pos)| markers.get(&id).map(|m| (pos, m)))
+        .collect();
+    placed.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.id.cmp(&b.1.id)));
+
+    let mut endnotes = Endnotes {
+        numbers: HashMap::new(),
+        notes: Vec::new(),
+    };
+
+    for (index, (_, marker)) in placed.into_iter().enumerate() {
+        let number = index + 1;
+
+        // Resulting values: the entity's state with this marker applied
+        let entity = entities.get(&marker.entity_id);
+        let state = match entity {
+            Some(entity) => engine::entity_state_with_defaults(markers, entity, marker.position),
+            None => engine::entity_state_at(markers, &marker.entity_id, marker.position),
+        };
+
+        let entity_name = entity.map(|e| e.name.as_str()).unwrap_or("?");
+
+        let changes: Vec<String> = marker
+            .changes
+            .iter()
+            .map(|change| describe_change(change, &state, locale))
+            .collect();
+
+        let mut text = format!("{}: {}", entity_name, changes.join("; "));
+        if !marker.description.is_empty() {
+            text.push_str(&format!(". {}", markdown::to_plain_text(&marker.description).replace('\n', " ")));
+        }
+
+        endnotes.numbers.insert(marker.id.clone(), number);
+        endnotes.notes.push(Endnote { number, text });
+    }
+
+    endnotes
+}
@@ -0,0 +1,319 @@
+//! QuestScribe - State Computation Engine
+//!
+//! Computes an entity's state at a point in the story by replaying its markers in
+//! document order (see `compare_markers`). State is a nested JSON object: a field path like "stats.HP"
+//! is stored as `{"stats": {"HP": ...}}`.
+//!
+//! A field's declared default (see `FieldMetadata`) is the starting point for relative
+//! changes to it; without one, an unset field counts as 0. Numbers written to arc
+//! fields are clamped to the arc scale as they're applied (see arcs.rs).
+
+use crate::arcs;
+use crate::change_types;
+use crate::knowledge;
+use crate::state::{ChangeType, Entity, FieldChange, FieldType, Marker};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// Computed entity state (nested field groups)
+pub type EntityState = serde_json::Map<String, serde_json::Value>;
+
+// Helper function to set a nested value in a JSON object using a path like "stats.HP"
+pub fn set_nested_value(
+    state: &mut EntityState,
+    path: &str,
+    value: serde_json::Value,
+) {
+    let parts: Vec<&str> = path.split('.').collect();
+
+    if parts.len() == 1 {
+        // Simple field, no nesting
+        state.insert(path.to_string(), value);
+        return;
+    }
+
+    // Build the path recursively
+    fn insert_at_path(
+        obj: &mut EntityState,
+        parts: &[&str],
+        value: serde_json::Value,
+    ) {
+        if parts.len() == 1 {
+            obj.insert(parts[0].to_string(), value);
+        } else {
+            let entry = obj
+                .entry(parts[0].to_string())
+                .or_insert_with(|| serde_json::json!({}));
+
+            if let Some(nested_obj) = entry.as_object_mut() {
+                insert_at_path(nested_obj, &parts[1..], value);
+            }
+        }
+    }
+
+    insert_at_path(state, &parts, value);
+}
+
+// Helper function to get a nested value from a JSON object using a path
+pub fn get_nested_value<'a>(
+    state: &'a EntityState,
+    path: &str,
+) -> Option<&'a serde_json::Value> {
+    let parts: Vec<&str> = path.split('.').collect();
+
+    if parts.len() == 1 {
+        return state.get(path);
+    }
+
+    let mut current = state;
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return current.get(*part);
+        } else {
+            current = current.get(*part)?.as_object()?;
+        }
+    }
+
+    None
+}
+
+// Helper function to remove a nested value from a JSON object using a path
+pub fn remove_nested_value(
+    state: &mut EntityState,
+    path: &str,
+) {
+    let parts: Vec<&str> = path.split('.').collect();
+
+    if parts.len() == 1 {
+        // Simple field, remove directly
+        state.remove(path);
+        return;
+    }
+
+    // Navigate to parent and remove the field
+    fn remove_at_path(
+        obj: &mut EntityState,
+        parts: &[&str],
+    ) {
+        if parts.len() == 1 {
+            obj.remove(parts[0]);
+        } else if let Some(nested) = obj.get_mut(parts[0]) {
+            if let Some(nested_obj) = nested.as_object_mut() {
+                remove_at_path(nested_obj, &parts[1..]);
+            }
+        }
+    }
+
+    remove_at_path(state, &parts);
+}
+
+// Helper function to flatten a state object into field changes
+pub fn flatten_state_to_changes(
+    state: &EntityState,
+    prefix: String,
+    changes: &mut Vec<FieldChange>,
+) {
+    for (key, value) in state.iter() {
+        let field_name = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        if let Some(obj) = value.as_object() {
+            // Nested object - recurse
+            flatten_state_to_changes(obj, field_name, changes);
+        } else {
+            // Leaf value - create a field change
+            let value_str = match value {
+                serde_json::Value::Number(n) => n.to_string(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                serde_json::Value::String(s) => s.clone(),
+                _ => value.to_string(),
+            };
+
+            changes.push(FieldChange {
+                field_name,
+                value: value_str,
+                change_type: ChangeType::Absolute,
+                custom: None,
+            });
+        }
+    }
+}
+
+/// What the engine needs from an entity's field metadata: numeric defaults, and which fields are arcs
+#[derive(Debug, Clone, Default)]
+pub struct FieldDefaults {
+    values: HashMap<String, f64>,
+    arc_fields: HashSet<String>,
+}
+
+impl FieldDefaults {
+    /// A field's numeric default, if it declares one
+    pub fn get(&self, field: &str) -> Option<f64> {
+        self.values.get(field).copied()
+    }
+
+    // Keep numbers written to arc fields on the scale
+    fn bound(&self, field: &str, value: f64) -> f64 {
+        if self.arc_fields.contains(field) {
+            arcs::clamp(value)
+        } else {
+            value
+        }
+    }
+}
+
+/// Numeric defaults and arc fields of an entity, by field path
+pub fn field_defaults(entity: &Entity) -> FieldDefaults {
+    let values = entity
+        .field_metadata
+        .iter()
+        .filter_map(|(field, meta)| {
+            let default = meta.default_value.as_ref()?.parse::<f64>().ok()?;
+            Some((field.clone(), default))
+        })
+        .collect();
+    let arc_fields = entity
+        .field_metadata
+        .iter()
+        .filter(|(_, meta)| meta.field_type == Some(FieldType::Arc))
+        .map(|(field, _)| field.clone())
+        .collect();
+
+    FieldDefaults { values, arc_fields }
+}
+
+// Parse a stored value: number, then boolean, otherwise string
+fn parse_value(value: &str) -> serde_json::Value {
+    if let Ok(num) = value.parse::<f64>() {
+        serde_json::json!(num)
+    } else if value == "true" || value == "false" {
+        serde_json::json!(value.parse::<bool>().unwrap())
+    } else {
+        serde_json::json!(value)
+    }
+}
+
+/// Apply a single field change to a state, starting relative changes to unset fields from their default
+pub fn apply_change(state: &mut EntityState, change: &FieldChange, defaults: &FieldDefaults) {
+    match &change.change_type {
+        ChangeType::Remove => {
+            // Remove the field from the state
+            remove_nested_value(state, &change.field_name);
+        }
+        ChangeType::Absolute => {
+            // Try to parse as number, otherwise treat as string
+            let value = match parse_value(&change.value) {
+                serde_json::Value::Number(n) => {
+                    serde_json::json!(defaults.bound(&change.field_name, n.as_f64().unwrap_or(0.0)))
+                }
+                other => other,
+            };
+            set_nested_value(state, &change.field_name, value);
+        }
+        ChangeType::Relative => {
+            // Relative change - add to existing value
+            let value = if let Ok(delta) = change.value.parse::<f64>() {
+                let current_val = get_nested_value(state, &change.field_name)
+                    .and_then(|v| v.as_f64())
+                    .or_else(|| defaults.get(&change.field_name))
+                    .unwrap_or(0.0);
+                serde_json::json!(defaults.bound(&change.field_name, current_val + delta))
+            } else {
+                serde_json::json!(change.value)
+            };
+            set_nested_value(state, &change.field_name, value);
+        }
+        ChangeType::Custom => {
+            // The type's reducer computes the new value; a change it can't compute leaves the field as it is
+            let current_val = get_nested_value(state, &change.field_name)
+                .and_then(|v| v.as_f64())
+                .or_else(|| defaults.get(&change.field_name))
+                .unwrap_or(0.0);
+            if let Ok(value) = change_types::apply(current_val, change) {
+                set_nested_value(state, &change.field_name, serde_json::json!(defaults.bound(&change.field_name, value)));
+            }
+        }
+        ChangeType::Learn => {
+            // The value says how the fact was learned; without one, just record that it's known
+            let value = if change.value.trim().is_empty() {
+                serde_json::json!(true)
+            } else {
+                serde_json::json!(change.value)
+            };
+            set_nested_value(state, &knowledge::change_path(change), value);
+        }
+    }
+}
+
+/// Order in which markers are applied: by position, then by sequence within a position
+///
+/// Creation time and ID break any remaining ties, so the order never depends on
+/// HashMap iteration order.
+pub fn compare_markers(a: &Marker, b: &Marker) -> Ordering {
+    a.position
+        .cmp(&b.position)
+        .then_with(|| a.sequence.cmp(&b.sequence))
+        .then_with(|| a.created_at.cmp(&b.created_at))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+/// Replay markers in application order, starting from an empty state
+pub fn compute_state<'a>(markers: impl IntoIterator<Item = &'a Marker>) -> EntityState {
+    compute_state_with_defaults(markers, &FieldDefaults::default())
+}
+
+/// Replay markers in application order, with field defaults for relative changes
+pub fn compute_state_with_defaults<'a>(
+    markers: impl IntoIterator<Item = &'a Marker>,
+    defaults: &FieldDefaults,
+) -> EntityState {
+    // TODO markers are reminders, not story events
+    let mut relevant_markers: Vec<&Marker> = markers.into_iter().filter(|m| !m.todo).collect();
+
+    relevant_markers.sort_by(|a, b| compare_markers(a, b));
+
+    // Start with empty state (use Map for nested structure support)
+    let mut current_state = EntityState::new();
+
+    for marker in relevant_markers {
+        for change in &marker.changes {
+            apply_change(&mut current_state, change, defaults);
+        }
+    }
+
+    current_state
+}
+
+/// Compute an entity's state at a position (markers at the position are included)
+pub fn entity_state_at(markers: &HashMap<String, Marker>, entity_id: &str, position: usize) -> EntityState {
+    compute_state(
+        markers
+            .values()
+            .filter(|m| m.entity_id == entity_id && m.position <= position),
+    )
+}
+
+/// Like `entity_state_at`, honoring the entity's field defaults
+pub fn entity_state_with_defaults(markers: &HashMap<String, Marker>, entity: &Entity, position: usize) -> EntityState {
+    compute_state_with_defaults(
+        markers
+            .values()
+            .filter(|m| m.entity_id == entity.id && m.position <= position),
+        &field_defaults(entity),
+    )
+}
+
+/// Fill in defaults for fields declared on the entity but not set in the state (for sheets)
+pub fn fill_defaults(state: &mut EntityState, entity: &Entity) {
+    for field in &entity.fields {
+        let Some(default) = entity.field_metadata.get(field).and_then(|m| m.default_value.as_ref()) else {
+            continue;
+        };
+        if get_nested_value(state, field).is_none() {
+            set_nested_value(state, field, parse_value(default));
+        }
+    }
+}
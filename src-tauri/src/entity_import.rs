@@ -0,0 +1,300 @@
+//! QuestScribe - Entity Definition Import
+//!
+//! Loads a cast of entities from a YAML or JSON file, so worldbuilders can
+//! define dozens of characters in a text editor. A file is either a list of
+//! entities or an object with an `entities` list:
+//!
+//! ```yaml
+//! entities:
+//!   - name: Mira
+//!     color: "#4A90D9"
+//!     kind: character         # or location
+//!     fields:
+//!       - Level                 # just a field name
+//!       - name: stats.HP
+//!         type: number          # number, text, or boolean
+//!         default: 20
+//! ```
+//!
+//! Entities whose name matches an existing entity (case-insensitive) are
+//! updated: missing fields are added, and the color and field types are replaced
+//! when given. For entities created by the import, field defaults become one
+//! starting marker at the beginning of the document.
+
+use crate::colors;
+use crate::dates;
+use crate::mutations::{self, EntityUpdate, MutationContext, NewEntity, NewMarker};
+use crate::state::{ChangeType, Entity, EntityKind, FieldChange, FieldMetadata, FieldType, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Position of starting markers: the beginning of the first paragraph
+pub const START_POSITION: usize = 1;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum EntityFile {
+    Wrapped { entities: Vec<EntityDefinition> },
+    List(Vec<EntityDefinition>),
+}
+
+/// One entity to create or update (also produced by the importers in tool_import.rs)
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntityDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub kind: Option<EntityKind>,
+    #[serde(default)]
+    pub fields: Vec<FieldEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum FieldEntry {
+    Name(String),
+    Definition {
+        name: String,
+        #[serde(default, rename = "type")]
+        field_type: Option<FieldType>,
+        #[serde(default)]
+        default: Option<serde_json::Value>,
+    },
+}
+
+struct FieldDefinition {
+    name: String,
+    field_type: Option<FieldType>,
+    default: Option<String>,
+}
+
+/// What an import created or changed
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityImport {
+    pub created: Vec<Entity>,
+    pub updated: Vec<Entity>,
+    pub markers: Vec<Marker>, // Starting markers; the editor still has to place their nodes
+}
+
+/// Parse an entity definition file, choosing YAML or JSON by extension
+fn parse_file(path: &Path, text: &str) -> Result<Vec<EntityDefinition>, String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    let file: EntityFile = match extension.as_str() {
+        "yaml" | "yml" => serde_yaml::from_str(text)
+            .map_err(|e| format!("Failed to parse YAML: {}", e))?,
+        "json" => serde_json::from_str(text)
+            .map_err(|e| format!("Failed to parse JSON: {}", e))?,
+        _ => return Err(format!("Unsupported entity file format: {}", extension)),
+    };
+
+    Ok(match file {
+        EntityFile::Wrapped { entities } => entities,
+        EntityFile::List(entities) => entities,
+    })
+}
+
+fn default_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// Check a field's default against its declared type
+fn validate_default(field: &FieldDefinition) -> Result<(), String> {
+    let (Some(field_type), Some(default)) = (field.field_type, &field.default) else {
+        return Ok(());
+    };
+
+    if !field_type.accepts(default) {
+        let type_name = format!("{:?}", field_type).to_lowercase();
+        return Err(format!("Default \"{}\" of field \"{}\" is not a valid {}", default, field.name, type_name));
+    }
+
+    Ok(())
+}
+
+fn field_definitions(definition: &EntityDefinition) -> Result<Vec<FieldDefinition>, String> {
+    let mut fields = Vec::new();
+    let mut seen = HashSet::new();
+
+    for entry in &definition.fields {
+        let field = match entry {
+            FieldEntry::Name(name) => FieldDefinition {
+                name: name.trim().to_string(),
+                field_type: None,
+                default: None,
+            },
+            FieldEntry::Definition { name, field_type, default } => FieldDefinition {
+                name: name.trim().to_string(),
+                field_type: *field_type,
+                default: default.as_ref().map(default_text),
+            },
+        };
+
+        if field.name.is_empty() {
+            return Err("Field names can't be empty".to_string());
+        }
+        if !seen.insert(field.name.clone()) {
+            return Err(format!("Field \"{}\" is listed twice", field.name));
+        }
+        validate_default(&field)?;
+
+        fields.push(field);
+    }
+
+    Ok(fields)
+}
+
+/// Import entity definitions from the file at `path` (already read into `text`)
+///
+/// The whole file is validated before any entity is touched.
+pub fn import_entities(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    path: &Path,
+    text: &str,
+) -> Result<EntityImport, String> {
+    let definitions = parse_file(path, text)?;
+    import_definitions(entities, markers, context, &definitions)
+}
+
+/// Create or update entities from parsed definitions, validating all of them first
+pub fn import_definitions(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    definitions: &[EntityDefinition],
+) -> Result<EntityImport, String> {
+    let mut errors = Vec::new();
+    let mut names = HashSet::new();
+    let mut validated = Vec::with_capacity(definitions.len());
+    for definition in definitions {
+        let name = definition.name.trim();
+        if name.is_empty() {
+            errors.push("An entity has no name".to_string());
+            continue;
+        }
+        if !names.insert(name.to_lowercase()) {
+            errors.push(format!("{}: defined twice", name));
+        }
+        let color = match definition.color.as_deref().map(colors::parse_color).transpose() {
+            Ok(color) => color,
+            Err(e) => {
+                errors.push(format!("{}: {}", name, e));
+                None
+            }
+        };
+        match field_definitions(definition) {
+            Ok(fields) => validated.push((name, color, definition.kind, fields)),
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let now = dates::now();
+    let mut result = EntityImport {
+        created: Vec::new(),
+        updated: Vec::new(),
+        markers: Vec::new(),
+    };
+
+    for (name, color, kind, fields) in validated {
+        let existing = entities
+            .values()
+            .find(|e| e.name.trim().eq_ignore_ascii_case(name))
+            .map(|e| e.id.clone());
+        let is_new = existing.is_none();
+
+        let entity_id = match existing {
+            Some(id) => id,
+            None => mutations::create_entity(
+                entities,
+                context,
+                NewEntity { name: name.to_string(), color: color.clone(), kind },
+            )?
+            .id,
+        };
+
+        // Recoloring an existing entity recolors its markers too
+        if let Some(color) = color.filter(|_| !is_new) {
+            mutations::update_entity(
+                entities,
+                markers,
+                EntityUpdate { entity_id: entity_id.clone(), name: None, color: Some(color) },
+            )?;
+        }
+
+        let entity = entities.get_mut(&entity_id).ok_or("Entity not found")?;
+        for field in &fields {
+            if !entity.fields.contains(&field.name) {
+                entity.fields.push(field.name.clone());
+            }
+            let metadata = entity
+                .field_metadata
+                .entry(field.name.clone())
+                .or_insert(FieldMetadata {
+                    created_at: now,
+                    last_modified: now,
+                    field_type: None,
+                    default_value: None,
+                });
+            if field.field_type.is_some() {
+                metadata.field_type = field.field_type;
+                metadata.last_modified = now;
+            }
+        }
+        let entity = entity.clone();
+
+        // Starting values for new entities (re-imports don't stack more starting markers)
+        let changes: Vec<FieldChange> = fields
+            .iter()
+            .filter_map(|field| {
+                field.default.as_ref().map(|default| FieldChange {
+                    field_name: field.name.clone(),
+                    change_type: ChangeType::Absolute,
+                    value: default.clone(),
+                    custom: None,
+                })
+            })
+            .collect();
+
+        if is_new && !changes.is_empty() {
+            let marker = mutations::insert_marker(
+                entities,
+                markers,
+                context,
+                NewMarker {
+                    position: START_POSITION,
+                    entity_id: entity_id.clone(),
+                    changes,
+                    visual: None,
+                    description: None,
+                    tags: None,
+                    story_time: None,
+                    todo: false,
+                },
+            )?;
+            result.markers.push(marker);
+        }
+
+        if is_new {
+            result.created.push(entities.get(&entity_id).cloned().unwrap_or(entity));
+        } else {
+            result.updated.push(entity);
+        }
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,191 @@
+//! QuestScribe - Entity Packs
+//!
+//! An entity pack (`.qsent`) bundles selected entities without any document
+//! content, for sharing character kits between authors or carrying a cast into
+//! the next book. Each packed entity keeps its fields, field metadata, color,
+//! and portrait, plus a template: its state at the chosen position, as a list of
+//! absolute changes.
+//!
+//! Importing creates new entities (with new IDs); each template becomes a
+//! starting marker at the beginning of the document. Entities whose name is
+//! already taken are skipped rather than duplicated.
+
+use crate::base64;
+use crate::engine;
+use crate::entity_import::START_POSITION;
+use crate::ids;
+use crate::mutations::{self, MutationContext, NewMarker};
+use crate::state::{Entity, FieldChange, Marker, Portrait};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Identifies entity pack files
+pub const FORMAT_NAME: &str = "questscribe-entities";
+
+/// Incremented on breaking changes to the pack format
+pub const FORMAT_VERSION: u32 = 1;
+
+// Portraits are shown small; keep packs and documents a reasonable size
+const MAX_PORTRAIT_BYTES: usize = 2 * 1024 * 1024;
+const PORTRAIT_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityPack {
+    pub format: String,
+    pub format_version: u32,
+    pub entities: Vec<PackedEntity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackedEntity {
+    pub entity: Entity,
+    #[serde(default)]
+    pub template: Vec<FieldChange>, // Starting state as absolute changes
+}
+
+/// What importing a pack created
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityPackImport {
+    pub created: Vec<Entity>,
+    pub skipped: Vec<String>, // Names that already exist in the document
+    pub markers: Vec<Marker>, // Starting markers; the editor still has to place their nodes
+}
+
+/// Read an image file as a portrait
+pub fn load_portrait(path: &Path) -> Result<Portrait, String> {
+    let mime_type = match path.extension().and_then(|s| s.to_str()).map(|s| s.to_lowercase()).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        _ => return Err("Portraits must be PNG, JPEG, or WebP images".to_string()),
+    };
+
+    let bytes = fs::read(path)
+        .map_err(|e| format!("Failed to read portrait: {}", e))?;
+    if bytes.len() > MAX_PORTRAIT_BYTES {
+        return Err(format!("Portrait is larger than {} MB", MAX_PORTRAIT_BYTES / (1024 * 1024)));
+    }
+
+    Ok(Portrait {
+        mime_type: mime_type.to_string(),
+        data: base64::encode(&bytes),
+    })
+}
+
+/// Check a portrait from outside the document (e.g., a pack) against the limits `load_portrait` applies
+pub fn check_portrait(portrait: &Portrait) -> Result<(), String> {
+    if !PORTRAIT_TYPES.contains(&portrait.mime_type.as_str()) {
+        return Err("Portraits must be PNG, JPEG, or WebP images".to_string());
+    }
+    let bytes = base64::decode(&portrait.data).ok_or("Portrait data is not valid base64")?;
+    if bytes.len() > MAX_PORTRAIT_BYTES {
+        return Err(format!("Portrait is larger than {} MB", MAX_PORTRAIT_BYTES / (1024 * 1024)));
+    }
+    Ok(())
+}
+
+/// Pack entities with their state at `position` as the template
+pub fn build_pack(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    entity_ids: &[String],
+    position: usize,
+) -> Result<EntityPack, String> {
+    let mut packed = Vec::with_capacity(entity_ids.len());
+
+    for entity_id in entity_ids {
+        let entity = entities
+            .get(entity_id)
+            .ok_or_else(|| format!("Entity not found: {}", entity_id))?;
+
+        let state = engine::entity_state_with_defaults(markers, entity, position);
+        let mut template = Vec::new();
+        engine::flatten_state_to_changes(&state, String::new(), &mut template);
+
+        packed.push(PackedEntity {
+            entity: entity.clone(),
+            template,
+        });
+    }
+
+    Ok(EntityPack {
+        format: FORMAT_NAME.to_string(),
+        format_version: FORMAT_VERSION,
+        entities: packed,
+    })
+}
+
+/// Parse a pack file, rejecting other formats and newer versions
+pub fn parse_pack(json: &str) -> Result<EntityPack, String> {
+    let pack: EntityPack = serde_json::from_str(json)
+        .map_err(|e| format!("Failed to parse entity pack: {}", e))?;
+
+    if pack.format != FORMAT_NAME {
+        return Err("This file is not a QuestScribe entity pack".to_string());
+    }
+    if pack.format_version > FORMAT_VERSION {
+        return Err("This entity pack was made by a newer version of QuestScribe".to_string());
+    }
+
+    Ok(pack)
+}
+
+/// Add a pack's entities to the document
+///
+/// Fails on an invalid portrait or template, possibly after adding some entities;
+/// callers work on copies of the maps.
+pub fn import_pack(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    pack: EntityPack,
+) -> Result<EntityPackImport, String> {
+    let mut result = EntityPackImport {
+        created: Vec::new(),
+        skipped: Vec::new(),
+        markers: Vec::new(),
+    };
+
+    for packed in pack.entities {
+        let name_taken = entities
+            .values()
+            .any(|e| e.name.trim().eq_ignore_ascii_case(packed.entity.name.trim()));
+        if name_taken {
+            result.skipped.push(packed.entity.name);
+            continue;
+        }
+
+        if let Some(portrait) = &packed.entity.portrait {
+            check_portrait(portrait).map_err(|e| format!("{}: {}", packed.entity.name, e))?;
+        }
+
+        let mut entity = packed.entity;
+        entity.id = ids::new_id();
+        entities.insert(entity.id.clone(), entity.clone());
+
+        if !packed.template.is_empty() {
+            let marker = mutations::insert_marker(
+                entities,
+                markers,
+                context,
+                NewMarker {
+                    position: START_POSITION,
+                    entity_id: entity.id.clone(),
+                    changes: packed.template,
+                    visual: None,
+                    description: None,
+                    tags: None,
+                    story_time: None,
+                    todo: false,
+                },
+            )?;
+            result.markers.push(marker);
+        }
+
+        result.created.push(entities.get(&entity.id).cloned().unwrap_or(entity));
+    }
+
+    Ok(result)
+}
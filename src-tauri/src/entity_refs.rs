@@ -0,0 +1,81 @@
+//! QuestScribe - Entity Reference Nodes
+//!
+//! An entity mention node is an inline leaf in the editor that refers to an
+//! entity by ID instead of spelling out its name:
+//!
+//! ```json
+//! { "type": "entity_mention", "attrs": { "id": "<entity ID>", "label": "Katniss" } }
+//! ```
+//!
+//! `label` is the name when the node was last updated; it's what plain-text
+//! views of the content (word counts, excerpts, chapter titles) read. Renaming
+//! an entity relabels its nodes in the stored content, and exports render the
+//! entity's current name, so a reference never goes stale the way a typed name
+//! does. A node whose entity was deleted keeps its last label.
+//!
+//! References count as mentions (see mentions.rs), without any name matching.
+
+use crate::state::Entity;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Node type of entity mentions
+pub const ENTITY_MENTION: &str = "entity_mention";
+
+/// Entity ID of a mention node (None for other nodes)
+pub fn mention_entity_id(node: &Value) -> Option<&str> {
+    if node.get("type").and_then(|t| t.as_str()) != Some(ENTITY_MENTION) {
+        return None;
+    }
+    node.get("attrs").and_then(|a| a.get("id")).and_then(|id| id.as_str())
+}
+
+/// Stored label of a mention node
+pub fn mention_label(node: &Value) -> &str {
+    node.get("attrs").and_then(|a| a.get("label")).and_then(|l| l.as_str()).unwrap_or("")
+}
+
+/// Set the label of every mention of an entity, returning how many were changed
+pub fn relabel(node: &mut Value, entity_id: &str, name: &str) -> usize {
+    if mention_entity_id(node) == Some(entity_id) {
+        if mention_label(node) == name {
+            return 0;
+        }
+        node["attrs"]["label"] = json!(name);
+        return 1;
+    }
+
+    node.get_mut("content")
+        .and_then(|c| c.as_array_mut())
+        .map(|children| children.iter_mut().map(|child| relabel(child, entity_id, name)).sum())
+        .unwrap_or(0)
+}
+
+/// Replace every mention node with a text node of the entity's current name (keeping its
+/// marks), for export. Positions after a mention shift, so only do this to a copy that's
+/// about to be rendered.
+pub fn resolve_for_export(node: &mut Value, entities: &HashMap<String, Entity>) {
+    let Some(children) = node.get_mut("content").and_then(|c| c.as_array_mut()) else {
+        return;
+    };
+
+    for child in children.iter_mut() {
+        let Some(entity_id) = mention_entity_id(child) else {
+            resolve_for_export(child, entities);
+            continue;
+        };
+        let name = entities
+            .get(entity_id)
+            .map(|e| e.name.clone())
+            .unwrap_or_else(|| mention_label(child).to_string());
+
+        let mut text = json!({ "type": "text", "text": name });
+        if let Some(marks) = child.get("marks") {
+            text["marks"] = marks.clone();
+        }
+        *child = text;
+    }
+
+    // ProseMirror doesn't allow empty text nodes (a deleted entity without a label)
+    children.retain(|child| child.get("text").and_then(|t| t.as_str()) != Some(""));
+}
@@ -0,0 +1,155 @@
+//! QuestScribe - Export Validation
+//!
+//! A dry run of a manuscript export: what the exporter would drop or mangle,
+//! found before any file is written. The exporter keeps top-level paragraphs
+//! and headings with their text, bold and italic, and endnote references;
+//! everything else is reported:
+//!
+//! - **Unsupported nodes**: lists, block quotes, images, hard breaks, ...
+//! - **Unsupported marks**: formatting other than bold and italic (RTF and DOCX;
+//!   plain text keeps no formatting by design)
+//! - **Missing images**: local image files that don't exist
+//! - **Problematic characters**: control characters (which make a DOCX file
+//!   unreadable), and characters outside the Basic Multilingual Plane in RTF,
+//!   which many readers show as "?"
+//! - **Open TODOs**: TODO markers still in the document (their changes are
+//!   left out of every computed state, so the manuscript isn't final)
+
+use crate::positions;
+use crate::engine;
+use crate::settings::ExportFormat;
+use crate::state::Marker;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Marks the RTF and DOCX writers render
+const SUPPORTED_MARKS: &[&str] = &["strong", "em", "code"];
+/// Inline nodes the writers keep (markers become endnote references or are left out)
+const SUPPORTED_INLINE: &[&str] = &["text", "marker", "entity_mention", "hard_break"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportIssueKind {
+    UnsupportedNode,
+    UnsupportedMark,
+    MissingImage,
+    ProblematicCharacter,
+    OpenTodo,
+}
+
+/// One kind of problem (e.g., "bullet_list" nodes) and everywhere it occurs
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportIssue {
+    pub kind: ExportIssueKind,
+    pub detail: String, // Node or mark type, image source, character (e.g., "U+0007"), or TODO note
+    pub positions: Vec<usize>,
+}
+
+struct Issues(Vec<ExportIssue>);
+
+impl Issues {
+    // Record an occurrence, grouped with earlier ones of the same kind and detail
+    fn add(&mut self, kind: ExportIssueKind, detail: String, position: usize) {
+        match self.0.iter_mut().find(|i| i.kind == kind && i.detail == detail) {
+            Some(issue) => issue.positions.push(position),
+            None => self.0.push(ExportIssue { kind, detail, positions: vec![position] }),
+        }
+    }
+}
+
+fn node_type(node: &Value) -> &str {
+    node.get("type").and_then(|t| t.as_str()).unwrap_or("")
+}
+
+// Whether the character causes trouble in the format
+fn character_problem(ch: char, format: ExportFormat) -> bool {
+    let control = ch.is_control() && !matches!(ch, '\t' | '\n' | '\r');
+    match format {
+        ExportFormat::Docx => control || matches!(ch, '\u{FFFE}' | '\u{FFFF}'),
+        ExportFormat::Rtf => control || ch.len_utf16() > 1,
+        ExportFormat::Txt => control,
+    }
+}
+
+fn check_text(node: &Value, pos: usize, format: ExportFormat, issues: &mut Issues) {
+    if format != ExportFormat::Txt {
+        for mark in node.get("marks").and_then(|m| m.as_array()).into_iter().flatten() {
+            let mark_type = node_type(mark);
+            if !SUPPORTED_MARKS.contains(&mark_type) {
+                issues.add(ExportIssueKind::UnsupportedMark, mark_type.to_string(), pos);
+            }
+        }
+    }
+
+    let text = node.get("text").and_then(|t| t.as_str()).unwrap_or("");
+    let mut offset = 0;
+    for ch in text.chars() {
+        if character_problem(ch, format) {
+            issues.add(ExportIssueKind::ProblematicCharacter, format!("U+{:04X}", ch as u32), pos + offset);
+        }
+        offset += ch.len_utf16();
+    }
+}
+
+// Whether a local image file is missing (remote and inline images aren't checked)
+fn image_missing(src: &str, base_dir: Option<&Path>) -> bool {
+    if src.is_empty() || ["http://", "https://", "data:"].iter().any(|scheme| src.starts_with(scheme)) {
+        return false;
+    }
+    let path = Path::new(src.strip_prefix("file://").unwrap_or(src));
+    match base_dir {
+        Some(base) if path.is_relative() => !base.join(path).exists(),
+        _ => !path.exists(),
+    }
+}
+
+/// Everything an export of the document to the format would lose, and its open TODOs
+///
+/// Relative image paths are resolved against `base_dir` (the document's folder).
+pub fn validate(
+    doc: &Value,
+    markers: &HashMap<String, Marker>,
+    format: ExportFormat,
+    base_dir: Option<&Path>,
+) -> Vec<ExportIssue> {
+    let mut issues = Issues(Vec::new());
+
+    let mut pos = 0;
+    for block in doc.get("content").and_then(|c| c.as_array()).into_iter().flatten() {
+        let block_type = node_type(block);
+        if matches!(block_type, "paragraph" | "heading") {
+            let mut inline_pos = pos + 1;
+            for inline in block.get("content").and_then(|c| c.as_array()).into_iter().flatten() {
+                let inline_type = node_type(inline);
+                if inline_type == "text" {
+                    check_text(inline, inline_pos, format, &mut issues);
+                } else if !SUPPORTED_INLINE.contains(&inline_type) {
+                    issues.add(ExportIssueKind::UnsupportedNode, inline_type.to_string(), inline_pos);
+                }
+                inline_pos += positions::node_size(inline);
+            }
+        } else {
+            issues.add(ExportIssueKind::UnsupportedNode, block_type.to_string(), pos);
+        }
+        pos += positions::node_size(block);
+    }
+
+    positions::for_each_node(doc, |node, pos| {
+        if node_type(node) == "image" {
+            let src = node.get("attrs").and_then(|a| a.get("src")).and_then(|s| s.as_str()).unwrap_or("");
+            if image_missing(src, base_dir) {
+                issues.add(ExportIssueKind::MissingImage, src.to_string(), pos);
+            }
+        }
+    });
+
+    let mut todos: Vec<&Marker> = markers.values().filter(|m| m.todo).collect();
+    todos.sort_by(|a, b| engine::compare_markers(a, b));
+    for marker in todos {
+        issues.add(ExportIssueKind::OpenTodo, marker.description.trim().to_string(), marker.position);
+    }
+
+    issues.0
+}
@@ -0,0 +1,79 @@
+//! QuestScribe - Export Path Templates
+//!
+//! Export paths may contain tokens that are filled in when the file is written,
+//! so repeated or automated exports get tidy, distinct names:
+//!
+//! - `{title}`: the document title (see `DocumentPreferences::title`)
+//! - `{date}`: the export date, e.g. "2024-03-09"
+//! - `{time}`: the export time, e.g. "1430"
+//! - `{wordcount}`: words in the exported text
+//!
+//! e.g. `~/Exports/{title}-{date}-{wordcount}.docx`. Token values never contain
+//! path separators or characters that are invalid in file names.
+
+use crate::dates;
+
+/// Values of the tokens for one export
+pub struct PathTokens {
+    pub title: String,
+    pub timestamp: i64,
+    pub utc_offset_minutes: i32,
+    pub word_count: usize,
+}
+
+// Replace characters that aren't allowed in file names on some platform
+fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .trim()
+        .chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) || c.is_control() { '-' } else { c })
+        .collect();
+    cleaned.trim_matches('.').to_string()
+}
+
+fn token_value(name: &str, tokens: &PathTokens) -> Option<String> {
+    let local = tokens.timestamp + tokens.utc_offset_minutes as i64 * 60;
+    match name {
+        "title" => Some(sanitize(&tokens.title)),
+        "date" => Some(dates::format_day(dates::day_number(tokens.timestamp, tokens.utc_offset_minutes))),
+        "time" => {
+            let minutes = local.rem_euclid(86_400) / 60;
+            Some(format!("{:02}{:02}", minutes / 60, minutes % 60))
+        }
+        "wordcount" => Some(tokens.word_count.to_string()),
+        _ => None,
+    }
+}
+
+/// Fill in the tokens of an export path
+///
+/// Braces around anything other than a token name are kept as they are.
+pub fn resolve(template: &str, tokens: &PathTokens) -> Result<String, String> {
+    let mut resolved = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        resolved.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let name_len = after.find('}').filter(|len| {
+            *len > 0 && after[..*len].chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+
+        match name_len {
+            Some(len) => {
+                let name = &after[..len];
+                let value = token_value(name, tokens)
+                    .ok_or_else(|| format!("Unknown export path token: {{{}}}", name))?;
+                resolved.push_str(&value);
+                rest = &after[len + 1..];
+            }
+            None => {
+                resolved.push('{');
+                rest = after;
+            }
+        }
+    }
+    resolved.push_str(rest);
+
+    Ok(resolved)
+}
@@ -0,0 +1,532 @@
+//! QuestScribe - Manuscript Exporters
+//!
+//! Each export format is an `Exporter`: a name, the file extensions it writes,
+//! the options only it understands, and a function rendering the manuscript
+//! (paragraphs built from the document, with endnotes, sheets and front and
+//! back matter already added) to the file's bytes. Exports pick the exporter
+//! by the file's extension, and `list_export_formats` describes them all to
+//! the export dialog, so a new format is one more implementation added to
+//! `EXPORTERS`.
+
+use crate::dates;
+use crate::markdown;
+use crate::preferences::ExportStyle;
+use crate::state::{Entity, Marker};
+use crate::track_changes;
+use docx_rs::*;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+
+/// A text run with formatting
+#[derive(Clone)]
+pub struct TextRun {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub note: bool, // Endnote reference number (rendered superscript)
+    pub monospace: bool, // Code text (e.g., status windows), in MONOSPACE_FONT; may hold line breaks ("\n")
+    pub suggestion: Option<track_changes::SuggestionKind>, // Pending tracked change (deletions struck through, insertions underlined)
+}
+
+/// A paragraph with its type and runs
+pub struct FormattedParagraph {
+    pub node_type: String, // "paragraph" or "heading"
+    pub level: Option<u32>, // heading level (1-6)
+    pub runs: Vec<TextRun>,
+    pub rtl: bool, // right-to-left paragraph direction (Hebrew, Arabic, ...)
+    pub marker_anchors: Vec<(usize, String)>, // Marker IDs, each with the index of the run it comes before
+    pub align: Option<Alignment>, // None = the direction's (left, or right for RTL); title page lines are centered
+    pub indent: u32, // Indentation level, each INDENT_TWIPS deeper from the paragraph's leading side
+    pub page_break: bool, // Starts a new page
+}
+
+/// Paragraph alignment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+    Justify,
+}
+
+impl Alignment {
+    /// Alignment of a ProseMirror `textAlign` attribute
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Alignment::Left),
+            "center" => Some(Alignment::Center),
+            "right" => Some(Alignment::Right),
+            "justify" => Some(Alignment::Justify),
+            _ => None,
+        }
+    }
+}
+
+/// Font of monospace runs
+pub const MONOSPACE_FONT: &str = "Courier New";
+
+/// Width of one indentation level, in twips (half an inch; the unit RTF and DOCX use)
+pub const INDENT_TWIPS: u32 = 720;
+/// Deepest indentation level exported
+pub const MAX_INDENT: u32 = 8;
+
+/// What an exporter renders
+pub struct Manuscript<'a> {
+    pub paragraphs: &'a [FormattedParagraph],
+    pub style: &'a ExportStyle,
+    pub entities: &'a HashMap<String, Entity>, // Without redacted entities
+    pub markers: &'a HashMap<String, Marker>, // Without redacted markers
+    pub options: &'a Map<String, Value>, // Format options, by name (see Exporter::options)
+    pub author: Option<&'a str>, // The document's author (from its book matter), credited with DOCX comments
+}
+
+impl Manuscript<'_> {
+    /// A yes/no format option (false when not given)
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.get(name).and_then(|v| v.as_bool()).unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionKind {
+    Bool,
+}
+
+/// An option only some formats understand
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+    pub description: &'static str,
+}
+
+pub trait Exporter: Sync {
+    /// Name shown in the export dialog
+    fn name(&self) -> &'static str;
+    /// File extensions, lowercase without the dot; the first is the usual one
+    fn extensions(&self) -> &'static [&'static str];
+    /// Options this format understands besides the common ones (endnotes, redaction, range)
+    fn options(&self) -> Vec<ExportOption> {
+        Vec::new()
+    }
+    fn render(&self, manuscript: &Manuscript) -> Result<Vec<u8>, String>;
+}
+
+/// An export format, as listed to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportFormatInfo {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+    pub options: Vec<ExportOption>,
+}
+
+/// Every export format
+pub const EXPORTERS: &[&dyn Exporter] = &[&TxtExporter, &RtfExporter, &DocxExporter];
+
+/// The exporter writing files with an extension (case-insensitive)
+pub fn for_extension(extension: &str) -> Option<&'static dyn Exporter> {
+    let extension = extension.to_lowercase();
+    EXPORTERS.iter().copied().find(|e| e.extensions().contains(&extension.as_str()))
+}
+
+pub fn list_formats() -> Vec<ExportFormatInfo> {
+    EXPORTERS
+        .iter()
+        .map(|e| ExportFormatInfo { name: e.name(), extensions: e.extensions(), options: e.options() })
+        .collect()
+}
+
+// Escape text for RTF: control characters plus \uN escapes for everything outside ASCII
+// (the document is declared \ansi, so raw non-ASCII bytes would be misread)
+pub fn escape_rtf_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '{' => escaped.push_str("\\{"),
+            '}' => escaped.push_str("\\}"),
+            '\t' => escaped.push_str("\\tab "),
+            '\n' => escaped.push_str("\\line "),
+            c if c.is_ascii() => escaped.push(c),
+            c => {
+                // \u takes a signed 16-bit value; characters outside the BMP become surrogate pairs.
+                // The trailing '?' is the fallback for readers without Unicode support (\uc1).
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{}?", *unit as i16));
+                }
+            }
+        }
+    }
+
+    escaped
+}
+
+/// Plain text of a paragraph (endnote references as "[N]", suggested deletions as "[-text-]"
+/// and insertions as "{+text+}")
+pub fn paragraph_plain_text(para: &FormattedParagraph) -> String {
+    para.runs
+        .iter()
+        .map(|r| match r.suggestion {
+            _ if r.note => format!("[{}]", r.text),
+            Some(track_changes::SuggestionKind::Deletion) => format!("[-{}-]", r.text),
+            Some(track_changes::SuggestionKind::Insertion) => format!("{{+{}+}}", r.text),
+            None => r.text.clone(),
+        })
+        .collect()
+}
+
+// Attach a marker's description to a DOCX paragraph as a Word comment at the marker's
+// place, credited to the document's author (or QuestScribe) and naming the marker's entity
+fn add_marker_comment(paragraph: Paragraph, comment_id: usize, marker: &Marker, manuscript: &Manuscript) -> Paragraph {
+    let author = manuscript.author.unwrap_or("QuestScribe");
+    let entity = manuscript
+        .entities
+        .get(&marker.entity_id)
+        .map_or(marker.entity_id.as_str(), |e| e.name.as_str());
+    let text = format!("{}: {}", entity, markdown::to_plain_text(&marker.description));
+    let comment = Comment::new(comment_id)
+        .author(author)
+        .date(dates::format_utc(marker.modified_at))
+        .add_paragraph(Paragraph::new().add_run(Run::new().add_text(text)));
+
+    paragraph.add_comment_start(comment).add_comment_end(comment_id)
+}
+
+pub struct TxtExporter;
+
+impl Exporter for TxtExporter {
+    fn name(&self) -> &'static str {
+        "Plain text"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["txt"]
+    }
+
+    fn render(&self, manuscript: &Manuscript) -> Result<Vec<u8>, String> {
+        let plain_text = manuscript
+            .paragraphs
+            .iter()
+            .map(paragraph_plain_text)
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        Ok(plain_text.into_bytes())
+    }
+}
+
+pub struct RtfExporter;
+
+impl Exporter for RtfExporter {
+    fn name(&self) -> &'static str {
+        "Rich Text Format"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["rtf"]
+    }
+
+    fn render(&self, manuscript: &Manuscript) -> Result<Vec<u8>, String> {
+        let style = manuscript.style;
+        let body_size = style.body_half_points();
+        let mut rtf_content = format!(
+            "{{\\rtf1\\ansi\\deff0\\uc1\n{{\\fonttbl{{\\f0 {};}}{{\\f1 {};}}}}\n\\f0\\fs{}\n",
+            escape_rtf_text(&style.font_family),
+            MONOSPACE_FONT,
+            body_size
+        );
+
+        for para in manuscript.paragraphs {
+            if para.page_break {
+                rtf_content.push_str("\\page\n");
+            }
+
+            // Paragraph direction, alignment and indentation (reset with \pard so they don't leak
+            // into the next paragraph); RTL paragraphs are right-aligned and indented from the right.
+            // \pard leaves character properties alone, so each run sets its direction below.
+            if para.rtl {
+                rtf_content.push_str("\\pard\\rtlpar ");
+            } else {
+                rtf_content.push_str("\\pard\\ltrpar ");
+            }
+            match para.align.or(para.rtl.then_some(Alignment::Right)) {
+                Some(Alignment::Left) => rtf_content.push_str("\\ql "),
+                Some(Alignment::Center) => rtf_content.push_str("\\qc "),
+                Some(Alignment::Right) => rtf_content.push_str("\\qr "),
+                Some(Alignment::Justify) => rtf_content.push_str("\\qj "),
+                None => {}
+            }
+            if para.indent > 0 {
+                let side = if para.rtl { "ri" } else { "li" };
+                rtf_content.push_str(&format!("\\{}{} ", side, para.indent * INDENT_TWIPS));
+            }
+
+            // Handle headings with larger font size
+            if para.node_type == "heading" {
+                rtf_content.push_str(&format!("\\fs{} \\b ", style.heading_half_points(para.level)));
+            }
+
+            // Process each text run with its own formatting
+            for run in &para.runs {
+                rtf_content.push_str(if para.rtl { "\\rtlch " } else { "\\ltrch " });
+                if run.note {
+                    rtf_content.push_str(&format!("{{\\super {}}}", escape_rtf_text(&run.text)));
+                    continue;
+                }
+                if run.bold {
+                    rtf_content.push_str("\\b ");
+                }
+                if run.italic {
+                    rtf_content.push_str("\\i ");
+                }
+                if run.monospace {
+                    rtf_content.push_str("\\f1 ");
+                }
+                match run.suggestion {
+                    Some(track_changes::SuggestionKind::Deletion) => rtf_content.push_str("\\strike "),
+                    Some(track_changes::SuggestionKind::Insertion) => rtf_content.push_str("\\ul "),
+                    None => {}
+                }
+                rtf_content.push_str(&escape_rtf_text(&run.text));
+                match run.suggestion {
+                    Some(track_changes::SuggestionKind::Deletion) => rtf_content.push_str("\\strike0 "),
+                    Some(track_changes::SuggestionKind::Insertion) => rtf_content.push_str("\\ulnone "),
+                    None => {}
+                }
+                if run.monospace {
+                    rtf_content.push_str("\\f0 ");
+                }
+                if run.italic {
+                    rtf_content.push_str("\\i0 ");
+                }
+                if run.bold {
+                    rtf_content.push_str("\\b0 ");
+                }
+            }
+
+            // Reset heading formatting
+            if para.node_type == "heading" {
+                rtf_content.push_str(&format!("\\b0 \\fs{} ", body_size));
+            }
+
+            rtf_content.push_str("\\par\n");
+            if style.blank_line_between_paragraphs {
+                rtf_content.push_str("\\par\n");
+            }
+        }
+
+        rtf_content.push('}');
+        Ok(rtf_content.into_bytes())
+    }
+}
+
+pub struct DocxExporter;
+
+impl Exporter for DocxExporter {
+    fn name(&self) -> &'static str {
+        "Word document"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["docx"]
+    }
+
+    fn options(&self) -> Vec<ExportOption> {
+        vec![ExportOption {
+            name: "marker_comments",
+            kind: OptionKind::Bool,
+            description: "Marker descriptions as Word comments",
+        }]
+    }
+
+    fn render(&self, manuscript: &Manuscript) -> Result<Vec<u8>, String> {
+        let style = manuscript.style;
+        let body_size = style.body_half_points();
+        let marker_comments = manuscript.flag("marker_comments");
+        let mut docx = Docx::new();
+        let mut comment_id = 0;
+
+        for para in manuscript.paragraphs {
+            let mut paragraph = Paragraph::new();
+
+            // Described markers become Word comments, placed before the run they precede
+            let mut comments = para
+                .marker_anchors
+                .iter()
+                .filter(|_| marker_comments)
+                .filter_map(|(run_index, id)| manuscript.markers.get(id).map(|marker| (*run_index, marker)))
+                .filter(|(_, marker)| !marker.description.trim().is_empty())
+                .peekable();
+
+            // Determine font size for headings
+            let is_heading = para.node_type == "heading";
+            let font_size = if is_heading {
+                style.heading_half_points(para.level)
+            } else {
+                body_size
+            };
+
+            // Add each text run with its own formatting
+            for (run_index, run) in para.runs.iter().enumerate() {
+                while let Some((_, marker)) = comments.next_if(|(at, _)| *at <= run_index) {
+                    comment_id += 1;
+                    paragraph = add_marker_comment(paragraph, comment_id, marker, manuscript);
+                }
+
+                // Line breaks within the run (e.g., the lines of a status window)
+                let mut text_run = Run::new();
+                for (index, line) in run.text.split('\n').enumerate() {
+                    if index > 0 {
+                        text_run = text_run.add_break(BreakType::TextWrapping);
+                    }
+                    text_run = text_run.add_text(line);
+                }
+                let font = if run.monospace { MONOSPACE_FONT } else { style.font_family.as_str() };
+                text_run = text_run
+                    .size(font_size)
+                    .fonts(RunFonts::new().ascii(font).hi_ansi(font).cs(font));
+
+                // For headings, make all text bold
+                if is_heading || run.bold {
+                    text_run = text_run.bold();
+                }
+                if run.italic {
+                    text_run = text_run.italic();
+                }
+                if run.note {
+                    text_run = text_run.vert_align(VertAlignType::SuperScript);
+                }
+                match run.suggestion {
+                    Some(track_changes::SuggestionKind::Deletion) => text_run = text_run.strike(),
+                    Some(track_changes::SuggestionKind::Insertion) => text_run = text_run.underline("single"),
+                    None => {}
+                }
+
+                paragraph = paragraph.add_run(text_run);
+            }
+            for (_, marker) in comments {
+                comment_id += 1;
+                paragraph = add_marker_comment(paragraph, comment_id, marker, manuscript);
+            }
+
+            // RTL paragraphs get their direction after packing (see `set_docx_direction`). In a
+            // bidi paragraph Word reads left and right as its start and end, so an RTL paragraph
+            // is right-aligned and indented from the right without asking for either.
+            if para.rtl {
+                paragraph = paragraph.style(RTL_PLACEHOLDER_STYLE);
+            }
+            let align = match para.align {
+                Some(Alignment::Left) if para.rtl => Some(Alignment::Right),
+                Some(Alignment::Right) if para.rtl => Some(Alignment::Left),
+                align => align,
+            };
+            match align {
+                Some(Alignment::Left) => paragraph = paragraph.align(AlignmentType::Left),
+                Some(Alignment::Center) => paragraph = paragraph.align(AlignmentType::Center),
+                Some(Alignment::Right) => paragraph = paragraph.align(AlignmentType::Right),
+                Some(Alignment::Justify) => paragraph = paragraph.align(AlignmentType::Both),
+                None => {}
+            }
+            if para.indent > 0 {
+                let twips = (para.indent * INDENT_TWIPS) as i32;
+                paragraph = paragraph.indent(Some(twips), None, None, None);
+            }
+            if para.page_break {
+                paragraph = paragraph.page_break_before(true);
+            }
+
+            docx = docx.add_paragraph(paragraph);
+        }
+
+        // Write to a buffer using Cursor for Seek trait
+        let mut buf = Cursor::new(Vec::new());
+        docx.build()
+            .pack(&mut buf)
+            .map_err(|e| format!("Failed to pack DOCX: {}", e))?;
+
+        if manuscript.paragraphs.iter().any(|p| p.rtl) {
+            return set_docx_direction(buf.into_inner());
+        }
+        Ok(buf.into_inner())
+    }
+}
+
+/// Paragraph style RTL paragraphs are built with, until `set_docx_direction` replaces it
+const RTL_PLACEHOLDER_STYLE: &str = "QuestScribeRtl";
+
+/// Paragraph properties that come after w:bidi (in schema order), up to the end of w:pPr
+const AFTER_BIDI: &[&str] = &[
+    "<w:adjustRightInd", "<w:snapToGrid", "<w:spacing", "<w:ind", "<w:contextualSpacing",
+    "<w:mirrorIndents", "<w:suppressOverlap", "<w:jc", "<w:textDirection", "<w:textAlignment",
+    "<w:textboxTightWrap", "<w:outlineLvl", "<w:divId", "<w:cnfStyle", "<w:rPr", "<w:sectPr",
+    "<w:pPrChange", "</w:pPr>",
+];
+
+/// Run properties that come after w:rtl (in schema order), up to the end of w:rPr
+const AFTER_RTL: &[&str] = &["<w:cs/", "<w:cs ", "<w:cs>", "<w:em ", "<w:lang", "<w:eastAsianLayout", "</w:rPr>"];
+
+// Insert `element` before the first of `before` in `xml` (or at the end if none is there)
+fn insert_before_first(xml: &str, before: &[&str], element: &str) -> String {
+    let at = before.iter().filter_map(|tag| xml.find(tag)).min().unwrap_or(xml.len());
+    format!("{}{}{}", &xml[..at], element, &xml[at..])
+}
+
+// Give the paragraphs built with the placeholder style w:bidi, and their runs w:rtl
+fn mark_rtl_paragraphs(xml: &str) -> String {
+    let paragraph = Regex::new(r"(?s)<w:p(?:\s[^>]*[^/])?>.*?</w:p>").expect("valid paragraph pattern");
+    let placeholder = Regex::new(&format!(r#"<w:pStyle w:val="{}"\s*/>"#, RTL_PLACEHOLDER_STYLE))
+        .expect("valid placeholder pattern");
+    let properties = Regex::new(r"(?s)<w:pPr>.*?</w:pPr>").expect("valid properties pattern");
+    let run = Regex::new(r"(?s)(<w:r(?:\s[^>]*[^/])?>)(<w:rPr>.*?</w:rPr>|<w:rPr\s*/>)?").expect("valid run pattern");
+
+    paragraph
+        .replace_all(xml, |p: &Captures| {
+            let text = &p[0];
+            if !placeholder.is_match(text) {
+                return text.to_string();
+            }
+
+            let text = placeholder.replace(text, "");
+            let text = properties.replace(&text, |pr: &Captures| insert_before_first(&pr[0], AFTER_BIDI, "<w:bidi/>"));
+            run.replace_all(&text, |r: &Captures| {
+                let run_properties = match r.get(2).map(|m| m.as_str()) {
+                    Some(rpr) if rpr.starts_with("<w:rPr>") => insert_before_first(rpr, AFTER_RTL, "<w:rtl/>"),
+                    _ => "<w:rPr><w:rtl/></w:rPr>".to_string(),
+                };
+                format!("{}{}", &r[1], run_properties)
+            })
+            .into_owned()
+        })
+        .into_owned()
+}
+
+// docx-rs can't write paragraph or run direction, so rewrite the packed document's body
+// to add them: w:bidi on RTL paragraphs, and w:rtl on their runs
+fn set_docx_direction(packed: Vec<u8>) -> Result<Vec<u8>, String> {
+    let error = |e: zip::result::ZipError| format!("Failed to set RTL direction in DOCX: {}", e);
+    let mut archive = zip::ZipArchive::new(Cursor::new(packed)).map_err(error)?;
+    let mut output = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(error)?;
+        if file.name() != "word/document.xml" {
+            output.raw_copy_file(file).map_err(error)?;
+            continue;
+        }
+
+        let mut xml = String::new();
+        file.read_to_string(&mut xml)
+            .map_err(|e| format!("Failed to read DOCX body: {}", e))?;
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        output.start_file(file.name(), options).map_err(error)?;
+        output.write_all(mark_rtl_paragraphs(&xml).as_bytes())
+            .map_err(|e| format!("Failed to write DOCX body: {}", e))?;
+    }
+
+    Ok(output.finish().map_err(error)?.into_inner())
+}
@@ -0,0 +1,321 @@
+//! QuestScribe - Formulas
+//!
+//! A small arithmetic language for values the author defines rather than the
+//! engine (e.g., custom change types, see change_types.rs):
+//!
+//! - numbers (`3`, `0.5`) and variables (`current`, `$1`)
+//! - `+ - * / % ^`, parentheses, and comparisons (`< <= > >= == !=`, 1 or 0)
+//! - functions: `min`, `max`, `abs`, `floor`, `ceil`, `round`, `sqrt`,
+//!   `clamp(x, low, high)`, and `if(condition, then, else)`
+//!
+//! e.g., `clamp(current + $1, -100, 100)`. Formulas are parsed once and can be
+//! evaluated with different variables; evaluation fails instead of producing
+//! infinities or NaN. Formulas longer than `MAX_LENGTH` or nested deeper than
+//! `MAX_DEPTH` are rejected, so parsing and evaluation can't exhaust the stack.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+/// A parsed formula
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    expr: Expr,
+}
+
+/// Longest formula source, in characters
+const MAX_LENGTH: usize = 1000;
+/// Deepest nesting of parentheses, function calls, signs, and powers
+const MAX_DEPTH: usize = 64;
+
+const FUNCTIONS: &[(&str, usize)] = &[
+    ("min", 0), // 0 = any number of arguments, at least one
+    ("max", 0),
+    ("abs", 1),
+    ("floor", 1),
+    ("ceil", 1),
+    ("round", 1),
+    ("sqrt", 1),
+    ("clamp", 3),
+    ("if", 3),
+];
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    source: &'a str,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_space(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_space();
+        self.chars.peek().copied()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.peek() == Some(expected) && self.chars.next().is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(format!("Expected \"{}\" in formula \"{}\"", expected, self.source))
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.additive()?;
+        let op = match self.peek() {
+            Some('<') => {
+                self.chars.next();
+                if self.eat('=') { Op::LessOrEqual } else { Op::Less }
+            }
+            Some('>') => {
+                self.chars.next();
+                if self.eat('=') { Op::GreaterOrEqual } else { Op::Greater }
+            }
+            Some(c @ ('=' | '!')) => {
+                self.chars.next();
+                self.expect('=')?;
+                if c == '=' { Op::Equal } else { Op::NotEqual }
+            }
+            _ => return Ok(left),
+        };
+        let right = self.additive()?;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        while let Some(c @ ('+' | '-')) = self.peek() {
+            self.chars.next();
+            let op = if c == '+' { Op::Add } else { Op::Subtract };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(c @ ('*' | '/' | '%')) = self.peek() {
+            self.chars.next();
+            let op = match c {
+                '*' => Op::Multiply,
+                '/' => Op::Divide,
+                _ => Op::Remainder,
+            };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    // Every nested expression passes through here, so this is where depth is counted
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!("Formula \"{}\" is nested too deeply", self.source));
+        }
+        self.depth += 1;
+        let expr = self.signed();
+        self.depth -= 1;
+        expr
+    }
+
+    fn signed(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        let base = self.primary()?;
+        if self.eat('^') {
+            // Right-associative, and binds tighter than a leading minus: -2^2 = -4
+            return Ok(Expr::Binary(Op::Power, Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn take_while(&mut self, accept: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.chars.next_if(|c| accept(*c)) {
+            taken.push(c);
+        }
+        taken
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let expr = self.comparison()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let digits = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                digits
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("Invalid number \"{}\" in formula \"{}\"", digits, self.source))
+            }
+            Some('$') => {
+                self.chars.next();
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                if digits.is_empty() {
+                    return Err(format!("\"$\" must be followed by an argument number in formula \"{}\"", self.source));
+                }
+                Ok(Expr::Variable(format!("${}", digits)))
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                if !self.eat('(') {
+                    return Ok(Expr::Variable(name));
+                }
+
+                let &(_, arity) = FUNCTIONS
+                    .iter()
+                    .find(|(function, _)| *function == name)
+                    .ok_or_else(|| format!("Unknown function \"{}\" in formula \"{}\"", name, self.source))?;
+                let mut args = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        args.push(self.comparison()?);
+                        if self.eat(')') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                if (arity == 0 && args.is_empty()) || (arity > 0 && args.len() != arity) {
+                    return Err(format!("Wrong number of arguments to \"{}\" in formula \"{}\"", name, self.source));
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Some(c) => Err(format!("Unexpected \"{}\" in formula \"{}\"", c, self.source)),
+            None => Err(format!("Formula \"{}\" ends too early", self.source)),
+        }
+    }
+}
+
+fn collect_variables(expr: &Expr, names: &mut Vec<String>) {
+    match expr {
+        Expr::Number(_) => {}
+        Expr::Variable(name) => {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        Expr::Negate(inner) => collect_variables(inner, names),
+        Expr::Binary(_, left, right) => {
+            collect_variables(left, names);
+            collect_variables(right, names);
+        }
+        Expr::Call(_, args) => args.iter().for_each(|arg| collect_variables(arg, names)),
+    }
+}
+
+fn eval(expr: &Expr, variables: &HashMap<String, f64>) -> Result<f64, String> {
+    let truth = |b: bool| if b { 1.0 } else { 0.0 };
+    let value = match expr {
+        Expr::Number(n) => *n,
+        Expr::Variable(name) => *variables
+            .get(name)
+            .ok_or_else(|| format!("Formula variable \"{}\" has no value", name))?,
+        Expr::Negate(inner) => -eval(inner, variables)?,
+        Expr::Binary(op, left, right) => {
+            let (a, b) = (eval(left, variables)?, eval(right, variables)?);
+            match op {
+                Op::Add => a + b,
+                Op::Subtract => a - b,
+                Op::Multiply => a * b,
+                Op::Divide | Op::Remainder if b == 0.0 => return Err("Formula divides by zero".to_string()),
+                Op::Divide => a / b,
+                Op::Remainder => a.rem_euclid(b),
+                Op::Power => a.powf(b),
+                Op::Less => truth(a < b),
+                Op::LessOrEqual => truth(a <= b),
+                Op::Greater => truth(a > b),
+                Op::GreaterOrEqual => truth(a >= b),
+                Op::Equal => truth(a == b),
+                Op::NotEqual => truth(a != b),
+            }
+        }
+        Expr::Call(name, args) => {
+            if name == "if" {
+                // Only the chosen branch is evaluated, so it may guard a division
+                let branch = if eval(&args[0], variables)? != 0.0 { &args[1] } else { &args[2] };
+                return eval(branch, variables);
+            }
+            let values = args.iter().map(|arg| eval(arg, variables)).collect::<Result<Vec<f64>, String>>()?;
+            match name.as_str() {
+                "min" => values.iter().copied().fold(f64::INFINITY, f64::min),
+                "max" => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                "abs" => values[0].abs(),
+                "floor" => values[0].floor(),
+                "ceil" => values[0].ceil(),
+                "round" => values[0].round(),
+                "sqrt" => values[0].sqrt(),
+                _ => values[0].max(values[1]).min(values[2]), // clamp, without panicking when low > high
+            }
+        }
+    };
+
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err("Formula result is not a finite number".to_string())
+    }
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Formula, String> {
+        if source.chars().count() > MAX_LENGTH {
+            return Err(format!("Formula is longer than {} characters", MAX_LENGTH));
+        }
+        let mut parser = Parser { chars: source.chars().peekable(), source, depth: 0 };
+        let expr = parser.comparison()?;
+        if let Some(c) = parser.peek() {
+            return Err(format!("Unexpected \"{}\" in formula \"{}\"", c, source));
+        }
+        Ok(Formula { expr })
+    }
+
+    /// Names of the variables the formula uses, in order of first use
+    pub fn variables(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        collect_variables(&self.expr, &mut names);
+        names
+    }
+
+    pub fn evaluate(&self, variables: &HashMap<String, f64>) -> Result<f64, String> {
+        eval(&self.expr, variables)
+    }
+}
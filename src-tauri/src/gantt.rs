@@ -0,0 +1,264 @@
+//! QuestScribe - Gantt Timeline Export
+//!
+//! Renders the story's structure as a Gantt-like chart across the document:
+//! one row per entity lifecycle (first to last marker), one row per status
+//! effect, and one row per plot thread (colored by status until it resolves).
+//! Chapter starts are drawn as vertical lines. The chart is a standalone SVG,
+//! optionally wrapped in an HTML page, so it can be embedded in a wiki or
+//! printed without the app.
+//!
+//! A status effect is a field with a limited lifetime: any field under the
+//! `status` group, or any field that some marker removes. Each stretch from
+//! being set to being removed (or to the end of the document) is one bar.
+
+use crate::chapters::Chapter;
+use crate::continuity_report::escape_html;
+use crate::engine;
+use crate::knowledge;
+use crate::plot_threads::{PlotThread, ThreadStatus};
+use crate::state::{ChangeType, Entity, Marker};
+use std::collections::{HashMap, HashSet};
+
+/// Field group whose fields always count as status effects
+pub const STATUS_GROUP: &str = "status";
+
+const LABEL_WIDTH: f64 = 220.0;
+const CHART_WIDTH: f64 = 800.0;
+const HEADER_HEIGHT: f64 = 36.0;
+const SECTION_HEIGHT: f64 = 28.0;
+const ROW_HEIGHT: f64 = 22.0;
+const BAR_HEIGHT: f64 = 14.0;
+const MIN_BAR_WIDTH: f64 = 3.0;
+
+const OPEN_COLOR: &str = "#E07A5F";
+const DEVELOPED_COLOR: &str = "#F2CC8F";
+
+/// One bar, in document positions
+pub struct Bar {
+    pub start: usize,
+    pub end: usize,
+    pub color: String,
+    pub tooltip: String,
+}
+
+pub struct Row {
+    pub label: String,
+    pub bars: Vec<Bar>,
+}
+
+pub struct Section {
+    pub title: &'static str,
+    pub rows: Vec<Row>,
+}
+
+fn sorted_entities(entities: &HashMap<String, Entity>) -> Vec<&Entity> {
+    let mut sorted: Vec<&Entity> = entities.values().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    sorted
+}
+
+/// An entity's markers in document order, leaving out open TODOs
+pub fn entity_markers<'a>(markers: &'a HashMap<String, Marker>, entity_id: &str) -> Vec<&'a Marker> {
+    let mut list: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity_id && !m.todo).collect();
+    list.sort_by(|a, b| engine::compare_markers(a, b));
+    list
+}
+
+fn is_within(path: &str, group: &str) -> bool {
+    path == group || path.starts_with(&format!("{}.", group))
+}
+
+/// Set-to-removed stretches of an entity's status effects, as (field, start, end)
+///
+/// `entity_markers` must be in document order. Effects still active at the end
+/// run to `document_size`.
+pub fn status_spans(entity_markers: &[&Marker], document_size: usize) -> Vec<(String, usize, usize)> {
+    let removed: HashSet<String> = entity_markers
+        .iter()
+        .flat_map(|m| m.changes.iter())
+        .filter(|c| c.change_type == ChangeType::Remove)
+        .map(|c| c.field_name.clone())
+        .collect();
+    let is_status = |path: &str| is_within(path, STATUS_GROUP) || removed.iter().any(|r| is_within(path, r));
+
+    let mut open: Vec<(String, usize)> = Vec::new();
+    let mut spans = Vec::new();
+
+    for marker in entity_markers {
+        for change in &marker.changes {
+            let path = knowledge::change_path(change);
+            if change.change_type == ChangeType::Remove {
+                // Removing a group ends every effect inside it
+                open.retain(|(field, start)| {
+                    let ends = is_within(field, &path);
+                    if ends {
+                        spans.push((field.clone(), *start, marker.position));
+                    }
+                    !ends
+                });
+            } else if is_status(&path) && !open.iter().any(|(field, _)| *field == path) {
+                open.push((path, marker.position));
+            }
+        }
+    }
+
+    spans.extend(open.into_iter().map(|(field, start)| (field, start, document_size)));
+    spans.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    spans
+}
+
+fn thread_row(thread: &PlotThread, markers: &HashMap<String, Marker>, document_size: usize) -> Row {
+    let timeline = thread.timeline(markers);
+    let mut bars = Vec::new();
+
+    for (index, event) in timeline.iter().enumerate() {
+        let color = match event.status {
+            ThreadStatus::Open => OPEN_COLOR,
+            ThreadStatus::Developed => DEVELOPED_COLOR,
+            ThreadStatus::Resolved => break,
+        };
+        let end = timeline.get(index + 1).map(|e| e.position).unwrap_or(document_size);
+        let status = if event.status == ThreadStatus::Open { "open" } else { "developed" };
+        bars.push(Bar {
+            start: event.position,
+            end,
+            color: color.to_string(),
+            tooltip: format!("{}: {} ({}–{})", thread.name, status, event.position, end),
+        });
+    }
+
+    Row {
+        label: thread.name.clone(),
+        bars,
+    }
+}
+
+/// Collect the rows of the chart
+pub fn build_sections(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    threads: &[PlotThread],
+    document_size: usize,
+) -> Vec<Section> {
+    let mut lifecycles = Vec::new();
+    let mut effects = Vec::new();
+
+    for entity in sorted_entities(entities) {
+        let list = entity_markers(markers, &entity.id);
+        let (Some(first), Some(last)) = (list.first(), list.last()) else {
+            continue;
+        };
+
+        lifecycles.push(Row {
+            label: entity.name.clone(),
+            bars: vec![Bar {
+                start: first.position,
+                end: last.position,
+                color: entity.color.clone(),
+                tooltip: format!("{}: {}–{}", entity.name, first.position, last.position),
+            }],
+        });
+
+        // One row per effect, holding every stretch it's active
+        let mut rows: Vec<Row> = Vec::new();
+        for (field, start, end) in status_spans(&list, document_size) {
+            let label = format!("{}: {}", entity.name, field);
+            let bar = Bar {
+                start,
+                end,
+                color: entity.color.clone(),
+                tooltip: format!("{} ({}–{})", label, start, end),
+            };
+            match rows.iter_mut().find(|r| r.label == label) {
+                Some(row) => row.bars.push(bar),
+                None => rows.push(Row { label, bars: vec![bar] }),
+            }
+        }
+        effects.extend(rows);
+    }
+
+    let thread_rows = threads
+        .iter()
+        .map(|thread| thread_row(thread, markers, document_size))
+        .collect();
+
+    vec![
+        Section { title: "Entities", rows: lifecycles },
+        Section { title: "Status effects", rows: effects },
+        Section { title: "Plot threads", rows: thread_rows },
+    ]
+}
+
+/// Render the chart as a standalone SVG image
+pub fn render_svg(sections: &[Section], chapters: &[Chapter], document_size: usize) -> String {
+    let document_size = document_size.max(1) as f64;
+    let x = |pos: usize| LABEL_WIDTH + (pos as f64).min(document_size) * CHART_WIDTH / document_size;
+
+    let row_count: usize = sections.iter().map(|s| s.rows.len()).sum();
+    let height = HEADER_HEIGHT + sections.len() as f64 * SECTION_HEIGHT + row_count as f64 * ROW_HEIGHT;
+    let width = LABEL_WIDTH + CHART_WIDTH + 10.0;
+
+    let mut body = String::new();
+
+    // Chapter starts
+    for chapter in chapters {
+        let cx = x(chapter.start);
+        body.push_str(&format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#ccc\" stroke-dasharray=\"3,3\"/>\n",
+            cx, HEADER_HEIGHT - 12.0, cx, height
+        ));
+        body.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"11\" fill=\"#555\">{}</text>\n",
+            cx + 3.0,
+            HEADER_HEIGHT - 16.0,
+            escape_html(&chapter.title)
+        ));
+    }
+
+    let mut y = HEADER_HEIGHT;
+    for section in sections {
+        body.push_str(&format!(
+            "<text x=\"4\" y=\"{:.1}\" font-size=\"13\" font-weight=\"bold\">{}</text>\n",
+            y + SECTION_HEIGHT - 9.0,
+            escape_html(section.title)
+        ));
+        y += SECTION_HEIGHT;
+
+        for row in &section.rows {
+            body.push_str(&format!(
+                "<text x=\"12\" y=\"{:.1}\" font-size=\"12\">{}</text>\n",
+                y + ROW_HEIGHT - 7.0,
+                escape_html(&row.label)
+            ));
+            for bar in &row.bars {
+                let start = x(bar.start);
+                let bar_width = (x(bar.end) - start).max(MIN_BAR_WIDTH);
+                body.push_str(&format!(
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"3\" fill=\"{}\"><title>{}</title></rect>\n",
+                    start,
+                    y + (ROW_HEIGHT - BAR_HEIGHT) / 2.0,
+                    bar_width,
+                    BAR_HEIGHT,
+                    escape_html(&bar.color),
+                    escape_html(&bar.tooltip)
+                ));
+            }
+            y += ROW_HEIGHT;
+        }
+    }
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" viewBox=\"0 0 {:.0} {:.0}\" font-family=\"sans-serif\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n{}</svg>\n",
+        width, height, width, height, body
+    )
+}
+
+/// Render the chart as an HTML page with the SVG inline
+pub fn render_html(svg: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Story Timeline</title>\n</head>\n\
+         <body style=\"font-family: sans-serif;\">\n<h1>Story Timeline</h1>\n{}</body>\n</html>\n",
+        svg
+    )
+}
@@ -0,0 +1,240 @@
+//! QuestScribe - Entity Glossary
+//!
+//! An alphabetized encyclopedia of the cast and places, for a book's appendix
+//! or a companion wiki. Each entry has the entity's name, its aliases, its
+//! description and notes, the chapter it first appears in, and its state at
+//! the end of the document.
+//!
+//! Aliases, description and notes are ordinary fields, found by name in any
+//! case: "aliases" (a list, or comma-separated text), "description" and
+//! "notes". Every other field is listed with the final state. An entity first
+//! appears where its name is first mentioned or its first marker is,
+//! whichever comes first (see mentions.rs).
+
+use crate::chapters::Chapter;
+use crate::engine::{self, EntityState};
+use crate::i18n;
+use crate::mentions;
+use crate::preferences::ExportStyle;
+use crate::recap;
+use crate::state::{Entity, EntityKind, Marker};
+use crate::synopses::styled_run;
+use docx_rs::{Docx, Paragraph};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+
+const ALIAS_FIELDS: &[&str] = &["aliases", "alias"];
+const DESCRIPTION_FIELD: &str = "description";
+const NOTES_FIELD: &str = "notes";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlossaryFormat {
+    Markdown,
+    Docx,
+    Json,
+}
+
+impl GlossaryFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(GlossaryFormat::Markdown),
+            "docx" => Ok(GlossaryFormat::Docx),
+            "json" => Ok(GlossaryFormat::Json),
+            _ => Err(format!("Unknown glossary format: {}", format)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlossaryEntry {
+    pub entity_id: String,
+    pub name: String,
+    pub kind: EntityKind,
+    pub aliases: Vec<String>,
+    pub description: Option<String>,
+    pub notes: Option<String>,
+    pub first_position: Option<usize>,
+    pub first_chapter: Option<String>, // Title of the chapter at first_position
+    pub final_state: BTreeMap<String, String>, // Other fields at the end of the document, flattened
+}
+
+// Top-level state value whose key matches one of the names, in any case
+fn take_field(state: &mut EntityState, names: &[&str]) -> Option<Value> {
+    let key = state.keys().find(|key| names.iter().any(|name| key.eq_ignore_ascii_case(name)))?.clone();
+    state.remove(&key)
+}
+
+fn text_of(value: Option<Value>) -> Option<String> {
+    let text = match value? {
+        Value::String(text) => text,
+        other => recap::format_value(&other.to_string()),
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn aliases_of(value: Option<Value>) -> Vec<String> {
+    let aliases: Vec<String> = match value {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string()))
+            .collect(),
+        Some(Value::String(text)) => text.split(',').map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+    aliases.into_iter().map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect()
+}
+
+/// An entity's aliases at the end of the document
+pub fn entity_aliases(entity: &Entity, markers: &HashMap<String, Marker>) -> Vec<String> {
+    let mut state = engine::entity_state_with_defaults(markers, entity, usize::MAX);
+    engine::fill_defaults(&mut state, entity);
+    aliases_of(take_field(&mut state, ALIAS_FIELDS))
+}
+
+/// Build an entry for every entity, sorted by name (ignoring case)
+pub fn build_glossary(
+    doc: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    chapters: &[Chapter],
+) -> Vec<GlossaryEntry> {
+    // First mention of each entity (mentions come in document order)
+    let mut first_mentions: HashMap<String, usize> = HashMap::new();
+    for mention in mentions::find_mentions(doc, entities) {
+        first_mentions.entry(mention.entity_id).or_insert(mention.position);
+    }
+
+    let mut entries: Vec<GlossaryEntry> = entities
+        .values()
+        .map(|entity| {
+            let mut state = engine::entity_state_with_defaults(markers, entity, usize::MAX);
+            engine::fill_defaults(&mut state, entity);
+
+            let aliases = aliases_of(take_field(&mut state, ALIAS_FIELDS));
+            let description = text_of(take_field(&mut state, &[DESCRIPTION_FIELD]));
+            let notes = text_of(take_field(&mut state, &[NOTES_FIELD]));
+
+            let first_marker = markers.values().filter(|m| m.entity_id == entity.id).map(|m| m.position).min();
+            let first_position = first_mentions.get(&entity.id).copied().into_iter().chain(first_marker).min();
+            let first_chapter = first_position.and_then(|position| {
+                chapters
+                    .iter()
+                    .find(|c| position >= c.start && position < c.end)
+                    .map(|c| c.title.clone())
+            });
+
+            GlossaryEntry {
+                entity_id: entity.id.clone(),
+                name: entity.name.clone(),
+                kind: entity.kind,
+                aliases,
+                description,
+                notes,
+                first_position,
+                first_chapter,
+                final_state: recap::flat_values(&state),
+            }
+        })
+        .collect();
+
+    entries.sort_by(|a, b| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.entity_id.cmp(&b.entity_id))
+    });
+    entries
+}
+
+// Section letter of an entry ("#" for names that don't start with a letter)
+fn initial(entry: &GlossaryEntry) -> String {
+    match entry.name.trim().chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => "#".to_string(),
+    }
+}
+
+// Lines under an entry's name, in display order
+fn entry_lines(entry: &GlossaryEntry, locale: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    if !entry.aliases.is_empty() {
+        lines.push(i18n::tr(locale, "glossary.aliases", &[("aliases", &entry.aliases.join(", "))]));
+    }
+    if let Some(chapter) = &entry.first_chapter {
+        lines.push(i18n::tr(locale, "glossary.first_appearance", &[("chapter", chapter)]));
+    }
+    lines.extend(entry.description.iter().cloned());
+    lines.extend(entry.notes.iter().cloned());
+    lines
+}
+
+pub fn render_markdown(entries: &[GlossaryEntry], locale: &str) -> String {
+    let mut out = format!("# {}\n", i18n::tr(locale, "glossary.heading", &[]));
+    let mut section = String::new();
+
+    for entry in entries {
+        let letter = initial(entry);
+        if letter != section {
+            out.push_str(&format!("\n## {}\n", letter));
+            section = letter;
+        }
+
+        out.push_str(&format!("\n### {}\n\n", entry.name));
+        for line in entry_lines(entry, locale) {
+            out.push_str(&format!("{}\n\n", line));
+        }
+        if !entry.final_state.is_empty() {
+            out.push_str(&format!("**{}**\n\n", i18n::tr(locale, "glossary.final_state", &[])));
+            for (field, value) in &entry.final_state {
+                out.push_str(&format!("- {}: {}\n", field, value));
+            }
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Write the glossary as a DOCX file, in the document's export style
+pub fn render_docx(entries: &[GlossaryEntry], style: &ExportStyle, locale: &str) -> Result<Vec<u8>, String> {
+    let body_size = style.body_half_points();
+    let heading = i18n::tr(locale, "glossary.heading", &[]);
+    let mut docx = Docx::new().add_paragraph(
+        Paragraph::new().add_run(styled_run(&heading, style.heading_half_points(Some(1)), style).bold()),
+    );
+    let mut section = String::new();
+
+    for entry in entries {
+        let letter = initial(entry);
+        if letter != section {
+            docx = docx.add_paragraph(
+                Paragraph::new().add_run(styled_run(&letter, style.heading_half_points(Some(2)), style).bold()),
+            );
+            section = letter;
+        }
+
+        docx = docx.add_paragraph(
+            Paragraph::new().add_run(styled_run(&entry.name, style.heading_half_points(Some(3)), style).bold()),
+        );
+        for line in entry_lines(entry, locale) {
+            docx = docx.add_paragraph(Paragraph::new().add_run(styled_run(&line, body_size, style)));
+        }
+        if !entry.final_state.is_empty() {
+            let label = i18n::tr(locale, "glossary.final_state", &[]);
+            docx = docx.add_paragraph(Paragraph::new().add_run(styled_run(&label, body_size, style).italic()));
+            for (field, value) in &entry.final_state {
+                let line = format!("{}: {}", field, value);
+                docx = docx.add_paragraph(Paragraph::new().add_run(styled_run(&line, body_size, style)));
+            }
+        }
+    }
+
+    let mut buf = Cursor::new(Vec::new());
+    docx.build()
+        .pack(&mut buf)
+        .map_err(|e| format!("Failed to pack DOCX: {}", e))?;
+    Ok(buf.into_inner())
+}
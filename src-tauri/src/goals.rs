@@ -0,0 +1,72 @@
+//! QuestScribe - Word Count Goals
+//!
+//! A document can set a total word target (e.g., 80,000 for a novel) and a daily
+//! target. Document progress comes from the current content; daily progress comes
+//! from today's writing sessions, including the one still in progress.
+
+use crate::dates;
+use crate::sessions::{self, WritingSession};
+use serde::{Deserialize, Serialize};
+
+/// Goals saved with the document (None = no goal of that kind)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WordGoals {
+    #[serde(default)]
+    pub document_words: Option<usize>,
+    #[serde(default)]
+    pub daily_words: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GoalProgress {
+    pub word_count: usize,
+    pub document_goal: Option<usize>,
+    pub document_percent: Option<f64>, // Capped at 100
+    pub words_remaining: Option<usize>,
+    pub words_today: i64, // Net words across today's sessions
+    pub daily_goal: Option<usize>,
+    pub daily_percent: Option<f64>, // Capped at 100
+    pub daily_goal_met: bool,
+}
+
+fn percent(done: f64, goal: usize) -> f64 {
+    if goal == 0 {
+        return 100.0;
+    }
+    (done / goal as f64 * 100.0).clamp(0.0, 100.0)
+}
+
+/// Compute progress toward the goals from the current word count and session history
+pub fn goal_progress(
+    goals: &WordGoals,
+    word_count: usize,
+    history: &[WritingSession],
+    active: Option<&WritingSession>,
+    utc_offset_minutes: i32,
+    now: i64,
+) -> GoalProgress {
+    let today = dates::day_number(now, utc_offset_minutes);
+
+    let mut words_today = sessions::daily_totals(history, utc_offset_minutes, None)
+        .get(&today)
+        .map(|d| d.words)
+        .unwrap_or(0);
+
+    // The active session counts up to the current content
+    if let Some(session) = active {
+        if dates::day_number(session.started_at, utc_offset_minutes) == today {
+            words_today += word_count as i64 - session.start_word_count as i64;
+        }
+    }
+
+    GoalProgress {
+        word_count,
+        document_goal: goals.document_words,
+        document_percent: goals.document_words.map(|goal| percent(word_count as f64, goal)),
+        words_remaining: goals.document_words.map(|goal| goal.saturating_sub(word_count)),
+        words_today,
+        daily_goal: goals.daily_words,
+        daily_percent: goals.daily_words.map(|goal| percent(words_today as f64, goal)),
+        daily_goal_met: goals.daily_words.is_some_and(|goal| words_today >= goal as i64),
+    }
+}
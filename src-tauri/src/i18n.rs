@@ -0,0 +1,444 @@
+//! QuestScribe - Localization of Backend-Generated Text
+//!
+//! The frontend translates its own UI, but some text is produced by the backend
+//! and ends up inside the manuscript or an exported file (character sheet headers,
+//! marker descriptions such as "Duplicated from ...", report messages, export labels).
+//! Those strings are looked up here by key.
+//!
+//! # Locale Resolution
+//!
+//! - The document language (saved in the `.qsd` file) wins when set
+//! - Otherwise the application locale is used
+//! - Unknown locales and missing keys fall back to English
+
+/// Locale used when nothing else is configured
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Bundled locales as (code, native name) pairs
+pub const SUPPORTED_LOCALES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("es", "Español"),
+    ("fr", "Français"),
+    ("de", "Deutsch"),
+    ("pt", "Português"),
+];
+
+// Message tables: (key, template). Placeholders use the form {name}.
+const EN: &[(&str, &str)] = &[
+    ("sheet.header", "=== {name} ==="),
+    ("marker.duplicated_from", "Duplicated from {name}"),
+    ("chapter.untitled", "Untitled section"),
+    ("document.untitled", "Untitled"),
+    ("report.whole_document", "Whole document"),
+    ("report.col.chapter", "Chapter"),
+    ("report.col.start", "Start"),
+    ("report.col.end", "End"),
+    ("report.col.entity", "Entity"),
+    ("report.col.markers", "Markers"),
+    ("report.col.changes", "Changes"),
+    ("report.col.fields", "Fields Touched"),
+    ("report.col.deltas", "Net Changes"),
+    ("report.col.field", "Field"),
+    ("recap.nothing", "Nothing changed."),
+    ("recap.changed", "{name}'s {field} went from {before} to {after}."),
+    ("recap.set", "{name} gained {field}: {after}."),
+    ("recap.removed", "{name} no longer has {field}."),
+    ("recap.gained", "{name} acquired {items}."),
+    ("recap.lost", "{name} lost {items}."),
+    ("synopsis.heading", "Chapter Synopses"),
+    ("endnote.heading", "Notes"),
+    ("sheet.appendix_heading", "Character Sheets"),
+    ("matter.by_author", "by {author}"),
+    ("matter.characters_heading", "Characters"),
+    ("matter.glossary_heading", "Glossary"),
+    ("glossary.heading", "Glossary"),
+    ("glossary.aliases", "Also known as {aliases}"),
+    ("glossary.first_appearance", "First appears in {chapter}"),
+    ("glossary.final_state", "At the end"),
+    ("endnote.removed", "{field} removed"),
+    ("endnote.learned", "learns {fact}"),
+    ("endnote.result", "now {value}"),
+    ("continuity.co_location", "{name} appears in a scene at {scene}, but is tracked at {tracked} (since position {since})"),
+    ("continuity.premature_knowledge", "{name} appears alongside \"{fact}\" before learning it (learns it at position {learned_at})"),
+    ("continuity.travel", "{name} travels {distance} {unit} from {from} to {to} in {hours} hours (at most {max_speed} {unit} per hour is plausible)"),
+    ("continuity.open_todo", "Open TODO for {name}: {note}"),
+    ("continuity.no_description", "(no description)"),
+    ("continuity.misspelling", "\"{found}\" looks like a misspelling of \"{expected}\""),
+    ("continuity.descriptor", "\"{found}\" contradicts {name}'s {descriptor} ({tracked})"),
+    ("descriptor.pronouns", "pronouns"),
+    ("descriptor.eye_color", "eye color"),
+    ("descriptor.hair", "hair"),
+    ("descriptor.height", "height"),
+    ("report.continuity_title", "Continuity Report"),
+    ("report.findings", "{count} findings"),
+    ("report.no_issues", "No issues found."),
+    ("report.position", "Position {position}"),
+    ("report.position_in_chapter", "Position {position} ({chapter})"),
+    ("report.section.continuity", "Continuity"),
+    ("report.section.orphaned", "Orphaned markers"),
+    ("report.section.chekhov", "Chekhov's gun"),
+    ("report.section.unused", "Unused entities"),
+    ("report.section.threads", "Unresolved plot threads"),
+    ("report.orphaned", "Marker for {name} is orphaned: {reasons}"),
+    ("report.orphan.missing_entity", "its entity no longer exists"),
+    ("report.orphan.out_of_range", "it is past the end of the document"),
+    ("report.orphan.missing_from_text", "its marker is missing from the text"),
+    ("report.chekhov.never_referenced", "{name}: {field} is set here and never referenced again"),
+    ("report.chekhov.removed_without_introduction", "{name}: {field} is removed without ever being introduced"),
+    ("report.unused.no_markers", "{name} has no markers"),
+    ("report.unused.never_mentioned", "{name} is never mentioned in the text"),
+    ("report.thread.unresolved", "\"{name}\" is still {status} at the end of the document"),
+    ("report.thread.open", "open"),
+    ("report.thread.developed", "developed"),
+    ("report.thread.resolved", "resolved"),
+];
+
+const ES: &[(&str, &str)] = &[
+    ("sheet.header", "=== {name} ==="),
+    ("marker.duplicated_from", "Duplicado de {name}"),
+    ("chapter.untitled", "Sección sin título"),
+    ("document.untitled", "Sin título"),
+    ("report.whole_document", "Documento completo"),
+    ("report.col.chapter", "Capítulo"),
+    ("report.col.start", "Inicio"),
+    ("report.col.end", "Fin"),
+    ("report.col.entity", "Entidad"),
+    ("report.col.markers", "Marcadores"),
+    ("report.col.changes", "Cambios"),
+    ("report.col.fields", "Campos modificados"),
+    ("report.col.deltas", "Cambios netos"),
+    ("report.col.field", "Campo"),
+    ("recap.nothing", "No hubo cambios."),
+    ("recap.changed", "{field} de {name} pasó de {before} a {after}."),
+    ("recap.set", "{name} obtuvo {field}: {after}."),
+    ("recap.removed", "{name} ya no tiene {field}."),
+    ("recap.gained", "{name} consiguió {items}."),
+    ("recap.lost", "{name} perdió {items}."),
+    ("synopsis.heading", "Sinopsis por capítulo"),
+    ("endnote.heading", "Notas"),
+    ("sheet.appendix_heading", "Fichas de personaje"),
+    ("matter.by_author", "por {author}"),
+    ("matter.characters_heading", "Personajes"),
+    ("matter.glossary_heading", "Glosario"),
+    ("glossary.heading", "Glosario"),
+    ("glossary.aliases", "También conocido como {aliases}"),
+    ("glossary.first_appearance", "Aparece por primera vez en {chapter}"),
+    ("glossary.final_state", "Al final"),
+    ("endnote.removed", "{field} eliminado"),
+    ("endnote.learned", "descubre {fact}"),
+    ("endnote.result", "ahora {value}"),
+    ("continuity.co_location", "{name} aparece en una escena en {scene}, pero su ubicación registrada es {tracked} (desde la posición {since})"),
+    ("continuity.premature_knowledge", "{name} aparece junto a \"{fact}\" antes de saberlo (lo descubre en la posición {learned_at})"),
+    ("continuity.travel", "{name} viaja {distance} {unit} de {from} a {to} en {hours} horas (lo verosímil es como máximo {max_speed} {unit} por hora)"),
+    ("continuity.open_todo", "TODO pendiente para {name}: {note}"),
+    ("continuity.no_description", "(sin descripción)"),
+    ("continuity.misspelling", "\"{found}\" parece una errata de \"{expected}\""),
+    ("continuity.descriptor", "\"{found}\" contradice el valor registrado de {name} para {descriptor} ({tracked})"),
+    ("descriptor.pronouns", "pronombres"),
+    ("descriptor.eye_color", "color de ojos"),
+    ("descriptor.hair", "cabello"),
+    ("descriptor.height", "estatura"),
+    ("report.continuity_title", "Informe de continuidad"),
+    ("report.findings", "{count} hallazgos"),
+    ("report.no_issues", "No se encontraron problemas."),
+    ("report.position", "Posición {position}"),
+    ("report.position_in_chapter", "Posición {position} ({chapter})"),
+    ("report.section.continuity", "Continuidad"),
+    ("report.section.orphaned", "Marcadores huérfanos"),
+    ("report.section.chekhov", "El arma de Chéjov"),
+    ("report.section.unused", "Entidades sin usar"),
+    ("report.section.threads", "Tramas sin resolver"),
+    ("report.orphaned", "El marcador de {name} está huérfano: {reasons}"),
+    ("report.orphan.missing_entity", "su entidad ya no existe"),
+    ("report.orphan.out_of_range", "está más allá del final del documento"),
+    ("report.orphan.missing_from_text", "falta su marcador en el texto"),
+    ("report.chekhov.never_referenced", "{name}: {field} se establece aquí y no se vuelve a mencionar"),
+    ("report.chekhov.removed_without_introduction", "{name}: {field} se elimina sin haberse introducido nunca"),
+    ("report.unused.no_markers", "{name} no tiene marcadores"),
+    ("report.unused.never_mentioned", "{name} nunca se menciona en el texto"),
+    ("report.thread.unresolved", "\"{name}\" sigue {status} al final del documento"),
+    ("report.thread.open", "abierta"),
+    ("report.thread.developed", "en desarrollo"),
+    ("report.thread.resolved", "resuelta"),
+];
+
+const FR: &[(&str, &str)] = &[
+    ("sheet.header", "=== {name} ==="),
+    ("marker.duplicated_from", "Dupliqué depuis {name}"),
+    ("chapter.untitled", "Section sans titre"),
+    ("document.untitled", "Sans titre"),
+    ("report.whole_document", "Document entier"),
+    ("report.col.chapter", "Chapitre"),
+    ("report.col.start", "Début"),
+    ("report.col.end", "Fin"),
+    ("report.col.entity", "Entité"),
+    ("report.col.markers", "Marqueurs"),
+    ("report.col.changes", "Modifications"),
+    ("report.col.fields", "Champs modifiés"),
+    ("report.col.deltas", "Variations nettes"),
+    ("report.col.field", "Champ"),
+    ("recap.nothing", "Rien n'a changé."),
+    ("recap.changed", "{field} de {name} est passé de {before} à {after}."),
+    ("recap.set", "{name} a obtenu {field} : {after}."),
+    ("recap.removed", "{name} n'a plus {field}."),
+    ("recap.gained", "{name} a acquis {items}."),
+    ("recap.lost", "{name} a perdu {items}."),
+    ("synopsis.heading", "Synopsis des chapitres"),
+    ("endnote.heading", "Notes"),
+    ("sheet.appendix_heading", "Fiches de personnage"),
+    ("matter.by_author", "par {author}"),
+    ("matter.characters_heading", "Personnages"),
+    ("matter.glossary_heading", "Glossaire"),
+    ("glossary.heading", "Glossaire"),
+    ("glossary.aliases", "Aussi appelé {aliases}"),
+    ("glossary.first_appearance", "Première apparition : {chapter}"),
+    ("glossary.final_state", "À la fin"),
+    ("endnote.removed", "{field} supprimé"),
+    ("endnote.learned", "apprend {fact}"),
+    ("endnote.result", "désormais {value}"),
+    ("continuity.co_location", "{name} apparaît dans une scène à {scene}, mais se trouve à {tracked} selon le suivi (depuis la position {since})"),
+    ("continuity.premature_knowledge", "{name} apparaît avec « {fact} » avant de l'apprendre (l'apprend à la position {learned_at})"),
+    ("continuity.travel", "{name} parcourt {distance} {unit} de {from} à {to} en {hours} heures (au plus {max_speed} {unit} par heure est plausible)"),
+    ("continuity.open_todo", "TODO ouvert pour {name} : {note}"),
+    ("continuity.no_description", "(sans description)"),
+    ("continuity.misspelling", "« {found} » ressemble à une faute de frappe pour « {expected} »"),
+    ("continuity.descriptor", "« {found} » contredit la valeur suivie de {name} pour {descriptor} ({tracked})"),
+    ("descriptor.pronouns", "pronoms"),
+    ("descriptor.eye_color", "couleur des yeux"),
+    ("descriptor.hair", "cheveux"),
+    ("descriptor.height", "taille"),
+    ("report.continuity_title", "Rapport de continuité"),
+    ("report.findings", "{count} constats"),
+    ("report.no_issues", "Aucun problème trouvé."),
+    ("report.position", "Position {position}"),
+    ("report.position_in_chapter", "Position {position} ({chapter})"),
+    ("report.section.continuity", "Continuité"),
+    ("report.section.orphaned", "Marqueurs orphelins"),
+    ("report.section.chekhov", "Le fusil de Tchekhov"),
+    ("report.section.unused", "Entités inutilisées"),
+    ("report.section.threads", "Intrigues non résolues"),
+    ("report.orphaned", "Le marqueur de {name} est orphelin : {reasons}"),
+    ("report.orphan.missing_entity", "son entité n'existe plus"),
+    ("report.orphan.out_of_range", "il se trouve après la fin du document"),
+    ("report.orphan.missing_from_text", "son marqueur est absent du texte"),
+    ("report.chekhov.never_referenced", "{name} : {field} est défini ici et n'est plus jamais évoqué"),
+    ("report.chekhov.removed_without_introduction", "{name} : {field} est retiré sans avoir jamais été introduit"),
+    ("report.unused.no_markers", "{name} n'a aucun marqueur"),
+    ("report.unused.never_mentioned", "{name} n'est jamais mentionné dans le texte"),
+    ("report.thread.unresolved", "« {name} » est encore {status} à la fin du document"),
+    ("report.thread.open", "ouverte"),
+    ("report.thread.developed", "développée"),
+    ("report.thread.resolved", "résolue"),
+];
+
+const DE: &[(&str, &str)] = &[
+    ("sheet.header", "=== {name} ==="),
+    ("marker.duplicated_from", "Dupliziert von {name}"),
+    ("chapter.untitled", "Unbenannter Abschnitt"),
+    ("document.untitled", "Unbenannt"),
+    ("report.whole_document", "Gesamtes Dokument"),
+    ("report.col.chapter", "Kapitel"),
+    ("report.col.start", "Anfang"),
+    ("report.col.end", "Ende"),
+    ("report.col.entity", "Entität"),
+    ("report.col.markers", "Markierungen"),
+    ("report.col.changes", "Änderungen"),
+    ("report.col.fields", "Geänderte Felder"),
+    ("report.col.deltas", "Nettoänderungen"),
+    ("report.col.field", "Feld"),
+    ("recap.nothing", "Nichts hat sich geändert."),
+    ("recap.changed", "{field} von {name} änderte sich von {before} auf {after}."),
+    ("recap.set", "{name} erhielt {field}: {after}."),
+    ("recap.removed", "{name} hat {field} nicht mehr."),
+    ("recap.gained", "{name} erhielt {items}."),
+    ("recap.lost", "{name} verlor {items}."),
+    ("synopsis.heading", "Kapitelübersicht"),
+    ("endnote.heading", "Anmerkungen"),
+    ("sheet.appendix_heading", "Charakterbögen"),
+    ("matter.by_author", "von {author}"),
+    ("matter.characters_heading", "Figuren"),
+    ("matter.glossary_heading", "Glossar"),
+    ("glossary.heading", "Glossar"),
+    ("glossary.aliases", "Auch bekannt als {aliases}"),
+    ("glossary.first_appearance", "Erster Auftritt: {chapter}"),
+    ("glossary.final_state", "Am Ende"),
+    ("endnote.removed", "{field} entfernt"),
+    ("endnote.learned", "erfährt {fact}"),
+    ("endnote.result", "jetzt {value}"),
+    ("continuity.co_location", "{name} erscheint in einer Szene in {scene}, ist aber in {tracked} erfasst (seit Position {since})"),
+    ("continuity.premature_knowledge", "{name} erscheint zusammen mit „{fact}“, erfährt es aber erst an Position {learned_at}"),
+    ("continuity.travel", "{name} reist {distance} {unit} von {from} nach {to} in {hours} Stunden (plausibel sind höchstens {max_speed} {unit} pro Stunde)"),
+    ("continuity.open_todo", "Offenes TODO für {name}: {note}"),
+    ("continuity.no_description", "(keine Beschreibung)"),
+    ("continuity.misspelling", "„{found}“ sieht aus wie ein Tippfehler für „{expected}“"),
+    ("continuity.descriptor", "„{found}“ widerspricht dem erfassten Wert für {descriptor} von {name} ({tracked})"),
+    ("descriptor.pronouns", "Pronomen"),
+    ("descriptor.eye_color", "Augenfarbe"),
+    ("descriptor.hair", "Haare"),
+    ("descriptor.height", "Größe"),
+    ("report.continuity_title", "Kontinuitätsbericht"),
+    ("report.findings", "{count} Befunde"),
+    ("report.no_issues", "Keine Probleme gefunden."),
+    ("report.position", "Position {position}"),
+    ("report.position_in_chapter", "Position {position} ({chapter})"),
+    ("report.section.continuity", "Kontinuität"),
+    ("report.section.orphaned", "Verwaiste Marker"),
+    ("report.section.chekhov", "Tschechows Gewehr"),
+    ("report.section.unused", "Ungenutzte Entitäten"),
+    ("report.section.threads", "Ungelöste Handlungsstränge"),
+    ("report.orphaned", "Der Marker für {name} ist verwaist: {reasons}"),
+    ("report.orphan.missing_entity", "seine Entität existiert nicht mehr"),
+    ("report.orphan.out_of_range", "er liegt hinter dem Ende des Dokuments"),
+    ("report.orphan.missing_from_text", "sein Marker fehlt im Text"),
+    ("report.chekhov.never_referenced", "{name}: {field} wird hier gesetzt und nie wieder aufgegriffen"),
+    ("report.chekhov.removed_without_introduction", "{name}: {field} wird entfernt, ohne je eingeführt worden zu sein"),
+    ("report.unused.no_markers", "{name} hat keine Marker"),
+    ("report.unused.never_mentioned", "{name} wird im Text nie erwähnt"),
+    ("report.thread.unresolved", "„{name}“ ist am Ende des Dokuments noch {status}"),
+    ("report.thread.open", "offen"),
+    ("report.thread.developed", "in Entwicklung"),
+    ("report.thread.resolved", "gelöst"),
+];
+
+const PT: &[(&str, &str)] = &[
+    ("sheet.header", "=== {name} ==="),
+    ("marker.duplicated_from", "Duplicado de {name}"),
+    ("chapter.untitled", "Seção sem título"),
+    ("document.untitled", "Sem título"),
+    ("report.whole_document", "Documento inteiro"),
+    ("report.col.chapter", "Capítulo"),
+    ("report.col.start", "Início"),
+    ("report.col.end", "Fim"),
+    ("report.col.entity", "Entidade"),
+    ("report.col.markers", "Marcadores"),
+    ("report.col.changes", "Alterações"),
+    ("report.col.fields", "Campos alterados"),
+    ("report.col.deltas", "Variações líquidas"),
+    ("report.col.field", "Campo"),
+    ("recap.nothing", "Nada mudou."),
+    ("recap.changed", "{field} de {name} passou de {before} para {after}."),
+    ("recap.set", "{name} obteve {field}: {after}."),
+    ("recap.removed", "{name} não tem mais {field}."),
+    ("recap.gained", "{name} adquiriu {items}."),
+    ("recap.lost", "{name} perdeu {items}."),
+    ("synopsis.heading", "Sinopses dos capítulos"),
+    ("endnote.heading", "Notas"),
+    ("sheet.appendix_heading", "Fichas de personagem"),
+    ("matter.by_author", "por {author}"),
+    ("matter.characters_heading", "Personagens"),
+    ("matter.glossary_heading", "Glossário"),
+    ("glossary.heading", "Glossário"),
+    ("glossary.aliases", "Também conhecido como {aliases}"),
+    ("glossary.first_appearance", "Aparece pela primeira vez em {chapter}"),
+    ("glossary.final_state", "No final"),
+    ("endnote.removed", "{field} removido"),
+    ("endnote.learned", "descobre {fact}"),
+    ("endnote.result", "agora {value}"),
+    ("continuity.co_location", "{name} aparece numa cena em {scene}, mas está registrado em {tracked} (desde a posição {since})"),
+    ("continuity.premature_knowledge", "{name} aparece junto com \"{fact}\" antes de descobrir isso (descobre na posição {learned_at})"),
+    ("continuity.travel", "{name} viaja {distance} {unit} de {from} a {to} em {hours} horas (o plausível é no máximo {max_speed} {unit} por hora)"),
+    ("continuity.open_todo", "TODO pendente para {name}: {note}"),
+    ("continuity.no_description", "(sem descrição)"),
+    ("continuity.misspelling", "\"{found}\" parece um erro de grafia de \"{expected}\""),
+    ("continuity.descriptor", "\"{found}\" contradiz o valor registrado de {descriptor} de {name} ({tracked})"),
+    ("descriptor.pronouns", "pronomes"),
+    ("descriptor.eye_color", "cor dos olhos"),
+    ("descriptor.hair", "cabelo"),
+    ("descriptor.height", "altura"),
+    ("report.continuity_title", "Relatório de continuidade"),
+    ("report.findings", "{count} ocorrências"),
+    ("report.no_issues", "Nenhum problema encontrado."),
+    ("report.position", "Posição {position}"),
+    ("report.position_in_chapter", "Posição {position} ({chapter})"),
+    ("report.section.continuity", "Continuidade"),
+    ("report.section.orphaned", "Marcadores órfãos"),
+    ("report.section.chekhov", "A arma de Tchekhov"),
+    ("report.section.unused", "Entidades não usadas"),
+    ("report.section.threads", "Tramas não resolvidas"),
+    ("report.orphaned", "O marcador de {name} está órfão: {reasons}"),
+    ("report.orphan.missing_entity", "sua entidade não existe mais"),
+    ("report.orphan.out_of_range", "está além do fim do documento"),
+    ("report.orphan.missing_from_text", "seu marcador não está no texto"),
+    ("report.chekhov.never_referenced", "{name}: {field} é definido aqui e nunca mais é mencionado"),
+    ("report.chekhov.removed_without_introduction", "{name}: {field} é removido sem nunca ter sido apresentado"),
+    ("report.unused.no_markers", "{name} não tem marcadores"),
+    ("report.unused.never_mentioned", "{name} nunca é mencionado no texto"),
+    ("report.thread.unresolved", "\"{name}\" ainda está {status} no fim do documento"),
+    ("report.thread.open", "aberta"),
+    ("report.thread.developed", "em desenvolvimento"),
+    ("report.thread.resolved", "resolvida"),
+];
+
+/// Map a locale tag like "pt-BR" or "es_MX" to a bundled locale code
+///
+/// Returns `None` if no bundled locale matches the language part of the tag.
+pub fn normalize_locale(locale: &str) -> Option<&'static str> {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+
+    SUPPORTED_LOCALES
+        .iter()
+        .find(|(code, _)| *code == language)
+        .map(|(code, _)| *code)
+}
+
+/// Native name of a locale's language (e.g., "Español"), falling back to English
+pub fn language_name(locale: &str) -> &'static str {
+    let code = normalize_locale(locale).unwrap_or(DEFAULT_LOCALE);
+
+    SUPPORTED_LOCALES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+        .unwrap_or("English")
+}
+
+fn messages(locale: &str) -> &'static [(&'static str, &'static str)] {
+    match normalize_locale(locale).unwrap_or(DEFAULT_LOCALE) {
+        "es" => ES,
+        "fr" => FR,
+        "de" => DE,
+        "pt" => PT,
+        _ => EN,
+    }
+}
+
+fn lookup(table: &[(&'static str, &'static str)], key: &str) -> Option<&'static str> {
+    table.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+}
+
+/// Translate a message key into the given locale, filling in `{placeholder}` arguments
+///
+/// Falls back to English when the locale lacks the key, and to the key itself
+/// when no table has it (so a missing translation is visible rather than blank).
+pub fn tr(locale: &str, key: &str, args: &[(&str, &str)]) -> String {
+    let template = lookup(messages(locale), key)
+        .or_else(|| lookup(EN, key))
+        .unwrap_or(key);
+
+    // One pass over the template, so braces inside a substituted value are left alone
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        text.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = after
+            .find('}')
+            .and_then(|close| args.iter().find(|(name, _)| *name == &after[..close]).map(|(_, v)| (close, *v)));
+        match value {
+            Some((close, value)) => {
+                text.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                text.push('{');
+                rest = after;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
@@ -0,0 +1,209 @@
+//! QuestScribe - Marker Icon Packs
+//!
+//! Marker visuals normally use an emoji, but a marker can also reference an image
+//! from an icon pack via `MarkerVisual::icon_ref` ("<pack_id>/<icon_name>").
+//! The emoji stays on the marker as a fallback for when the pack isn't available.
+//!
+//! Packs are installed from a folder of SVG/PNG files and stored either inside
+//! the document (travels with the file) or in the app data directory (shared by
+//! all documents on this machine). Icon data is kept base64-encoded so packs
+//! serialize into the JSON document format unchanged.
+
+use crate::base64;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Largest image accepted into a pack; icons are tiny and documents shouldn't balloon
+const MAX_ICON_BYTES: usize = 512 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IconPack {
+    pub id: String,
+    pub name: String,
+    pub icons: Vec<PackIcon>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackIcon {
+    pub name: String,      // File stem, e.g. "sword"
+    pub mime_type: String, // "image/svg+xml" or "image/png"
+    pub data: String,      // Base64-encoded file contents
+}
+
+/// Where an installed pack lives
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackLocation {
+    Document,
+    App,
+}
+
+/// Summary of a pack for listing in the UI (without image data)
+#[derive(Debug, Clone, Serialize)]
+pub struct IconPackInfo {
+    pub id: String,
+    pub name: String,
+    pub location: PackLocation,
+    pub icon_names: Vec<String>,
+}
+
+/// An icon ready for rendering (in the editor or an export)
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedIcon {
+    pub mime_type: String,
+    pub data_uri: String,
+}
+
+impl IconPack {
+    pub fn info(&self, location: PackLocation) -> IconPackInfo {
+        IconPackInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            location,
+            icon_names: self.icons.iter().map(|i| i.name.clone()).collect(),
+        }
+    }
+}
+
+impl PackIcon {
+    pub fn resolve(&self) -> ResolvedIcon {
+        ResolvedIcon {
+            mime_type: self.mime_type.clone(),
+            data_uri: format!("data:{};base64,{}", self.mime_type, self.data),
+        }
+    }
+}
+
+/// Split an icon reference "<pack_id>/<icon_name>" into its parts
+pub fn parse_icon_ref(icon_ref: &str) -> Result<(&str, &str), String> {
+    icon_ref
+        .split_once('/')
+        .filter(|(pack, icon)| !pack.is_empty() && !icon.is_empty())
+        .ok_or_else(|| format!("Invalid icon reference: {}", icon_ref))
+}
+
+/// Find an icon in a set of packs
+pub fn find_icon<'a>(packs: impl IntoIterator<Item = &'a IconPack>, icon_ref: &str) -> Option<&'a PackIcon> {
+    let (pack_id, icon_name) = parse_icon_ref(icon_ref).ok()?;
+
+    packs
+        .into_iter()
+        .find(|p| p.id == pack_id)
+        .and_then(|p| p.icons.iter().find(|i| i.name == icon_name))
+}
+
+/// Build a pack from every .svg and .png file in a directory
+pub fn load_pack_from_dir(source_dir: &Path, id: String, name: String) -> Result<IconPack, String> {
+    let entries = fs::read_dir(source_dir)
+        .map_err(|e| format!("Failed to read icon folder: {}", e))?;
+
+    let mut icons = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Failed to read icon folder: {}", e))?.path();
+
+        let mime_type = match path.extension().and_then(|s| s.to_str()).map(|s| s.to_lowercase()).as_deref() {
+            Some("svg") => "image/svg+xml",
+            Some("png") => "image/png",
+            _ => continue,
+        };
+        let icon_name = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(stem) => stem.to_string(),
+            None => continue,
+        };
+
+        let bytes = fs::read(&path)
+            .map_err(|e| format!("Failed to read icon {}: {}", path.display(), e))?;
+        if bytes.len() > MAX_ICON_BYTES {
+            return Err(format!("Icon {} is larger than {} KB", icon_name, MAX_ICON_BYTES / 1024));
+        }
+
+        icons.push(PackIcon {
+            name: icon_name,
+            mime_type: mime_type.to_string(),
+            data: base64::encode(&bytes),
+        });
+    }
+
+    if icons.is_empty() {
+        return Err("No SVG or PNG icons found in the selected folder".to_string());
+    }
+
+    icons.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(IconPack { id, name, icons })
+}
+
+// Whether a pack ID can name a file in the icon directory (no separators, "..", or absolute paths)
+fn is_plain_id(pack_id: &str) -> bool {
+    !pack_id.is_empty()
+        && !pack_id.contains(['/', '\\', ':'])
+        && !pack_id.contains("..")
+        && !Path::new(pack_id).is_absolute()
+}
+
+/// Path of an app-level pack file inside the app data icon directory
+pub fn app_pack_path(pack_dir: &Path, pack_id: &str) -> Result<PathBuf, String> {
+    if !is_plain_id(pack_id) {
+        return Err(format!("Invalid icon pack ID: {}", pack_id));
+    }
+    Ok(pack_dir.join(format!("{}.json", pack_id)))
+}
+
+// Every app-level pack with the file it was read from
+fn read_app_packs(pack_dir: &Path) -> Result<Vec<(PathBuf, IconPack)>, String> {
+    let entries = match fs::read_dir(pack_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut packs = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("json") {
+            continue;
+        }
+
+        let json = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read icon pack: {}", e))?;
+        let pack: IconPack = serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse icon pack {}: {}", path.display(), e))?;
+        packs.push((path, pack));
+    }
+
+    Ok(packs)
+}
+
+/// Load all app-level packs from the app data icon directory (missing directory = no packs)
+pub fn load_app_packs(pack_dir: &Path) -> Result<Vec<IconPack>, String> {
+    let mut packs: Vec<IconPack> = read_app_packs(pack_dir)?.into_iter().map(|(_, pack)| pack).collect();
+    packs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(packs)
+}
+
+/// Delete an app-level pack; only a file in the icon directory holding a pack with this ID is removed
+pub fn remove_app_pack(pack_dir: &Path, pack_id: &str) -> Result<(), String> {
+    if !is_plain_id(pack_id) {
+        return Err(format!("Invalid icon pack ID: {}", pack_id));
+    }
+
+    let (path, _) = read_app_packs(pack_dir)?
+        .into_iter()
+        .find(|(_, pack)| pack.id == pack_id)
+        .ok_or("Icon pack not found")?;
+
+    fs::remove_file(&path)
+        .map_err(|e| format!("Failed to remove icon pack: {}", e))
+}
+
+/// Save an app-level pack into the app data icon directory
+pub fn save_app_pack(pack_dir: &Path, pack: &IconPack) -> Result<(), String> {
+    fs::create_dir_all(pack_dir)
+        .map_err(|e| format!("Failed to create icon pack folder: {}", e))?;
+
+    let json = serde_json::to_string_pretty(pack)
+        .map_err(|e| format!("Failed to serialize icon pack: {}", e))?;
+
+    fs::write(app_pack_path(pack_dir, &pack.id)?, json)
+        .map_err(|e| format!("Failed to write icon pack: {}", e))
+}
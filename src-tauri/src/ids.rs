@@ -0,0 +1,75 @@
+//! QuestScribe - Identifiers and Replay Mode
+//!
+//! Every ID the backend generates (entities, markers, threads, suggestions...)
+//! comes from `new_id()`. Normally that's a random v4 UUID; in replay mode IDs
+//! are sequential and the clock is fixed (see dates.rs), so replaying the same
+//! commands against the same document creates the same IDs and timestamps, and
+//! (since saved files list entities and markers in ID order) saves the same
+//! file. That's what tests, document replays and sync need to compare runs.
+//!
+//! Sequential IDs keep the UUID shape ("00000000-0000-4000-8000-000000000001")
+//! so nothing downstream needs to tell them apart. Replay mode is process-wide;
+//! turning it off returns to random IDs and the system clock. Sequential IDs a
+//! loaded document already uses are skipped (see `reserve`), so a new ID never
+//! overwrites an existing entity, marker, or thread event.
+
+use crate::dates;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+/// Replay mode settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayMode {
+    pub first_id: u64, // Sequence number of the next ID
+    pub fixed_time: Option<i64>, // Unix seconds `now()` returns; None = the system clock
+}
+
+// Next sequence number while replay mode is on
+static SEQUENCE: Mutex<Option<u64>> = Mutex::new(None);
+
+const SEQUENTIAL_PREFIX: &str = "00000000-0000-4000-8000-";
+
+// Sequence number of a sequential ID (None for random UUIDs)
+fn sequence_number(id: &str) -> Option<u64> {
+    id.strip_prefix(SEQUENTIAL_PREFIX)
+        .filter(|hex| hex.len() == 12)
+        .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+}
+
+/// A new unique ID (sequential in replay mode)
+pub fn new_id() -> String {
+    let mut sequence = SEQUENCE.lock().unwrap();
+    match sequence.as_mut() {
+        Some(next) => {
+            let id = format!("{}{:012x}", SEQUENTIAL_PREFIX, *next);
+            *next += 1;
+            id
+        }
+        None => uuid::Uuid::new_v4().to_string(),
+    }
+}
+
+/// Turn replay mode on (Some) or off (None)
+pub fn set_replay_mode(mode: Option<ReplayMode>) {
+    *SEQUENCE.lock().unwrap() = mode.as_ref().map(|m| m.first_id);
+    dates::set_fixed_clock(mode.and_then(|m| m.fixed_time));
+}
+
+/// Move the sequence past the sequential IDs in `in_use` (no-op outside replay mode)
+pub fn reserve<'a>(in_use: impl IntoIterator<Item = &'a str>) {
+    let mut sequence = SEQUENCE.lock().unwrap();
+    if let Some(next) = sequence.as_mut() {
+        for number in in_use.into_iter().filter_map(sequence_number) {
+            if number >= *next {
+                *next = number + 1;
+            }
+        }
+    }
+}
+
+/// Current replay mode (None = off)
+pub fn replay_mode() -> Option<ReplayMode> {
+    let first_id = (*SEQUENCE.lock().unwrap())?;
+    Some(ReplayMode { first_id, fixed_time: dates::fixed_clock() })
+}
@@ -0,0 +1,319 @@
+//! QuestScribe - Document Importers
+//!
+//! Each import format is an `Importer`: a name, the file extensions it
+//! usually has, a sniff of the file's bytes saying how sure it is the file is
+//! in its format, and a function reading the file into ProseMirror JSON.
+//!
+//! Files are routed by what they contain rather than what they're called, so
+//! a ".doc" that's really RTF goes to the RTF importer and a ".txt" written in
+//! Markdown keeps its headings and emphasis. Magic bytes (RTF's "{\rtf", a
+//! Word zip, an old Word compound file) are certain; Markdown features in
+//! text are likely; any UTF-8 text is plausible as plain text. The surest
+//! importer wins, and on a tie the one the extension names, then the first in
+//! `IMPORTERS`.
+
+use crate::markdown;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::Cursor;
+
+/// How sure an importer is that a file is in its format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    No,
+    Plausible, // It can read the file (e.g., any text is plain text)
+    Likely,    // The content has features of the format
+    Certain,   // The file starts with the format's signature
+}
+
+pub trait Importer: Sync {
+    /// Name shown in the import dialog
+    fn name(&self) -> &'static str;
+    /// File extensions, lowercase without the dot; the first is the usual one
+    fn extensions(&self) -> &'static [&'static str];
+    fn sniff(&self, bytes: &[u8]) -> Confidence;
+    /// Read the file into ProseMirror JSON
+    fn import(&self, bytes: &[u8]) -> Result<String, String>;
+}
+
+/// An import format, as listed to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportFormatInfo {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+}
+
+/// Every import format (plain text first, so it wins ties between text formats)
+pub const IMPORTERS: &[&dyn Importer] = &[&TxtImporter, &MarkdownImporter, &RtfImporter, &WordImporter];
+
+/// The importer for a file's content, preferring the one its extension names on a tie
+pub fn detect(bytes: &[u8], extension: &str) -> Option<&'static dyn Importer> {
+    let extension = extension.to_lowercase();
+    let mut best: Option<(Confidence, bool, &'static dyn Importer)> = None;
+
+    for importer in IMPORTERS.iter().copied() {
+        let confidence = importer.sniff(bytes);
+        if confidence == Confidence::No {
+            continue;
+        }
+        let named = importer.extensions().contains(&extension.as_str());
+        if best.is_none_or(|(c, n, _)| (confidence, named) > (c, n)) {
+            best = Some((confidence, named, importer));
+        }
+    }
+
+    best.map(|(_, _, importer)| importer)
+}
+
+pub fn list_formats() -> Vec<ImportFormatInfo> {
+    IMPORTERS
+        .iter()
+        .map(|i| ImportFormatInfo { name: i.name(), extensions: i.extensions() })
+        .collect()
+}
+
+// The file as UTF-8 text, without a byte order mark
+fn utf8_text(bytes: &[u8]) -> Option<&str> {
+    let bytes = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    std::str::from_utf8(bytes).ok()
+}
+
+fn read_text(bytes: &[u8]) -> Result<&str, String> {
+    utf8_text(bytes).ok_or_else(|| "Failed to read file: it isn't UTF-8 text".to_string())
+}
+
+// ProseMirror JSON of a document's blocks
+fn doc_json(blocks: Vec<Value>) -> String {
+    let doc = json!({
+        "type": "doc",
+        "content": blocks
+    });
+
+    serde_json::to_string(&doc).unwrap()
+}
+
+// Helper to convert plain text to ProseMirror JSON
+fn text_to_prosemirror(text: &str) -> String {
+    let mut paragraphs = Vec::new();
+
+    for line in text.split("\n\n") {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        paragraphs.push(json!({
+            "type": "paragraph",
+            "content": [{
+                "type": "text",
+                "text": line.trim()
+            }]
+        }));
+    }
+
+    doc_json(paragraphs)
+}
+
+pub struct TxtImporter;
+
+impl Importer for TxtImporter {
+    fn name(&self) -> &'static str {
+        "Plain text"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["txt", "text"]
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> Confidence {
+        match utf8_text(bytes) {
+            Some(_) => Confidence::Plausible,
+            None => Confidence::No,
+        }
+    }
+
+    fn import(&self, bytes: &[u8]) -> Result<String, String> {
+        let text = read_text(bytes)?.replace("\r\n", "\n");
+        Ok(text_to_prosemirror(&text))
+    }
+}
+
+pub struct MarkdownImporter;
+
+// ProseMirror text nodes of Markdown spans (links keep their text)
+fn inline_nodes(spans: &[markdown::Span]) -> Vec<Value> {
+    spans
+        .iter()
+        .map(|span| {
+            let mut node = json!({ "type": "text", "text": span.text });
+            let marks: Vec<Value> = [(span.bold, "strong"), (span.italic, "em"), (span.code, "code")]
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, mark)| json!({ "type": mark }))
+                .collect();
+            if !marks.is_empty() {
+                node["marks"] = json!(marks);
+            }
+            node
+        })
+        .collect()
+}
+
+// Convert Markdown to ProseMirror JSON: headings, paragraphs, emphasis and scene breaks.
+// List items and block quotes become paragraphs of their text.
+fn markdown_to_prosemirror(text: &str) -> String {
+    let mut blocks: Vec<Value> = markdown::parse(text)
+        .into_iter()
+        .map(|block| match block {
+            markdown::Block::Heading { level, spans } => {
+                json!({ "type": "heading", "attrs": { "level": level }, "content": inline_nodes(&spans) })
+            }
+            markdown::Block::Paragraph(spans) | markdown::Block::Quote(spans) => {
+                json!({ "type": "paragraph", "content": inline_nodes(&spans) })
+            }
+            markdown::Block::ListItem { marker, spans, .. } => {
+                let mut content = vec![json!({ "type": "text", "text": format!("{} ", marker) })];
+                content.extend(inline_nodes(&spans));
+                json!({ "type": "paragraph", "content": content })
+            }
+            markdown::Block::Break => json!({ "type": "paragraph", "content": [{ "type": "text", "text": "* * *" }] }),
+        })
+        .collect();
+
+    // Headings or paragraphs that were only markup have no text nodes, which ProseMirror rejects
+    for block in &mut blocks {
+        if block["content"].as_array().is_some_and(|c| c.is_empty()) {
+            block.as_object_mut().unwrap().remove("content");
+        }
+    }
+
+    doc_json(blocks)
+}
+
+impl Importer for MarkdownImporter {
+    fn name(&self) -> &'static str {
+        "Markdown"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["md", "markdown"]
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> Confidence {
+        match utf8_text(bytes) {
+            Some(text) if markdown::looks_like_markdown(text) => Confidence::Likely,
+            Some(_) => Confidence::Plausible,
+            None => Confidence::No,
+        }
+    }
+
+    fn import(&self, bytes: &[u8]) -> Result<String, String> {
+        Ok(markdown_to_prosemirror(read_text(bytes)?))
+    }
+}
+
+pub struct RtfImporter;
+
+// Basic RTF text extraction - strips RTF control codes
+fn extract_text_from_rtf(rtf: &str) -> String {
+    let mut result = String::new();
+    let mut in_group: i32 = 0;
+    let mut chars = rtf.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' => {
+                in_group += 1;
+            }
+            '}' => {
+                in_group = in_group.saturating_sub(1);
+            }
+            '\\' => {
+                // Skip control word
+                while let Some(&next_ch) = chars.peek() {
+                    if next_ch.is_alphabetic() || next_ch == '-' || next_ch.is_numeric() {
+                        chars.next();
+                    } else {
+                        if next_ch == ' ' {
+                            chars.next(); // consume delimiter space
+                        }
+                        break;
+                    }
+                }
+
+                // Handle special RTF escapes
+                if let Some(&('\\' | '{' | '}')) = chars.peek() {
+                    result.push(chars.next().unwrap());
+                }
+            }
+            _ if in_group <= 2 => {
+                // Only include text in main content (group level 1-2)
+                result.push(ch);
+            }
+            _ => {}
+        }
+    }
+
+    result.trim().to_string()
+}
+
+impl Importer for RtfImporter {
+    fn name(&self) -> &'static str {
+        "Rich Text Format"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["rtf"]
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> Confidence {
+        if bytes.trim_ascii_start().starts_with(b"{\\rtf") {
+            Confidence::Certain
+        } else {
+            Confidence::No
+        }
+    }
+
+    fn import(&self, bytes: &[u8]) -> Result<String, String> {
+        // Basic RTF text extraction (formatting will be lost); RTF is ASCII with escapes
+        let content = String::from_utf8_lossy(bytes);
+        let text = extract_text_from_rtf(&content);
+        Ok(text_to_prosemirror(&text))
+    }
+}
+
+pub struct WordImporter;
+
+// Signature of the compound files Word 97-2003 saved .doc files as
+const COMPOUND_FILE_SIGNATURE: &[u8] = b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1";
+
+impl Importer for WordImporter {
+    fn name(&self) -> &'static str {
+        "Word document"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["docx", "doc"]
+    }
+
+    fn sniff(&self, bytes: &[u8]) -> Confidence {
+        if bytes.starts_with(COMPOUND_FILE_SIGNATURE) {
+            return Confidence::Certain;
+        }
+        if !bytes.starts_with(b"PK\x03\x04") {
+            return Confidence::No;
+        }
+        // A zip is only a Word document if it has the main document part
+        match zip::ZipArchive::new(Cursor::new(bytes)) {
+            Ok(archive) if archive.file_names().any(|name| name == "word/document.xml") => Confidence::Certain,
+            _ => Confidence::No,
+        }
+    }
+
+    fn import(&self, _bytes: &[u8]) -> Result<String, String> {
+        // DOCX/DOC files are binary and cannot be imported without a parsing library
+        // Due to compatibility issues with available Rust libraries, DOCX import is not currently supported.
+        // When it is, Word comments and tracked changes (w:comment, w:ins, w:del) should be carried over
+        // as marker descriptions and suggestions (see track_changes.rs) rather than dropped.
+        Err("DOCX/DOC import is not currently supported. Please export your document as plain text (.txt) or RTF (.rtf) first, then import it.".to_string())
+    }
+}
@@ -0,0 +1,104 @@
+//! QuestScribe - Knowledge Tracking
+//!
+//! Tracks who knows what, and since when, for managing dramatic irony. A
+//! `learn` change records that an entity learns a fact: its field name is the
+//! fact (e.g., "Aldric is the traitor") and its value, if any, says how they
+//! learned it. Facts live in the entity's state under the `knowledge` group, so
+//! they show on character sheets and a `remove` change on "knowledge.<fact>"
+//! makes an entity forget.
+
+use crate::engine;
+use crate::state::{ChangeType, Entity, FieldChange, Marker};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Field group that holds learned facts
+pub const KNOWLEDGE_FIELD: &str = "knowledge";
+
+/// State path of a fact ("knowledge.<fact>")
+pub fn fact_path(fact: &str) -> String {
+    let prefix = format!("{}.", KNOWLEDGE_FIELD);
+    if fact.starts_with(&prefix) {
+        fact.to_string()
+    } else {
+        format!("{}{}", prefix, fact)
+    }
+}
+
+/// State path a change writes to (learned facts go under the knowledge group)
+pub fn change_path(change: &FieldChange) -> String {
+    match change.change_type {
+        ChangeType::Learn => fact_path(&change.field_name),
+        _ => change.field_name.clone(),
+    }
+}
+
+/// The fact a change teaches, if it's a learn change
+pub fn learned_fact(change: &FieldChange) -> Option<&str> {
+    (change.change_type == ChangeType::Learn).then(|| {
+        change
+            .field_name
+            .strip_prefix(&format!("{}.", KNOWLEDGE_FIELD))
+            .unwrap_or(&change.field_name)
+    })
+}
+
+/// An entity that knows a fact at some position
+#[derive(Debug, Clone, Serialize)]
+pub struct Knower {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub learned_at: usize, // Position of the latest change that set the fact
+    pub source: Option<String>, // How they learned it, if recorded
+}
+
+/// First position at which each entity learns each fact, keyed by (entity ID, fact)
+pub fn first_learned(markers: &HashMap<String, Marker>) -> HashMap<(String, String), usize> {
+    let mut first: HashMap<(String, String), usize> = HashMap::new();
+
+    for marker in markers.values().filter(|m| !m.todo) {
+        for fact in marker.changes.iter().filter_map(learned_fact) {
+            first
+                .entry((marker.entity_id.clone(), fact.to_string()))
+                .and_modify(|pos| *pos = (*pos).min(marker.position))
+                .or_insert(marker.position);
+        }
+    }
+
+    first
+}
+
+/// Everyone who knows a fact at a position (markers at the position are included), sorted by name
+pub fn who_knows(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    fact: &str,
+    position: usize,
+) -> Vec<Knower> {
+    let path = fact_path(fact);
+
+    let mut knowers: Vec<Knower> = entities
+        .values()
+        .filter_map(|entity| {
+            let state = engine::entity_state_with_defaults(markers, entity, position);
+            let value = engine::get_nested_value(&state, &path)?;
+
+            let learned_at = markers
+                .values()
+                .filter(|m| m.entity_id == entity.id && m.position <= position && !m.todo)
+                .filter(|m| m.changes.iter().any(|c| c.change_type != ChangeType::Remove && change_path(c) == path))
+                .map(|m| m.position)
+                .max()?;
+
+            Some(Knower {
+                entity_id: entity.id.clone(),
+                entity_name: entity.name.clone(),
+                learned_at,
+                source: value.as_str().map(str::to_string),
+            })
+        })
+        .collect();
+
+    knowers.sort_by(|a, b| a.entity_name.cmp(&b.entity_name));
+    knowers
+}
@@ -0,0 +1,272 @@
+//! QuestScribe - Legacy Document Reader
+//!
+//! Files saved before documents carried a format version are read through a
+//! normalizing pass first, so files from the earliest releases keep opening:
+//!
+//! - change types in other spellings or case ("Set", "add", "delete") become
+//!   the current ones, and changes keyed "field"/"type" get the current keys
+//! - change values that aren't text (numbers, booleans) become text
+//! - markers without a visual get the default icon in their entity's color,
+//!   and fractional positions are rounded
+//! - field metadata without timestamps (or saved as a bare timestamp) is
+//!   filled in
+//! - fields markers change but the entity doesn't list are added to it
+//!
+//! Anything still unreadable afterwards is quarantined (see quarantine.rs).
+//! When the pass changes something, a migration record listing what it did is
+//! added to the document's migration history, which is saved with it, and the
+//! diagnostic log (see logging.rs) gets the same notes. Every save writes the
+//! current format version, so a file is only migrated once.
+
+use crate::dates;
+use crate::knowledge;
+use crate::logging;
+use crate::state::{Document, FieldMetadata};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Format version written by this release
+pub const FORMAT_VERSION: u32 = 1;
+
+const DEFAULT_ICON: &str = "⭐";
+const DEFAULT_COLOR: &str = "#FFD700";
+
+/// What reading an older file changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Migration {
+    pub at: i64,
+    pub from_version: u32,
+    pub to_version: u32,
+    pub notes: Vec<String>,
+}
+
+/// Format version of a parsed file (0 = saved before versions were written)
+pub fn file_version(value: &Value) -> u32 {
+    value.get("format_version").and_then(|v| v.as_u64()).unwrap_or(0) as u32
+}
+
+// Current spelling of a change type, for the spellings older files used
+fn change_type_spelling(name: &str) -> Option<&'static str> {
+    match name.trim().to_lowercase().as_str() {
+        "absolute" | "set" | "assign" | "=" => Some("absolute"),
+        "relative" | "add" | "increment" | "modify" | "delta" | "+" | "+=" => Some("relative"),
+        "remove" | "delete" | "unset" | "clear" => Some("remove"),
+        "learn" | "learns" | "learned" => Some("learn"),
+        "custom" => Some("custom"),
+        _ => None,
+    }
+}
+
+// Move a value to its current key unless that key is already set
+fn rename_key(object: &mut Map<String, Value>, old: &str, new: &str) -> bool {
+    if object.contains_key(new) {
+        return false;
+    }
+    match object.remove(old) {
+        Some(value) => {
+            object.insert(new.to_string(), value);
+            true
+        }
+        None => false,
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    change_keys: usize,
+    change_types: usize,
+    values: usize,
+    visuals: usize,
+    positions: usize,
+    timestamps: usize,
+}
+
+fn normalize_change(change: &mut Value, counts: &mut Counts) {
+    let Some(object) = change.as_object_mut() else {
+        return;
+    };
+
+    let renamed_field = rename_key(object, "field", "field_name");
+    let renamed_type = rename_key(object, "type", "change_type");
+    if renamed_field || renamed_type {
+        counts.change_keys += 1;
+    }
+
+    let name = object.get("change_type").and_then(|t| t.as_str());
+    if let Some((current, name)) = name.and_then(|name| Some((change_type_spelling(name)?, name))) {
+        if current != name {
+            object.insert("change_type".to_string(), json!(current));
+            counts.change_types += 1;
+        }
+    }
+
+    match object.get("value") {
+        Some(Value::String(_)) => {}
+        Some(Value::Null) | None => {
+            object.insert("value".to_string(), json!(""));
+            counts.values += 1;
+        }
+        Some(other) => {
+            let text = other.to_string();
+            object.insert("value".to_string(), json!(text));
+            counts.values += 1;
+        }
+    }
+}
+
+fn normalize_marker(marker: &mut Value, entity_colors: &Map<String, Value>, counts: &mut Counts) {
+    let Some(object) = marker.as_object_mut() else {
+        return;
+    };
+
+    if let Some(position) = object.get("position").and_then(|p| p.as_f64()) {
+        if object.get("position").is_some_and(|p| !p.is_u64()) {
+            object.insert("position".to_string(), json!(position.max(0.0).round() as u64));
+            counts.positions += 1;
+        }
+    }
+
+    let color = object
+        .get("entity_id")
+        .and_then(|id| id.as_str())
+        .and_then(|id| entity_colors.get(id))
+        .cloned()
+        .unwrap_or_else(|| json!(DEFAULT_COLOR));
+    let visual = object.entry("visual".to_string()).or_insert_with(|| json!({}));
+    if let Some(visual) = visual.as_object_mut() {
+        let mut filled = false;
+        if !visual.contains_key("icon") {
+            visual.insert("icon".to_string(), json!(DEFAULT_ICON));
+            filled = true;
+        }
+        if !visual.contains_key("color") {
+            visual.insert("color".to_string(), color);
+            filled = true;
+        }
+        if filled {
+            counts.visuals += 1;
+        }
+    }
+
+    for change in object.get_mut("changes").and_then(|c| c.as_array_mut()).into_iter().flatten() {
+        normalize_change(change, counts);
+    }
+    for outcome in object.get_mut("outcomes").and_then(|o| o.as_array_mut()).into_iter().flatten() {
+        for change in outcome.get_mut("changes").and_then(|c| c.as_array_mut()).into_iter().flatten() {
+            normalize_change(change, counts);
+        }
+    }
+}
+
+fn normalize_entity(entity: &mut Value, now: i64, counts: &mut Counts) {
+    let Some(metadata) = entity.get_mut("field_metadata").and_then(|m| m.as_object_mut()) else {
+        return;
+    };
+
+    for meta in metadata.values_mut() {
+        if let Some(timestamp) = meta.as_i64() {
+            *meta = json!({ "created_at": timestamp, "last_modified": timestamp });
+            counts.timestamps += 1;
+            continue;
+        }
+        let Some(object) = meta.as_object_mut() else {
+            continue;
+        };
+        let known = object
+            .get("created_at")
+            .or_else(|| object.get("last_modified"))
+            .and_then(|t| t.as_i64())
+            .unwrap_or(now);
+        let mut filled = false;
+        for key in ["created_at", "last_modified"] {
+            if !object.get(key).is_some_and(|t| t.is_i64()) {
+                object.insert(key.to_string(), json!(known));
+                filled = true;
+            }
+        }
+        if filled {
+            counts.timestamps += 1;
+        }
+    }
+}
+
+/// Bring a file saved before format versions up to the current layout, returning what changed
+pub fn normalize(value: &mut Value) -> Vec<String> {
+    let now = dates::now();
+    let mut counts = Counts::default();
+    let mut notes = Vec::new();
+
+    if let Some(content) = value.get_mut("content").filter(|c| !c.is_string() && !c.is_null()) {
+        *content = json!(content.to_string());
+        notes.push("Stored the content as text".to_string());
+    }
+
+    let mut entity_colors = Map::new();
+    for entity in value.get_mut("entities").and_then(|e| e.as_array_mut()).into_iter().flatten() {
+        normalize_entity(entity, now, &mut counts);
+        if let (Some(id), Some(color)) = (entity.get("id").and_then(|i| i.as_str()), entity.get("color")) {
+            entity_colors.insert(id.to_string(), color.clone());
+        }
+    }
+    for marker in value.get_mut("markers").and_then(|m| m.as_array_mut()).into_iter().flatten() {
+        normalize_marker(marker, &entity_colors, &mut counts);
+    }
+
+    let described = [
+        (counts.change_keys, format!("Renamed the keys of {} change(s)", counts.change_keys)),
+        (counts.change_types, format!("Updated the change type spelling of {} change(s)", counts.change_types)),
+        (counts.values, format!("Converted {} change value(s) to text", counts.values)),
+        (counts.visuals, format!("Gave {} marker(s) the default icon or color", counts.visuals)),
+        (counts.positions, format!("Rounded {} fractional marker position(s)", counts.positions)),
+        (counts.timestamps, format!("Filled in the timestamps of {} field(s)", counts.timestamps)),
+    ];
+    notes.extend(described.into_iter().filter(|(count, _)| *count > 0).map(|(_, note)| note));
+    notes
+}
+
+// Add fields the entities' markers change but the entities don't list; returns how many
+fn list_changed_fields(document: &mut Document) -> usize {
+    let mut added = 0;
+    for marker in &document.markers {
+        let Some(entity) = document.entities.iter_mut().find(|e| e.id == marker.entity_id) else {
+            continue;
+        };
+        for change in &marker.changes {
+            let field = knowledge::change_path(change);
+            if entity.fields.contains(&field) {
+                continue;
+            }
+            entity.fields.push(field.clone());
+            entity.field_metadata.entry(field).or_insert(FieldMetadata {
+                created_at: marker.created_at,
+                last_modified: marker.modified_at,
+                field_type: None,
+                default_value: None,
+            });
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Finish reading a file of an older format: fill in what the typed document still lacks,
+/// record the migration (if anything changed), and mark the document current
+pub fn complete(document: &mut Document, from_version: u32, mut notes: Vec<String>) {
+    let added = list_changed_fields(document);
+    if added > 0 {
+        notes.push(format!("Listed {} field(s) that markers change", added));
+    }
+
+    if !notes.is_empty() {
+        for note in &notes {
+            logging::record_event("load_document", &format!("Migrated from format {}: {}", from_version, note));
+        }
+        document.migrations.push(Migration {
+            at: dates::now(),
+            from_version,
+            to_version: FORMAT_VERSION,
+            notes,
+        });
+    }
+    document.format_version = FORMAT_VERSION;
+}
@@ -0,0 +1,207 @@
+//! QuestScribe - Optional LLM Summarization
+//!
+//! Recaps and synopses are built from structured change lists and rendered with
+//! fixed sentence templates. Users who want more natural prose can point
+//! QuestScribe at a language model, either a local server (Ollama, LM Studio,
+//! llama.cpp) or a hosted API with their own key.
+//!
+//! This is off by default. The configuration lives in the app data directory,
+//! never inside documents, so API keys don't travel with shared files. When the
+//! provider fails, callers fall back to the template prose.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Config file name inside the app data directory
+pub const CONFIG_FILE: &str = "llm.json";
+
+/// Wire protocol spoken by the configured endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[default]
+    OpenaiCompatible, // POST {endpoint}/chat/completions (OpenAI, LM Studio, llama.cpp, ...)
+    Ollama,           // POST {endpoint}/api/generate
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    #[serde(default)]
+    pub endpoint: String, // Base URL, e.g. "http://localhost:11434" or "https://api.openai.com/v1"
+    #[serde(default)]
+    pub api_key: Option<String>, // Sent as a Bearer token when set
+    #[serde(default)]
+    pub model: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            provider: ProviderKind::default(),
+            endpoint: String::new(),
+            api_key: None,
+            model: String::new(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+impl LlmConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if !(self.endpoint.starts_with("http://") || self.endpoint.starts_with("https://")) {
+            return Err("The endpoint must be an http:// or https:// URL".to_string());
+        }
+        if self.model.trim().is_empty() {
+            return Err("A model name is required".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Something that can turn a prompt into prose
+pub trait TextProvider {
+    fn complete(&self, prompt: &str) -> Result<String, String>;
+}
+
+struct OpenAiCompatible {
+    agent: ureq::Agent,
+    endpoint: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl TextProvider for OpenAiCompatible {
+    fn complete(&self, prompt: &str) -> Result<String, String> {
+        let mut request = self.agent.post(&format!("{}/chat/completions", self.endpoint));
+        if let Some(key) = &self.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+
+        let response: serde_json::Value = request
+            .send_json(serde_json::json!({
+                "model": self.model,
+                "messages": [{ "role": "user", "content": prompt }],
+            }))
+            .map_err(|e| format!("Summarization request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Failed to read summarization response: {}", e))?;
+
+        response
+            .pointer("/choices/0/message/content")
+            .and_then(|c| c.as_str())
+            .map(|c| c.trim().to_string())
+            .ok_or_else(|| "Summarization response had no text".to_string())
+    }
+}
+
+struct Ollama {
+    agent: ureq::Agent,
+    endpoint: String,
+    model: String,
+}
+
+impl TextProvider for Ollama {
+    fn complete(&self, prompt: &str) -> Result<String, String> {
+        let response: serde_json::Value = self
+            .agent
+            .post(&format!("{}/api/generate", self.endpoint))
+            .send_json(serde_json::json!({
+                "model": self.model,
+                "prompt": prompt,
+                "stream": false,
+            }))
+            .map_err(|e| format!("Summarization request failed: {}", e))?
+            .into_json()
+            .map_err(|e| format!("Failed to read summarization response: {}", e))?;
+
+        response
+            .get("response")
+            .and_then(|c| c.as_str())
+            .map(|c| c.trim().to_string())
+            .ok_or_else(|| "Summarization response had no text".to_string())
+    }
+}
+
+/// Build the configured provider, or None when summarization is turned off
+pub fn provider_from_config(config: &LlmConfig) -> Option<Box<dyn TextProvider>> {
+    if !config.enabled || config.validate().is_err() {
+        return None;
+    }
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(config.timeout_secs.max(1)))
+        .build();
+    let endpoint = config.endpoint.trim_end_matches('/').to_string();
+
+    Some(match config.provider {
+        ProviderKind::OpenaiCompatible => Box::new(OpenAiCompatible {
+            agent,
+            endpoint,
+            api_key: config.api_key.clone().filter(|k| !k.is_empty()),
+            model: config.model.clone(),
+        }),
+        ProviderKind::Ollama => Box::new(Ollama {
+            agent,
+            endpoint,
+            model: config.model.clone(),
+        }),
+    })
+}
+
+/// Load the config (a missing file means summarization is off)
+pub fn load_config(path: &Path) -> Result<LlmConfig, String> {
+    if !path.exists() {
+        return Ok(LlmConfig::default());
+    }
+
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read summarization settings: {}", e))?;
+
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse summarization settings: {}", e))
+}
+
+pub fn save_config(path: &Path, config: &LlmConfig) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create app data folder: {}", e))?;
+    }
+
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("Failed to serialize summarization settings: {}", e))?;
+
+    fs::write(path, json)
+        .map_err(|e| format!("Failed to write summarization settings: {}", e))
+}
+
+/// Prompt asking the model to narrate a structured change list
+///
+/// `language` is a language name (e.g., "Español") so the model answers in the
+/// document's language.
+pub fn summary_prompt(kind: &str, changes: &serde_json::Value, language: &str) -> String {
+    format!(
+        "You are helping an author of a LitRPG novel. Below is a JSON list of how each \
+         character's tracked state changed. Write a short {} in {}, in past tense, as \
+         flowing prose. Mention every change, invent no events, and do not use lists or \
+         headings.\n\n{}",
+        kind,
+        language,
+        serde_json::to_string_pretty(changes).unwrap_or_default()
+    )
+}
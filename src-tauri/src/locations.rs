@@ -0,0 +1,165 @@
+//! QuestScribe - Location Tracking
+//!
+//! Any entity can be placed somewhere with an absolute change to the reserved
+//! `location` field, whose value is the ID (or name) of a location entity (an
+//! entity of kind `location`). Removing the field means the entity's whereabouts
+//! are unknown. Replaying those changes answers where everyone is at any point
+//! in the story.
+//!
+//! Locations can record distances to each other (in the document's measurement
+//! units), which the continuity checker uses to flag implausibly fast travel.
+
+use crate::engine;
+use crate::state::{ChangeType, Entity, EntityKind, Marker};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Field holding an entity's current location
+pub const LOCATION_FIELD: &str = "location";
+
+/// A location as shown in query results
+#[derive(Debug, Clone, Serialize)]
+pub struct LocationRef {
+    pub id: String,
+    pub name: String,
+}
+
+/// An entity present at a location
+#[derive(Debug, Clone, Serialize)]
+pub struct Presence {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub since_position: usize, // Position of the marker that moved them there
+}
+
+/// One move in an entity's travel log
+#[derive(Debug, Clone, Serialize)]
+pub struct TravelEntry {
+    pub marker_id: String,
+    pub position: usize,
+    pub from: Option<LocationRef>, // None = whereabouts unknown before
+    pub to: Option<LocationRef>,   // None = whereabouts unknown after (field removed)
+}
+
+/// Resolve a `location` value to a location entity: its ID, or else its name
+/// (case-insensitive). Other values (e.g., free text from before locations
+/// existed) don't resolve and are ignored by location queries.
+pub fn resolve_location<'a>(entities: &'a HashMap<String, Entity>, value: &str) -> Option<&'a Entity> {
+    let is_location = |e: &&Entity| e.kind == EntityKind::Location;
+
+    entities.get(value).filter(is_location).or_else(|| {
+        entities
+            .values()
+            .filter(is_location)
+            .find(|e| e.name.trim().eq_ignore_ascii_case(value.trim()))
+    })
+}
+
+/// Distance between two locations, if either of them records it
+pub fn distance(entities: &HashMap<String, Entity>, from_id: &str, to_id: &str) -> Option<f64> {
+    if from_id == to_id {
+        return Some(0.0);
+    }
+    let recorded = |a: &str, b: &str| entities.get(a).and_then(|e| e.distances.get(b)).copied();
+    recorded(from_id, to_id).or_else(|| recorded(to_id, from_id))
+}
+
+fn location_ref(entity: &Entity) -> LocationRef {
+    LocationRef {
+        id: entity.id.clone(),
+        name: entity.name.clone(),
+    }
+}
+
+// An entity's markers in story order, without TODO reminders
+fn sorted_markers<'a>(markers: &'a HashMap<String, Marker>, entity_id: &str) -> Vec<&'a Marker> {
+    let mut list: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity_id && !m.todo).collect();
+    list.sort_by(|a, b| engine::compare_markers(a, b));
+    list
+}
+
+// Where a marker moves its entity: None = no location change,
+// Some(None) = whereabouts unknown from here on (removed or not a known location)
+fn location_change<'a>(entities: &'a HashMap<String, Entity>, marker: &Marker) -> Option<Option<&'a Entity>> {
+    marker
+        .changes
+        .iter()
+        .rev()
+        .find(|c| c.field_name == LOCATION_FIELD)
+        .map(|c| match c.change_type {
+            ChangeType::Remove => None,
+            _ => resolve_location(entities, &c.value),
+        })
+}
+
+/// Where an entity is at a position (markers at the position are included),
+/// with the position of the marker that moved it there
+pub fn location_at<'a>(
+    entities: &'a HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    entity_id: &str,
+    position: usize,
+) -> Option<(&'a Entity, usize)> {
+    let mut current = None;
+    for marker in sorted_markers(markers, entity_id) {
+        if marker.position > position {
+            break;
+        }
+        if let Some(change) = location_change(entities, marker) {
+            current = change.map(|location| (location, marker.position));
+        }
+    }
+    current
+}
+
+/// Every entity at a location at a position, sorted by name
+pub fn who_is_at(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    location_id: &str,
+    position: usize,
+) -> Vec<Presence> {
+    let mut present: Vec<Presence> = entities
+        .values()
+        .filter_map(|entity| {
+            let (at, since) = location_at(entities, markers, &entity.id, position)?;
+            (at.id == location_id).then(|| Presence {
+                entity_id: entity.id.clone(),
+                entity_name: entity.name.clone(),
+                since_position: since,
+            })
+        })
+        .collect();
+
+    present.sort_by(|a, b| a.entity_name.cmp(&b.entity_name));
+    present
+}
+
+/// Every location change of an entity, in story order
+pub fn travel_log(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    entity_id: &str,
+) -> Vec<TravelEntry> {
+    let mut log = Vec::new();
+    let mut current: Option<&Entity> = None;
+
+    for marker in sorted_markers(markers, entity_id) {
+        let Some(next) = location_change(entities, marker) else {
+            continue;
+        };
+        if next.map(|e| &e.id) == current.map(|e| &e.id) {
+            continue;
+        }
+
+        log.push(TravelEntry {
+            marker_id: marker.id.clone(),
+            position: marker.position,
+            from: current.map(location_ref),
+            to: next.map(location_ref),
+        });
+        current = next;
+    }
+
+    log
+}
@@ -0,0 +1,120 @@
+//! QuestScribe - Document Lock Files
+//!
+//! While a document is open, QuestScribe keeps an advisory lock file next to it
+//! (`.~lock.<file name>`), recording which app instance opened it. Another
+//! instance opening the same file sees the lock, warns the user, and can fall
+//! back to read-only mode; its saves are refused while the lock is held, so the
+//! second window can't silently overwrite the first.
+//!
+//! Locks are advisory: nothing stops other programs from writing the file. A lock
+//! left behind by a crashed instance on this machine is detected as stale.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Who holds a document's lock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub instance_id: String, // Random ID of the app instance that holds the lock
+    pub pid: u32,
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub user: String,
+    pub acquired_at: i64,
+}
+
+/// Path of the lock file for a document
+pub fn lock_path(document_path: &Path) -> PathBuf {
+    let file_name = document_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    document_path.with_file_name(format!(".~lock.{}", file_name))
+}
+
+fn env_value(names: &[&str]) -> String {
+    names
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .unwrap_or_default()
+}
+
+fn host_name() -> String {
+    env_value(&["HOSTNAME", "COMPUTERNAME"])
+}
+
+impl LockInfo {
+    fn new(instance_id: &str, now: i64) -> Self {
+        Self {
+            instance_id: instance_id.to_string(),
+            pid: std::process::id(),
+            host: host_name(),
+            user: env_value(&["USER", "USERNAME"]),
+            acquired_at: now,
+        }
+    }
+
+    // A lock from a process on this machine that no longer exists. Only Linux exposes
+    // running processes through the file system; elsewhere locks are never considered stale.
+    fn is_stale(&self) -> bool {
+        let proc_dir = Path::new("/proc");
+        !self.host.is_empty()
+            && self.host == host_name()
+            && proc_dir.is_dir()
+            && !proc_dir.join(self.pid.to_string()).exists()
+    }
+
+    /// Short description for warnings (e.g., "alice on studio-pc")
+    pub fn holder(&self) -> String {
+        match (self.user.is_empty(), self.host.is_empty()) {
+            (false, false) => format!("{} on {}", self.user, self.host),
+            (false, true) => self.user.clone(),
+            (true, false) => self.host.clone(),
+            (true, true) => format!("process {}", self.pid),
+        }
+    }
+}
+
+/// Read a document's lock, ignoring missing, unreadable, and stale lock files
+pub fn read_lock(document_path: &Path) -> Option<LockInfo> {
+    let json = fs::read_to_string(lock_path(document_path)).ok()?;
+    let info: LockInfo = serde_json::from_str(&json).ok()?;
+
+    if info.is_stale() {
+        return None;
+    }
+
+    Some(info)
+}
+
+/// The lock on a document held by a different app instance, if any
+pub fn foreign_lock(document_path: &Path, instance_id: &str) -> Option<LockInfo> {
+    read_lock(document_path).filter(|info| info.instance_id != instance_id)
+}
+
+/// Take the lock for this instance (overwrites any existing lock)
+pub fn write_lock(document_path: &Path, instance_id: &str, now: i64) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&LockInfo::new(instance_id, now))
+        .map_err(|e| format!("Failed to serialize lock: {}", e))?;
+
+    fs::write(lock_path(document_path), json)
+        .map_err(|e| format!("Failed to write lock file: {}", e))
+}
+
+/// Remove the lock if this instance holds it
+pub fn remove_lock(document_path: &Path, instance_id: &str) {
+    let path = lock_path(document_path);
+
+    let ours = fs::read_to_string(&path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<LockInfo>(&json).ok())
+        .is_some_and(|info| info.instance_id == instance_id);
+
+    if ours {
+        // Best effort: a leftover lock is reported as stale later
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,168 @@
+//! QuestScribe - Diagnostic Logging
+//!
+//! An opt-in structured log for bug reports, off unless the "diagnostic
+//! logging" setting is on. Each line of the log is a JSON record (see the
+//! tracing-subscriber JSON format) of one of:
+//!
+//! - a command invocation: its name, the window it came from, and how long it
+//!   took to dispatch. Synchronous commands run during dispatch, so that's
+//!   their duration; async commands are only timed until they're spawned.
+//! - a panic, with its location and the command that was running. A panic
+//!   while a lock is held is what leaves a session's state unusable.
+//! - a failed document load or save, with the error, and what loading an
+//!   older file migrated (see legacy.rs).
+//!
+//! Command results are returned to the webview without passing back through
+//! the dispatcher, so other errors aren't logged. Files rotate daily in the
+//! "logs" folder of the app data directory and the last week is kept. The
+//! global subscriber can only be installed once, so turning logging off only
+//! stops recording; the files stay open until the app exits.
+
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+/// Log folder inside the app data directory
+pub const LOG_DIR: &str = "logs";
+const FILE_PREFIX: &str = "questscribe";
+const FILE_SUFFIX: &str = "log";
+const KEPT_FILES: usize = 7;
+
+/// Records returned by get_recent_logs when no limit is given
+pub const DEFAULT_RECENT: usize = 200;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static WRITER: OnceLock<WorkerGuard> = OnceLock::new(); // Flushes the log when dropped at exit
+
+thread_local! {
+    // Command being dispatched on this thread, for panic records
+    static CURRENT_COMMAND: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Install the global subscriber writing to the log folder, once
+fn start(dir: &Path) -> Result<(), String> {
+    if WRITER.get().is_some() {
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create log folder: {}", e))?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(FILE_PREFIX)
+        .filename_suffix(FILE_SUFFIX)
+        .max_log_files(KEPT_FILES)
+        .build(dir)
+        .map_err(|e| format!("Failed to open log file: {}", e))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_writer(writer)
+        .with_max_level(tracing::Level::INFO)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|e| format!("Failed to start logging: {}", e))?;
+
+    WRITER.set(guard).ok();
+    install_panic_hook();
+    Ok(())
+}
+
+/// Turn logging on or off (the setting changed, or at startup)
+pub fn set_enabled(dir: &Path, enabled: bool) -> Result<(), String> {
+    if enabled {
+        start(dir)?;
+    }
+    ENABLED.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+// Record panics before the default hook prints them
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if is_enabled() {
+            let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+            let command = CURRENT_COMMAND.with(|c| c.borrow().clone()).unwrap_or_default();
+            tracing::error!(panic = %info, location = %location, command = %command, "panic");
+        }
+        default_hook(info);
+    }));
+}
+
+/// Run a command dispatch, recording it when logging is on
+pub fn record_command(command: &str, window: &str, dispatch: impl FnOnce()) {
+    if !is_enabled() {
+        dispatch();
+        return;
+    }
+
+    CURRENT_COMMAND.with(|c| *c.borrow_mut() = Some(command.to_string()));
+    let started = Instant::now();
+    dispatch();
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+    CURRENT_COMMAND.with(|c| *c.borrow_mut() = None);
+
+    tracing::info!(command = %command, window = %window, duration_ms = duration_ms, "command");
+}
+
+/// Record something an operation did that the dispatcher can't see (e.g., a file migration)
+pub fn record_event(operation: &str, message: &str) {
+    if is_enabled() {
+        tracing::info!(operation = %operation, message = %message, "event");
+    }
+}
+
+/// Record a failed operation that the dispatcher can't see (e.g., a document load)
+pub fn record_error(operation: &str, error: &str) {
+    if is_enabled() {
+        tracing::error!(operation = %operation, error = %error, "error");
+    }
+}
+
+/// The most recent log records, oldest first (lines that aren't JSON are skipped)
+pub fn recent_logs(dir: &Path, limit: usize) -> Result<Vec<Value>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    // Daily files are named by date, so name order is age order
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read log folder: {}", e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(FILE_PREFIX) && n.ends_with(FILE_SUFFIX))
+        })
+        .collect();
+    files.sort();
+
+    let mut records = Vec::new();
+    for path in files.iter().rev() {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read log file: {}", e))?;
+        let mut file_records: Vec<Value> = text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+
+        file_records.extend(records);
+        records = file_records;
+        if records.len() >= limit {
+            break;
+        }
+    }
+
+    let skip = records.len().saturating_sub(limit);
+    Ok(records.split_off(skip))
+}
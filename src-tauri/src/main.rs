@@ -11,475 +11,989 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod analysis;
+mod arc_outline;
+mod arcs;
+mod base64;
+mod batch;
+mod book_matter;
+mod bundle;
+mod change_types;
+mod chapter_import;
+mod chapters;
+mod chronology;
+mod clipboard;
+mod colors;
+mod content;
+mod continuity;
+mod continuity_report;
+mod csv;
+mod dates;
+mod descriptors;
+mod endnotes;
+mod engine;
+mod entity_import;
+mod entity_pack;
+mod entity_refs;
+mod export_check;
+mod export_paths;
+mod exporters;
+mod formula;
+mod gantt;
+mod glossary;
+mod goals;
+mod i18n;
+mod icons;
+mod ids;
+mod importers;
+mod knowledge;
+mod legacy;
+mod llm;
+mod locations;
+mod lockfile;
+mod logging;
+mod markdown;
+mod marker_csv;
+mod mentions;
+mod mutations;
+mod name_check;
+mod notes_vault;
+mod outline;
+mod pins;
+mod plot_threads;
+mod plottr_export;
+mod positions;
+mod preferences;
+mod progress;
+mod quarantine;
+mod recap;
+mod redaction;
+mod reports;
+mod restructure;
+mod search;
+mod settings;
+mod sessions;
+mod sheet_tokens;
+mod sheets;
 mod state;
+mod stats;
+mod status_window;
+mod strict;
+mod structure;
+mod suggestions;
+mod synopses;
+mod tool_import;
+mod track_changes;
+mod typography;
+mod visibility;
+mod visual_rules;
 
 use serde::Serialize;
-use state::{Entity, Marker, FieldChange, MarkerVisual, Document, AppState, ChangeType};
+use positions::TextEdit;
+use exporters::{FormattedParagraph, TextRun};
+use state::{Entity, EntityKind, Marker, MarkerOutcome, FieldChange, MarkerVisual, Document, AppState, DocumentState};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io::Cursor;
-use docx_rs::*;
+use tauri::Manager;
 
-// Helper function to set a nested value in a JSON object using a path like "stats.HP"
-fn set_nested_value(
-    state: &mut serde_json::Map<String, serde_json::Value>,
-    path: &str,
-    value: serde_json::Value,
-) {
-    let parts: Vec<&str> = path.split('.').collect();
-
-    if parts.len() == 1 {
-        // Simple field, no nesting
-        state.insert(path.to_string(), value);
-        return;
-    }
+// Tauri command to get all entities
+#[tauri::command]
+fn get_all_entities(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<Entity> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    entities.values().cloned().collect()
+}
 
-    // Build the path recursively
-    fn insert_at_path(
-        obj: &mut serde_json::Map<String, serde_json::Value>,
-        parts: &[&str],
-        value: serde_json::Value,
-    ) {
-        if parts.len() == 1 {
-            obj.insert(parts[0].to_string(), value);
-        } else {
-            let entry = obj
-                .entry(parts[0].to_string())
-                .or_insert_with(|| serde_json::json!({}));
-
-            if let Some(nested_obj) = entry.as_object_mut() {
-                insert_at_path(nested_obj, &parts[1..], value);
-            }
-        }
-    }
+// Tauri command to get entity state formatted as a character sheet
+#[tauri::command]
+fn format_character_sheet(
+    entity_id: String,
+    position: usize,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    character_sheet_at(&doc, &locale, &entity_id, position)
+}
 
-    insert_at_path(state, &parts, value);
+// Tauri command to get an entity's character sheet at a position as a tree of groups and
+// fields, with formatted values and the marker that last changed each field (see sheets.rs)
+#[tauri::command]
+fn get_character_sheet(
+    entity_id: String,
+    position: usize,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<sheets::CharacterSheet, String> {
+    let doc = state.document(session_id.as_deref());
+    let layouts = doc.preferences.lock().unwrap().sheet_layouts.clone();
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    let entity = entities.get(&entity_id).ok_or("Entity not found")?;
+    Ok(sheets::build(entity, &markers, position, sheets::layout_for(&layouts, entity)))
 }
 
-// Helper function to get a nested value from a JSON object using a path
-fn get_nested_value<'a>(
-    state: &'a serde_json::Map<String, serde_json::Value>,
-    path: &str,
-) -> Option<&'a serde_json::Value> {
-    let parts: Vec<&str> = path.split('.').collect();
+// Tauri command to draw an entity's state at a position as a LitRPG status window, in the
+// given style or the document's (see status_window.rs)
+#[tauri::command]
+fn format_status_window(
+    entity_id: String,
+    position: usize,
+    style: Option<status_window::StatusWindowStyle>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let doc = state.document(session_id.as_deref());
+    let (layouts, document_style) = {
+        let preferences = doc.preferences.lock().unwrap();
+        (preferences.sheet_layouts.clone(), preferences.status_window.clone())
+    };
+    let style = style.unwrap_or(document_style);
+    style.validate()?;
 
-    if parts.len() == 1 {
-        return state.get(path);
-    }
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+    let entity = entities.get(&entity_id).ok_or("Entity not found")?;
+    let sheet = sheets::build(entity, &markers, position, sheets::layout_for(&layouts, entity));
 
-    let mut current = state;
-    for (i, part) in parts.iter().enumerate() {
-        if i == parts.len() - 1 {
-            return current.get(*part);
-        } else {
-            current = current.get(*part)?.as_object()?;
-        }
-    }
+    Ok(status_window::render(&sheet, &style).join("\n"))
+}
 
-    None
+// Tauri command to get an entity's character sheet at the start or (default) end of a chapter
+#[tauri::command]
+fn format_character_sheet_at_chapter(
+    entity_id: String,
+    chapter: usize,
+    edge: Option<chapters::ChapterEdge>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let position = chapter_query_position(&doc, &locale, content, chapter, edge)?;
+    character_sheet_at(&doc, &locale, &entity_id, position)
 }
 
-// Helper function to remove a nested value from a JSON object using a path
-fn remove_nested_value(
-    state: &mut serde_json::Map<String, serde_json::Value>,
-    path: &str,
-) {
-    let parts: Vec<&str> = path.split('.').collect();
+// Helper function to resolve a chapter-scoped query to a position (see chapters::chapter_position)
+fn chapter_query_position(
+    doc: &DocumentState,
+    locale: &str,
+    content: Option<String>,
+    chapter: usize,
+    edge: Option<chapters::ChapterEdge>,
+) -> Result<usize, String> {
+    let doc_json = document_json(doc, content)?;
+    chapters::chapter_position(&doc_json, chapter, edge.unwrap_or_default(), &i18n::tr(locale, "chapter.untitled", &[]))
+}
 
-    if parts.len() == 1 {
-        // Simple field, remove directly
-        state.remove(path);
-        return;
-    }
+// Helper function to format an entity's state at a position as a character sheet
+fn character_sheet_at(doc: &DocumentState, locale: &str, entity_id: &str, position: usize) -> Result<String, String> {
+    let layouts = doc.preferences.lock().unwrap().sheet_layouts.clone();
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
 
-    // Navigate to parent and remove the field
-    fn remove_at_path(
-        obj: &mut serde_json::Map<String, serde_json::Value>,
-        parts: &[&str],
-    ) {
-        if parts.len() == 1 {
-            obj.remove(parts[0]);
-        } else if let Some(nested) = obj.get_mut(parts[0]) {
-            if let Some(nested_obj) = nested.as_object_mut() {
-                remove_at_path(nested_obj, &parts[1..]);
-            }
-        }
-    }
+    // Get entity
+    let entity = entities
+        .get(entity_id)
+        .ok_or("Entity not found")?;
 
-    remove_at_path(state, &parts);
+    // Replay this entity's markers up to the position, showing defaults for fields not yet set
+    let sheet = sheets::build(entity, &markers, position, sheets::layout_for(&layouts, entity));
+
+    // Format as character sheet
+    let mut text = i18n::tr(locale, "sheet.header", &[("name", &entity.name)]);
+    text.push('\n');
+    text.push_str(&sheets::render_text(&sheet));
+
+    Ok(text)
 }
 
-// Helper function to flatten a state object into field changes
-fn flatten_state_to_changes(
-    state: &serde_json::Map<String, serde_json::Value>,
-    prefix: String,
-    changes: &mut Vec<FieldChange>,
-) {
-    for (key, value) in state.iter() {
-        let field_name = if prefix.is_empty() {
-            key.clone()
-        } else {
-            format!("{}.{}", prefix, key)
-        };
+// Tauri command to declare a field's value type (e.g., "arc" for an emotional scale)
+#[tauri::command]
+fn set_field_type(
+    entity_id: String,
+    field_name: String,
+    field_type: Option<state::FieldType>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Entity, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
 
-        if let Some(obj) = value.as_object() {
-            // Nested object - recurse
-            flatten_state_to_changes(obj, field_name, changes);
-        } else {
-            // Leaf value - create a field change
-            let value_str = match value {
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::String(s) => s.clone(),
-                _ => value.to_string(),
-            };
+    mutations::set_field_type(&mut entities, &entity_id, &field_name, field_type)
+}
 
-            changes.push(FieldChange {
-                field_name,
-                value: value_str,
-                change_type: ChangeType::Absolute,
-            });
-        }
-    }
+// Tauri command to declare the descriptor fields (pronouns, eye color, hair, height) on an
+// entity (see descriptors.rs)
+#[tauri::command]
+fn add_descriptor_fields(
+    entity_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Entity, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
+
+    descriptors::add_descriptor_fields(&mut entities, &entity_id)
 }
 
-// Tauri command to get all entities
+// Tauri command to set (or clear) the value a field has before any marker sets it
 #[tauri::command]
-fn get_all_entities(state: tauri::State<AppState>) -> Vec<Entity> {
-    let entities = state.entities.lock().unwrap();
-    entities.values().cloned().collect()
+fn set_field_default(
+    entity_id: String,
+    field_name: String,
+    default_value: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Entity, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
+
+    mutations::set_field_default(&mut entities, &entity_id, &field_name, default_value)
 }
 
-// Helper function to format a state object as a character sheet string
-fn format_state_as_sheet(state: &serde_json::Map<String, serde_json::Value>, indent: usize) -> String {
-    let mut lines = Vec::new();
-    let indent_str = "  ".repeat(indent);
+// Tauri command to sample an entity's emotional arcs, after each change or at the given positions
+#[tauri::command]
+fn get_emotional_arc(
+    entity_id: String,
+    field: Option<String>,
+    positions: Option<Vec<usize>>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<arcs::EmotionalArc>, String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
 
-    for (key, value) in state.iter() {
-        if let Some(obj) = value.as_object() {
-            // Nested category
-            lines.push(format!("{}{}", indent_str, key));
-            lines.push(format_state_as_sheet(obj, indent + 1));
-        } else {
-            // Value
-            lines.push(format!("{}{}: {}", indent_str, key, value));
-        }
-    }
+    let entity = entities
+        .get(&entity_id)
+        .ok_or("Entity not found")?;
 
-    lines.join("\n")
+    Ok(arcs::emotional_arcs(entity, &markers, field.as_deref(), positions.as_deref()))
 }
 
-// Tauri command to get entity state formatted as a character sheet
+// Tauri command to suggest values for a field in a new marker, from the document's history
+// (position defaults to the end of the document)
 #[tauri::command]
-fn format_character_sheet(
+fn suggest_field_values(
     entity_id: String,
-    position: usize,
+    field: String,
+    position: Option<usize>,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
-) -> Result<String, String> {
-    let entities = state.entities.lock().unwrap();
-    let markers = state.markers.lock().unwrap();
+) -> Result<Vec<suggestions::ValueSuggestion>, String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
 
-    // Get entity
     let entity = entities
         .get(&entity_id)
         .ok_or("Entity not found")?;
 
-    // Get all markers for this entity up to the position
-    let mut relevant_markers: Vec<&Marker> = markers
-        .values()
-        .filter(|m| m.entity_id == entity_id && m.position <= position)
-        .collect();
+    Ok(suggestions::suggest_field_values(
+        entity,
+        &entities,
+        &markers,
+        &field,
+        position.unwrap_or(usize::MAX),
+    ))
+}
 
-    // Sort by position
-    relevant_markers.sort_by_key(|m| m.position);
+// Tauri command to fuzzy-search entity names, field paths, and marker descriptions and tags
+#[tauri::command]
+fn fuzzy_search(
+    query: String,
+    limit: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<search::SearchResult> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
 
-    // Start with empty state (use Map for nested structure support)
-    let mut current_state = serde_json::Map::new();
+    search::fuzzy_search(&entities, &markers, &query, limit.unwrap_or(20))
+}
 
-    // Apply each marker's changes
-    for marker in relevant_markers {
-        for change in &marker.changes {
-            match &change.change_type {
-                ChangeType::Remove => {
-                    remove_nested_value(&mut current_state, &change.field_name);
-                }
-                ChangeType::Absolute => {
-                    let value = if let Ok(num) = change.value.parse::<f64>() {
-                        serde_json::json!(num)
-                    } else if change.value == "true" || change.value == "false" {
-                        serde_json::json!(change.value.parse::<bool>().unwrap())
-                    } else {
-                        serde_json::json!(change.value)
-                    };
-                    set_nested_value(&mut current_state, &change.field_name, value);
-                }
-                ChangeType::Relative => {
-                    let value = if let Ok(delta) = change.value.parse::<f64>() {
-                        let current_val = get_nested_value(&current_state, &change.field_name)
-                            .and_then(|v| v.as_f64())
-                            .unwrap_or(0.0);
-                        serde_json::json!(current_val + delta)
-                    } else {
-                        serde_json::json!(change.value)
-                    };
-                    set_nested_value(&mut current_state, &change.field_name, value);
-                }
-            }
-        }
+// Tauri command to list the entities at a location at a position
+#[tauri::command]
+fn who_is_at(
+    location_id: String,
+    position: usize,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<locations::Presence>, String> {
+    let doc = state.document(session_id.as_deref());
+    presence_at(&doc, &location_id, position)
+}
+
+// Tauri command to list the entities at a location at the start or (default) end of a chapter
+#[tauri::command]
+fn who_is_at_chapter(
+    location_id: String,
+    chapter: usize,
+    edge: Option<chapters::ChapterEdge>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<locations::Presence>, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let position = chapter_query_position(&doc, &locale, content, chapter, edge)?;
+    presence_at(&doc, &location_id, position)
+}
+
+// Helper function to list the entities at a location at a position
+fn presence_at(doc: &DocumentState, location_id: &str, position: usize) -> Result<Vec<locations::Presence>, String> {
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    match entities.get(location_id) {
+        Some(entity) if entity.kind == EntityKind::Location => {}
+        Some(_) => return Err("Entity is not a location".to_string()),
+        None => return Err("Location not found".to_string()),
     }
 
-    // Format as character sheet
-    let mut sheet = format!("=== {} ===\n", entity.name);
-    sheet.push_str(&format_state_as_sheet(&current_state, 0));
+    Ok(locations::who_is_at(&entities, &markers, location_id, position))
+}
+
+// Tauri command to set (or clear, with no distance) the distance between two locations
+#[tauri::command]
+fn set_location_distance(
+    location_id: String,
+    other_location_id: String,
+    distance: Option<f64>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
 
-    Ok(sheet)
+    mutations::set_location_distance(&mut entities, &location_id, &other_location_id, distance)
 }
 
-// Tauri command to get entity state at a position
+// Tauri command to get every location change of an entity in story order
 #[tauri::command]
-fn get_entity_state(
+fn get_travel_log(
     entity_id: String,
-    position: usize,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
-) -> Result<serde_json::Value, String> {
-    let entities = state.entities.lock().unwrap();
-    let markers = state.markers.lock().unwrap();
+) -> Result<Vec<locations::TravelEntry>, String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
 
-    // Verify entity exists
     if !entities.contains_key(&entity_id) {
         return Err("Entity not found".to_string());
     }
 
-    // Get all markers for this entity before the position
-    let mut relevant_markers: Vec<_> = markers
-        .values()
-        .filter(|m| m.entity_id == entity_id && m.position <= position)
-        .collect();
-
-    // Sort by position
-    relevant_markers.sort_by_key(|m| m.position);
+    Ok(locations::travel_log(&entities, &markers, &entity_id))
+}
 
-    // Start with empty state (use Map for nested structure support)
-    let mut current_state = serde_json::Map::new();
+// Tauri command to list everyone who knows a fact at a position
+#[tauri::command]
+fn who_knows(
+    fact: String,
+    position: usize,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<knowledge::Knower>, String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
 
-    // Apply each marker's changes
-    for marker in relevant_markers {
-        for change in &marker.changes {
-            match &change.change_type {
-                ChangeType::Remove => {
-                    // Remove the field from the state
-                    remove_nested_value(&mut current_state, &change.field_name);
-                }
-                ChangeType::Absolute => {
-                    // Try to parse as number, otherwise treat as string
-                    let value = if let Ok(num) = change.value.parse::<f64>() {
-                        serde_json::json!(num)
-                    } else if change.value == "true" || change.value == "false" {
-                        serde_json::json!(change.value.parse::<bool>().unwrap())
-                    } else {
-                        serde_json::json!(change.value)
-                    };
-                    set_nested_value(&mut current_state, &change.field_name, value);
-                }
-                ChangeType::Relative => {
-                    // Relative change - add to existing value
-                    let value = if let Ok(delta) = change.value.parse::<f64>() {
-                        let current_val = get_nested_value(&current_state, &change.field_name)
-                            .and_then(|v| v.as_f64())
-                            .unwrap_or(0.0);
-                        serde_json::json!(current_val + delta)
-                    } else {
-                        serde_json::json!(change.value)
-                    };
-                    set_nested_value(&mut current_state, &change.field_name, value);
-                }
-            }
-        }
-    }
+    Ok(knowledge::who_knows(&entities, &markers, &fact, position))
+}
 
-    Ok(serde_json::Value::Object(current_state))
+// Tauri command to list everyone who knows a fact at the start or (default) end of a chapter
+#[tauri::command]
+fn who_knows_at_chapter(
+    fact: String,
+    chapter: usize,
+    edge: Option<chapters::ChapterEdge>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<knowledge::Knower>, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let position = chapter_query_position(&doc, &locale, content, chapter, edge)?;
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(knowledge::who_knows(&entities, &markers, &fact, position))
 }
 
-// Tauri command to create a new entity
+// Tauri command to create a plot thread, opened at a position (or at a marker)
 #[tauri::command]
-fn create_entity(
+fn create_plot_thread(
     name: String,
-    color: Option<String>,
+    description: Option<String>,
+    position: usize,
+    marker_id: Option<String>,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
-) -> Result<Entity, String> {
-    let mut entities = state.entities.lock().unwrap();
+) -> Result<plot_threads::PlotThread, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let markers = doc.markers.lock().unwrap();
 
-    let entity = Entity {
-        id: uuid::Uuid::new_v4().to_string(),
-        name,
-        fields: Vec::new(),
-        color: color.unwrap_or_else(|| "#FFD700".to_string()),
-        field_metadata: std::collections::HashMap::new(),
+    let opened = plot_threads::NewThreadEvent {
+        status: plot_threads::ThreadStatus::Open,
+        position,
+        marker_id,
+        note: None,
     };
+    let thread = plot_threads::create_thread(&markers, name, description.unwrap_or_default(), opened)?;
 
-    entities.insert(entity.id.clone(), entity.clone());
-
-    Ok(entity)
+    doc.plot_threads.lock().unwrap().push(thread.clone());
+    Ok(thread)
 }
 
-// Tauri command to update an entity's name and/or color
+// Tauri command to rename a plot thread or change its description
 #[tauri::command]
-fn update_entity(
-    entity_id: String,
+fn update_plot_thread(
+    thread_id: String,
     name: Option<String>,
-    color: Option<String>,
+    description: Option<String>,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
-) -> Result<Entity, String> {
-    let mut entities = state.entities.lock().unwrap();
-    let mut markers = state.markers.lock().unwrap();
+) -> Result<plot_threads::PlotThread, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut threads = doc.plot_threads.lock().unwrap();
+
+    let thread = threads
+        .iter_mut()
+        .find(|t| t.id == thread_id)
+        .ok_or("Plot thread not found")?;
+
+    if let Some(name) = name {
+        if name.trim().is_empty() {
+            return Err("Thread name can't be empty".to_string());
+        }
+        thread.name = name;
+    }
+    if let Some(description) = description {
+        thread.description = description;
+    }
 
-    let entity = entities
-        .get_mut(&entity_id)
-        .ok_or("Entity not found")?;
+    Ok(thread.clone())
+}
 
-    if let Some(n) = name {
-        entity.name = n;
+// Tauri command to delete a plot thread
+#[tauri::command]
+fn delete_plot_thread(
+    thread_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut threads = doc.plot_threads.lock().unwrap();
+
+    let count = threads.len();
+    threads.retain(|t| t.id != thread_id);
+    if threads.len() == count {
+        return Err("Plot thread not found".to_string());
     }
-    if let Some(new_color) = color {
-        entity.color = new_color.clone();
 
-        // Update all markers for this entity to use the new color
-        for marker in markers.values_mut() {
-            if marker.entity_id == entity_id {
-                marker.visual.color = new_color.clone();
-            }
-        }
-    }
+    Ok(())
+}
 
-    Ok(entity.clone())
+// Tauri command to record a status change (open/developed/resolved) of a plot thread
+#[tauri::command]
+fn add_thread_event(
+    thread_id: String,
+    event: plot_threads::NewThreadEvent,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<plot_threads::ThreadEvent, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let markers = doc.markers.lock().unwrap();
+    let mut threads = doc.plot_threads.lock().unwrap();
+
+    let thread = threads
+        .iter_mut()
+        .find(|t| t.id == thread_id)
+        .ok_or("Plot thread not found")?;
+
+    thread.add_event(&markers, event)
 }
 
-// Tauri command to delete an entity
+// Tauri command to remove a status change from a plot thread
 #[tauri::command]
-fn delete_entity(
-    entity_id: String,
+fn remove_thread_event(
+    thread_id: String,
+    event_id: String,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
 ) -> Result<(), String> {
-    let mut entities = state.entities.lock().unwrap();
-    let mut markers = state.markers.lock().unwrap();
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let markers = doc.markers.lock().unwrap();
+    let mut threads = doc.plot_threads.lock().unwrap();
 
-    // Check if entity exists
-    if !entities.contains_key(&entity_id) {
-        return Err("Entity not found".to_string());
-    }
+    let thread = threads
+        .iter_mut()
+        .find(|t| t.id == thread_id)
+        .ok_or("Plot thread not found")?;
 
-    // Delete all markers associated with this entity
-    markers.retain(|_, marker| marker.entity_id != entity_id);
+    thread.remove_event(&markers, &event_id)
+}
 
-    // Delete the entity
-    entities.remove(&entity_id);
+// Tauri command to get all plot threads
+#[tauri::command]
+fn get_plot_threads(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<plot_threads::PlotThread> {
+    let doc = state.document(session_id.as_deref());
+    let threads = doc.plot_threads.lock().unwrap().clone();
+    threads
+}
 
-    Ok(())
+// Tauri command to list the threads still unresolved at a position (default: the end of the document)
+#[tauri::command]
+fn get_unresolved_threads(
+    position: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<plot_threads::ThreadState> {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+    let threads = doc.plot_threads.lock().unwrap();
+
+    plot_threads::unresolved_threads(&threads, &markers, position.unwrap_or(usize::MAX))
 }
 
-// Return type for duplicate_entity command
-#[derive(Serialize)]
-struct DuplicateEntityResult {
-    entity: Entity,
-    marker: Option<Marker>,
+// Tauri command to get a plot thread's events in story order
+#[tauri::command]
+fn get_thread_timeline(
+    thread_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<plot_threads::ThreadEvent>, String> {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+    let threads = doc.plot_threads.lock().unwrap();
+
+    let thread = threads
+        .iter()
+        .find(|t| t.id == thread_id)
+        .ok_or("Plot thread not found")?;
+
+    Ok(thread.timeline(&markers))
 }
 
-// Tauri command to duplicate an entity
-// Creates a copy with a new ID and name, preserving fields and metadata
+// Tauri command to get entity state at a position, in narrative (default) or chronological order
 #[tauri::command]
-fn duplicate_entity(
+fn get_entity_state(
     entity_id: String,
-    new_name: String,
-    cursor_position: usize,
+    position: usize,
+    order: Option<chronology::StateOrder>,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
-) -> Result<DuplicateEntityResult, String> {
-    let mut entities = state.entities.lock().unwrap();
-    let mut markers = state.markers.lock().unwrap();
+) -> Result<serde_json::Value, String> {
+    let doc = state.document(session_id.as_deref());
+    entity_state_at(&doc, &entity_id, position, order)
+}
 
-    // Get the source entity
-    let source_entity = entities
-        .get(&entity_id)
-        .ok_or("Entity not found")?
-        .clone();
+// Tauri command to get entity state at the start or (default) end of a chapter
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn get_entity_state_at_chapter(
+    entity_id: String,
+    chapter: usize,
+    edge: Option<chapters::ChapterEdge>,
+    order: Option<chronology::StateOrder>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<serde_json::Value, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let position = chapter_query_position(&doc, &locale, content, chapter, edge)?;
+    entity_state_at(&doc, &entity_id, position, order)
+}
 
-    // Create a new entity with copied fields and metadata
-    let new_entity = Entity {
-        id: uuid::Uuid::new_v4().to_string(),
-        name: new_name,
-        fields: source_entity.fields.clone(),
-        color: source_entity.color.clone(),
-        field_metadata: source_entity.field_metadata.clone(),
-    };
+// Helper function to get entity state at a position
+fn entity_state_at(
+    doc: &DocumentState,
+    entity_id: &str,
+    position: usize,
+    order: Option<chronology::StateOrder>,
+) -> Result<serde_json::Value, String> {
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
 
-    let new_entity_id = new_entity.id.clone();
-    entities.insert(new_entity_id.clone(), new_entity.clone());
+    // Verify entity exists
+    let entity = entities.get(entity_id).ok_or("Entity not found")?;
 
-    // Get the current state of the source entity at cursor position
-    let relevant_markers: Vec<_> = markers
-        .values()
-        .filter(|m| m.entity_id == entity_id && m.position <= cursor_position)
-        .collect();
+    // Replay this entity's markers up to the position
+    let current_state = chronology::entity_state_at(&markers, entity, position, order.unwrap_or_default());
 
-    if !relevant_markers.is_empty() {
-        // Compute the current state by applying all markers
-        let mut current_state = serde_json::Map::new();
-        let mut sorted_markers = relevant_markers.clone();
-        sorted_markers.sort_by_key(|m| m.position);
-
-        for marker in sorted_markers {
-            for change in &marker.changes {
-                match &change.change_type {
-                    ChangeType::Remove => {
-                        remove_nested_value(&mut current_state, &change.field_name);
-                    }
-                    ChangeType::Absolute => {
-                        let value = if let Ok(num) = change.value.parse::<f64>() {
-                            serde_json::json!(num)
-                        } else if change.value == "true" || change.value == "false" {
-                            serde_json::json!(change.value.parse::<bool>().unwrap())
-                        } else {
-                            serde_json::json!(change.value)
-                        };
-                        set_nested_value(&mut current_state, &change.field_name, value);
-                    }
-                    ChangeType::Relative => {
-                        let value = if let Ok(delta) = change.value.parse::<f64>() {
-                            let current_val = get_nested_value(&current_state, &change.field_name)
-                                .and_then(|v| v.as_f64())
-                                .unwrap_or(0.0);
-                            serde_json::json!(current_val + delta)
-                        } else {
-                            serde_json::json!(change.value)
-                        };
-                        set_nested_value(&mut current_state, &change.field_name, value);
-                    }
-                }
-            }
-        }
+    Ok(serde_json::Value::Object(current_state))
+}
 
-        // Convert the computed state into field changes (all absolute values)
-        let mut changes = Vec::new();
-        flatten_state_to_changes(&current_state, String::new(), &mut changes);
+// Tauri command to get entity state at an in-world moment, whatever the document order
+#[tauri::command]
+fn get_entity_state_at_story_time(
+    entity_id: String,
+    story_time: f64,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<serde_json::Value, String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    let entity = entities.get(&entity_id).ok_or("Entity not found")?;
+
+    let current_state = chronology::chronological_state_at(&markers, entity, story_time, usize::MAX);
+
+    Ok(serde_json::Value::Object(current_state))
+}
+
+// Tauri command to freeze an entity's state at a position under a label (see pins.rs)
+#[tauri::command]
+fn pin_entity_state(
+    entity_id: String,
+    position: usize,
+    label: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<pins::StatePin, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    let entity = entities.get(&entity_id).ok_or("Entity not found")?;
+    let pin = pins::pin_state(entity, &markers, position, &label, dates::now())?;
+
+    doc.pins.lock().unwrap().push(pin.clone());
+    Ok(pin)
+}
+
+// Tauri command to compare an entity's state at a position with one of its pins
+#[tauri::command]
+fn compare_with_pin(
+    entity_id: String,
+    position: usize,
+    pin_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<pins::PinComparison, String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    let entity = entities.get(&entity_id).ok_or("Entity not found")?;
+    let stored = doc.pins.lock().unwrap();
+    let pin = stored.iter().find(|p| p.id == pin_id).ok_or("Pin not found")?;
+
+    pins::compare(pin, entity, &markers, position)
+}
+
+// Tauri command to list pins, optionally only those of one entity
+#[tauri::command]
+fn get_entity_pins(
+    entity_id: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<pins::StatePin> {
+    let doc = state.document(session_id.as_deref());
+    let pins = doc.pins.lock().unwrap();
+
+    pins.iter()
+        .filter(|p| entity_id.as_ref().is_none_or(|id| &p.entity_id == id))
+        .cloned()
+        .collect()
+}
+
+// Tauri command to delete a pin
+#[tauri::command]
+fn delete_entity_pin(
+    pin_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut pins = doc.pins.lock().unwrap();
+
+    let before = pins.len();
+    pins.retain(|p| p.id != pin_id);
+    if pins.len() == before {
+        return Err("Pin not found".to_string());
+    }
+    Ok(())
+}
+
+// Helper function to gather the settings entity/marker mutations depend on
+fn mutation_context(state: &AppState, doc: &DocumentState) -> mutations::MutationContext {
+    let preferences = doc.preferences.lock().unwrap();
+    mutations::MutationContext {
+        default_entity_color: state.settings.lock().unwrap().default_entity_color.clone(),
+        visual_rules: doc
+            .visual_rules
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or_else(visual_rules::default_rules),
+        default_marker_icon: preferences.default_marker_icon.clone(),
+        strict: preferences.strict_mode,
+        change_types: doc.change_types.lock().unwrap().clone(),
+    }
+}
+
+// Tauri command to list the open document sessions
+#[tauri::command]
+fn list_document_sessions(state: tauri::State<AppState>) -> Vec<String> {
+    let mut sessions: Vec<String> = state.documents.lock().unwrap().keys().cloned().collect();
+    sessions.sort();
+    sessions
+}
+
+// Tauri command to discard a document session's state (e.g., when its window closes)
+#[tauri::command]
+fn close_document_session(
+    session_id: String,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state
+        .documents
+        .lock()
+        .unwrap()
+        .remove(&session_id)
+        .ok_or("Document session not found")?;
+
+    release_document_lock(&state, &doc);
+
+    Ok(())
+}
+
+// Tauri command to create a new entity
+#[tauri::command]
+fn create_entity(
+    name: String,
+    color: Option<String>,
+    kind: Option<EntityKind>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Entity, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+
+    mutations::create_entity(&mut entities, &context, mutations::NewEntity { name, color, kind })
+}
+
+// Tauri command to create many entities in one call (e.g., a cast list pasted from an outline)
+#[tauri::command]
+fn create_entities(
+    specs: Vec<mutations::EntitySpec>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<Entity>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+
+    mutations::create_entities(&mut entities, &context, specs)
+}
+
+// Tauri command to update an entity's name and/or color
+#[tauri::command]
+fn update_entity(
+    entity_id: String,
+    name: Option<String>,
+    color: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Entity, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    let entity = mutations::update_entity(
+        &mut entities,
+        &mut markers,
+        mutations::EntityUpdate { entity_id, name, color },
+    )?;
+
+    // Keep the labels of the entity's mention nodes in the stored content current
+    if let Some(content) = doc.content.lock().unwrap().as_mut() {
+        entity_refs::relabel(content, &entity.id, &entity.name);
+    }
+
+    Ok(entity)
+}
+
+// Tauri command to give every entity a distinct color from a named palette (see colors.rs),
+// recoloring their markers too
+#[tauri::command]
+fn recolor_entities(
+    palette: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<Entity>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let colors = state
+        .settings
+        .lock()
+        .unwrap()
+        .palettes
+        .get(&palette)
+        .cloned()
+        .ok_or_else(|| format!("Palette not found: {}", palette))?;
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::recolor_entities(&mut entities, &mut markers, &colors)
+}
+
+// Tauri command to set an entity's portrait from a PNG/JPEG/WebP file (None removes it)
+#[tauri::command]
+fn set_entity_portrait(
+    entity_id: String,
+    file_path: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Entity, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+
+    let portrait = file_path
+        .map(|path| entity_pack::load_portrait(Path::new(&path)))
+        .transpose()?;
+
+    let mut entities = doc.entities.lock().unwrap();
+    let entity = entities
+        .get_mut(&entity_id)
+        .ok_or("Entity not found")?;
+
+    entity.portrait = portrait;
+
+    Ok(entity.clone())
+}
+
+// Tauri command to delete an entity
+#[tauri::command]
+fn delete_entity(
+    entity_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::delete_entity(&mut entities, &mut markers, &entity_id)?;
+    doc.pins.lock().unwrap().retain(|pin| pin.entity_id != entity_id);
+    Ok(())
+}
+
+// Return type for duplicate_entity command
+#[derive(Serialize)]
+struct DuplicateEntityResult {
+    entity: Entity,
+    marker: Option<Marker>,
+}
+
+// Tauri command to duplicate an entity
+// Creates a copy with a new ID and name, preserving fields and metadata
+#[tauri::command]
+fn duplicate_entity(
+    entity_id: String,
+    new_name: String,
+    cursor_position: usize,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<DuplicateEntityResult, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let locale = state.locale_for(&doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    // Get the source entity
+    let source_entity = entities
+        .get(&entity_id)
+        .ok_or("Entity not found")?
+        .clone();
+
+    // Create a new entity with copied fields and metadata
+    let new_entity = Entity {
+        id: ids::new_id(),
+        name: new_name,
+        fields: source_entity.fields.clone(),
+        color: source_entity.color.clone(),
+        field_metadata: source_entity.field_metadata.clone(),
+        portrait: source_entity.portrait.clone(),
+        kind: source_entity.kind,
+        distances: source_entity.distances.clone(),
+    };
+
+    let new_entity_id = new_entity.id.clone();
+    entities.insert(new_entity_id.clone(), new_entity.clone());
+
+    // Get the current state of the source entity at cursor position
+    let relevant_markers: Vec<_> = markers
+        .values()
+        .filter(|m| m.entity_id == entity_id && m.position <= cursor_position)
+        .collect();
+
+    if !relevant_markers.is_empty() {
+        // Compute the current state by applying all markers
+        let current_state = engine::compute_state_with_defaults(relevant_markers, &engine::field_defaults(&source_entity));
+
+        // Convert the computed state into field changes (all absolute values)
+        let mut changes = Vec::new();
+        engine::flatten_state_to_changes(&current_state, String::new(), &mut changes);
 
         // Create an initial marker for the new entity at cursor position
         if !changes.is_empty() {
-            let now = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as i64;
+            let now = dates::now();
 
             let marker = Marker {
-                id: uuid::Uuid::new_v4().to_string(),
+                id: ids::new_id(),
                 position: cursor_position,
                 entity_id: new_entity_id.clone(),
                 changes,
                 visual: MarkerVisual {
                     icon: "📋".to_string(),
                     color: source_entity.color.clone(),
+                    icon_ref: None,
                 },
-                description: format!("Duplicated from {}", source_entity.name),
+                description: i18n::tr(&locale, "marker.duplicated_from", &[("name", &source_entity.name)]),
                 created_at: now,
                 modified_at: now,
+                tags: Vec::new(),
+                story_time: None,
+                sequence: mutations::next_sequence(&markers, cursor_position),
+                pin: None,
+                active_outcome: None,
+                outcomes: Vec::new(),
+                todo: false,
             };
 
             let marker_clone = marker.clone();
@@ -504,105 +1018,251 @@ fn duplicate_entity(
 fn delete_field_completely(
     entity_id: String,
     field_name: String,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
 ) -> Result<(), String> {
-    let mut entities = state.entities.lock().unwrap();
-    let mut markers = state.markers.lock().unwrap();
-
-    // Check if entity exists
-    let entity = entities
-        .get_mut(&entity_id)
-        .ok_or("Entity not found")?;
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
 
-    // Remove field from entity's fields list
-    entity.fields.retain(|f| f != &field_name);
+    mutations::delete_field_completely(&mut entities, &mut markers, &entity_id, &field_name)
+}
 
-    // Remove ALL changes for this field from all markers belonging to this entity
-    // This includes absolute, relative, AND remove markers
-    for marker in markers.values_mut() {
-        if marker.entity_id == entity_id {
-            marker.changes.retain(|change| change.field_name != field_name);
-        }
-    }
+// Tauri command to move a field or group of fields to a new path in the entity and all its markers
+#[tauri::command]
+fn move_field_subtree(
+    entity_id: String,
+    old_prefix: String,
+    new_prefix: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Entity, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
 
-    Ok(())
+    mutations::move_field_subtree(&mut entities, &mut markers, &entity_id, &old_prefix, &new_prefix)
 }
 
 // Tauri command to insert a marker
+// (Tauri maps each argument to a field of the invoke payload, hence the long parameter list)
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn insert_marker(
     position: usize,
     entity_id: String,
     changes: Vec<FieldChange>,
-    visual: MarkerVisual,
+    visual: Option<MarkerVisual>,
     description: Option<String>,
+    tags: Option<Vec<String>>,
+    story_time: Option<f64>,
+    todo: Option<bool>,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
 ) -> Result<Marker, String> {
-    let mut markers = state.markers.lock().unwrap();
-    let mut entities = state.entities.lock().unwrap();
-
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
-
-    let marker = Marker {
-        id: uuid::Uuid::new_v4().to_string(),
-        position,
-        entity_id: entity_id.clone(),
-        changes: changes.clone(),
-        visual,
-        description: description.unwrap_or_default(),
-        created_at: now,
-        modified_at: now,
-    };
-
-    markers.insert(marker.id.clone(), marker.clone());
-
-    // Update entity's field list and metadata with any new fields from this marker
-    if let Some(entity) = entities.get_mut(&entity_id) {
-        for change in &changes {
-            // Add to fields list if not present
-            if !entity.fields.contains(&change.field_name) {
-                entity.fields.push(change.field_name.clone());
-            }
-
-            // Update metadata - create if new, or update last_modified if existing
-            entity.field_metadata.entry(change.field_name.clone())
-                .and_modify(|meta| meta.last_modified = now)
-                .or_insert(state::FieldMetadata {
-                    created_at: now,
-                    last_modified: now,
-                });
-        }
-    }
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::insert_marker(
+        &mut entities,
+        &mut markers,
+        &context,
+        mutations::NewMarker {
+            position,
+            entity_id,
+            changes,
+            visual,
+            description,
+            tags,
+            story_time,
+            todo: todo.unwrap_or(false),
+        },
+    )
+}
 
-    Ok(marker)
+// Tauri command to run several entity/marker mutations atomically (all or nothing)
+#[tauri::command]
+fn run_batch(
+    commands: Vec<batch::BatchCommand>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<serde_json::Value>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    batch::run_batch(&mut entities, &mut markers, &context, commands)
 }
 
 // Tauri command to get all markers
 #[tauri::command]
-fn get_all_markers(state: tauri::State<AppState>) -> Vec<Marker> {
-    let markers = state.markers.lock().unwrap();
+fn get_all_markers(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<Marker> {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
     markers.values().cloned().collect()
 }
 
+// Tauri command to render a marker's description (Markdown) as sanitized HTML for tooltips
+// (see markdown.rs)
+#[tauri::command]
+fn render_marker_description(
+    marker_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+    let marker = markers.get(&marker_id).ok_or("Marker not found")?;
+
+    Ok(markdown::to_html(&marker.description))
+}
+
 // Tauri command to get markers at a specific position
 #[tauri::command]
 fn get_markers_at_position(
     position: usize,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
 ) -> Vec<Marker> {
-    let markers = state.markers.lock().unwrap();
-    markers
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+    let mut at_position: Vec<Marker> = markers
         .values()
         .filter(|m| m.position == position)
         .cloned()
-        .collect()
+        .collect();
+    at_position.sort_by(engine::compare_markers);
+    at_position
+}
+
+// Tauri command to move some of a marker's changes into a new marker
+// (at the same position unless new_position is given)
+#[tauri::command]
+fn split_marker(
+    marker_id: String,
+    change_indices: Vec<usize>,
+    new_position: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<restructure::SplitResult, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+
+    restructure::split_marker(&mut markers, &marker_id, &change_indices, new_position)
+}
+
+// Tauri command to merge markers of one entity at one position into a single marker
+#[tauri::command]
+fn merge_markers(
+    marker_ids: Vec<String>,
+    strategy: Option<restructure::MergeStrategy>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<restructure::MergeResult, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    restructure::merge_markers(&entities, &mut markers, &context, &marker_ids, strategy.unwrap_or_default())
+}
+
+// Tauri command to list groups of markers that share an entity and a position
+#[tauri::command]
+fn find_merge_candidates(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<restructure::MergeCandidate> {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+
+    restructure::find_merge_candidates(&markers)
+}
+
+// Tauri command to copy markers to another entity, optionally shifted by an offset
+#[tauri::command]
+fn copy_markers(
+    marker_ids: Vec<String>,
+    target_entity_id: String,
+    position_offset: Option<i64>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<Marker>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    // Work on copies so a failed copy leaves no partial copies behind
+    let mut new_entities = entities.clone();
+    let mut new_markers = markers.clone();
+    let copied = restructure::copy_markers(
+        &mut new_entities,
+        &mut new_markers,
+        &context,
+        &marker_ids,
+        &target_entity_id,
+        position_offset.unwrap_or(0),
+    )?;
+
+    *entities = new_entities;
+    *markers = new_markers;
+
+    Ok(copied)
+}
+
+// Tauri command to move one entity's markers in the range start..end to another entity
+#[tauri::command]
+fn reassign_markers_in_range(
+    start: usize,
+    end: usize,
+    from_entity: String,
+    to_entity: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<Marker>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    restructure::reassign_markers_in_range(&mut entities, &mut markers, &context, start, end, &from_entity, &to_entity)
+}
+
+// Tauri command to set the order in which the markers at a position are applied
+#[tauri::command]
+fn reorder_markers(
+    position: usize,
+    marker_ids: Vec<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<Marker>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::reorder_markers(&entities, &mut markers, &context, position, &marker_ids)
 }
 
 // Tauri command to update an existing marker
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn update_marker(
     marker_id: String,
     position: Option<usize>,
@@ -610,181 +1270,2768 @@ fn update_marker(
     changes: Option<Vec<FieldChange>>,
     visual: Option<MarkerVisual>,
     description: Option<String>,
+    todo: Option<bool>,
+    session_id: Option<String>,
     state: tauri::State<AppState>,
 ) -> Result<Marker, String> {
-    let mut markers = state.markers.lock().unwrap();
-    let mut entities = state.entities.lock().unwrap();
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::update_marker(
+        &mut entities,
+        &mut markers,
+        &context,
+        mutations::MarkerUpdate {
+            marker_id,
+            position,
+            entity_id,
+            changes,
+            visual,
+            description,
+            todo,
+        },
+    )
+}
 
-    let marker = markers
-        .get_mut(&marker_id)
-        .ok_or("Marker not found")?;
+// Tauri command to pin a marker to the start of a heading (e.g., "Chapter 12"), or unpin it.
+// A pinned marker follows its heading whenever positions are synced with the content.
+#[tauri::command]
+fn pin_marker_to_heading(
+    marker_id: String,
+    heading: Option<String>,
+    content: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Marker, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    let Some(heading) = heading.map(|h| h.trim().to_string()).filter(|h| !h.is_empty()) else {
+        return mutations::set_marker_pin(&entities, &mut markers, &context, &marker_id, None, None);
+    };
 
-    // Update fields if provided
-    if let Some(pos) = position {
-        marker.position = pos;
-    }
-    if let Some(ent_id) = entity_id {
-        marker.entity_id = ent_id;
-    }
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
+    let doc_json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse document JSON: {}", e))?;
+    let pin = chapters::HeadingPin { heading };
+    let position = chapters::resolve_pin(&doc_json, &pin)
+        .ok_or_else(|| format!("Heading not found: {}", pin.heading))?;
 
-    if let Some(chgs) = &changes {
-        marker.changes = chgs.clone();
+    mutations::set_marker_pin(&entities, &mut markers, &context, &marker_id, Some(pin), Some(position))
+}
 
-        // Update entity's field list and metadata with any new fields
-        if let Some(entity) = entities.get_mut(&marker.entity_id) {
-            for change in chgs {
-                // Add to fields list if not present
-                if !entity.fields.contains(&change.field_name) {
-                    entity.fields.push(change.field_name.clone());
-                }
+// Tauri command to give a marker alternative outcomes (e.g., "duel won" / "duel lost") with one
+// active, or to clear them (no outcomes). State after the marker follows the active outcome.
+#[tauri::command]
+fn set_marker_outcomes(
+    marker_id: String,
+    outcomes: Vec<MarkerOutcome>,
+    active: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Marker, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
 
-                // Update metadata
-                entity.field_metadata.entry(change.field_name.clone())
-                    .and_modify(|meta| meta.last_modified = now)
-                    .or_insert(state::FieldMetadata {
-                        created_at: now,
-                        last_modified: now,
-                    });
-            }
-        }
-    }
-    if let Some(vis) = visual {
-        marker.visual = vis;
-    }
-    if let Some(desc) = description {
-        marker.description = desc;
-    }
+    mutations::set_marker_outcomes(&mut entities, &mut markers, &context, &marker_id, outcomes, active)
+}
 
-    // Update modified timestamp
-    marker.modified_at = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
+// Tauri command to switch which of a marker's outcomes is active
+#[tauri::command]
+fn switch_marker_outcome(
+    marker_id: String,
+    outcome: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Marker, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::switch_marker_outcome(&mut entities, &mut markers, &context, &marker_id, &outcome)
+}
 
-    Ok(marker.clone())
+// Tauri command to replace a marker's tags
+#[tauri::command]
+fn set_marker_tags(
+    marker_id: String,
+    tags: Vec<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Marker, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::set_marker_tags(&mut markers, &marker_id, tags)
+}
+
+// Tauri command to set (or clear) the in-world time of a marker (see chronology.rs)
+#[tauri::command]
+fn set_marker_story_time(
+    marker_id: String,
+    story_time: Option<f64>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Marker, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::set_marker_story_time(&mut markers, &marker_id, story_time)
+}
+
+// Tauri command to get marker counts per document segment for a scrollbar heatmap.
+// Without a document size, the last marker position is used as the end of the document.
+// With visible_only, markers hidden by the document's visibility filters aren't counted.
+#[tauri::command]
+fn get_marker_density(
+    buckets: usize,
+    document_size: Option<usize>,
+    entity_id: Option<String>,
+    tag: Option<String>,
+    visible_only: Option<bool>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> analysis::MarkerDensity {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+    let visibility = doc.visibility.lock().unwrap();
+
+    let document_size = document_size
+        .unwrap_or_else(|| markers.values().map(|m| m.position + 1).max().unwrap_or(1));
+
+    analysis::marker_density(
+        &markers,
+        buckets,
+        document_size,
+        entity_id.as_deref(),
+        tag.as_deref(),
+        visible_only.unwrap_or(false).then_some(&*visibility),
+    )
+}
+
+// Tauri command to delete a marker
+#[tauri::command]
+fn delete_marker(
+    marker_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    mutations::delete_marker(&entities, &mut markers, &context, &marker_id)
+}
+
+// Tauri command to update marker positions (for text changes)
+#[tauri::command]
+fn update_marker_positions(
+    position_updates: Vec<(String, usize)>, // (marker_id, new_position)
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let context = mutation_context(&state, &doc);
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    let mut moved = Vec::with_capacity(position_updates.len());
+    for (marker_id, new_position) in position_updates {
+        if let Some(marker) = markers.get(&marker_id) {
+            moved.push(Marker { position: new_position, ..marker.clone() });
+        }
+    }
+
+    if context.strict {
+        strict::check_batch(&entities, &markers, &moved, &[])?;
+    }
+
+    for marker in moved {
+        markers.insert(marker.id.clone(), marker);
+    }
+
+    Ok(())
+}
+
+// Result of mapping marker positions through text edits
+#[derive(Serialize)]
+struct TextEditResult {
+    moved: usize,
+    deleted_marker_ids: Vec<String>,
+}
+
+// Helper function to shift markers through a sequence of text edits.
+// All edits are validated before anything changes, so a bad edit leaves the markers untouched.
+// With `strict_entities` given (strict mode), edits that introduce a violation (by deleting
+// markers along with their text) are refused.
+fn shift_markers_for_edits(
+    markers: &mut HashMap<String, Marker>,
+    edits: &[TextEdit],
+    strict_entities: Option<&HashMap<String, Entity>>,
+) -> Result<TextEditResult, String> {
+    for edit in edits {
+        edit.validate()?;
+    }
+
+    let mut moved = 0;
+    let mut new_positions = Vec::new();
+    let mut deleted_marker_ids = Vec::new();
+
+    for (marker_id, marker) in markers.iter() {
+        let pinned = marker.pin.is_some();
+        let mut position = Some(marker.position);
+        for edit in edits {
+            position = position.and_then(|pos| match edit.map_leaf(pos) {
+                // Pinned markers outlive their text; syncing puts them back at their heading
+                None if pinned => Some(edit.from),
+                mapped => mapped,
+            });
+        }
+
+        match position {
+            Some(pos) if pos != marker.position => new_positions.push((marker_id.clone(), pos)),
+            Some(_) => {}
+            None => deleted_marker_ids.push(marker_id.clone()),
+        }
+    }
+
+    if let Some(entities) = strict_entities {
+        let shifted: Vec<Marker> = new_positions
+            .iter()
+            .map(|(marker_id, pos)| Marker { position: *pos, ..markers[marker_id].clone() })
+            .collect();
+        strict::check_batch(entities, markers, &shifted, &deleted_marker_ids)?;
+    }
+
+    for (marker_id, pos) in new_positions {
+        if let Some(marker) = markers.get_mut(&marker_id) {
+            marker.position = pos;
+            moved += 1;
+        }
+    }
+
+    // Markers inside a replaced range were deleted along with their text
+    for marker_id in &deleted_marker_ids {
+        markers.remove(marker_id);
+    }
+
+    Ok(TextEditResult {
+        moved,
+        deleted_marker_ids,
+    })
+}
+
+// Helper function to drop the stored content after edits known only by position (which can't be
+// applied to it), so realigning against it can't move markers back; set_content restores it
+fn forget_content(doc: &DocumentState) {
+    *doc.content.lock().unwrap() = None;
+}
+
+// Tauri command to shift markers after the range from..to was replaced by inserted_len positions.
+// The stored content is dropped (see forget_content); apply_content_steps keeps it instead.
+#[tauri::command]
+fn apply_text_edit(
+    from: usize,
+    to: usize,
+    inserted_len: usize,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<TextEditResult, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let strict = doc.preferences.lock().unwrap().strict_mode;
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    let edits = [TextEdit { from, to, inserted_len }];
+    let result = shift_markers_for_edits(&mut markers, &edits, strict.then_some(&*entities))?;
+    plot_threads::shift_positions(&mut doc.plot_threads.lock().unwrap(), &edits);
+    track_changes::shift_ranges(&mut doc.suggestions.lock().unwrap(), &edits);
+    forget_content(&doc);
+
+    Ok(result)
+}
+
+// Tauri command to shift markers through a batch of edits (e.g., all steps of one transaction)
+#[tauri::command]
+fn apply_text_edits(
+    edits: Vec<TextEdit>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<TextEditResult, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let strict = doc.preferences.lock().unwrap().strict_mode;
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    let result = shift_markers_for_edits(&mut markers, &edits, strict.then_some(&*entities))?;
+    plot_threads::shift_positions(&mut doc.plot_threads.lock().unwrap(), &edits);
+    track_changes::shift_ranges(&mut doc.suggestions.lock().unwrap(), &edits);
+    forget_content(&doc);
+
+    Ok(result)
+}
+
+// Helper function to realign stored marker positions with the marker nodes embedded in
+// the ProseMirror content, which is the source of truth, and pinned markers with their
+// headings. Returns how many markers moved.
+fn resync_marker_positions(markers: &mut HashMap<String, Marker>, content: &str) -> usize {
+    match serde_json::from_str(content) {
+        Ok(doc_json) => realign_markers(markers, &doc_json),
+        Err(_) => 0, // Not ProseMirror JSON (e.g., empty editor) - nothing to sync against
+    }
+}
+
+// Helper function to realign marker positions with parsed content (see resync_marker_positions)
+fn realign_markers(markers: &mut HashMap<String, Marker>, doc_json: &serde_json::Value) -> usize {
+    let mut moved = 0;
+    for (marker_id, position) in positions::marker_node_positions(doc_json) {
+        if let Some(marker) = markers.get_mut(&marker_id).filter(|m| m.pin.is_none()) {
+            if marker.position != position {
+                marker.position = position;
+                moved += 1;
+            }
+        }
+    }
+
+    // A pinned marker whose heading is gone stays where it last was
+    for marker in markers.values_mut() {
+        let Some(position) = marker.pin.as_ref().and_then(|pin| chapters::resolve_pin(doc_json, pin)) else {
+            continue;
+        };
+        if marker.position != position {
+            marker.position = position;
+            moved += 1;
+        }
+    }
+
+    moved
+}
+
+// Tauri command to convert pasted HTML or RTF (from Word, Google Docs, ...) into ProseMirror JSON
+#[tauri::command]
+fn convert_clipboard_content(html_or_rtf: String) -> serde_json::Value {
+    clipboard::convert(&html_or_rtf)
+}
+
+// Tauri command to get the size of a document in ProseMirror positions
+#[tauri::command]
+fn get_document_size(content: String) -> Result<usize, String> {
+    let doc_json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse document JSON: {}", e))?;
+
+    Ok(positions::content_size(&doc_json))
+}
+
+// Tauri command to convert an offset within a piece of text between position units
+// ("utf16" = JavaScript string offsets, "char" = Unicode scalar values)
+#[tauri::command]
+fn convert_text_offset(
+    text: String,
+    offset: usize,
+    from_unit: String,
+    to_unit: String,
+) -> Result<usize, String> {
+    let char_index = match from_unit.as_str() {
+        "utf16" => positions::utf16_to_char_index(&text, offset),
+        "char" => offset.min(text.chars().count()),
+        _ => return Err(format!("Unknown position unit: {}", from_unit)),
+    };
+
+    match to_unit.as_str() {
+        "utf16" => Ok(positions::char_index_to_utf16(&text, char_index)),
+        "char" => Ok(char_index),
+        _ => Err(format!("Unknown position unit: {}", to_unit)),
+    }
+}
+
+// Tauri command to replace the content stored in the backend (see content.rs); markers are
+// realigned with it. Returns how many markers moved.
+#[tauri::command]
+fn set_content(
+    content: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<usize, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let doc_json: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse document JSON: {}", e))?;
+
+    let mut markers = doc.markers.lock().unwrap();
+    let moved = realign_markers(&mut markers, &doc_json);
+    *doc.content.lock().unwrap() = Some(doc_json);
+
+    Ok(moved)
+}
+
+// Tauri command to get the content stored in the backend, as ProseMirror JSON
+#[tauri::command]
+fn get_content(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    document_content(&state.document(session_id.as_deref()), None)
+}
+
+// Tauri command to apply a transaction's steps (ProseMirror `Step.toJSON()`) to the stored
+// content. Markers and plot threads shift like with apply_text_edits, and are then
+// realigned with the marker nodes; nothing changes if a step doesn't apply.
+#[tauri::command]
+fn apply_content_steps(
+    steps: Vec<serde_json::Value>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<TextEditResult, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let strict = doc.preferences.lock().unwrap().strict_mode;
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+    let mut content = doc.content.lock().unwrap();
+
+    let current = content
+        .as_ref()
+        .ok_or("No document content: send it with set_content first")?;
+    let applied = content::apply_steps(current, &steps)?;
+
+    let mut result = shift_markers_for_edits(&mut markers, &applied.edits, strict.then_some(&*entities))?;
+    plot_threads::shift_positions(&mut doc.plot_threads.lock().unwrap(), &applied.edits);
+    track_changes::shift_ranges(&mut doc.suggestions.lock().unwrap(), &applied.edits);
+    result.moved += realign_markers(&mut markers, &applied.doc);
+    *content = Some(applied.doc);
+
+    Ok(result)
+}
+
+// Result of a search and replace over the stored content
+#[derive(Serialize)]
+struct ReplaceResult {
+    replacements: usize,
+    content: serde_json::Value, // The updated document, for the editor to load
+    moved: usize,
+}
+
+// Tauri command to replace text in the stored content. Each replacement shifts markers and
+// plot threads after it, and matches never cross marker nodes.
+#[tauri::command]
+fn replace_in_document(
+    find: String,
+    replace: String,
+    options: Option<content::ReplaceOptions>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<ReplaceResult, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let strict = doc.preferences.lock().unwrap().strict_mode;
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+    let mut content = doc.content.lock().unwrap();
+
+    let current = content
+        .as_ref()
+        .ok_or("No document content: send it with set_content first")?;
+    let replaced = content::replace_text(current, &find, &replace, &options.unwrap_or_default())?;
+    if replaced.count == 0 {
+        return Ok(ReplaceResult { replacements: 0, content: replaced.doc, moved: 0 });
+    }
+
+    let mut moved = shift_markers_for_edits(&mut markers, &replaced.edits, strict.then_some(&*entities))?.moved;
+    plot_threads::shift_positions(&mut doc.plot_threads.lock().unwrap(), &replaced.edits);
+    track_changes::shift_ranges(&mut doc.suggestions.lock().unwrap(), &replaced.edits);
+    moved += realign_markers(&mut markers, &replaced.doc);
+    *content = Some(replaced.doc.clone());
+
+    Ok(ReplaceResult { replacements: replaced.count, content: replaced.doc, moved })
+}
+
+// Helper function to put the nodes of markers created in the backend (by an import) into the
+// document, moving the other markers, plot threads, and suggestions after them like any other
+// edit. The placed markers' positions are updated to where their nodes went.
+fn place_marker_nodes(
+    doc: &DocumentState,
+    markers: &mut HashMap<String, Marker>,
+    doc_json: &serde_json::Value,
+    placed: &mut [Marker],
+) -> Result<serde_json::Value, String> {
+    let applied = content::insert_marker_nodes(doc_json, &placed.iter().collect::<Vec<_>>())?;
+
+    shift_markers_for_edits(markers, &applied.edits, None)?;
+    plot_threads::shift_positions(&mut doc.plot_threads.lock().unwrap(), &applied.edits);
+    track_changes::shift_ranges(&mut doc.suggestions.lock().unwrap(), &applied.edits);
+    realign_markers(markers, &applied.doc);
+
+    for marker in placed.iter_mut() {
+        if let Some(stored) = markers.get(&marker.id) {
+            marker.position = stored.position;
+        }
+    }
+    Ok(applied.doc)
+}
+
+// Helper function to move markers and plot threads through a change the backend made to the
+// stored content, and store the changed content. Returns how many markers moved.
+fn store_content_change(
+    doc: &DocumentState,
+    markers: &mut HashMap<String, Marker>,
+    content: &mut Option<serde_json::Value>,
+    applied: content::AppliedSteps,
+) -> Result<usize, String> {
+    let mut moved = shift_markers_for_edits(markers, &applied.edits, None)?.moved;
+    plot_threads::shift_positions(&mut doc.plot_threads.lock().unwrap(), &applied.edits);
+    moved += realign_markers(markers, &applied.doc);
+    *content = Some(applied.doc);
+
+    Ok(moved)
+}
+
+// Tauri command to get the document's pending suggestions (tracked changes)
+#[tauri::command]
+fn get_suggestions(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<track_changes::Suggestion> {
+    state.document(session_id.as_deref()).suggestions.lock().unwrap().clone()
+}
+
+// Result of proposing an edit
+#[derive(Serialize)]
+struct SuggestedEdit {
+    suggestions: Vec<track_changes::Suggestion>, // The deletion and/or insertion recorded
+    content: serde_json::Value, // The updated document (with any inserted text), for the editor to load
+    moved: usize,
+}
+
+// Tauri command to propose replacing from..to with `text` instead of editing the stored content
+// directly (see track_changes.rs). The old text stays until the deletion is accepted.
+#[tauri::command]
+fn suggest_edit(
+    from: usize,
+    to: usize,
+    text: String,
+    author: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<SuggestedEdit, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+    let mut content = doc.content.lock().unwrap();
+    let mut suggestions = doc.suggestions.lock().unwrap();
+
+    let current = content
+        .as_ref()
+        .ok_or("No document content: send it with set_content first")?;
+    let proposed = track_changes::propose(current, &mut suggestions, from, to, &text, &author, dates::now())?;
+
+    let updated = proposed.applied.doc.clone();
+    let moved = store_content_change(&doc, &mut markers, &mut content, proposed.applied)?;
+
+    Ok(SuggestedEdit { suggestions: proposed.suggestions, content: updated, moved })
+}
+
+// Result of accepting or rejecting suggestions
+#[derive(Serialize)]
+struct ResolvedSuggestions {
+    content: serde_json::Value, // The updated document, for the editor to load
+    moved: usize,
+}
+
+// Tauri command to accept or reject a suggestion: an accepted deletion or a rejected
+// insertion takes its text out of the stored content
+#[tauri::command]
+fn resolve_suggestion(
+    suggestion_id: String,
+    accept: bool,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<ResolvedSuggestions, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+    let mut content = doc.content.lock().unwrap();
+    let mut suggestions = doc.suggestions.lock().unwrap();
+
+    let current = content
+        .as_ref()
+        .ok_or("No document content: send it with set_content first")?;
+    let applied = track_changes::resolve(current, &mut suggestions, &suggestion_id, accept)?;
+
+    let updated = applied.doc.clone();
+    let moved = store_content_change(&doc, &mut markers, &mut content, applied)?;
+
+    Ok(ResolvedSuggestions { content: updated, moved })
+}
+
+// Tauri command to accept or reject every pending suggestion
+#[tauri::command]
+fn resolve_all_suggestions(
+    accept: bool,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<ResolvedSuggestions, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+    let mut content = doc.content.lock().unwrap();
+    let mut suggestions = doc.suggestions.lock().unwrap();
+
+    let current = content
+        .as_ref()
+        .ok_or("No document content: send it with set_content first")?;
+    let applied = track_changes::resolve_all(current, &mut suggestions, accept)?;
+
+    let updated = applied.doc.clone();
+    let moved = store_content_change(&doc, &mut markers, &mut content, applied)?;
+
+    Ok(ResolvedSuggestions { content: updated, moved })
+}
+
+// Tauri command to realign marker positions with the marker nodes in the content
+#[tauri::command]
+fn sync_marker_positions(
+    content: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<usize, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+
+    Ok(resync_marker_positions(&mut markers, &content))
+}
+
+// Content with its chapter titles turned into headings, and the chapters that result
+#[derive(Serialize)]
+struct DetectedChapters {
+    content: serde_json::Value,
+    promoted: usize,
+    chapters: Vec<chapters::Chapter>,
+    moved: usize,
+}
+
+// Tauri command to turn chapter title paragraphs into chapter headings, matching `pattern`
+// (or the document's chapter pattern), or promoting the shallowest headings when neither is set.
+// The rewritten content becomes the stored content (see content.rs).
+#[tauri::command]
+fn detect_chapters(
+    content: Option<String>,
+    pattern: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<DetectedChapters, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let locale = state.locale_for(&doc);
+    let mut doc_json = document_json(&doc, content)?;
+
+    let pattern = pattern
+        .filter(|p| !p.trim().is_empty())
+        .or_else(|| doc.preferences.lock().unwrap().chapter_pattern.clone());
+    let regex = pattern
+        .map(|p| regex::Regex::new(&p).map_err(|e| format!("Invalid chapter pattern: {}", e)))
+        .transpose()?;
+
+    let promoted = chapters::detect_chapters(&mut doc_json, regex.as_ref());
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+
+    // Converted nodes keep their size, so there are no edits to shift anything through
+    let mut markers = doc.markers.lock().unwrap();
+    let mut stored = doc.content.lock().unwrap();
+    let applied = content::AppliedSteps { doc: doc_json.clone(), edits: Vec::new() };
+    let moved = store_content_change(&doc, &mut markers, &mut stored, applied)?;
+
+    Ok(DetectedChapters {
+        content: doc_json,
+        promoted,
+        chapters: chapter_list,
+        moved,
+    })
+}
+
+// Helper function to parse optional ProseMirror content passed to analysis commands
+fn parse_optional_content(doc: &DocumentState, content: Option<String>) -> Result<Option<serde_json::Value>, String> {
+    match content {
+        Some(c) => serde_json::from_str(&c)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse document JSON: {}", e)),
+        None => Ok(doc.content.lock().unwrap().clone()),
+    }
+}
+
+// Helper function to get the document content for commands that need it: the given JSON,
+// else the content stored in the backend (see content.rs)
+fn document_json(doc: &DocumentState, content: Option<String>) -> Result<serde_json::Value, String> {
+    parse_optional_content(doc, content)?
+        .ok_or_else(|| "No document content: pass it, or send it with set_content first".to_string())
+}
+
+// Helper function like document_json, for commands that need the JSON string
+fn document_content(doc: &DocumentState, content: Option<String>) -> Result<String, String> {
+    match content {
+        Some(content) => Ok(content),
+        None => serde_json::to_string(&document_json(doc, None)?)
+            .map_err(|e| format!("Failed to serialize document content: {}", e)),
+    }
+}
+
+// Tauri command to list the TODO markers still in the document, in document order
+#[tauri::command]
+fn get_open_todos(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<Marker> {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+
+    let mut todos: Vec<Marker> = markers.values().filter(|m| m.todo).cloned().collect();
+    todos.sort_by(engine::compare_markers);
+    todos
+}
+
+// Tauri command to report markers with a missing entity or a position outside the document
+#[tauri::command]
+fn find_orphaned_markers(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<analysis::OrphanedMarker>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = parse_optional_content(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(analysis::find_orphaned_markers(&entities, &markers, doc_json.as_ref()))
+}
+
+// Tauri command to list entities with no markers or (with content) no mentions in the text
+#[tauri::command]
+fn get_unused_entities(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<analysis::UnusedEntity>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = parse_optional_content(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(analysis::find_unused_entities(&entities, &markers, doc_json.as_ref()))
+}
+
+// Tauri command to count how often each entity is mentioned, by name and by mention node
+#[tauri::command]
+fn get_mention_counts(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<mentions::MentionCount>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = document_json(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+
+    Ok(mentions::mention_counts(&doc_json, &entities))
+}
+
+// Tauri command to find long stretches where an entity has no markers and isn't mentioned
+// (`min_gap` in words; default about ten chapters)
+#[tauri::command]
+fn find_state_gaps(
+    entity_id: String,
+    min_gap: Option<usize>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<analysis::StateGap>, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let doc_json = document_json(&doc, content)?;
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let entities = doc.entities.lock().unwrap();
+    let entity = entities.get(&entity_id).ok_or("Entity not found")?;
+    let mut markers = doc.markers.lock().unwrap().clone();
+    resync_marker_positions(&mut markers, &doc_json.to_string());
+
+    Ok(analysis::find_state_gaps(
+        entity,
+        &entities,
+        &markers,
+        &doc_json,
+        &chapter_list,
+        min_gap.unwrap_or(analysis::GAP_MIN_WORDS),
+    ))
+}
+
+// Tauri command to report how each of an entity's fields is used, to find stale fields
+#[tauri::command]
+fn get_field_usage(
+    entity_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<analysis::FieldUsage>, String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    let entity = entities
+        .get(&entity_id)
+        .ok_or("Entity not found")?;
+
+    Ok(analysis::field_usage(entity, &markers))
+}
+
+// Tauri command to list the markers strict mode objects to (whether or not it's on)
+#[tauri::command]
+fn get_strict_violations(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<strict::StrictViolation> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    strict::violations(&entities, &markers)
+}
+
+// Tauri command to report fields set once and never referenced again, and fields
+// removed without being introduced. With content, later paragraphs naming a field count too.
+#[tauri::command]
+fn get_chekhov_report(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<analysis::ChekhovItem>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = parse_optional_content(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(analysis::chekhov_report(&entities, &markers, doc_json.as_ref()))
+}
+
+// Helper function to get the travel speed limit from the document's preferences
+fn travel_limit(doc: &DocumentState) -> continuity::TravelLimit {
+    let preferences = doc.preferences.lock().unwrap();
+    continuity::TravelLimit {
+        max_speed: preferences.max_travel_speed(),
+        unit: preferences.distance_unit(),
+    }
+}
+
+// Tauri command to run the continuity checker over the document (see continuity.rs)
+#[tauri::command]
+fn check_continuity(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<continuity::ContinuityIssue>, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let doc_json = document_json(&doc, content)?;
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(continuity::check_continuity(
+        &entities,
+        &markers,
+        &doc_json,
+        &chapter_list,
+        &travel_limit(&doc),
+        &locale,
+    ))
+}
+
+// Tauri command to find near-miss spellings of entity names and aliases (see name_check.rs)
+#[tauri::command]
+fn check_name_spelling(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<name_check::NameMisspelling>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = document_json(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(name_check::find_misspellings(&doc_json, &entities, &markers))
+}
+
+// Tauri command to find text contradicting the entities' tracked descriptors (see descriptors.rs)
+#[tauri::command]
+fn scan_descriptors(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<descriptors::DescriptorMismatch>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = document_json(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(descriptors::scan_descriptors(&doc_json, &entities, &markers))
+}
+
+// Tauri command to run every validator and write the findings as a Markdown or HTML report
+#[tauri::command]
+fn export_continuity_report(
+    file_path: String,
+    format: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let format = continuity_report::ReportFormat::parse(&format)?;
+    let doc_json = document_json(&doc, content)?;
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap().clone();
+    realign_markers(&mut markers, &doc_json);
+    let threads = doc.plot_threads.lock().unwrap();
+
+    let sections = continuity_report::build_report(
+        &entities,
+        &markers,
+        &threads,
+        &doc_json,
+        &chapter_list,
+        &travel_limit(&doc),
+        &locale,
+    );
+    let report = match format {
+        continuity_report::ReportFormat::Markdown => continuity_report::render_markdown(&sections, &locale),
+        continuity_report::ReportFormat::Html => continuity_report::render_html(&sections, &locale),
+    };
+
+    fs::write(&file_path, report)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to export entity lifecycles, status effects, and plot threads as a
+// Gantt-style timeline across chapters (.svg, or .html with the SVG inline)
+#[tauri::command]
+fn export_timeline(
+    file_path: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let extension = Path::new(&file_path)
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let doc_json = document_json(&doc, content)?;
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let document_size = positions::content_size(&doc_json);
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap().clone();
+    realign_markers(&mut markers, &doc_json);
+    let threads = doc.plot_threads.lock().unwrap();
+
+    let sections = gantt::build_sections(&entities, &markers, &threads, document_size);
+    let svg = gantt::render_svg(&sections, &chapter_list, document_size);
+    let output = match extension.as_str() {
+        "svg" => svg,
+        "html" | "htm" => gantt::render_html(&svg),
+        _ => return Err(format!("Unsupported timeline format: {}", extension)),
+    };
+
+    fs::write(&file_path, output)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to find markers repeated within `max_distance` positions (default 3)
+#[tauri::command]
+fn find_duplicate_markers(
+    max_distance: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<analysis::DuplicateMarkers> {
+    let doc = state.document(session_id.as_deref());
+    let markers = doc.markers.lock().unwrap();
+
+    analysis::find_duplicate_markers(&markers, max_distance.unwrap_or(analysis::DUPLICATE_DISTANCE))
+}
+
+// Tauri command to delete every duplicate marker (keeping the first of each run),
+// returning the IDs that were removed
+#[tauri::command]
+fn delete_duplicates(
+    max_distance: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<String>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut markers = doc.markers.lock().unwrap();
+
+    let max_distance = max_distance.unwrap_or(analysis::DUPLICATE_DISTANCE);
+    let removed: Vec<String> = analysis::find_duplicate_markers(&markers, max_distance)
+        .into_iter()
+        .flat_map(|group| group.duplicate_marker_ids)
+        .collect();
+
+    for marker_id in &removed {
+        markers.remove(marker_id);
+    }
+
+    Ok(removed)
+}
+
+// Tauri command to delete all orphaned markers, returning the IDs that were removed
+#[tauri::command]
+fn remove_orphaned_markers(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<String>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let doc_json = parse_optional_content(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    let removed: Vec<String> = analysis::find_orphaned_markers(&entities, &markers, doc_json.as_ref())
+        .into_iter()
+        .map(|orphan| orphan.marker_id)
+        .collect();
+
+    for marker_id in &removed {
+        markers.remove(marker_id);
+    }
+
+    Ok(removed)
+}
+
+// Helper function to resolve report groups: chapters from the content, or the whole document
+fn report_groups(
+    doc: &DocumentState,
+    group_by: &str,
+    content: Option<String>,
+    markers: &HashMap<String, Marker>,
+    locale: &str,
+) -> Result<Vec<chapters::Chapter>, String> {
+    match group_by {
+        "chapter" => {
+            let doc_json = parse_optional_content(doc, content)?
+                .ok_or("Grouping by chapter requires the document content")?;
+            Ok(chapters::chapters_from_content(&doc_json, &i18n::tr(locale, "chapter.untitled", &[])))
+        }
+        "document" => Ok(vec![chapters::Chapter {
+            index: 0,
+            title: i18n::tr(locale, "report.whole_document", &[]),
+            start: 0,
+            end: markers.values().map(|m| m.position + 1).max().unwrap_or(0),
+        }]),
+        _ => Err(format!("Unknown grouping: {}", group_by)),
+    }
+}
+
+// Tauri command to summarize changes per entity per chapter ("chapter") or overall ("document")
+#[tauri::command]
+fn get_change_report(
+    group_by: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<reports::ChangeReportRow>, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+    let groups = report_groups(&doc, &group_by, content, &markers, &locale)?;
+
+    Ok(reports::change_report(&entities, &markers, &groups))
+}
+
+// Tauri command to write the change report as a CSV file
+#[tauri::command]
+fn export_change_report_csv(
+    file_path: String,
+    group_by: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+    let groups = report_groups(&doc, &group_by, content, &markers, &locale)?;
+
+    let rows = reports::change_report(&entities, &markers, &groups);
+
+    fs::write(&file_path, reports::change_report_csv(&rows, &locale))
+        .map_err(|e| format!("Failed to write file: {}", e))?;
+
+    Ok(())
+}
+
+// Helper function to build the state matrix for the given entities (all when None, or all
+// not hidden with visible_only) at the given chapter ends (every chapter's end, from the
+// content, when None)
+fn build_state_matrix(
+    doc: &DocumentState,
+    locale: &str,
+    entity_ids: Option<Vec<String>>,
+    visible_only: bool,
+    chapter_ends: Option<Vec<usize>>,
+    content: Option<String>,
+) -> Result<reports::StateMatrix, String> {
+    let doc_json = parse_optional_content(doc, content)?;
+    let chapter_list = doc_json
+        .as_ref()
+        .map(|d| chapters::chapters_from_content(d, &i18n::tr(locale, "chapter.untitled", &[])))
+        .unwrap_or_default();
+    let ends = match chapter_ends {
+        Some(ends) => ends,
+        None if doc_json.is_some() => chapter_list.iter().map(|c| c.end).collect(),
+        None => return Err("Chapter ends or the document content are required".to_string()),
+    };
+
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+    let visibility = doc.visibility.lock().unwrap();
+    let selected: Vec<&Entity> = match entity_ids {
+        Some(ids) => ids
+            .iter()
+            .map(|id| entities.get(id).ok_or_else(|| format!("Entity not found: {}", id)))
+            .collect::<Result<_, _>>()?,
+        None => {
+            let mut all: Vec<&Entity> = entities
+                .values()
+                .filter(|e| !visible_only || visibility.is_entity_visible(&e.id))
+                .collect();
+            all.sort_by(|a, b| a.name.cmp(&b.name));
+            all
+        }
+    };
+
+    Ok(reports::state_matrix(&selected, &markers, reports::matrix_columns(&ends, &chapter_list)))
+}
+
+// Tauri command to get entity values at the end of every chapter in one call (fields × chapters per entity)
+#[tauri::command]
+fn get_state_matrix(
+    entity_ids: Option<Vec<String>>,
+    visible_only: Option<bool>,
+    chapter_ends: Option<Vec<usize>>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<reports::StateMatrix, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+
+    build_state_matrix(&doc, &locale, entity_ids, visible_only.unwrap_or(false), chapter_ends, content)
+}
+
+// Tauri command to write the state matrix as a CSV file
+#[tauri::command]
+fn export_state_matrix_csv(
+    file_path: String,
+    entity_ids: Option<Vec<String>>,
+    visible_only: Option<bool>,
+    chapter_ends: Option<Vec<usize>>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let matrix = build_state_matrix(&doc, &locale, entity_ids, visible_only.unwrap_or(false), chapter_ends, content)?;
+
+    fs::write(&file_path, reports::state_matrix_csv(&matrix, &locale))
+        .map_err(|e| format!("Failed to write file: {}", e))?;
+
+    Ok(())
+}
+
+// Tauri command to create markers in bulk from a CSV file (see marker_csv.rs).
+// Content is needed for rows that give a chapter instead of a position. With content (given
+// or stored), the markers' nodes are put into it and it becomes the stored content.
+#[tauri::command]
+fn import_markers_csv(
+    file_path: String,
+    entity_mapping: Option<HashMap<String, String>>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<marker_csv::MarkerImport, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let locale = state.locale_for(&doc);
+
+    let text = fs::read_to_string(&file_path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    let doc_json = parse_optional_content(&doc, content)?;
+    let chapter_list = doc_json
+        .as_ref()
+        .map(|d| chapters::chapters_from_content(d, &i18n::tr(&locale, "chapter.untitled", &[])));
+
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    // Work on copies so a failure leaves the document untouched
+    let mut new_entities = entities.clone();
+    let mut new_markers = markers.clone();
+    let mut imported = marker_csv::import_markers(
+        &mut new_entities,
+        &mut new_markers,
+        &context,
+        &text,
+        &entity_mapping.unwrap_or_default(),
+        chapter_list.as_deref(),
+        doc_json.as_ref().map(positions::content_size),
+        0,
+    )?;
+
+    // Without content the editor still has to place the nodes
+    if let Some(doc_json) = &doc_json {
+        let updated = place_marker_nodes(&doc, &mut new_markers, doc_json, &mut imported.markers)?;
+        *doc.content.lock().unwrap() = Some(updated.clone());
+        imported.content = Some(updated);
+    }
+
+    *entities = new_entities;
+    *markers = new_markers;
+
+    Ok(imported)
+}
+
+// Tauri command to write markers (optionally filtered) as a CSV file, one row per
+// field change. Content is only used to fill in the chapter column.
+#[tauri::command]
+fn export_markers_csv(
+    file_path: String,
+    filter: Option<marker_csv::MarkerFilter>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let chapter_list = parse_optional_content(&doc, content)?
+        .map(|d| chapters::chapters_from_content(&d, &i18n::tr(&locale, "chapter.untitled", &[])));
+
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    let output = marker_csv::export_markers(
+        &entities,
+        &markers,
+        &filter.unwrap_or_default(),
+        chapter_list.as_deref(),
+    );
+
+    fs::write(&file_path, output)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to create or update entities from a YAML/JSON definition file
+// (see entity_import.rs)
+#[tauri::command]
+fn import_entities(
+    file_path: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<entity_import::EntityImport, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+
+    let text = fs::read_to_string(&file_path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    // Work on copies so a failure leaves the document untouched
+    let mut new_entities = entities.clone();
+    let mut new_markers = markers.clone();
+    let imported = entity_import::import_entities(
+        &mut new_entities,
+        &mut new_markers,
+        &context,
+        Path::new(&file_path),
+        &text,
+    )?;
+
+    *entities = new_entities;
+    *markers = new_markers;
+
+    Ok(imported)
+}
+
+// Tauri command to import characters, places and plotlines from a Plottr or Campfire Write
+// export (see tool_import.rs). With content, Plottr cards are placed at the matching chapters.
+#[tauri::command]
+fn import_from_tool(
+    file_path: String,
+    tool: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<tool_import::ToolImport, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let locale = state.locale_for(&doc);
+    let tool = tool_import::SourceTool::parse(&tool)?;
+
+    let text = fs::read_to_string(&file_path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let chapter_list = parse_optional_content(&doc, content)?
+        .map(|d| chapters::chapters_from_content(&d, &i18n::tr(&locale, "chapter.untitled", &[])))
+        .unwrap_or_default();
+
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+    let mut threads = doc.plot_threads.lock().unwrap();
+
+    // Work on copies so a failure leaves the document untouched
+    let mut new_entities = entities.clone();
+    let mut new_markers = markers.clone();
+    let mut new_threads = threads.clone();
+    let imported = tool_import::import_from_tool(
+        &mut new_entities,
+        &mut new_markers,
+        &mut new_threads,
+        &context,
+        tool,
+        &text,
+        &chapter_list,
+    )?;
+
+    *entities = new_entities;
+    *markers = new_markers;
+    *threads = new_threads;
+
+    Ok(imported)
+}
+
+// Tauri command to save selected entities as a .qsent entity pack (see entity_pack.rs).
+// Templates hold each entity's state at the position (default: end of the document).
+#[tauri::command]
+fn export_entity_pack(
+    file_path: String,
+    entity_ids: Vec<String>,
+    position: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    let pack = entity_pack::build_pack(&entities, &markers, &entity_ids, position.unwrap_or(usize::MAX))?;
+
+    let json = serde_json::to_string_pretty(&pack)
+        .map_err(|e| format!("Failed to serialize entity pack: {}", e))?;
+
+    fs::write(&file_path, json)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to add the entities from a .qsent entity pack to the document
+#[tauri::command]
+fn import_entity_pack(
+    file_path: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<entity_pack::EntityPackImport, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+
+    let json = fs::read_to_string(&file_path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let pack = entity_pack::parse_pack(&json)?;
+
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    // Work on copies so a failure leaves the document untouched
+    let mut new_entities = entities.clone();
+    let mut new_markers = markers.clone();
+    let imported = entity_pack::import_pack(&mut new_entities, &mut new_markers, &context, pack)?;
+
+    *entities = new_entities;
+    *markers = new_markers;
+
+    Ok(imported)
+}
+
+// Helper function to put saved items in ID order, so the same state always saves the same file
+fn sorted_by_id<T>(mut items: Vec<T>, id: impl Fn(&T) -> &String) -> Vec<T> {
+    items.sort_by(|a, b| id(a).cmp(id(b)));
+    items
+}
+
+// Tauri command to save document
+#[tauri::command]
+fn save_document(
+    file_path: String,
+    content: Option<String>,
+    utc_offset_minutes: Option<i32>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+
+    // Don't overwrite a file another QuestScribe instance has open
+    if let Some(lock) = lockfile::foreign_lock(Path::new(&file_path), &state.instance_id) {
+        return Err(format!(
+            "This document is open in another QuestScribe window ({}). Save it under a different name instead.",
+            lock.holder()
+        ));
+    }
+
+    // Content sent with the save replaces the stored content
+    let content = document_content(&doc, content)?;
+    if let Ok(doc_json) = serde_json::from_str(&content) {
+        *doc.content.lock().unwrap() = Some(doc_json);
+    }
+
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    // Positions in the saved file must match the content they annotate
+    resync_marker_positions(&mut markers, &content);
+
+    // Today's progress snapshot; the previous history is kept if the write fails
+    let mut progress_history = doc.progress.lock().unwrap().clone();
+    if let Ok(word_count) = content_word_count(&content) {
+        progress::record_snapshot(
+            &mut progress_history,
+            progress::take_snapshot(word_count, &entities, &markers, utc_offset_minutes.unwrap_or(0), dates::now()),
+        );
+    }
+
+    let document = Document {
+        content,
+        entities: sorted_by_id(entities.values().cloned().collect(), |e| &e.id),
+        markers: sorted_by_id(markers.values().cloned().collect(), |m| &m.id),
+        language: doc.document_language.lock().unwrap().clone(),
+        icon_packs: doc.icon_packs.lock().unwrap().clone(),
+        visual_rules: doc.visual_rules.lock().unwrap().clone(),
+        goals: doc.goals.lock().unwrap().clone(),
+        preferences: doc.preferences.lock().unwrap().clone(),
+        plot_threads: doc.plot_threads.lock().unwrap().clone(),
+        progress: progress_history,
+        visibility: doc.visibility.lock().unwrap().clone(),
+        suggestions: doc.suggestions.lock().unwrap().clone(),
+        change_types: doc.change_types.lock().unwrap().clone(),
+        notes_vault: doc.notes_vault.lock().unwrap().clone(),
+        pins: doc.pins.lock().unwrap().clone(),
+        quarantine: doc.quarantine.lock().unwrap().clone(),
+        format_version: legacy::FORMAT_VERSION,
+        migrations: doc.migrations.lock().unwrap().clone(),
+    };
+
+    let json = serde_json::to_string_pretty(&document)
+        .map_err(|e| format!("Failed to serialize document: {}", e))
+        .inspect_err(|e| logging::record_error("save_document", e))?;
+
+    fs::write(&file_path, json)
+        .map_err(|e| format!("Failed to write file: {}", e))
+        .inspect_err(|e| logging::record_error("save_document", e))?;
+
+    *doc.progress.lock().unwrap() = document.progress;
+
+    // Saving (possibly under a new name) means this session now owns the file.
+    // The save itself succeeded, so a lock that can't be written isn't an error.
+    take_document_lock(&state, &doc, Path::new(&file_path)).ok();
+
+    Ok(())
+}
+
+// Tauri command to save a spoiler-free copy of the document for beta readers.
+// Redacted markers and everything derived from them (their marker nodes, the entity
+// fields only they introduced, entities only they mention) are left out of the copy;
+// the open document itself is not changed.
+#[tauri::command]
+fn export_redacted_document(
+    file_path: String,
+    content: Option<String>,
+    redaction: Option<redaction::RedactionOptions>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let options = redaction.unwrap_or_default();
+
+    let mut doc_json = document_json(&doc, content)?;
+
+    let mut markers = doc.markers.lock().unwrap().clone();
+    realign_markers(&mut markers, &doc_json);
+
+    let redacted = redaction::redact(&doc.entities.lock().unwrap(), &markers, &options);
+    let plot_threads = redaction::redact_threads(
+        &doc.plot_threads.lock().unwrap(),
+        &doc_json,
+        &markers,
+        &redacted.markers,
+        &options,
+    );
+    redaction::strip_marker_nodes(&mut doc_json, &redacted.markers);
+
+    let content = serde_json::to_string(&doc_json)
+        .map_err(|e| format!("Failed to serialize document content: {}", e))?;
+
+    // Removing marker nodes shifts everything after them
+    let mut markers = redacted.markers;
+    resync_marker_positions(&mut markers, &content);
+
+    let document = Document {
+        content,
+        entities: sorted_by_id(redacted.entities.into_values().collect(), |e| &e.id),
+        markers: sorted_by_id(markers.into_values().collect(), |m| &m.id),
+        language: doc.document_language.lock().unwrap().clone(),
+        icon_packs: doc.icon_packs.lock().unwrap().clone(),
+        visual_rules: doc.visual_rules.lock().unwrap().clone(),
+        goals: doc.goals.lock().unwrap().clone(),
+        preferences: doc.preferences.lock().unwrap().clone(),
+        plot_threads,
+        progress: Vec::new(), // Per-entity history would reveal redacted entities
+        visibility: visibility::VisibilityFilters::default(), // The author's view, not the reader's
+        suggestions: Vec::new(), // Editorial back-and-forth, not for readers
+        change_types: doc.change_types.lock().unwrap().clone(),
+        notes_vault: None, // Spoilers, even encrypted
+        pins: Vec::new(), // Author's comparison points
+        quarantine: Vec::new(), // Unchecked data
+        format_version: legacy::FORMAT_VERSION,
+        migrations: Vec::new(),
+    };
+
+    let json = serde_json::to_string_pretty(&document)
+        .map_err(|e| format!("Failed to serialize document: {}", e))?;
+
+    fs::write(&file_path, json)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to load document
+#[tauri::command]
+fn load_document(
+    file_path: String,
+    read_only: Option<bool>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Document, String> {
+    let doc = state.document(session_id.as_deref());
+    let json = fs::read_to_string(&file_path)
+        .map_err(|e| format!("Failed to read file: {}", e))
+        .inspect_err(|e| logging::record_error("load_document", e))?;
+
+    // Entities and markers that don't check out are quarantined rather than failing the load
+    let document = quarantine::read_document(&json)
+        .inspect_err(|e| logging::record_error("load_document", e))?;
+    for item in &document.quarantine {
+        let item_id = item.item_id.as_deref().unwrap_or("(no ID)");
+        logging::record_error("load_document", &format!("Quarantined {}: {}", item_id, item.reason));
+    }
+
+    // Clear and load entities
+    let mut entities = doc.entities.lock().unwrap();
+    entities.clear();
+    for entity in &document.entities {
+        entities.insert(entity.id.clone(), entity.clone());
+    }
+
+    // Clear and load markers
+    let mut markers = doc.markers.lock().unwrap();
+    markers.clear();
+    for marker in &document.markers {
+        markers.insert(marker.id.clone(), marker.clone());
+    }
+
+    // Older files may carry positions computed with a different unit; trust the content
+    resync_marker_positions(&mut markers, &document.content);
+
+    *doc.document_language.lock().unwrap() = document.language.clone();
+    *doc.icon_packs.lock().unwrap() = document.icon_packs.clone();
+    *doc.visual_rules.lock().unwrap() = document.visual_rules.clone();
+    *doc.goals.lock().unwrap() = document.goals.clone();
+    *doc.preferences.lock().unwrap() = document.preferences.clone();
+    *doc.plot_threads.lock().unwrap() = document.plot_threads.clone();
+    *doc.progress.lock().unwrap() = document.progress.clone();
+    *doc.visibility.lock().unwrap() = document.visibility.clone();
+    *doc.suggestions.lock().unwrap() = document.suggestions.clone();
+    *doc.change_types.lock().unwrap() = document.change_types.clone();
+    *doc.notes_vault.lock().unwrap() = document.notes_vault.clone();
+    *doc.unlocked_vault.lock().unwrap() = None;
+    *doc.pins.lock().unwrap() = document.pins.clone();
+    *doc.quarantine.lock().unwrap() = document.quarantine.clone();
+    *doc.migrations.lock().unwrap() = document.migrations.clone();
+    *doc.content.lock().unwrap() = serde_json::from_str(&document.content).ok();
+
+    // In replay mode, new IDs must not collide with the loaded ones
+    drop(markers);
+    drop(entities);
+    reserve_document_ids(&doc);
+
+    let read_only = read_only.unwrap_or(false);
+    *doc.read_only.lock().unwrap() = read_only;
+
+    // Lock the file unless it's only being reviewed or another instance already has
+    // it open (the frontend checks with check_document_lock first and warns the user)
+    if !read_only && lockfile::foreign_lock(Path::new(&file_path), &state.instance_id).is_none() {
+        take_document_lock(&state, &doc, Path::new(&file_path)).ok();
+    } else {
+        release_document_lock(&state, &doc);
+    }
+
+    Ok(document)
+}
+
+// Tauri command to get the entities and markers set aside when the document was loaded
+#[tauri::command]
+fn get_quarantine(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<quarantine::QuarantinedItem> {
+    let doc = state.document(session_id.as_deref());
+    let items = doc.quarantine.lock().unwrap().clone();
+    items
+}
+
+// Tauri command to move a quarantined entity or marker into the document, optionally with
+// corrected JSON (see quarantine.rs)
+#[tauri::command]
+fn restore_quarantined(
+    entry_id: String,
+    data: Option<serde_json::Value>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<quarantine::RestoredItem, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+    let change_types = doc.change_types.lock().unwrap().clone();
+    let mut items = doc.quarantine.lock().unwrap();
+
+    quarantine::restore(
+        &mut items,
+        &mut entities,
+        &mut markers,
+        &change_types,
+        &entry_id,
+        data,
+    )
+}
+
+// Tauri command to delete a quarantined entity or marker for good
+#[tauri::command]
+fn discard_quarantined(
+    entry_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut items = doc.quarantine.lock().unwrap();
+
+    let before = items.len();
+    items.retain(|item| item.id != entry_id);
+    if items.len() == before {
+        return Err("Quarantined item not found".to_string());
+    }
+    Ok(())
+}
+
+// Tauri command to create new document (clear everything)
+#[tauri::command]
+fn new_document(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+
+    entities.clear();
+    markers.clear();
+    *doc.document_language.lock().unwrap() = None;
+    doc.icon_packs.lock().unwrap().clear();
+    *doc.visual_rules.lock().unwrap() = None;
+    *doc.goals.lock().unwrap() = goals::WordGoals::default();
+    *doc.preferences.lock().unwrap() = preferences::DocumentPreferences::default();
+    doc.plot_threads.lock().unwrap().clear();
+    doc.progress.lock().unwrap().clear();
+    *doc.visibility.lock().unwrap() = visibility::VisibilityFilters::default();
+    doc.suggestions.lock().unwrap().clear();
+    doc.change_types.lock().unwrap().clear();
+    *doc.notes_vault.lock().unwrap() = None;
+    *doc.unlocked_vault.lock().unwrap() = None;
+    doc.pins.lock().unwrap().clear();
+    doc.quarantine.lock().unwrap().clear();
+    doc.migrations.lock().unwrap().clear();
+    *doc.content.lock().unwrap() = None;
+    *doc.read_only.lock().unwrap() = false;
+    release_document_lock(&state, &doc);
+
+    Ok(())
+}
+
+// Helper function to lock a file for a session, releasing the session's previous lock
+fn take_document_lock(state: &AppState, doc: &DocumentState, path: &Path) -> Result<(), String> {
+    let mut locked_path = doc.locked_path.lock().unwrap();
+
+    if let Some(previous) = locked_path.take() {
+        if previous != path {
+            lockfile::remove_lock(&previous, &state.instance_id);
+        }
+    }
+
+    lockfile::write_lock(path, &state.instance_id, dates::now())?;
+    *locked_path = Some(path.to_path_buf());
+
+    Ok(())
+}
+
+// Helper function to release the lock a session holds, if any
+fn release_document_lock(state: &AppState, doc: &DocumentState) {
+    if let Some(path) = doc.locked_path.lock().unwrap().take() {
+        lockfile::remove_lock(&path, &state.instance_id);
+    }
+}
+
+// Tauri command to check whether another QuestScribe instance has a document open
+// (None = free to open for editing)
+#[tauri::command]
+fn check_document_lock(
+    file_path: String,
+    state: tauri::State<AppState>,
+) -> Option<lockfile::LockInfo> {
+    lockfile::foreign_lock(Path::new(&file_path), &state.instance_id)
+}
+
+// Tauri command to lock a document for this session.
+// With force, a lock held by another instance is taken over (the user chose "open anyway").
+#[tauri::command]
+fn lock_document(
+    file_path: String,
+    force: Option<bool>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let path = Path::new(&file_path);
+
+    if !force.unwrap_or(false) {
+        if let Some(lock) = lockfile::foreign_lock(path, &state.instance_id) {
+            return Err(format!("This document is already open ({})", lock.holder()));
+        }
+    }
+
+    take_document_lock(&state, &state.document(session_id.as_deref()), path)
+}
+
+// Tauri command to check whether a session's document is read-only
+#[tauri::command]
+fn is_read_only(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> bool {
+    *state.document(session_id.as_deref()).read_only.lock().unwrap()
+}
+
+// Tauri command to switch read-only mode on or off.
+// Leaving read-only mode takes the file lock, so it fails while another instance has the file open.
+#[tauri::command]
+fn set_read_only(
+    read_only: bool,
+    file_path: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+
+    if read_only {
+        release_document_lock(&state, &doc);
+    } else if let Some(file_path) = file_path {
+        let path = Path::new(&file_path);
+        if let Some(lock) = lockfile::foreign_lock(path, &state.instance_id) {
+            return Err(format!("This document is open elsewhere ({})", lock.holder()));
+        }
+        take_document_lock(&state, &doc, path)?;
+    }
+
+    *doc.read_only.lock().unwrap() = read_only;
+
+    Ok(())
+}
+
+// Tauri command to release this session's document lock (e.g., before closing the window)
+#[tauri::command]
+fn unlock_document(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) {
+    release_document_lock(&state, &state.document(session_id.as_deref()));
+}
+
+// Helper function to record command invocations in the diagnostic log (see logging.rs)
+fn with_logging(
+    handler: impl Fn(tauri::Invoke) + Send + Sync + 'static,
+) -> impl Fn(tauri::Invoke) + Send + Sync + 'static {
+    move |invoke| {
+        let command = invoke.message.command().to_string();
+        let window = invoke.message.window().label().to_string();
+        logging::record_command(&command, &window, || handler(invoke));
+    }
+}
+
+// Helper function to get a path inside the app data directory
+fn app_data_path(app: &tauri::AppHandle, name: &str) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join(name))
+        .ok_or_else(|| "Could not determine app data directory".to_string())
+}
+
+// Helper function to get the folder holding app-level icon packs
+fn icon_pack_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app_data_path(app, "icon_packs")
+}
+
+// Tauri command to install an icon pack from a folder of SVG/PNG files,
+// either into the current document or into app data for all documents
+#[tauri::command]
+fn install_icon_pack(
+    source_dir: String,
+    name: String,
+    location: icons::PackLocation,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<icons::IconPackInfo, String> {
+    let doc = state.document(session_id.as_deref());
+    let pack = icons::load_pack_from_dir(
+        &PathBuf::from(&source_dir),
+        ids::new_id(),
+        name,
+    )?;
+    let info = pack.info(location);
+
+    match location {
+        icons::PackLocation::Document => {
+            doc.ensure_writable()?;
+            doc.icon_packs.lock().unwrap().push(pack);
+        }
+        icons::PackLocation::App => icons::save_app_pack(&icon_pack_dir(&app)?, &pack)?,
+    }
+
+    Ok(info)
+}
+
+// Tauri command to list document and app icon packs
+#[tauri::command]
+fn list_icon_packs(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<icons::IconPackInfo>, String> {
+    let doc = state.document(session_id.as_deref());
+    let mut packs: Vec<icons::IconPackInfo> = doc
+        .icon_packs
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|p| p.info(icons::PackLocation::Document))
+        .collect();
+
+    for pack in icons::load_app_packs(&icon_pack_dir(&app)?)? {
+        packs.push(pack.info(icons::PackLocation::App));
+    }
+
+    Ok(packs)
+}
+
+// Tauri command to remove an icon pack from the document or app data
+#[tauri::command]
+fn remove_icon_pack(
+    pack_id: String,
+    location: icons::PackLocation,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    match location {
+        icons::PackLocation::Document => {
+            doc.ensure_writable()?;
+            let mut packs = doc.icon_packs.lock().unwrap();
+            let before = packs.len();
+            packs.retain(|p| p.id != pack_id);
+            if packs.len() == before {
+                return Err("Icon pack not found".to_string());
+            }
+        }
+        icons::PackLocation::App => {
+            icons::remove_app_pack(&icon_pack_dir(&app)?, &pack_id)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Tauri command to resolve an icon reference into a data URI for rendering.
+// Document packs take precedence over app packs with the same ID.
+#[tauri::command]
+fn resolve_icon(
+    icon_ref: String,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<icons::ResolvedIcon, String> {
+    let doc = state.document(session_id.as_deref());
+    if let Some(icon) = icons::find_icon(doc.icon_packs.lock().unwrap().iter(), &icon_ref) {
+        return Ok(icon.resolve());
+    }
+
+    let app_packs = icons::load_app_packs(&icon_pack_dir(&app)?)?;
+    icons::find_icon(&app_packs, &icon_ref)
+        .map(|icon| icon.resolve())
+        .ok_or_else(|| format!("Icon not found: {}", icon_ref))
+}
+
+// Tauri command to get the visual rules in effect for this document
+#[tauri::command]
+fn get_visual_rules(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<visual_rules::VisualRule> {
+    state
+        .document(session_id.as_deref())
+        .visual_rules
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(visual_rules::default_rules)
+}
+
+// Tauri command to replace the document's visual rules (an empty list disables auto visuals)
+#[tauri::command]
+fn set_visual_rules(
+    mut rules: Vec<visual_rules::VisualRule>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    for rule in &mut rules {
+        if let Some(icon_ref) = &rule.icon_ref {
+            icons::parse_icon_ref(icon_ref)?;
+        }
+        if let Some(color) = &rule.color {
+            rule.color = Some(colors::parse_color(color)?);
+        }
+    }
+
+    *doc.visual_rules.lock().unwrap() = Some(rules);
+
+    Ok(())
+}
+
+// Tauri command to go back to the built-in visual rules
+#[tauri::command]
+fn reset_visual_rules(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<visual_rules::VisualRule>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+
+    *doc.visual_rules.lock().unwrap() = None;
+
+    Ok(visual_rules::default_rules())
+}
+
+// Helper function to count the words in ProseMirror content
+fn content_word_count(content: &str) -> Result<usize, String> {
+    let doc_json: serde_json::Value = serde_json::from_str(content)
+        .map_err(|e| format!("Failed to parse document JSON: {}", e))?;
+
+    Ok(stats::document_word_count(&doc_json))
+}
+
+// Tauri command to start a writing session at the current word count
+#[tauri::command]
+fn start_writing_session(
+    content: Option<String>,
+    document_path: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<sessions::WritingSession, String> {
+    let doc = state.document(session_id.as_deref());
+    let word_count = stats::document_word_count(&document_json(&doc, content)?);
+    let mut active = doc.writing_session.lock().unwrap();
+
+    if active.is_some() {
+        return Err("A writing session is already in progress".to_string());
+    }
+
+    let now = dates::now();
+    let session = sessions::WritingSession {
+        id: ids::new_id(),
+        document_path,
+        started_at: now,
+        ended_at: now,
+        start_word_count: word_count,
+        end_word_count: word_count,
+    };
+
+    *active = Some(session.clone());
+
+    Ok(session)
+}
+
+// Tauri command to end the active writing session and append it to the history
+#[tauri::command]
+fn end_writing_session(
+    content: Option<String>,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<sessions::WritingSession, String> {
+    let doc = state.document(session_id.as_deref());
+    let word_count = stats::document_word_count(&document_json(&doc, content)?);
+    let history_path = app_data_path(&app, sessions::HISTORY_FILE)?;
+
+    let mut session = doc
+        .writing_session
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("No writing session in progress")?;
+
+    session.ended_at = dates::now();
+    session.end_word_count = word_count;
+
+    let mut history = sessions::load_history(&history_path)?;
+    history.sessions.push(session.clone());
+    sessions::save_history(&history_path, &history)?;
+
+    Ok(session)
+}
+
+// Tauri command to get the active writing session, if any
+#[tauri::command]
+fn get_active_writing_session(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Option<sessions::WritingSession> {
+    state.document(session_id.as_deref()).writing_session.lock().unwrap().clone()
+}
+
+// Tauri command to get writing history with daily totals and streaks.
+// utc_offset_minutes is the user's offset east of UTC (the negation of JS getTimezoneOffset()).
+#[tauri::command]
+fn get_writing_history(
+    utc_offset_minutes: Option<i32>,
+    days: Option<i64>,
+    app: tauri::AppHandle,
+) -> Result<sessions::WritingHistorySummary, String> {
+    let history = sessions::load_history(&app_data_path(&app, sessions::HISTORY_FILE)?)?;
+
+    Ok(sessions::summarize(&history, utc_offset_minutes.unwrap_or(0), days, dates::now()))
+}
+
+// Tauri command to get the heading tree with section ranges, word counts, and nearby markers
+#[tauri::command]
+fn get_document_outline(
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<outline::OutlineSection>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = document_json(&doc, content)?;
+
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(outline::document_outline(&doc_json, &entities, &markers))
+}
+
+// Tauri command to summarize what changed for each entity between two positions.
+// With use_llm, the prose comes from the configured language model when one is enabled.
+// Runs off the main thread since the model call can take a while.
+#[tauri::command(async)]
+fn generate_recap(
+    from_position: usize,
+    to_position: usize,
+    use_llm: Option<bool>,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<recap::Recap, String> {
+    let doc = state.document(session_id.as_deref());
+    if from_position > to_position {
+        return Err(format!("Invalid recap range: {}..{}", from_position, to_position));
+    }
+
+    let locale = state.locale_for(&doc);
+    let mut recap = {
+        let entities = doc.entities.lock().unwrap();
+        let markers = doc.markers.lock().unwrap();
+        recap::generate_recap(&entities, &markers, from_position, to_position, &locale)
+    };
+
+    if use_llm.unwrap_or(false) {
+        let config = llm::load_config(&app_data_path(&app, llm::CONFIG_FILE)?)?;
+        match llm::provider_from_config(&config) {
+            Some(provider) => recap::narrate(&mut recap, provider.as_ref(), &locale),
+            None => recap.llm_error = Some("Summarization is not configured".to_string()),
+        }
+    }
+
+    Ok(recap)
+}
+
+// Tauri command to outline an entity's trajectory chapter by chapter, as Markdown
+#[tauri::command]
+fn generate_arc_outline(
+    entity_id: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let doc_json = document_json(&doc, content)?;
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+    let entity = entities.get(&entity_id).ok_or("Entity not found")?;
+
+    Ok(arc_outline::generate_arc_outline(entity, &markers, &chapter_list))
+}
+
+// Tauri command to get the language model settings used for summaries
+#[tauri::command]
+fn get_llm_config(app: tauri::AppHandle) -> Result<llm::LlmConfig, String> {
+    llm::load_config(&app_data_path(&app, llm::CONFIG_FILE)?)
+}
+
+// Tauri command to save the language model settings used for summaries
+#[tauri::command]
+fn set_llm_config(config: llm::LlmConfig, app: tauri::AppHandle) -> Result<(), String> {
+    config.validate()?;
+    llm::save_config(&app_data_path(&app, llm::CONFIG_FILE)?, &config)
+}
+
+// Tauri command to send a short prompt to the configured provider and return its reply
+#[tauri::command(async)]
+fn test_llm_provider(app: tauri::AppHandle) -> Result<String, String> {
+    let config = llm::load_config(&app_data_path(&app, llm::CONFIG_FILE)?)?;
+    let provider = llm::provider_from_config(&config)
+        .ok_or("Summarization is not configured")?;
+
+    provider.complete("Reply with the single word: ready")
+}
+
+// Tauri command to get the document's preferences
+#[tauri::command]
+fn get_document_preferences(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> preferences::DocumentPreferences {
+    state.document(session_id.as_deref()).preferences.lock().unwrap().clone()
+}
+
+// Tauri command to replace the document's preferences
+#[tauri::command]
+fn set_document_preferences(
+    preferences: preferences::DocumentPreferences,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    preferences.validate()?;
+
+    *doc.preferences.lock().unwrap() = preferences;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct NotesVaultStatus {
+    exists: bool,
+    unlocked: bool,
+}
+
+// Helper function to change the unlocked vault's notes and re-seal it, so the
+// document never holds them unencrypted
+fn modify_notes_vault<T>(
+    doc: &DocumentState,
+    change: impl FnOnce(&mut notes_vault::UnlockedVault) -> Result<T, String>,
+) -> Result<T, String> {
+    doc.ensure_writable()?;
+    let mut unlocked = doc.unlocked_vault.lock().unwrap();
+    let vault = unlocked.as_mut().ok_or("Notes vault is locked")?;
+
+    let result = change(vault)?;
+    *doc.notes_vault.lock().unwrap() = Some(vault.seal()?);
+
+    Ok(result)
+}
+
+// Tauri command to tell whether the document has a notes vault and whether it's unlocked
+#[tauri::command]
+fn get_notes_vault_status(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> NotesVaultStatus {
+    let doc = state.document(session_id.as_deref());
+    let exists = doc.notes_vault.lock().unwrap().is_some();
+    let unlocked = doc.unlocked_vault.lock().unwrap().is_some();
+    NotesVaultStatus { exists, unlocked }
+}
+
+// Tauri command to add an empty notes vault with its own passphrase (see notes_vault.rs).
+// The new vault starts unlocked.
+#[tauri::command]
+fn create_notes_vault(
+    passphrase: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let mut unlocked = doc.unlocked_vault.lock().unwrap();
+    let mut sealed = doc.notes_vault.lock().unwrap();
+    if sealed.is_some() {
+        return Err("The document already has a notes vault".to_string());
+    }
+
+    let vault = notes_vault::create(&passphrase)?;
+    *sealed = Some(vault.seal()?);
+    *unlocked = Some(vault);
+
+    Ok(())
+}
+
+// Tauri command to decrypt the notes vault for this session
+#[tauri::command]
+fn unlock_notes_vault(
+    passphrase: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<notes_vault::VaultNote>, String> {
+    let doc = state.document(session_id.as_deref());
+    let sealed = doc.notes_vault.lock().unwrap().clone().ok_or("The document has no notes vault")?;
+
+    let vault = notes_vault::unlock(&sealed, &passphrase)?;
+    let notes = vault.notes.clone();
+    *doc.unlocked_vault.lock().unwrap() = Some(vault);
+
+    Ok(notes)
+}
+
+// Tauri command to lock the notes vault, forgetting the key and the decrypted notes
+#[tauri::command]
+fn lock_notes_vault(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) {
+    *state.document(session_id.as_deref()).unlocked_vault.lock().unwrap() = None;
+}
+
+// Tauri command to list the notes in the unlocked vault
+#[tauri::command]
+fn get_vault_notes(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<notes_vault::VaultNote>, String> {
+    let doc = state.document(session_id.as_deref());
+    let unlocked = doc.unlocked_vault.lock().unwrap();
+
+    unlocked
+        .as_ref()
+        .map(|vault| vault.notes.clone())
+        .ok_or_else(|| "Notes vault is locked".to_string())
+}
+
+// Tauri command to add a note to the unlocked vault (no ID) or replace one
+#[tauri::command]
+fn save_vault_note(
+    note_id: Option<String>,
+    title: String,
+    body: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<notes_vault::VaultNote, String> {
+    let doc = state.document(session_id.as_deref());
+    modify_notes_vault(&doc, |vault| vault.save_note(note_id, title, body))
+}
+
+// Tauri command to delete a note from the unlocked vault
+#[tauri::command]
+fn delete_vault_note(
+    note_id: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    modify_notes_vault(&doc, |vault| vault.delete_note(&note_id))
+}
+
+// Tauri command to change the passphrase of the unlocked vault
+#[tauri::command]
+fn change_vault_passphrase(
+    passphrase: String,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    modify_notes_vault(&doc, |vault| vault.change_passphrase(&passphrase))
+}
+
+// Tauri command to list the bundled story structure templates
+#[tauri::command]
+fn get_structure_templates() -> Vec<structure::StructureTemplate> {
+    structure::builtin_templates()
+}
+
+// Tauri command to map a structure template's beats onto the document and report the
+// beats without text or markers. Uses the named bundled template, else the document's own.
+#[tauri::command]
+fn check_structure(
+    template: Option<String>,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<structure::StructureReport, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let doc_json = document_json(&doc, content)?;
+
+    let builtins = structure::builtin_templates();
+    let template = match template {
+        Some(name) => builtins
+            .into_iter()
+            .find(|t| t.name.eq_ignore_ascii_case(&name))
+            .ok_or_else(|| format!("Structure template not found: {}", name))?,
+        None => match doc.preferences.lock().unwrap().structure_template.clone() {
+            Some(template) => template,
+            None => builtins.into_iter().next().ok_or("No structure templates")?,
+        },
+    };
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let mut markers = doc.markers.lock().unwrap().clone();
+    resync_marker_positions(&mut markers, &doc_json.to_string());
+
+    Ok(structure::check_structure(&template, &doc_json, &chapter_list, &markers))
+}
+
+// Tauri command to get the document's custom change types
+#[tauri::command]
+fn get_change_types(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<change_types::CustomChangeType> {
+    state.document(session_id.as_deref()).change_types.lock().unwrap().clone()
+}
+
+// Tauri command to replace the document's custom change types (see change_types.rs). Changes
+// using a redefined type pick up its new reducer; returns how many markers were updated.
+#[tauri::command]
+fn set_change_types(
+    types: Vec<change_types::CustomChangeType>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<usize, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    change_types::validate_all(&types)?;
+
+    let types: Vec<change_types::CustomChangeType> = types
+        .into_iter()
+        .map(|t| change_types::CustomChangeType { name: t.name.trim().to_string(), ..t })
+        .collect();
+    let updated = change_types::redefine(&mut doc.markers.lock().unwrap(), &types);
+    *doc.change_types.lock().unwrap() = types;
+
+    Ok(updated)
+}
+
+// Tauri command to get the document's visibility filters (hidden entities and tags, collapsed groups)
+#[tauri::command]
+fn get_visibility_filters(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> visibility::VisibilityFilters {
+    state.document(session_id.as_deref()).visibility.lock().unwrap().clone()
+}
+
+// Tauri command to replace the document's visibility filters; returns them cleaned up.
+// They only affect what is shown, so this works in read-only mode too.
+#[tauri::command]
+fn set_visibility_filters(
+    mut filters: visibility::VisibilityFilters,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> visibility::VisibilityFilters {
+    let doc = state.document(session_id.as_deref());
+    filters.normalize(&doc.entities.lock().unwrap());
+
+    *doc.visibility.lock().unwrap() = filters.clone();
+    filters
+}
+
+// Tauri command to get the document's word count goals
+#[tauri::command]
+fn get_word_goals(
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> goals::WordGoals {
+    state.document(session_id.as_deref()).goals.lock().unwrap().clone()
+}
+
+// Tauri command to set the document's word count goals (None clears a goal)
+#[tauri::command]
+fn set_word_goals(
+    document_words: Option<usize>,
+    daily_words: Option<usize>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<goals::WordGoals, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+
+    let mut goals = doc.goals.lock().unwrap();
+    goals.document_words = document_words;
+    goals.daily_words = daily_words;
+
+    Ok(goals.clone())
+}
+
+// Tauri command to get the document's daily progress snapshots for the last `days` days (all when None)
+#[tauri::command]
+fn get_progress_history(
+    days: Option<u32>,
+    utc_offset_minutes: Option<i32>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Vec<progress::ProgressSnapshot> {
+    let doc = state.document(session_id.as_deref());
+    let history = doc.progress.lock().unwrap();
+
+    progress::history_since(&history, days, utc_offset_minutes.unwrap_or(0), dates::now())
+}
+
+// Tauri command to get progress toward the document and daily word count goals
+#[tauri::command]
+fn get_goal_progress(
+    content: Option<String>,
+    utc_offset_minutes: Option<i32>,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<goals::GoalProgress, String> {
+    let doc = state.document(session_id.as_deref());
+    let word_count = stats::document_word_count(&document_json(&doc, content)?);
+    let history = sessions::load_history(&app_data_path(&app, sessions::HISTORY_FILE)?)?;
+    let active = doc.writing_session.lock().unwrap().clone();
+    let goals = doc.goals.lock().unwrap().clone();
+
+    Ok(goals::goal_progress(
+        &goals,
+        word_count,
+        &history.sessions,
+        active.as_ref(),
+        utc_offset_minutes.unwrap_or(0),
+        dates::now(),
+    ))
+}
+
+// Locale info returned to the frontend for language pickers
+#[derive(Serialize)]
+struct LocaleInfo {
+    code: String,
+    name: String,
+}
+
+// Tauri command to list the bundled locales for backend-generated text
+#[tauri::command]
+fn get_supported_locales() -> Vec<LocaleInfo> {
+    i18n::SUPPORTED_LOCALES
+        .iter()
+        .map(|(code, name)| LocaleInfo {
+            code: code.to_string(),
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+// Tauri command to get the application locale
+#[tauri::command]
+fn get_app_locale(state: tauri::State<AppState>) -> String {
+    state.settings.lock().unwrap().locale.clone()
+}
+
+// Tauri command to set the application locale (e.g., "de" or "pt-BR")
+#[tauri::command]
+fn set_app_locale(
+    locale: String,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let update = settings::SettingsUpdate {
+        locale: Some(locale),
+        ..Default::default()
+    };
+
+    Ok(update_app_settings(&app, &state, update)?.locale)
+}
+
+// Helper function to get a path inside the app config directory
+fn app_config_path(app: &tauri::AppHandle, name: &str) -> Result<PathBuf, String> {
+    app.path_resolver()
+        .app_config_dir()
+        .map(|dir| dir.join(name))
+        .ok_or_else(|| "Could not determine app config directory".to_string())
+}
+
+// Helper function to apply a settings update and persist the result
+fn update_app_settings(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    update: settings::SettingsUpdate,
+) -> Result<settings::AppSettings, String> {
+    let updated = modify_app_settings(app, state, |settings| settings.apply(update))?;
+    logging::set_enabled(&app_data_path(app, logging::LOG_DIR)?, updated.diagnostic_logging)?;
+    Ok(updated)
+}
+
+// Helper function to change the settings and persist the result
+fn modify_app_settings(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    change: impl FnOnce(&mut settings::AppSettings) -> Result<(), String>,
+) -> Result<settings::AppSettings, String> {
+    let mut current = state.settings.lock().unwrap();
+
+    // Validate and save a copy first so a failed write doesn't leave unsaved changes in memory
+    let mut updated = current.clone();
+    change(&mut updated)?;
+    settings::save_settings(&app_config_path(app, settings::FILE_NAME)?, &updated)?;
+
+    *current = updated.clone();
+
+    Ok(updated)
+}
+
+// Helper function to keep replay mode's sequential IDs clear of the ones a document uses
+fn reserve_document_ids(doc: &DocumentState) {
+    let entities = doc.entities.lock().unwrap();
+    let markers = doc.markers.lock().unwrap();
+    let plot_threads = doc.plot_threads.lock().unwrap();
+    let suggestions = doc.suggestions.lock().unwrap();
+    let pins = doc.pins.lock().unwrap();
+    let quarantine = doc.quarantine.lock().unwrap();
+    let icon_packs = doc.icon_packs.lock().unwrap();
+
+    ids::reserve(
+        entities
+            .keys()
+            .chain(markers.keys())
+            .chain(plot_threads.iter().flat_map(|t| std::iter::once(&t.id).chain(t.events.iter().map(|e| &e.id))))
+            .chain(suggestions.iter().map(|s| &s.id))
+            .chain(pins.iter().map(|p| &p.id))
+            .chain(quarantine.iter().flat_map(|q| std::iter::once(&q.id).chain(q.item_id.as_ref())))
+            .chain(icon_packs.iter().map(|p| &p.id))
+            .map(String::as_str),
+    );
+}
+
+// Tauri command to turn replay mode (sequential IDs, fixed clock) on or off (see ids.rs).
+// The sequence starts past any sequential ID the open documents already use.
+#[tauri::command]
+fn set_replay_mode(mode: Option<ids::ReplayMode>, state: tauri::State<AppState>) {
+    ids::set_replay_mode(mode);
+
+    let documents: Vec<_> = state.documents.lock().unwrap().values().cloned().collect();
+    for doc in documents {
+        reserve_document_ids(&doc);
+    }
+}
+
+// Tauri command to get the replay mode (None = off)
+#[tauri::command]
+fn get_replay_mode() -> Option<ids::ReplayMode> {
+    ids::replay_mode()
+}
+
+// Tauri command to get the most recent diagnostic log records, oldest first (see logging.rs)
+#[tauri::command]
+fn get_recent_logs(limit: Option<usize>, app: tauri::AppHandle) -> Result<Vec<serde_json::Value>, String> {
+    logging::recent_logs(&app_data_path(&app, logging::LOG_DIR)?, limit.unwrap_or(logging::DEFAULT_RECENT))
+}
+
+// Tauri command to get the application settings
+#[tauri::command]
+fn get_settings(state: tauri::State<AppState>) -> settings::AppSettings {
+    state.settings.lock().unwrap().clone()
+}
+
+// Tauri command to change some application settings (omitted fields stay unchanged)
+#[tauri::command]
+fn update_settings(
+    update: settings::SettingsUpdate,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<settings::AppSettings, String> {
+    update_app_settings(&app, &state, update)
+}
+
+// Tauri command to add or replace a named export profile
+#[tauri::command]
+fn save_export_profile(
+    name: String,
+    profile: settings::ExportProfile,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<settings::AppSettings, String> {
+    modify_app_settings(&app, &state, |settings| settings.set_export_profile(&name, profile))
+}
+
+// Tauri command to delete a named export profile
+#[tauri::command]
+fn delete_export_profile(
+    name: String,
+    app: tauri::AppHandle,
+    state: tauri::State<AppState>,
+) -> Result<settings::AppSettings, String> {
+    modify_app_settings(&app, &state, |settings| settings.remove_export_profile(&name))
 }
 
-// Tauri command to delete a marker
+// Tauri command to add or replace a named entity color palette
 #[tauri::command]
-fn delete_marker(
-    marker_id: String,
+fn save_palette(
+    name: String,
+    colors: Vec<String>,
+    app: tauri::AppHandle,
     state: tauri::State<AppState>,
-) -> Result<(), String> {
-    let mut markers = state.markers.lock().unwrap();
-
-    markers
-        .remove(&marker_id)
-        .ok_or("Marker not found")?;
-
-    Ok(())
+) -> Result<settings::AppSettings, String> {
+    modify_app_settings(&app, &state, |settings| settings.set_palette(&name, &colors))
 }
 
-// Tauri command to update marker positions (for text changes)
+// Tauri command to delete a named entity color palette
 #[tauri::command]
-fn update_marker_positions(
-    position_updates: Vec<(String, usize)>, // (marker_id, new_position)
+fn delete_palette(
+    name: String,
+    app: tauri::AppHandle,
     state: tauri::State<AppState>,
-) -> Result<(), String> {
-    let mut markers = state.markers.lock().unwrap();
-
-    for (marker_id, new_position) in position_updates {
-        if let Some(marker) = markers.get_mut(&marker_id) {
-            marker.position = new_position;
-        }
-    }
-
-    Ok(())
+) -> Result<settings::AppSettings, String> {
+    modify_app_settings(&app, &state, |settings| settings.remove_palette(&name))
 }
 
-// Tauri command to save document
+// Tauri command to restore the default application settings
 #[tauri::command]
-fn save_document(
-    file_path: String,
-    content: String,
+fn reset_settings(
+    app: tauri::AppHandle,
     state: tauri::State<AppState>,
-) -> Result<(), String> {
-    let entities = state.entities.lock().unwrap();
-    let markers = state.markers.lock().unwrap();
-
-    let document = Document {
-        content,
-        entities: entities.values().cloned().collect(),
-        markers: markers.values().cloned().collect(),
-    };
-
-    let json = serde_json::to_string_pretty(&document)
-        .map_err(|e| format!("Failed to serialize document: {}", e))?;
+) -> Result<settings::AppSettings, String> {
+    let defaults = settings::AppSettings::default();
+    settings::save_settings(&app_config_path(&app, settings::FILE_NAME)?, &defaults)?;
 
-    fs::write(&file_path, json)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+    *state.settings.lock().unwrap() = defaults.clone();
 
-    Ok(())
+    Ok(defaults)
 }
 
-// Tauri command to load document
+// Tauri command to get the document language (None means the app locale is used)
 #[tauri::command]
-fn load_document(
-    file_path: String,
+fn get_document_language(
+    session_id: Option<String>,
     state: tauri::State<AppState>,
-) -> Result<Document, String> {
-    let json = fs::read_to_string(&file_path)
-        .map_err(|e| format!("Failed to read file: {}", e))?;
-
-    let document: Document = serde_json::from_str(&json)
-        .map_err(|e| format!("Failed to parse document: {}", e))?;
-
-    // Clear and load entities
-    let mut entities = state.entities.lock().unwrap();
-    entities.clear();
-    for entity in &document.entities {
-        entities.insert(entity.id.clone(), entity.clone());
-    }
-
-    // Clear and load markers
-    let mut markers = state.markers.lock().unwrap();
-    markers.clear();
-    for marker in &document.markers {
-        markers.insert(marker.id.clone(), marker.clone());
-    }
-
-    Ok(document)
+) -> Option<String> {
+    state.document(session_id.as_deref()).document_language.lock().unwrap().clone()
 }
 
-// Tauri command to create new document (clear everything)
+// Tauri command to set or clear the document language
 #[tauri::command]
-fn new_document(state: tauri::State<AppState>) -> Result<(), String> {
-    let mut entities = state.entities.lock().unwrap();
-    let mut markers = state.markers.lock().unwrap();
+fn set_document_language(
+    language: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Option<String>, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let code = match language {
+        Some(lang) => Some(
+            i18n::normalize_locale(&lang)
+                .ok_or_else(|| format!("Unsupported locale: {}", lang))?
+                .to_string(),
+        ),
+        None => None,
+    };
 
-    entities.clear();
-    markers.clear();
+    *doc.document_language.lock().unwrap() = code.clone();
 
-    Ok(())
+    Ok(code)
 }
 
-// Represents a text run with formatting
-#[derive(Clone)]
-struct TextRun {
-    text: String,
-    bold: bool,
-    italic: bool,
+// Check whether a character belongs to a right-to-left script
+fn is_rtl_char(ch: char) -> bool {
+    matches!(ch as u32,
+        0x0590..=0x08FF      // Hebrew, Arabic, Syriac, Thaana, NKo, Samaritan, Mandaic
+        | 0xFB1D..=0xFDFF    // Hebrew and Arabic presentation forms
+        | 0xFE70..=0xFEFF    // Arabic presentation forms B
+        | 0x10800..=0x10FFF  // Historic RTL scripts
+        | 0x1E800..=0x1EFFF) // Adlam, Arabic mathematical symbols, ...
 }
 
-// Represents a paragraph with its type and runs
-struct FormattedParagraph {
-    node_type: String, // "paragraph" or "heading"
-    level: Option<u32>, // heading level (1-6)
-    runs: Vec<TextRun>,
+// Detect paragraph direction from its first strong character (like the Unicode bidi algorithm)
+fn detect_rtl(text: &str) -> bool {
+    text.chars()
+        .find(|ch| ch.is_alphabetic())
+        .map(is_rtl_char)
+        .unwrap_or(false)
 }
 
 // Helper function to convert ProseMirror JSON to structured format
-fn prosemirror_to_structured(doc_json: &serde_json::Value) -> (String, Vec<FormattedParagraph>) {
+// (`note_numbers` maps marker IDs to endnote numbers; other marker nodes are dropped)
+fn prosemirror_to_structured(
+    doc_json: &serde_json::Value,
+    note_numbers: &HashMap<String, usize>,
+) -> Vec<FormattedParagraph> {
     let mut paragraphs = Vec::new();
-    let mut plain_text_parts = Vec::new();
 
     if let Some(content) = doc_json.get("content").and_then(|c| c.as_array()) {
         for node in content {
@@ -792,7 +4039,7 @@ fn prosemirror_to_structured(doc_json: &serde_json::Value) -> (String, Vec<Forma
 
             match node_type {
                 "paragraph" | "heading" => {
-                    let runs = extract_runs_from_node(node);
+                    let (runs, marker_anchors) = extract_runs_from_node(node, note_numbers);
                     let level = if node_type == "heading" {
                         node.get("attrs")
                             .and_then(|a| a.get("level"))
@@ -802,14 +4049,33 @@ fn prosemirror_to_structured(doc_json: &serde_json::Value) -> (String, Vec<Forma
                         None
                     };
 
-                    // Build plain text
-                    let para_text: String = runs.iter().map(|r| r.text.as_str()).collect();
-                    plain_text_parts.push(para_text);
+                    // Explicit direction attribute wins, otherwise detect from the text
+                    let rtl = match node.get("attrs").and_then(|a| a.get("dir")).and_then(|d| d.as_str()) {
+                        Some("rtl") => true,
+                        Some("ltr") => false,
+                        _ => detect_rtl(&runs.iter().map(|r| r.text.as_str()).collect::<String>()),
+                    };
+
+                    // Alignment and indentation set in the editor
+                    let attrs = node.get("attrs");
+                    let align = attrs
+                        .and_then(|a| a.get("textAlign"))
+                        .and_then(|a| a.as_str())
+                        .and_then(exporters::Alignment::parse);
+                    let indent = attrs
+                        .and_then(|a| a.get("indent"))
+                        .and_then(|i| i.as_u64())
+                        .map_or(0, |i| i.min(exporters::MAX_INDENT as u64) as u32);
 
                     paragraphs.push(FormattedParagraph {
                         node_type: node_type.to_string(),
                         level,
                         runs,
+                        rtl,
+                        marker_anchors,
+                        align,
+                        indent,
+                        page_break: false,
                     });
                 }
                 _ => {}
@@ -817,18 +4083,61 @@ fn prosemirror_to_structured(doc_json: &serde_json::Value) -> (String, Vec<Forma
         }
     }
 
-    let plain_text = plain_text_parts.join("\n\n");
-    (plain_text, paragraphs)
+    paragraphs
 }
 
-fn extract_runs_from_node(node: &serde_json::Value) -> Vec<TextRun> {
+// Runs of a paragraph or heading, and where its markers are among them
+fn extract_runs_from_node(
+    node: &serde_json::Value,
+    note_numbers: &HashMap<String, usize>,
+) -> (Vec<TextRun>, Vec<(usize, String)>) {
     let mut runs = Vec::new();
+    let mut marker_anchors = Vec::new();
 
     if let Some(content) = node.get("content").and_then(|c| c.as_array()) {
         for item in content {
+            if item.get("type").and_then(|t| t.as_str()) == Some("marker") {
+                let marker_id = item
+                    .get("attrs")
+                    .and_then(|a| a.get("id"))
+                    .and_then(|id| id.as_str());
+                if let Some(marker_id) = marker_id {
+                    marker_anchors.push((runs.len(), marker_id.to_string()));
+                }
+                if let Some(number) = marker_id.and_then(|id| note_numbers.get(id)) {
+                    runs.push(TextRun {
+                        text: number.to_string(),
+                        bold: false,
+                        italic: false,
+                        note: true,
+                        monospace: false,
+                        suggestion: None,
+                    });
+                }
+                continue;
+            }
+
+            // Hard breaks continue the previous run on a new line
+            if item.get("type").and_then(|t| t.as_str()) == Some("hard_break") {
+                match runs.last_mut() {
+                    Some(run) if !run.note => run.text.push('\n'),
+                    _ => runs.push(TextRun {
+                        text: "\n".to_string(),
+                        bold: false,
+                        italic: false,
+                        note: false,
+                        monospace: false,
+                        suggestion: None,
+                    }),
+                }
+                continue;
+            }
+
             if let Some(text_content) = item.get("text").and_then(|t| t.as_str()) {
                 let mut bold = false;
                 let mut italic = false;
+                let mut monospace = false;
+                let mut suggestion = None;
 
                 if let Some(marks) = item.get("marks").and_then(|m| m.as_array()) {
                     for mark in marks {
@@ -836,6 +4145,9 @@ fn extract_runs_from_node(node: &serde_json::Value) -> Vec<TextRun> {
                             match mark_type {
                                 "strong" => bold = true,
                                 "em" => italic = true,
+                                "code" => monospace = true,
+                                track_changes::DELETION_MARK => suggestion = Some(track_changes::SuggestionKind::Deletion),
+                                track_changes::INSERTION_MARK => suggestion = Some(track_changes::SuggestionKind::Insertion),
                                 _ => {}
                             }
                         }
@@ -846,6 +4158,9 @@ fn extract_runs_from_node(node: &serde_json::Value) -> Vec<TextRun> {
                     text: text_content.to_string(),
                     bold,
                     italic,
+                    note: false,
+                    monospace,
+                    suggestion,
                 });
             }
         }
@@ -857,136 +4172,599 @@ fn extract_runs_from_node(node: &serde_json::Value) -> Vec<TextRun> {
             text: String::new(),
             bold: false,
             italic: false,
+            note: false,
+            monospace: false,
+            suggestion: None,
+        });
+    }
+
+    (runs, marker_anchors)
+}
+
+// Helper function to append the endnotes section after the manuscript
+fn append_endnotes(paragraphs: &mut Vec<FormattedParagraph>, endnotes: endnotes::Endnotes, locale: &str) {
+    if endnotes.notes.is_empty() {
+        return;
+    }
+
+    let heading = i18n::tr(locale, "endnote.heading", &[]);
+    paragraphs.push(FormattedParagraph {
+        node_type: "heading".to_string(),
+        level: Some(1),
+        rtl: detect_rtl(&heading),
+        runs: vec![TextRun { text: heading, bold: false, italic: false, note: false, monospace: false, suggestion: None }],
+        marker_anchors: Vec::new(),
+        align: None,
+        indent: 0,
+        page_break: false,
+    });
+
+    for note in endnotes.notes {
+        paragraphs.push(FormattedParagraph {
+            node_type: "paragraph".to_string(),
+            level: None,
+            rtl: detect_rtl(&note.text),
+            runs: vec![TextRun {
+                text: format!("{}. {}", note.number, note.text),
+                bold: false,
+                italic: false,
+                note: false,
+                monospace: false,
+                suggestion: None,
+            }],
+            marker_anchors: Vec::new(),
+            align: None,
+            indent: 0,
+            page_break: false,
+        });
+    }
+}
+
+// Helper function to append every entity's character sheet, as of a position (usize::MAX
+// for the end of the document)
+fn append_character_sheets(
+    paragraphs: &mut Vec<FormattedParagraph>,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    layouts: &[sheets::SheetLayout],
+    position: usize,
+    locale: &str,
+) {
+    if entities.is_empty() {
+        return;
+    }
+
+    let heading = i18n::tr(locale, "sheet.appendix_heading", &[]);
+    paragraphs.push(FormattedParagraph {
+        node_type: "heading".to_string(),
+        level: Some(1),
+        rtl: detect_rtl(&heading),
+        runs: vec![TextRun { text: heading, bold: false, italic: false, note: false, monospace: false, suggestion: None }],
+        marker_anchors: Vec::new(),
+        align: None,
+        indent: 0,
+        page_break: false,
+    });
+
+    let mut sorted: Vec<&Entity> = entities.values().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for entity in sorted {
+        let sheet = sheets::build(entity, markers, position, sheets::layout_for(layouts, entity));
+        let header = i18n::tr(locale, "sheet.header", &[("name", &entity.name)]);
+
+        paragraphs.push(FormattedParagraph {
+            node_type: "paragraph".to_string(),
+            level: None,
+            rtl: detect_rtl(&header),
+            runs: vec![TextRun { text: header, bold: true, italic: false, note: false, monospace: false, suggestion: None }],
+            marker_anchors: Vec::new(),
+            align: None,
+            indent: 0,
+            page_break: false,
         });
+        // Headline stats and groups in bold, each level indented
+        for (depth, bold, line) in sheets::styled_lines(&sheet) {
+            paragraphs.push(FormattedParagraph {
+                node_type: "paragraph".to_string(),
+                level: None,
+                rtl: detect_rtl(&line),
+                runs: vec![TextRun { text: line, bold, italic: false, note: false, monospace: false, suggestion: None }],
+                marker_anchors: Vec::new(),
+                align: None,
+                indent: (depth as u32).min(exporters::MAX_INDENT),
+                page_break: false,
+            });
+        }
+    }
+}
+
+// Helper function to convert a front or back matter block to a paragraph
+fn matter_paragraph(block: book_matter::MatterBlock) -> FormattedParagraph {
+    let run = |text: String, bold: bool| TextRun { text, bold, italic: false, note: false, monospace: false, suggestion: None };
+    let (node_type, level) = match block.style {
+        book_matter::MatterStyle::Title | book_matter::MatterStyle::Heading => ("heading", Some(1)),
+        book_matter::MatterStyle::Centered | book_matter::MatterStyle::Entry => ("paragraph", None),
+    };
+    let rtl = detect_rtl(block.term.as_deref().unwrap_or(&block.text));
+    let runs = match block.term {
+        Some(term) if block.text.is_empty() => vec![run(term, true)],
+        Some(term) => vec![run(term, true), run(format!(": {}", block.text), false)],
+        None => vec![run(block.text, false)],
+    };
+
+    FormattedParagraph {
+        node_type: node_type.to_string(),
+        level,
+        runs,
+        rtl,
+        marker_anchors: Vec::new(),
+        align: matches!(block.style, book_matter::MatterStyle::Title | book_matter::MatterStyle::Centered)
+            .then_some(exporters::Alignment::Center),
+        indent: 0,
+        page_break: block.page_break,
     }
+}
 
-    runs
+// Options for writing a manuscript (from the export dialog or an export profile)
+struct ManuscriptExport {
+    exporter: &'static dyn exporters::Exporter,
+    style: preferences::ExportStyle,
+    endnotes: bool,
+    append_sheets: bool,
+    redaction: Option<redaction::RedactionOptions>,
+    format_options: serde_json::Map<String, serde_json::Value>, // Options only some formats understand (see exporters.rs)
+    typography: typography::TypographyOptions,
+    range: Option<chapters::DocumentRange>, // None = the whole document
+    utc_offset_minutes: i32, // For the {date} and {time} path tokens
 }
 
-// Tauri command to export document to various formats
+// Tauri command to export document to various formats. With `endnotes`, each marker
+// becomes a numbered endnote listing its changes and resulting values; markers hidden
+// by `redaction` (see redaction.rs) get no note. With `range`, only the blocks within
+// the positions or chapters are exported (e.g., chapters 5-8 for a critique group).
+// With `marker_comments`, a DOCX export carries marker descriptions as Word comments;
+// `format_options` sets any other option of the format (see list_export_formats), and
+// `typography` curls quotes and sets dashes and ellipses in every format (see typography.rs).
+// The path may contain tokens (see export_paths.rs); returns the path written.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn export_document(
     file_path: String,
-    content: String,
-) -> Result<(), String> {
-    let path = PathBuf::from(&file_path);
-    let extension = path.extension()
+    content: Option<String>,
+    endnotes: Option<bool>,
+    redaction: Option<redaction::RedactionOptions>,
+    marker_comments: Option<bool>,
+    format_options: Option<serde_json::Map<String, serde_json::Value>>,
+    typography: Option<typography::TypographyOptions>,
+    range: Option<chapters::DocumentRange>,
+    utc_offset_minutes: Option<i32>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let extension = Path::new(&file_path)
+        .extension()
         .and_then(|s| s.to_str())
         .unwrap_or("txt");
+    let exporter = exporters::for_extension(extension)
+        .ok_or_else(|| format!("Unsupported file format: {}", extension))?;
+
+    let mut format_options = format_options.unwrap_or_default();
+    if let Some(marker_comments) = marker_comments {
+        format_options.insert("marker_comments".to_string(), serde_json::json!(marker_comments));
+    }
+
+    let options = ManuscriptExport {
+        exporter,
+        style: doc.preferences.lock().unwrap().export_style.clone(),
+        endnotes: endnotes.unwrap_or(false),
+        append_sheets: false,
+        redaction,
+        format_options,
+        typography: typography.unwrap_or_default(),
+        range,
+        utc_offset_minutes: utc_offset_minutes.unwrap_or(0),
+    };
+
+    let content = document_content(&doc, content)?;
+    write_manuscript(&doc, &locale, &file_path, &content, &options)
+}
+
+// Tauri command to export with a saved export profile. The file gets the profile's
+// format's extension, and path tokens are filled in; returns the path written.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn export_with_profile(
+    profile: String,
+    file_path: String,
+    content: Option<String>,
+    range: Option<chapters::DocumentRange>,
+    utc_offset_minutes: Option<i32>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<String, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let profile = state
+        .settings
+        .lock()
+        .unwrap()
+        .export_profiles
+        .get(&profile)
+        .cloned()
+        .ok_or_else(|| format!("Export profile not found: {}", profile))?;
+
+    let path = PathBuf::from(&file_path).with_extension(profile.format.extension());
+    let path = path.to_string_lossy().to_string();
+
+    let exporter = exporters::for_extension(profile.format.extension())
+        .ok_or_else(|| format!("Unsupported file format: {}", profile.format.extension()))?;
+    let mut format_options = serde_json::Map::new();
+    format_options.insert("marker_comments".to_string(), serde_json::json!(profile.marker_comments));
+
+    let options = ManuscriptExport {
+        exporter,
+        style: profile
+            .style
+            .unwrap_or_else(|| doc.preferences.lock().unwrap().export_style.clone()),
+        endnotes: profile.endnotes,
+        append_sheets: profile.append_sheets,
+        redaction: profile.redaction,
+        format_options,
+        typography: profile.typography,
+        range,
+        utc_offset_minutes: utc_offset_minutes.unwrap_or(0),
+    };
+
+    let content = document_content(&doc, content)?;
+    write_manuscript(&doc, &locale, &path, &content, &options)
+}
+
+// Tauri command to list the export formats, with the options each understands
+#[tauri::command]
+fn list_export_formats() -> Vec<exporters::ExportFormatInfo> {
+    exporters::list_formats()
+}
+
+// Tauri command to check what an export would drop or mangle (unsupported nodes and marks,
+// missing images, problematic characters) without writing a file
+#[tauri::command]
+fn validate_export(
+    content: Option<String>,
+    format: settings::ExportFormat,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<export_check::ExportIssue>, String> {
+    let doc = state.document(session_id.as_deref());
+    let doc_json = document_json(&doc, content)?;
+    let base_dir = doc
+        .locked_path
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|path| path.parent().map(Path::to_path_buf));
+
+    let markers = doc.markers.lock().unwrap();
+
+    Ok(export_check::validate(&doc_json, &markers, format, base_dir.as_deref()))
+}
+
+// Helper function to get the document's title: the one set in its preferences, else the
+// name of its file
+fn document_title(doc: &DocumentState, locale: &str) -> String {
+    if let Some(title) = doc.preferences.lock().unwrap().title.clone() {
+        return title;
+    }
+    doc.locked_path
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| i18n::tr(locale, "document.untitled", &[]))
+}
 
+// Helper function to write the manuscript, with its endnotes and character sheets, in an
+// export format. Returns the path written, with its tokens filled in.
+fn write_manuscript(
+    doc: &DocumentState,
+    locale: &str,
+    file_path: &str,
+    content: &str,
+    options: &ManuscriptExport,
+) -> Result<String, String> {
     // Parse ProseMirror JSON
-    let doc_json: serde_json::Value = serde_json::from_str(&content)
+    let mut doc_json: serde_json::Value = serde_json::from_str(content)
         .map_err(|e| format!("Failed to parse document JSON: {}", e))?;
 
-    let (plain_text, paragraphs) = prosemirror_to_structured(&doc_json);
-
-    match extension {
-        "txt" => {
-            fs::write(&file_path, plain_text)
-                .map_err(|e| format!("Failed to write file: {}", e))?;
-        }
-        "rtf" => {
-            let mut rtf_content = String::from("{\\rtf1\\ansi\\deff0\n{\\fonttbl{\\f0 Times New Roman;}}\n\\f0\\fs24\n");
-
-            for para in paragraphs {
-                // Handle headings with larger font size
-                if para.node_type == "heading" {
-                    let font_size = match para.level {
-                        Some(1) => 32,
-                        Some(2) => 28,
-                        Some(3) => 24,
-                        _ => 20,
-                    };
-                    rtf_content.push_str(&format!("\\fs{} \\b ", font_size));
-                }
+    // Pending suggestions show as struck-through deletions and underlined insertions. Their
+    // ranges are in the stored content, so other content (e.g., the editor's, without marker
+    // nodes) is exported without them.
+    let suggestions = doc.suggestions.lock().unwrap().clone();
+    if !suggestions.is_empty() && doc.content.lock().unwrap().as_ref() == Some(&doc_json) {
+        doc_json = track_changes::mark_for_export(&doc_json, &suggestions);
+    }
 
-                // Process each text run with its own formatting
-                for run in &para.runs {
-                    if run.bold {
-                        rtf_content.push_str("\\b ");
-                    }
-                    if run.italic {
-                        rtf_content.push_str("\\i ");
-                    }
-                    rtf_content.push_str(&run.text.replace("\\", "\\\\").replace("{", "\\{").replace("}", "\\}"));
-                    if run.italic {
-                        rtf_content.push_str("\\i0 ");
-                    }
-                    if run.bold {
-                        rtf_content.push_str("\\b0 ");
-                    }
-                }
+    // Embedded sheets show the state where they are in the full document (see sheet_tokens.rs)
+    sheet_tokens::stamp_positions(&mut doc_json);
 
-                // Reset heading formatting
-                if para.node_type == "heading" {
-                    rtf_content.push_str("\\b0 \\fs24 ");
-                }
+    // Only the requested part; sheets show the state at its end
+    let mut sheet_position = usize::MAX;
+    if let Some(range) = &options.range {
+        let spans = chapters::range_spans(&doc_json, range, &i18n::tr(locale, "chapter.untitled", &[]))?;
+        // Ends are exclusive: a marker at the end belongs to what follows
+        sheet_position = spans.iter().map(|(_, end)| end.saturating_sub(1)).max().unwrap_or(usize::MAX);
+        doc_json = chapters::slice_content(&doc_json, &spans);
+    }
 
-                rtf_content.push_str("\\par\n\\par\n");
+    let resolved_path = export_paths::resolve(
+        file_path,
+        &export_paths::PathTokens {
+            title: document_title(doc, locale),
+            timestamp: dates::now(),
+            utc_offset_minutes: options.utc_offset_minutes,
+            word_count: stats::document_word_count(&doc_json),
+        },
+    )?;
+    let file_path = resolved_path.as_str();
+
+    // Entity data for endnotes and sheets, without redacted markers
+    let (entities, markers) = {
+        let entities = doc.entities.lock().unwrap().clone();
+        let mut markers = doc.markers.lock().unwrap().clone();
+        resync_marker_positions(&mut markers, content);
+
+        match &options.redaction {
+            Some(redaction_options) => {
+                let redacted = redaction::redact(&entities, &markers, redaction_options);
+                (redacted.entities, redacted.markers)
             }
+            None => (entities, markers),
+        }
+    };
 
-            rtf_content.push('}');
+    let notes = options
+        .endnotes
+        .then(|| endnotes::build_endnotes(&doc_json, &entities, &markers, locale));
+
+    let note_numbers = notes
+        .as_ref()
+        .map(|notes| notes.numbers.clone())
+        .unwrap_or_default();
+    let (layouts, window_style) = {
+        let preferences = doc.preferences.lock().unwrap();
+        (preferences.sheet_layouts.clone(), preferences.status_window.clone())
+    };
+    sheet_tokens::expand(&mut doc_json, &entities, &markers, &layouts, &window_style);
+    // Entity mention nodes render as the entity's current name
+    entity_refs::resolve_for_export(&mut doc_json, &doc.entities.lock().unwrap());
+    let mut paragraphs = prosemirror_to_structured(&doc_json, &note_numbers);
+    if let Some(notes) = notes {
+        append_endnotes(&mut paragraphs, notes, locale);
+    }
+    if options.append_sheets {
+        append_character_sheets(&mut paragraphs, &entities, &markers, &layouts, sheet_position, locale);
+    }
 
-            fs::write(&file_path, rtf_content)
-                .map_err(|e| format!("Failed to write file: {}", e))?;
+    // Front and back matter from the document's preferences
+    let matter = doc.preferences.lock().unwrap().matter.clone();
+    let front = book_matter::front_matter(&matter, &document_title(doc, locale), locale);
+    if !front.is_empty() {
+        if let Some(first) = paragraphs.first_mut() {
+            first.page_break = true;
         }
-        "docx" => {
-            let mut docx = Docx::new();
-
-            for para in paragraphs {
-                let mut paragraph = Paragraph::new();
-
-                // Determine font size for headings
-                let is_heading = para.node_type == "heading";
-                let font_size = if is_heading {
-                    match para.level {
-                        Some(1) => 32,
-                        Some(2) => 28,
-                        Some(3) => 24,
-                        _ => 20,
-                    }
-                } else {
-                    24 // Default body text size (12pt * 2 = 24 half-points)
-                };
-
-                // Add each text run with its own formatting
-                for run in &para.runs {
-                    let mut text_run = Run::new()
-                        .add_text(&run.text)
-                        .size(font_size);
-
-                    // For headings, make all text bold
-                    if is_heading || run.bold {
-                        text_run = text_run.bold();
-                    }
-                    if run.italic {
-                        text_run = text_run.italic();
-                    }
+        paragraphs.splice(0..0, front.into_iter().map(matter_paragraph));
+    }
+    paragraphs.extend(
+        book_matter::back_matter(&matter, &entities, &markers, sheet_position, locale)
+            .into_iter()
+            .map(matter_paragraph),
+    );
+
+    typography::clean_paragraphs(&mut paragraphs, &options.typography);
+
+    let manuscript = exporters::Manuscript {
+        paragraphs: &paragraphs,
+        style: &options.style,
+        entities: &entities,
+        markers: &markers,
+        options: &options.format_options,
+        author: matter.author.as_deref().map(str::trim),
+    };
+    let bytes = options.exporter.render(&manuscript)?;
+    fs::write(file_path, bytes)
+        .map_err(|e| format!("Failed to write file: {}", e))?;
 
-                    paragraph = paragraph.add_run(text_run);
-                }
+    Ok(resolved_path)
+}
 
-                docx = docx.add_paragraph(paragraph);
+// Helper function to build the chapter synopses of a document
+fn chapter_synopses(
+    doc: &DocumentState,
+    content: Option<String>,
+    locale: &str,
+) -> Result<Vec<synopses::ChapterSynopsis>, String> {
+    let doc_json = document_json(doc, content)?;
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(locale, "chapter.untitled", &[]));
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap().clone();
+    resync_marker_positions(&mut markers, &doc_json.to_string());
+
+    Ok(synopses::generate_chapter_synopses(&doc_json, &entities, &markers, &chapter_list, locale))
+}
+
+// Helper function to have the configured language model write the synopses' summaries
+fn narrate_synopses(app: &tauri::AppHandle, synopsis_list: &mut [synopses::ChapterSynopsis], locale: &str) -> Result<(), String> {
+    let config = llm::load_config(&app_data_path(app, llm::CONFIG_FILE)?)?;
+    match llm::provider_from_config(&config) {
+        Some(provider) => synopses::narrate(synopsis_list, provider.as_ref(), locale),
+        None => {
+            for synopsis in synopsis_list.iter_mut().filter(|s| !s.changes.is_empty()) {
+                synopsis.llm_error = Some("Summarization is not configured".to_string());
             }
+        }
+    }
+    Ok(())
+}
 
-            // Write to a buffer using Cursor for Seek trait
-            let mut buf = Cursor::new(Vec::new());
-            docx.build()
-                .pack(&mut buf)
-                .map_err(|e| format!("Failed to pack DOCX: {}", e))?;
+// Tauri command to summarize every chapter: heading, opening paragraph and the entity changes in it.
+// With use_llm, the summaries come from the configured language model when one is enabled.
+// Runs off the main thread since the model calls can take a while.
+#[tauri::command(async)]
+fn generate_chapter_synopses(
+    content: Option<String>,
+    use_llm: Option<bool>,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<synopses::ChapterSynopsis>, String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let mut synopsis_list = chapter_synopses(&doc, content, &locale)?;
+    if use_llm.unwrap_or(false) {
+        narrate_synopses(&app, &mut synopsis_list, &locale)?;
+    }
+    Ok(synopsis_list)
+}
+
+// Tauri command to write the chapter synopses as a Markdown or DOCX file (with use_llm, as for
+// generate_chapter_synopses)
+#[tauri::command(async)]
+fn export_chapter_synopses(
+    file_path: String,
+    format: String,
+    content: Option<String>,
+    use_llm: Option<bool>,
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let format = synopses::SynopsisFormat::parse(&format)?;
+    let mut synopsis_list = chapter_synopses(&doc, content, &locale)?;
+    if use_llm.unwrap_or(false) {
+        narrate_synopses(&app, &mut synopsis_list, &locale)?;
+    }
 
-            fs::write(&file_path, buf.into_inner())
-                .map_err(|e| format!("Failed to write file: {}", e))?;
+    let bytes = match format {
+        synopses::SynopsisFormat::Markdown => synopses::render_markdown(&synopsis_list, &locale).into_bytes(),
+        synopses::SynopsisFormat::Docx => {
+            let style = doc.preferences.lock().unwrap().export_style.clone();
+            synopses::render_docx(&synopsis_list, &style, &locale)?
         }
-        _ => {
-            return Err(format!("Unsupported file format: {}", extension));
+    };
+
+    fs::write(&file_path, bytes)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to write an alphabetized encyclopedia of the entities (see glossary.rs)
+// as a Markdown, DOCX or JSON file
+#[tauri::command]
+fn export_glossary(
+    file_path: String,
+    format: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let format = glossary::GlossaryFormat::parse(&format)?;
+    let doc_json = document_json(&doc, content)?;
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap().clone();
+    resync_marker_positions(&mut markers, &doc_json.to_string());
+    let entries = glossary::build_glossary(&doc_json, &entities, &markers, &chapter_list);
+
+    let bytes = match format {
+        glossary::GlossaryFormat::Markdown => glossary::render_markdown(&entries, &locale).into_bytes(),
+        glossary::GlossaryFormat::Docx => {
+            let style = doc.preferences.lock().unwrap().export_style.clone();
+            glossary::render_docx(&entries, &style, &locale)?
         }
+        glossary::GlossaryFormat::Json => serde_json::to_string_pretty(&entries)
+            .map_err(|e| format!("Failed to serialize glossary: {}", e))?
+            .into_bytes(),
+    };
+
+    fs::write(&file_path, bytes)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to write the chapters, entity changes and plot threads as a Plottr
+// timeline (see plottr_export.rs)
+#[tauri::command]
+fn export_plottr_timeline(
+    file_path: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let doc_json = document_json(&doc, content)?;
+    let title = document_title(&doc, &locale);
+
+    let chapter_list = chapters::chapters_from_content(&doc_json, &i18n::tr(&locale, "chapter.untitled", &[]));
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap().clone();
+    resync_marker_positions(&mut markers, &doc_json.to_string());
+    let threads = doc.plot_threads.lock().unwrap();
+
+    let file = plottr_export::build_plottr_file(&title, &entities, &markers, &threads, &chapter_list, &locale);
+    let json = serde_json::to_string_pretty(&file)
+        .map_err(|e| format!("Failed to serialize Plottr file: {}", e))?;
+
+    fs::write(&file_path, json)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+// Tauri command to export the whole campaign (entities, timelines, relationship graph,
+// chapter summaries) as a zip of documented JSON for third-party tools (see bundle.rs).
+// Without content, the bundle has no chapter data.
+#[tauri::command]
+fn export_campaign_bundle(
+    file_path: String,
+    content: Option<String>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<(), String> {
+    let doc = state.document(session_id.as_deref());
+    let locale = state.locale_for(&doc);
+    let doc_json = parse_optional_content(&doc, content)?;
+    let entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap().clone();
+
+    if let Some(doc_json) = &doc_json {
+        resync_marker_positions(&mut markers, &doc_json.to_string());
     }
 
-    Ok(())
+    let source = bundle::CampaignSource {
+        entities: &entities,
+        markers: &markers,
+        content: doc_json.as_ref(),
+        language: doc.document_language.lock().unwrap().clone(),
+        locale: &locale,
+        untitled_chapter: &i18n::tr(&locale, "chapter.untitled", &[]),
+        now: dates::now(),
+    };
+
+    let mut buf = Cursor::new(Vec::new());
+    bundle::write_bundle(&mut buf, &source)?;
+
+    fs::write(&file_path, buf.into_inner())
+        .map_err(|e| format!("Failed to write file: {}", e))
 }
 
-// Tauri command to import document from RTF or DOCX
+// Tauri command to import a document, routed by its content rather than its extension
+// (see importers.rs)
 #[tauri::command]
 fn import_document(file_path: String) -> Result<String, String> {
     let path = PathBuf::from(&file_path);
@@ -994,100 +4772,109 @@ fn import_document(file_path: String) -> Result<String, String> {
         .and_then(|s| s.to_str())
         .unwrap_or("txt");
 
-    match extension {
-        "txt" => {
-            // Plain text - just read and convert to ProseMirror JSON
-            let content = fs::read_to_string(&file_path)
-                .map_err(|e| format!("Failed to read file: {}", e))?;
-
-            Ok(text_to_prosemirror(&content))
-        }
-        "rtf" => {
-            // Basic RTF text extraction (formatting will be lost)
-            let content = fs::read_to_string(&file_path)
-                .map_err(|e| format!("Failed to read file: {}", e))?;
+    let bytes = fs::read(&path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    let importer = importers::detect(&bytes, extension)
+        .ok_or_else(|| format!("Unsupported file format: {}", extension))?;
 
-            let text = extract_text_from_rtf(&content);
-            Ok(text_to_prosemirror(&text))
-        }
-        "docx" | "doc" => {
-            // DOCX/DOC files are binary and cannot be imported without a parsing library
-            // Due to compatibility issues with available Rust libraries, DOCX import is not currently supported
-            Err("DOCX/DOC import is not currently supported. Please export your document as plain text (.txt) or RTF (.rtf) first, then import it.".to_string())
-        }
-        _ => {
-            Err(format!("Unsupported file format: {}", extension))
-        }
-    }
+    importer.import(&bytes)
 }
 
-// Basic RTF text extraction - strips RTF control codes
-fn extract_text_from_rtf(rtf: &str) -> String {
-    let mut result = String::new();
-    let mut in_group: i32 = 0;
-    let mut chars = rtf.chars().peekable();
-
-    while let Some(ch) = chars.next() {
-        match ch {
-            '{' => {
-                in_group += 1;
-            }
-            '}' => {
-                in_group = in_group.saturating_sub(1);
-            }
-            '\\' => {
-                // Skip control word
-                while let Some(&next_ch) = chars.peek() {
-                    if next_ch.is_alphabetic() || next_ch == '-' || next_ch.is_numeric() {
-                        chars.next();
-                    } else {
-                        if next_ch == ' ' {
-                            chars.next(); // consume delimiter space
-                        }
-                        break;
-                    }
-                }
-
-                // Handle special RTF escapes
-                if let Some(&('\\' | '{' | '}')) = chars.peek() {
-                    result.push(chars.next().unwrap());
-                }
-            }
-            _ if in_group <= 2 => {
-                // Only include text in main content (group level 1-2)
-                result.push(ch);
-            }
-            _ => {}
-        }
-    }
+// Tauri command to list the formats documents can be imported from
+#[tauri::command]
+fn list_import_formats() -> Vec<importers::ImportFormatInfo> {
+    importers::list_formats()
+}
 
-    result.trim().to_string()
+// An appended chapter: the updated document, its chapters, and the markers from the sidecar file
+#[derive(Serialize)]
+struct AppendedChapter {
+    content: serde_json::Value,
+    chapters: Vec<chapters::Chapter>,
+    start: usize, // Position of the new chapter's heading
+    markers: Option<marker_csv::MarkerImport>, // None without a sidecar file
 }
 
-// Helper to convert plain text to ProseMirror JSON
-fn text_to_prosemirror(text: &str) -> String {
-    let mut paragraphs = Vec::new();
+// Tauri command to import a chapter file and append it to the end of the stored content,
+// creating the markers from its sidecar file when there is one (see chapter_import.rs)
+#[tauri::command]
+fn append_imported_chapter(
+    file_path: String,
+    entity_mapping: Option<HashMap<String, String>>,
+    session_id: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<AppendedChapter, String> {
+    let doc = state.document(session_id.as_deref());
+    doc.ensure_writable()?;
+    let locale = state.locale_for(&doc);
+    let untitled = i18n::tr(&locale, "chapter.untitled", &[]);
 
-    for line in text.split("\n\n") {
-        if line.trim().is_empty() {
-            continue;
-        }
+    let path = PathBuf::from(&file_path);
+    let imported: serde_json::Value = serde_json::from_str(&import_document(file_path.clone())?)
+        .map_err(|e| format!("Failed to parse imported document: {}", e))?;
+    let sidecar = chapter_import::sidecar_path(&path);
+    let sidecar_text = if sidecar.exists() {
+        Some(fs::read_to_string(&sidecar).map_err(|e| format!("Failed to read marker file: {}", e))?)
+    } else {
+        None
+    };
 
-        paragraphs.push(serde_json::json!({
-            "type": "paragraph",
-            "content": [{
-                "type": "text",
-                "text": line.trim()
-            }]
-        }));
+    let pattern = doc.preferences.lock().unwrap().chapter_pattern.clone();
+    let regex = pattern
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| regex::Regex::new(&p).map_err(|e| format!("Invalid chapter pattern: {}", e)))
+        .transpose()?;
+    let title = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| !s.trim().is_empty())
+        .unwrap_or(&untitled);
+    let chapter = chapter_import::prepare(imported, regex.as_ref(), title, &untitled);
+
+    let context = mutation_context(&state, &doc);
+    let mut entities = doc.entities.lock().unwrap();
+    let mut markers = doc.markers.lock().unwrap();
+    let mut content = doc.content.lock().unwrap();
+
+    let mut updated = content
+        .clone()
+        .ok_or("No document content: send it with set_content first")?;
+    let start = chapter_import::append(&mut updated, chapter.blocks);
+
+    // Work on copies so a bad sidecar row leaves the document untouched
+    let mut new_entities = entities.clone();
+    let mut new_markers = markers.clone();
+    let mut imported_markers = sidecar_text
+        .map(|text| {
+            marker_csv::import_markers(
+                &mut new_entities,
+                &mut new_markers,
+                &context,
+                &text,
+                &entity_mapping.unwrap_or_default(),
+                Some(&chapter.chapters),
+                Some(chapter.size),
+                start + chapter.heading_size,
+            )
+        })
+        .transpose()
+        .map_err(|e| format!("Failed to import {}: {}", sidecar.display(), e))?;
+
+    // The sidecar's markers need their nodes in the appended text
+    if let Some(imported) = imported_markers.as_mut() {
+        updated = place_marker_nodes(&doc, &mut new_markers, &updated, &mut imported.markers)?;
     }
-
-    let doc = serde_json::json!({
-        "type": "doc",
-        "content": paragraphs
-    });
-
-    serde_json::to_string(&doc).unwrap()
+    realign_markers(&mut new_markers, &updated);
+    *entities = new_entities;
+    *markers = new_markers;
+    *content = Some(updated.clone());
+
+    Ok(AppendedChapter {
+        chapters: chapters::chapters_from_content(&updated, &untitled),
+        content: updated,
+        start,
+        markers: imported_markers,
+    })
 }
 
 fn main() {
@@ -1096,27 +4883,216 @@ fn main() {
 
     tauri::Builder::default()
         .manage(app_state)
-        .invoke_handler(tauri::generate_handler![
+        .setup(|app| {
+            // Load saved preferences; an unreadable settings file shouldn't stop the app from starting
+            let handle = app.handle();
+            if let Ok(path) = app_config_path(&handle, settings::FILE_NAME) {
+                match settings::load_settings(&path) {
+                    Ok(loaded) => *handle.state::<AppState>().settings.lock().unwrap() = loaded,
+                    Err(e) => eprintln!("{}; using default settings", e),
+                }
+            }
+            if handle.state::<AppState>().settings.lock().unwrap().diagnostic_logging {
+                let dir = app_data_path(&handle, logging::LOG_DIR);
+                if let Err(e) = dir.and_then(|dir| logging::set_enabled(&dir, true)) {
+                    eprintln!("{}; diagnostic logging is off", e);
+                }
+            }
+            Ok(())
+        })
+        .invoke_handler(with_logging(tauri::generate_handler![
+            list_document_sessions,
+            close_document_session,
             get_all_entities,
             get_entity_state,
+            get_entity_state_at_chapter,
+            get_entity_state_at_story_time,
+            pin_entity_state,
+            compare_with_pin,
+            get_entity_pins,
+            delete_entity_pin,
+            set_field_type,
+            add_descriptor_fields,
+            set_field_default,
+            get_emotional_arc,
+            suggest_field_values,
+            fuzzy_search,
+            get_field_usage,
+            get_strict_violations,
+            who_is_at,
+            who_is_at_chapter,
+            get_travel_log,
+            set_location_distance,
+            who_knows,
+            who_knows_at_chapter,
+            create_plot_thread,
+            update_plot_thread,
+            delete_plot_thread,
+            add_thread_event,
+            remove_thread_event,
+            get_plot_threads,
+            get_unresolved_threads,
+            get_thread_timeline,
             format_character_sheet,
+            format_character_sheet_at_chapter,
+            get_character_sheet,
+            format_status_window,
             create_entity,
+            create_entities,
             update_entity,
+            recolor_entities,
+            set_entity_portrait,
             delete_entity,
             duplicate_entity,
             delete_field_completely,
+            move_field_subtree,
             insert_marker,
             update_marker,
             delete_marker,
+            run_batch,
+            pin_marker_to_heading,
+            set_marker_outcomes,
+            switch_marker_outcome,
+            set_marker_tags,
+            set_marker_story_time,
+            get_marker_density,
             update_marker_positions,
+            apply_text_edit,
+            apply_text_edits,
+            get_document_size,
+            convert_clipboard_content,
+            convert_text_offset,
+            sync_marker_positions,
+            set_content,
+            get_content,
+            apply_content_steps,
+            replace_in_document,
+            get_suggestions,
+            suggest_edit,
+            resolve_suggestion,
+            resolve_all_suggestions,
+            get_open_todos,
+            find_orphaned_markers,
+            remove_orphaned_markers,
+            find_duplicate_markers,
+            delete_duplicates,
+            check_continuity,
+            check_name_spelling,
+            scan_descriptors,
+            detect_chapters,
+            get_chekhov_report,
+            export_continuity_report,
+            export_timeline,
+            get_unused_entities,
+            get_mention_counts,
+            find_state_gaps,
+            get_change_report,
+            export_change_report_csv,
+            get_state_matrix,
+            export_state_matrix_csv,
+            import_markers_csv,
+            export_markers_csv,
+            import_entities,
+            import_from_tool,
+            export_entity_pack,
+            import_entity_pack,
             get_all_markers,
             get_markers_at_position,
+            render_marker_description,
+            reorder_markers,
+            split_marker,
+            merge_markers,
+            find_merge_candidates,
+            copy_markers,
+            reassign_markers_in_range,
             save_document,
+            export_redacted_document,
             load_document,
+            get_quarantine,
+            restore_quarantined,
+            discard_quarantined,
             new_document,
+            check_document_lock,
+            lock_document,
+            unlock_document,
+            is_read_only,
+            set_read_only,
             export_document,
+            list_export_formats,
+            export_with_profile,
+            validate_export,
+            export_campaign_bundle,
             import_document,
-        ])
+            list_import_formats,
+            append_imported_chapter,
+            get_supported_locales,
+            get_app_locale,
+            set_app_locale,
+            get_settings,
+            get_recent_logs,
+            set_replay_mode,
+            get_replay_mode,
+            update_settings,
+            reset_settings,
+            save_export_profile,
+            delete_export_profile,
+            save_palette,
+            delete_palette,
+            get_document_language,
+            set_document_language,
+            install_icon_pack,
+            list_icon_packs,
+            remove_icon_pack,
+            resolve_icon,
+            get_visual_rules,
+            set_visual_rules,
+            reset_visual_rules,
+            start_writing_session,
+            end_writing_session,
+            get_active_writing_session,
+            get_writing_history,
+            get_document_preferences,
+            set_document_preferences,
+            get_notes_vault_status,
+            create_notes_vault,
+            unlock_notes_vault,
+            lock_notes_vault,
+            get_vault_notes,
+            save_vault_note,
+            delete_vault_note,
+            change_vault_passphrase,
+            get_structure_templates,
+            check_structure,
+            get_change_types,
+            set_change_types,
+            get_visibility_filters,
+            set_visibility_filters,
+            get_word_goals,
+            set_word_goals,
+            get_goal_progress,
+            get_progress_history,
+            get_document_outline,
+            generate_recap,
+            generate_arc_outline,
+            generate_chapter_synopses,
+            export_chapter_synopses,
+            export_glossary,
+            export_plottr_timeline,
+            get_llm_config,
+            set_llm_config,
+            test_llm_provider,
+        ]))
+        .on_window_event(|event| {
+            // Sessions are keyed by window label ("main" is the default session), so release
+            // the lock held by a window that has closed
+            if let tauri::WindowEvent::Destroyed = event.event() {
+                let state = event.window().state::<AppState>();
+                let session = state.documents.lock().unwrap().get(event.window().label()).cloned();
+                if let Some(doc) = session {
+                    release_document_lock(&state, &doc);
+                }
+            }
+        })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
\ No newline at end of file
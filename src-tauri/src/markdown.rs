@@ -0,0 +1,308 @@
+//! QuestScribe - Markdown
+//!
+//! The small part of Markdown authors use in notes and manuscripts: ATX and
+//! underlined headings, paragraphs, bulleted and numbered lists, block quotes,
+//! thematic breaks (scene breaks), and strong, emphasis, code and links inline.
+//! Anything else is text. The Markdown importer (see importers.rs) turns the
+//! parsed blocks into editor content, and marker descriptions are rendered to
+//! HTML for tooltips.
+//!
+//! The HTML is safe to insert as is: every piece of text is escaped, so HTML
+//! written in the Markdown shows as text, and links keep only http, https and
+//! mailto addresses.
+
+use crate::continuity_report::escape_html;
+
+/// A run of inline text with its formatting
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    pub link: Option<String>, // Link target, as written
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading { level: usize, spans: Vec<Span> },
+    Paragraph(Vec<Span>),
+    ListItem { ordered: bool, marker: String, spans: Vec<Span> }, // marker: "-", "*", "+" or "3."
+    Quote(Vec<Span>),
+    Break,
+}
+
+// Level and text of an ATX heading line ("## Chapter 2")
+fn atx_heading(line: &str) -> Option<(usize, &str)> {
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+// Setext underline level ("===" under a heading is 1, "---" is 2)
+fn setext_level(line: &str) -> Option<usize> {
+    let trimmed = line.trim();
+    if trimmed.len() < 2 {
+        return None;
+    }
+    if trimmed.chars().all(|c| c == '=') {
+        Some(1)
+    } else if trimmed.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// A thematic break ("***", "---", "* * *"), which novels use as a scene break
+fn is_thematic_break(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && ['*', '-', '_'].iter().any(|&m| marks.iter().all(|&c| c == m))
+}
+
+// Marker and text of a list item line ("- potion", "2. sword")
+fn list_item(line: &str) -> Option<(bool, &str, &str)> {
+    let item = line.trim_start();
+    for bullet in ["- ", "+ ", "* "] {
+        if let Some(text) = item.strip_prefix(bullet) {
+            return Some((false, &item[..1], text));
+        }
+    }
+    let (number, text) = item.split_once(". ")?;
+    (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit())).then(|| (true, &item[..number.len() + 1], text))
+}
+
+/// Whether the text uses Markdown: headings, strong emphasis or links
+pub fn looks_like_markdown(text: &str) -> bool {
+    let heading = text.lines().any(|line| atx_heading(line).is_some_and(|(_, title)| !title.is_empty()));
+    let strong = ["**", "__"].iter().any(|d| {
+        text.split(d).count() >= 3 && text.split(d).nth(1).is_some_and(|s| !s.trim().is_empty() && !s.contains('\n'))
+    });
+    let link = text.find("](").is_some_and(|at| text[..at].contains('[') && text[at..].contains(')'));
+    heading || strong || link
+}
+
+/// Parse a line of Markdown into spans
+pub fn parse_inline(text: &str) -> Vec<Span> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans = Vec::new();
+    let mut current = Span::default();
+
+    let flush = |current: &mut Span, spans: &mut Vec<Span>| {
+        if !current.text.is_empty() {
+            spans.push(Span { text: std::mem::take(&mut current.text), ..current.clone() });
+        }
+    };
+    let find_from = |from: usize, delimiter: &[char]| (from..chars.len()).find(|&i| chars[i..].starts_with(delimiter));
+
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        match ch {
+            '\\' if chars.get(i + 1).is_some_and(|c| c.is_ascii_punctuation()) => {
+                current.text.push(chars[i + 1]);
+                i += 2;
+            }
+            '`' => match find_from(i + 1, &['`']) {
+                Some(end) => {
+                    flush(&mut current, &mut spans);
+                    let code: String = chars[i + 1..end].iter().collect();
+                    if !code.is_empty() {
+                        spans.push(Span { text: code, code: true, ..current.clone() });
+                    }
+                    i = end + 1;
+                }
+                None => {
+                    current.text.push(ch);
+                    i += 1;
+                }
+            },
+            '*' | '_' => {
+                let double = chars.get(i + 1) == Some(&ch);
+                let delimiter = if double { vec![ch, ch] } else { vec![ch] };
+                // Underscores inside words (snake_case) are text
+                let in_word = ch == '_'
+                    && i > 0
+                    && chars[i - 1].is_alphanumeric()
+                    && chars.get(i + delimiter.len()).is_some_and(|c| c.is_alphanumeric());
+                let open = if double { current.bold } else { current.italic };
+                if !in_word && (open || find_from(i + delimiter.len() + 1, &delimiter).is_some()) {
+                    flush(&mut current, &mut spans);
+                    if double {
+                        current.bold = !current.bold;
+                    } else {
+                        current.italic = !current.italic;
+                    }
+                } else {
+                    current.text.extend(&delimiter);
+                }
+                i += delimiter.len();
+            }
+            '[' => {
+                let rest: String = chars[i..].iter().collect();
+                let link = rest.find("](").and_then(|mid| Some((mid, mid + rest[mid..].find(')')?)));
+                match link {
+                    Some((mid, end)) => {
+                        flush(&mut current, &mut spans);
+                        let target = rest[mid + 2..end].trim().to_string();
+                        if mid > 1 {
+                            spans.push(Span { text: rest[1..mid].to_string(), link: Some(target), ..current.clone() });
+                        }
+                        i += rest[..=end].chars().count();
+                    }
+                    None => {
+                        current.text.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                current.text.push(ch);
+                i += 1;
+            }
+        }
+    }
+    flush(&mut current, &mut spans);
+
+    spans
+}
+
+// Lines waiting to become a paragraph or a quote
+struct Pending<'a> {
+    quote: bool,
+    lines: Vec<&'a str>,
+}
+
+impl Pending<'_> {
+    fn flush(&mut self, blocks: &mut Vec<Block>) {
+        if self.lines.is_empty() {
+            return;
+        }
+        let text = self.lines.iter().map(|l| l.trim()).collect::<Vec<_>>().join(" ");
+        let spans = parse_inline(&text);
+        blocks.push(if self.quote { Block::Quote(spans) } else { Block::Paragraph(spans) });
+        self.lines.clear();
+    }
+}
+
+/// Parse Markdown into blocks
+pub fn parse(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut pending = Pending { quote: false, lines: Vec::new() };
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            pending.flush(&mut blocks);
+            continue;
+        }
+
+        // A one-line paragraph underlined is a heading
+        if let (Some(level), [title], false) = (setext_level(line), pending.lines.as_slice(), pending.quote) {
+            blocks.push(Block::Heading { level, spans: parse_inline(title.trim()) });
+            pending.lines.clear();
+            continue;
+        }
+
+        if let Some((level, title)) = atx_heading(line) {
+            pending.flush(&mut blocks);
+            blocks.push(Block::Heading { level, spans: parse_inline(title) });
+        } else if is_thematic_break(line) {
+            pending.flush(&mut blocks);
+            blocks.push(Block::Break);
+        } else if let Some((ordered, marker, item)) = list_item(line) {
+            pending.flush(&mut blocks);
+            blocks.push(Block::ListItem { ordered, marker: marker.to_string(), spans: parse_inline(item) });
+        } else {
+            let quote = line.trim_start().strip_prefix('>');
+            if pending.quote != quote.is_some() {
+                pending.flush(&mut blocks);
+                pending.quote = quote.is_some();
+            }
+            pending.lines.push(quote.unwrap_or(line));
+        }
+    }
+    pending.flush(&mut blocks);
+
+    blocks
+}
+
+/// A link target safe to put in an href: http, https, or mailto
+pub fn safe_link(target: &str) -> Option<&str> {
+    let lower = target.to_lowercase();
+    ["http://", "https://", "mailto:"].iter().any(|scheme| lower.starts_with(scheme)).then_some(target)
+}
+
+fn spans_html(spans: &[Span]) -> String {
+    let mut html = String::new();
+    for span in spans {
+        let mut text = escape_html(&span.text);
+        if span.code {
+            text = format!("<code>{}</code>", text);
+        }
+        if span.italic {
+            text = format!("<em>{}</em>", text);
+        }
+        if span.bold {
+            text = format!("<strong>{}</strong>", text);
+        }
+        if let Some(target) = span.link.as_deref().and_then(safe_link) {
+            text = format!("<a href=\"{}\" rel=\"noopener noreferrer\">{}</a>", escape_html(target), text);
+        }
+        html.push_str(&text);
+    }
+    html
+}
+
+/// Render Markdown as sanitized HTML (a fragment, without a page around it)
+pub fn to_html(text: &str) -> String {
+    let mut html = String::new();
+    let mut open_list: Option<bool> = None; // Whether the open list is ordered
+
+    for block in parse(text) {
+        let item_of = match &block {
+            Block::ListItem { ordered, .. } => Some(*ordered),
+            _ => None,
+        };
+        if open_list.is_some() && open_list != item_of {
+            html.push_str(if open_list == Some(true) { "</ol>\n" } else { "</ul>\n" });
+            open_list = None;
+        }
+
+        match block {
+            Block::Heading { level, spans } => html.push_str(&format!("<h{0}>{1}</h{0}>\n", level, spans_html(&spans))),
+            Block::Paragraph(spans) => html.push_str(&format!("<p>{}</p>\n", spans_html(&spans))),
+            Block::Quote(spans) => html.push_str(&format!("<blockquote><p>{}</p></blockquote>\n", spans_html(&spans))),
+            Block::Break => html.push_str("<hr>\n"),
+            Block::ListItem { ordered, spans, .. } => {
+                if open_list.is_none() {
+                    html.push_str(if ordered { "<ol>\n" } else { "<ul>\n" });
+                    open_list = Some(ordered);
+                }
+                html.push_str(&format!("<li>{}</li>\n", spans_html(&spans)));
+            }
+        }
+    }
+    if let Some(ordered) = open_list {
+        html.push_str(if ordered { "</ol>\n" } else { "</ul>\n" });
+    }
+
+    html
+}
+
+/// The text of Markdown without its markup (list items keep their markers)
+pub fn to_plain_text(text: &str) -> String {
+    let spans_text = |spans: &[Span]| spans.iter().map(|s| s.text.as_str()).collect::<String>();
+    parse(text)
+        .iter()
+        .map(|block| match block {
+            Block::Heading { spans, .. } | Block::Paragraph(spans) | Block::Quote(spans) => spans_text(spans),
+            Block::ListItem { marker, spans, .. } => format!("{} {}", marker, spans_text(spans)),
+            Block::Break => "* * *".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,415 @@
+//! QuestScribe - Marker CSV Import and Export
+//!
+//! Export writes one row per field change, for auditing and pivoting in a
+//! spreadsheet. Its columns are a superset of what import reads, so an exported
+//! file can be edited and imported again. Markers without changes are left out,
+//! since a row without a change can't be imported.
+//!
+//! Import bulk-creates markers from a spreadsheet, for migrating an existing tracker.
+//! The first row is a header; columns are matched by name (case-insensitive):
+//!
+//! - `position` or `chapter`: where the marker goes. A chapter is its number
+//!   (1 = first chapter) or its title; the marker is placed at the end of that
+//!   chapter, so the change is in effect from the next chapter on.
+//! - `entity`: entity name, resolved through the entity mapping, then by name;
+//!   names that match nothing create a new entity
+//! - `field`, `change type` (absolute/set/=, relative/add/+, remove, learn, or
+//!   the name of a custom change type), `value`
+//! - `description` (optional)
+//!
+//! Consecutive rows with the same location, entity, and description become one
+//! marker with several changes. Every row is validated before anything is
+//! inserted; any error aborts the whole import.
+
+use crate::chapters::Chapter;
+use crate::csv;
+use crate::engine;
+use crate::mutations::{self, MutationContext, NewEntity, NewMarker};
+use crate::state::{ChangeType, CustomChange, Entity, FieldChange, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Column headers written by export
+const EXPORT_HEADER: [&str; 9] = [
+    "marker id", "position", "chapter", "entity", "field", "change type", "value", "description", "tags",
+];
+
+/// Which markers to export (every criterion is optional; all given ones must match)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MarkerFilter {
+    #[serde(default)]
+    pub entity_ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>, // Markers with any of these tags
+    #[serde(default)]
+    pub from_position: Option<usize>,
+    #[serde(default)]
+    pub to_position: Option<usize>, // Exclusive
+    #[serde(default)]
+    pub field: Option<String>, // Only changes to this field or its subfields (e.g., "stats")
+}
+
+impl MarkerFilter {
+    fn matches(&self, marker: &Marker) -> bool {
+        self.entity_ids.as_ref().is_none_or(|ids| ids.contains(&marker.entity_id))
+            && self.tags.as_ref().is_none_or(|tags| {
+                marker.tags.iter().any(|t| tags.iter().any(|wanted| wanted.eq_ignore_ascii_case(t)))
+            })
+            && self.from_position.is_none_or(|from| marker.position >= from)
+            && self.to_position.is_none_or(|to| marker.position < to)
+    }
+
+    fn matches_field(&self, field_name: &str) -> bool {
+        self.field.as_ref().is_none_or(|prefix| {
+            field_name == prefix || field_name.starts_with(&format!("{}.", prefix))
+        })
+    }
+}
+
+/// What an import created
+#[derive(Debug, Clone, Serialize)]
+pub struct MarkerImport {
+    pub created_entities: Vec<Entity>,
+    pub markers: Vec<Marker>, // In file order
+    pub content: Option<serde_json::Value>, // The document with the markers' nodes, when it was available
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Location {
+    Position(usize),
+    Chapter(String),
+}
+
+struct Row {
+    line: usize,
+    location: Location,
+    entity: String,
+    change: FieldChange,
+    description: String,
+}
+
+#[derive(Default)]
+struct Columns {
+    position: Option<usize>,
+    chapter: Option<usize>,
+    entity: Option<usize>,
+    field: Option<usize>,
+    change_type: Option<usize>,
+    value: Option<usize>,
+    description: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &[String]) -> Result<Self, String> {
+        let mut columns = Columns::default();
+
+        for (index, name) in header.iter().enumerate() {
+            let name = name.trim().to_lowercase().replace(['_', '-'], " ");
+            let slot = match name.as_str() {
+                "position" => &mut columns.position,
+                "chapter" => &mut columns.chapter,
+                "entity" | "entity name" | "character" => &mut columns.entity,
+                "field" | "field name" => &mut columns.field,
+                "change type" | "type" => &mut columns.change_type,
+                "value" => &mut columns.value,
+                "description" => &mut columns.description,
+                _ => continue, // Extra spreadsheet columns are ignored
+            };
+            slot.get_or_insert(index);
+        }
+
+        if columns.position.is_none() && columns.chapter.is_none() {
+            return Err("The CSV header needs a \"position\" or \"chapter\" column".to_string());
+        }
+        for (column, name) in [
+            (columns.entity, "entity"),
+            (columns.field, "field"),
+            (columns.change_type, "change type"),
+            (columns.value, "value"),
+        ] {
+            if column.is_none() {
+                return Err(format!("The CSV header has no \"{}\" column", name));
+            }
+        }
+
+        Ok(columns)
+    }
+}
+
+fn cell(record: &[String], column: Option<usize>) -> &str {
+    column
+        .and_then(|i| record.get(i))
+        .map(|s| s.trim())
+        .unwrap_or("")
+}
+
+// Custom changes are written with their type's name
+fn change_type_name(change: &FieldChange) -> &str {
+    match change.change_type {
+        ChangeType::Absolute => "absolute",
+        ChangeType::Relative => "relative",
+        ChangeType::Remove => "remove",
+        ChangeType::Learn => "learn",
+        ChangeType::Custom => change.custom.as_ref().map_or("custom", |c| c.name.as_str()),
+    }
+}
+
+fn parse_change_type(value: &str) -> Option<ChangeType> {
+    match value.to_lowercase().as_str() {
+        "absolute" | "set" | "=" => Some(ChangeType::Absolute),
+        "relative" | "add" | "+" => Some(ChangeType::Relative),
+        "remove" | "delete" => Some(ChangeType::Remove),
+        "learn" | "learns" => Some(ChangeType::Learn),
+        _ => None,
+    }
+}
+
+fn parse_row(line: usize, record: &[String], columns: &Columns) -> Result<Row, String> {
+    let position = cell(record, columns.position);
+    let chapter = cell(record, columns.chapter);
+    let location = if !position.is_empty() {
+        Location::Position(
+            position
+                .parse()
+                .map_err(|_| format!("Invalid position \"{}\"", position))?,
+        )
+    } else if !chapter.is_empty() {
+        Location::Chapter(chapter.to_string())
+    } else {
+        return Err("Missing position or chapter".to_string());
+    };
+
+    let entity = cell(record, columns.entity);
+    if entity.is_empty() {
+        return Err("Missing entity name".to_string());
+    }
+
+    let field_name = cell(record, columns.field);
+    if field_name.is_empty() {
+        return Err("Missing field name".to_string());
+    }
+
+    let change_type_text = cell(record, columns.change_type);
+    let (change_type, custom) = match parse_change_type(change_type_text) {
+        Some(change_type) => (change_type, None),
+        // Any other name is taken for one of the document's custom change types (checked on insert)
+        None if !change_type_text.is_empty() => (
+            ChangeType::Custom,
+            Some(CustomChange { name: change_type_text.to_string(), reducer: String::new() }),
+        ),
+        None => return Err("Missing change type".to_string()),
+    };
+
+    let value = cell(record, columns.value);
+    if change_type == ChangeType::Relative && value.parse::<f64>().is_err() {
+        return Err(format!("Relative change value \"{}\" is not a number", value));
+    }
+
+    Ok(Row {
+        line,
+        location,
+        entity: entity.to_string(),
+        change: FieldChange {
+            field_name: field_name.to_string(),
+            change_type,
+            value: value.to_string(),
+            custom,
+        },
+        description: cell(record, columns.description).to_string(),
+    })
+}
+
+// Position at the end of a chapter's text (chapter numbers are 1-based)
+fn chapter_position(chapter: &str, chapters: Option<&[Chapter]>) -> Result<usize, String> {
+    let chapters = chapters.ok_or("Chapter locations need the document content")?;
+
+    let found = match chapter.parse::<usize>() {
+        Ok(number) => number.checked_sub(1).and_then(|index| chapters.get(index)),
+        Err(_) => chapters.iter().find(|c| c.title.eq_ignore_ascii_case(chapter)),
+    };
+
+    let chapter_range = found.ok_or_else(|| format!("No chapter \"{}\"", chapter))?;
+
+    // Inside the chapter's last block, just before its closing token
+    Ok(chapter_range.end.saturating_sub(1).max(chapter_range.start + 1))
+}
+
+/// Parse, validate, and insert markers from CSV text
+///
+/// `entity_mapping` maps entity names used in the file to entity IDs.
+/// `chapters` and `doc_size` come from the document content when it's available.
+/// `offset` is added to every location, for a file describing content that is
+/// placed later in the document (see chapter_import.rs).
+#[allow(clippy::too_many_arguments)]
+pub fn import_markers(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    text: &str,
+    entity_mapping: &HashMap<String, String>,
+    chapters: Option<&[Chapter]>,
+    doc_size: Option<usize>,
+    offset: usize,
+) -> Result<MarkerImport, String> {
+    let mut records = csv::parse(text)?.into_iter();
+    let (_, header) = records.next().ok_or("The CSV file is empty")?;
+    let columns = Columns::from_header(&header)?;
+
+    // Validate every row first, so a bad row can't leave a partial import behind
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (line, record) in records {
+        match parse_row(line, &record, &columns) {
+            Ok(row) => rows.push(row),
+            Err(e) => errors.push(format!("Line {}: {}", line, e)),
+        }
+    }
+
+    let mut positions = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let position = match &row.location {
+            Location::Position(pos) => Ok(*pos),
+            Location::Chapter(chapter) => chapter_position(chapter, chapters),
+        };
+        match position {
+            Ok(pos) if doc_size.is_some_and(|size| pos > size) => {
+                errors.push(format!("Line {}: Position {} is past the end of the document", row.line, pos));
+            }
+            Ok(pos) => positions.push(pos + offset),
+            Err(e) => errors.push(format!("Line {}: {}", row.line, e)),
+        }
+
+        if let Some(id) = entity_mapping.get(&row.entity) {
+            if !entities.contains_key(id) {
+                errors.push(format!("Line {}: \"{}\" is mapped to an entity that doesn't exist", row.line, row.entity));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    // Resolve entity names: mapping, then existing name, then a new entity
+    let mut created_entities = Vec::new();
+    let mut entity_ids: HashMap<String, String> = HashMap::new();
+    for row in &rows {
+        if entity_ids.contains_key(&row.entity) {
+            continue;
+        }
+        let existing = entity_mapping.get(&row.entity).cloned().or_else(|| {
+            entities
+                .values()
+                .find(|e| e.name.trim().eq_ignore_ascii_case(&row.entity))
+                .map(|e| e.id.clone())
+        });
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let entity = mutations::create_entity(
+                    entities,
+                    context,
+                    NewEntity { name: row.entity.clone(), color: None, kind: None },
+                )?;
+                let id = entity.id.clone();
+                created_entities.push(entity);
+                id
+            }
+        };
+        entity_ids.insert(row.entity.clone(), id);
+    }
+
+    // Group consecutive rows for the same marker
+    let mut groups: Vec<(usize, &Row, Vec<FieldChange>)> = Vec::new();
+    for (row, position) in rows.iter().zip(positions) {
+        match groups.last_mut() {
+            Some((pos, first, changes))
+                if *pos == position && first.entity == row.entity && first.description == row.description =>
+            {
+                changes.push(row.change.clone());
+            }
+            _ => groups.push((position, row, vec![row.change.clone()])),
+        }
+    }
+
+    let mut imported = Vec::with_capacity(groups.len());
+    for (position, first, changes) in groups {
+        let marker = mutations::insert_marker(
+            entities,
+            markers,
+            context,
+            NewMarker {
+                position,
+                entity_id: entity_ids[&first.entity].clone(),
+                changes,
+                visual: None,
+                description: Some(first.description.clone()),
+                tags: None,
+                story_time: None,
+                todo: false,
+            },
+        )?;
+        imported.push(marker);
+    }
+
+    Ok(MarkerImport {
+        created_entities,
+        markers: imported,
+        content: None,
+    })
+}
+
+// Title of the chapter a position falls in; positions past the last chapter's end belong to it
+fn chapter_title(chapters: &[Chapter], position: usize) -> Option<&str> {
+    chapters
+        .iter()
+        .find(|c| position >= c.start && position < c.end)
+        .or_else(|| chapters.last().filter(|c| position >= c.end))
+        .map(|c| c.title.as_str())
+}
+
+/// Write the markers matching `filter` as CSV, in document order
+///
+/// `chapters` (when the content is available) fills the chapter column.
+pub fn export_markers(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    filter: &MarkerFilter,
+    chapters: Option<&[Chapter]>,
+) -> String {
+    let mut selected: Vec<&Marker> = markers.values().filter(|m| filter.matches(m)).collect();
+    selected.sort_by(|a, b| engine::compare_markers(a, b));
+
+    let mut output = csv::format_row(&EXPORT_HEADER);
+
+    for marker in selected {
+        let position = marker.position.to_string();
+        let chapter = chapters
+            .and_then(|list| chapter_title(list, marker.position))
+            .unwrap_or("");
+        let entity = entities
+            .get(&marker.entity_id)
+            .map(|e| e.name.as_str())
+            .unwrap_or("");
+        let tags = marker.tags.join("; ");
+
+        // A marker without (matching) changes writes no rows
+        let changes = marker.changes.iter().filter(|c| filter.matches_field(&c.field_name));
+
+        for change in changes {
+            output.push_str(&csv::format_row(&[
+                marker.id.as_str(),
+                &position,
+                chapter,
+                entity,
+                &change.field_name,
+                change_type_name(change),
+                &change.value,
+                &marker.description,
+                &tags,
+            ]));
+        }
+    }
+
+    output
+}
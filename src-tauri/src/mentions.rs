@@ -0,0 +1,137 @@
+//! QuestScribe - Entity Mention Detection
+//!
+//! Finds where entity names appear in the document text. Matching is
+//! case-sensitive (names are proper nouns) and on whole words, so "Ann" doesn't
+//! match inside "Annual". A name split across differently formatted text runs
+//! (e.g., half of it bold) isn't found.
+//!
+//! Entity mention nodes (see entity_refs.rs) are mentions too, found by the ID
+//! they carry rather than by name.
+
+use crate::chapters;
+use crate::entity_refs;
+use crate::positions;
+use crate::state::Entity;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// One occurrence of an entity's name
+#[derive(Debug, Clone, Serialize)]
+pub struct Mention {
+    pub entity_id: String,
+    pub position: usize, // Document position of the first character of the name (or of the node)
+    pub reference: bool, // An entity mention node rather than the name in the text
+}
+
+// Whether the name occurs at `start` (a char index) as a whole word
+fn is_whole_word(chars: &[char], start: usize, len: usize) -> bool {
+    let before = start.checked_sub(1).map(|i| chars[i]);
+    let after = chars.get(start + len);
+
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(|c| c.is_alphanumeric())
+}
+
+/// Find every mention of every entity, in document order
+pub fn find_mentions(doc: &serde_json::Value, entities: &HashMap<String, Entity>) -> Vec<Mention> {
+    let names: Vec<(Vec<char>, &str)> = entities
+        .values()
+        .map(|e| (e.name.trim().chars().collect::<Vec<char>>(), e.id.as_str()))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+
+    let mut mentions = Vec::new();
+
+    positions::for_each_node(doc, |node, pos| {
+        if let Some(entity_id) = entity_refs::mention_entity_id(node).filter(|id| entities.contains_key(*id)) {
+            mentions.push(Mention { entity_id: entity_id.to_string(), position: pos, reference: true });
+            return;
+        }
+        let Some(text) = node.get("text").and_then(|t| t.as_str()) else {
+            return;
+        };
+        let chars: Vec<char> = text.chars().collect();
+
+        for (name, entity_id) in &names {
+            if name.len() > chars.len() {
+                continue;
+            }
+            for start in 0..=chars.len() - name.len() {
+                if chars[start..start + name.len()] == name[..] && is_whole_word(&chars, start, name.len()) {
+                    mentions.push(Mention {
+                        entity_id: entity_id.to_string(),
+                        position: pos + positions::char_index_to_utf16(text, start),
+                        reference: false,
+                    });
+                }
+            }
+        }
+    });
+
+    mentions.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.entity_id.cmp(&b.entity_id)));
+    mentions
+}
+
+/// Text blocks (paragraphs, headings) as (start, end, text)
+pub fn text_blocks(doc: &serde_json::Value) -> Vec<(usize, usize, String)> {
+    let mut blocks = Vec::new();
+
+    positions::for_each_node(doc, |node, pos| {
+        let is_text_block = node
+            .get("content")
+            .and_then(|c| c.as_array())
+            .is_some_and(|children| children.iter().any(|child| child.get("text").is_some()));
+        if is_text_block {
+            blocks.push((pos, pos + positions::node_size(node), chapters::node_text(node)));
+        }
+    });
+
+    blocks
+}
+
+/// How often an entity is mentioned, by name and by mention node
+#[derive(Debug, Clone, Serialize)]
+pub struct MentionCount {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub name_count: usize,
+    pub reference_count: usize,
+    pub first_position: Option<usize>,
+}
+
+/// Mention counts of every entity, most mentioned first
+pub fn mention_counts(doc: &serde_json::Value, entities: &HashMap<String, Entity>) -> Vec<MentionCount> {
+    let mut counts: HashMap<&str, MentionCount> = entities
+        .values()
+        .map(|e| {
+            let count = MentionCount {
+                entity_id: e.id.clone(),
+                entity_name: e.name.clone(),
+                name_count: 0,
+                reference_count: 0,
+                first_position: None,
+            };
+            (e.id.as_str(), count)
+        })
+        .collect();
+
+    // Mentions come in document order, so the first one seen is the first position
+    for mention in find_mentions(doc, entities) {
+        let Some(count) = counts.get_mut(mention.entity_id.as_str()) else {
+            continue;
+        };
+        if mention.reference {
+            count.reference_count += 1;
+        } else {
+            count.name_count += 1;
+        }
+        count.first_position.get_or_insert(mention.position);
+    }
+
+    let mut list: Vec<MentionCount> = counts.into_values().collect();
+    list.sort_by(|a, b| {
+        (b.name_count + b.reference_count)
+            .cmp(&(a.name_count + a.reference_count))
+            .then_with(|| a.entity_name.cmp(&b.entity_name))
+    });
+    list
+}
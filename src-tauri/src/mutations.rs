@@ -0,0 +1,900 @@
+//! QuestScribe - Entity and Marker Mutations
+//!
+//! The editing operations behind the entity/marker commands, written against
+//! plain maps so the same code serves single commands and atomic batches
+//! (see batch.rs). Callers lock the app state and pass the maps in.
+
+use crate::arcs;
+use crate::change_types::{self, CustomChangeType};
+use crate::chapters::HeadingPin;
+use crate::colors;
+use crate::dates;
+use crate::icons;
+use crate::ids;
+use crate::knowledge;
+use crate::strict;
+use crate::state::{ChangeType, Entity, EntityKind, FieldChange, FieldMetadata, FieldType, Marker, MarkerOutcome, MarkerVisual};
+use crate::visual_rules::{self, VisualRule};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Settings from outside the entity/marker maps that some mutations need
+pub struct MutationContext {
+    pub default_entity_color: String,
+    pub visual_rules: Vec<VisualRule>,
+    pub default_marker_icon: Option<String>,
+    pub strict: bool, // Refuse marker edits that introduce strict-mode violations (see strict.rs)
+    pub change_types: Vec<CustomChangeType>, // The document's custom change types
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewEntity {
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>, // None = the default entity color from settings, or a palette color if that's taken
+    #[serde(default, alias = "type")]
+    pub kind: Option<EntityKind>, // None = character
+}
+
+/// One entity of a batch creation
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntitySpec {
+    #[serde(flatten)]
+    pub entity: NewEntity,
+    #[serde(default)]
+    pub template: Option<String>, // Entity (ID or name) whose declared fields the new one starts with
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntityUpdate {
+    pub entity_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewMarker {
+    pub position: usize,
+    pub entity_id: String,
+    pub changes: Vec<FieldChange>,
+    #[serde(default)]
+    pub visual: Option<MarkerVisual>, // None = picked by the visual rules
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub story_time: Option<f64>,
+    #[serde(default)]
+    pub todo: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarkerUpdate {
+    pub marker_id: String,
+    #[serde(default)]
+    pub position: Option<usize>,
+    #[serde(default)]
+    pub entity_id: Option<String>,
+    #[serde(default)]
+    pub changes: Option<Vec<FieldChange>>,
+    #[serde(default)]
+    pub visual: Option<MarkerVisual>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub todo: Option<bool>,
+}
+
+/// Add any new fields from a marker's changes to the entity's field list and metadata
+pub fn record_fields(entity: &mut Entity, changes: &[FieldChange], now: i64) {
+    for change in changes {
+        let field_name = knowledge::change_path(change);
+
+        // Add to fields list if not present
+        if !entity.fields.contains(&field_name) {
+            entity.fields.push(field_name.clone());
+        }
+
+        // Update metadata - create if new, or update last_modified if existing
+        entity.field_metadata.entry(field_name)
+            .and_modify(|meta| meta.last_modified = now)
+            .or_insert(FieldMetadata {
+                created_at: now,
+                last_modified: now,
+                field_type: None,
+                default_value: None,
+            });
+    }
+}
+
+pub fn create_entity(
+    entities: &mut HashMap<String, Entity>,
+    context: &MutationContext,
+    new_entity: NewEntity,
+) -> Result<Entity, String> {
+    let color = match new_entity.color {
+        Some(color) => colors::parse_color(&color)?,
+        None => colors::distinct_color(&context.default_entity_color, entities.values().map(|e| e.color.as_str())),
+    };
+
+    let entity = Entity {
+        id: ids::new_id(),
+        name: new_entity.name,
+        fields: Vec::new(),
+        color,
+        field_metadata: HashMap::new(),
+        portrait: None,
+        kind: new_entity.kind.unwrap_or_default(),
+        distances: HashMap::new(),
+    };
+
+    entities.insert(entity.id.clone(), entity.clone());
+
+    Ok(entity)
+}
+
+// Entity a template refers to: by ID, else by name (ignoring case)
+fn find_template<'a>(entities: &'a HashMap<String, Entity>, template: &str) -> Option<&'a Entity> {
+    let template = template.trim();
+    entities.get(template).or_else(|| {
+        entities
+            .values()
+            .filter(|e| e.name.trim().eq_ignore_ascii_case(template))
+            .min_by(|a, b| a.id.cmp(&b.id))
+    })
+}
+
+/// Create several entities at once, in order; if any fails, none are created.
+/// A template may name an entity created earlier in the same call.
+pub fn create_entities(
+    entities: &mut HashMap<String, Entity>,
+    context: &MutationContext,
+    specs: Vec<EntitySpec>,
+) -> Result<Vec<Entity>, String> {
+    let mut working = entities.clone();
+    let mut created = Vec::with_capacity(specs.len());
+    let now = dates::now();
+
+    for (index, spec) in specs.into_iter().enumerate() {
+        let name = spec.entity.name.trim().to_string();
+        if name.is_empty() {
+            return Err(format!("Entity {}: name cannot be empty", index));
+        }
+
+        let template = match &spec.template {
+            Some(template) => Some(
+                find_template(&working, template)
+                    .cloned()
+                    .ok_or_else(|| format!("Entity {} ({}): template not found: {}", index, name, template))?,
+            ),
+            None => None,
+        };
+        let kind = spec.entity.kind.or(template.as_ref().map(|t| t.kind));
+
+        let new_entity = NewEntity { name: name.clone(), color: spec.entity.color, kind };
+        let mut entity = create_entity(&mut working, context, new_entity)
+            .map_err(|e| format!("Entity {} ({}): {}", index, name, e))?;
+
+        // Declared fields, types and defaults; the template's state stays with it
+        if let Some(template) = template {
+            entity.fields = template.fields.clone();
+            entity.field_metadata = template
+                .field_metadata
+                .iter()
+                .map(|(field, meta)| {
+                    let meta = FieldMetadata { created_at: now, last_modified: now, ..meta.clone() };
+                    (field.clone(), meta)
+                })
+                .collect();
+            working.insert(entity.id.clone(), entity.clone());
+        }
+
+        created.push(entity);
+    }
+
+    *entities = working;
+    Ok(created)
+}
+
+pub fn update_entity(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    update: EntityUpdate,
+) -> Result<Entity, String> {
+    let new_color = update.color.as_deref().map(colors::parse_color).transpose()?;
+    let entity = entities
+        .get_mut(&update.entity_id)
+        .ok_or("Entity not found")?;
+
+    if let Some(n) = update.name {
+        entity.name = n;
+    }
+    if let Some(new_color) = new_color {
+        entity.color = new_color.clone();
+
+        // Update all markers for this entity to use the new color
+        for marker in markers.values_mut() {
+            if marker.entity_id == update.entity_id {
+                marker.visual.color = new_color.clone();
+            }
+        }
+    }
+
+    Ok(entity.clone())
+}
+
+/// Give every entity a color from a palette, in name order, and recolor their markers
+///
+/// Colors are reused from the start once every color is taken.
+pub fn recolor_entities(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    palette: &[String],
+) -> Result<Vec<Entity>, String> {
+    let palette = colors::parse_palette(palette)?;
+
+    let mut order: Vec<(String, String)> = entities.values().map(|e| (e.name.to_lowercase(), e.id.clone())).collect();
+    order.sort();
+
+    order
+        .into_iter()
+        .zip(palette.iter().cycle())
+        .map(|((_, entity_id), color)| {
+            update_entity(
+                entities,
+                markers,
+                EntityUpdate { entity_id, name: None, color: Some(color.clone()) },
+            )
+        })
+        .collect()
+}
+
+/// Delete an entity along with all of its markers
+pub fn delete_entity(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    entity_id: &str,
+) -> Result<(), String> {
+    // Check if entity exists
+    if !entities.contains_key(entity_id) {
+        return Err("Entity not found".to_string());
+    }
+
+    // Delete all markers associated with this entity
+    markers.retain(|_, marker| marker.entity_id != entity_id);
+
+    // Delete the entity
+    entities.remove(entity_id);
+
+    // Forget distances to it (if it was a location)
+    for entity in entities.values_mut() {
+        entity.distances.remove(entity_id);
+    }
+
+    Ok(())
+}
+
+/// Remove a field from an entity's field list and from ALL of its markers
+/// (including absolute, relative, AND remove changes)
+pub fn delete_field_completely(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    entity_id: &str,
+    field_name: &str,
+) -> Result<(), String> {
+    let entity = entities
+        .get_mut(entity_id)
+        .ok_or("Entity not found")?;
+
+    entity.fields.retain(|f| f != field_name);
+
+    for marker in markers.values_mut() {
+        if marker.entity_id == entity_id {
+            marker.changes.retain(|change| change.field_name != field_name);
+        }
+    }
+
+    Ok(())
+}
+
+// `path` with `old_prefix` replaced by `new_prefix`, if it's the prefix itself or inside it
+fn move_path(path: &str, old_prefix: &str, new_prefix: &str) -> Option<String> {
+    if path == old_prefix {
+        return Some(new_prefix.to_string());
+    }
+    path.strip_prefix(old_prefix)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .map(|rest| format!("{}.{}", new_prefix, rest))
+}
+
+fn is_valid_path(path: &str) -> bool {
+    !path.is_empty() && path.split('.').all(|segment| !segment.trim().is_empty())
+}
+
+/// Move a field, or a whole group of fields, to a new path (e.g., "spells.fire" → "magic.fire")
+///
+/// Renames the entity's fields and their metadata and every change to them in the
+/// entity's markers, or nothing if a moved field would land on an existing one.
+pub fn move_field_subtree(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    entity_id: &str,
+    old_prefix: &str,
+    new_prefix: &str,
+) -> Result<Entity, String> {
+    let entity = entities
+        .get_mut(entity_id)
+        .ok_or("Entity not found")?;
+
+    if !is_valid_path(old_prefix) || !is_valid_path(new_prefix) {
+        return Err("Field paths can't be empty or have empty segments".to_string());
+    }
+    if old_prefix == new_prefix {
+        return Err("The new path is the same as the old one".to_string());
+    }
+    let in_knowledge = |path: &str| move_path(path, knowledge::KNOWLEDGE_FIELD, knowledge::KNOWLEDGE_FIELD).is_some();
+    if in_knowledge(old_prefix) || in_knowledge(new_prefix) {
+        return Err("Learned facts can't be moved".to_string());
+    }
+
+    let moved: Vec<(String, String)> = entity
+        .fields
+        .iter()
+        .filter_map(|field| move_path(field, old_prefix, new_prefix).map(|new| (field.clone(), new)))
+        .collect();
+    if moved.is_empty() {
+        return Err(format!("No fields under \"{}\"", old_prefix));
+    }
+    // Fields that stay put can't be overwritten
+    if let Some((_, taken)) = moved.iter().find(|(_, new)| {
+        entity.fields.iter().any(|f| f == new && move_path(f, old_prefix, new_prefix).is_none())
+    }) {
+        return Err(format!("Field \"{}\" already exists", taken));
+    }
+
+    for field in entity.fields.iter_mut() {
+        if let Some(new) = move_path(field, old_prefix, new_prefix) {
+            *field = new;
+        }
+    }
+    for (old, new) in &moved {
+        if let Some(metadata) = entity.field_metadata.remove(old) {
+            entity.field_metadata.insert(new.clone(), metadata);
+        }
+    }
+
+    let now = dates::now();
+    for marker in markers.values_mut().filter(|m| m.entity_id == entity_id) {
+        let mut changed = false;
+        for change in marker.changes.iter_mut().filter(|c| c.change_type != ChangeType::Learn) {
+            if let Some(new) = move_path(&change.field_name, old_prefix, new_prefix) {
+                change.field_name = new;
+                changed = true;
+            }
+        }
+        if changed {
+            marker.modified_at = now;
+        }
+    }
+
+    Ok(entity.clone())
+}
+
+/// Sequence that places a marker after every other marker at a position
+pub fn next_sequence(markers: &HashMap<String, Marker>, position: usize) -> u32 {
+    markers
+        .values()
+        .filter(|m| m.position == position)
+        .map(|m| m.sequence + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+// Check a marker visual's icon reference and normalize its color
+fn check_visual(visual: &mut MarkerVisual) -> Result<(), String> {
+    if let Some(icon_ref) = &visual.icon_ref {
+        icons::parse_icon_ref(icon_ref)?;
+    }
+    visual.color = colors::parse_color(&visual.color)?;
+    Ok(())
+}
+
+pub fn insert_marker(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    mut new_marker: NewMarker,
+) -> Result<Marker, String> {
+    change_types::resolve_changes(&context.change_types, &mut new_marker.changes)?;
+    if let Some(visual) = new_marker.visual.as_mut() {
+        check_visual(visual)?;
+    }
+    check_story_time(new_marker.story_time)?;
+    if let Some(entity) = entities.get(&new_marker.entity_id) {
+        arcs::validate_changes(entity, &new_marker.changes)?;
+    }
+
+    // Without explicit visuals, pick them from the document's visual rules
+    let visual = match new_marker.visual {
+        Some(v) => v,
+        None => {
+            let entity_color = entities
+                .get(&new_marker.entity_id)
+                .map(|e| e.color.clone())
+                .unwrap_or_else(|| context.default_entity_color.clone());
+            visual_rules::apply_rules(
+                &context.visual_rules,
+                &new_marker.changes,
+                &entity_color,
+                context.default_marker_icon.as_deref(),
+            )
+        }
+    };
+
+    let now = dates::now();
+
+    let marker = Marker {
+        id: ids::new_id(),
+        sequence: next_sequence(markers, new_marker.position),
+        position: new_marker.position,
+        entity_id: new_marker.entity_id,
+        changes: new_marker.changes,
+        visual,
+        description: new_marker.description.unwrap_or_default(),
+        created_at: now,
+        modified_at: now,
+        tags: new_marker.tags.unwrap_or_default(),
+        story_time: new_marker.story_time,
+        pin: None,
+        active_outcome: None,
+        outcomes: Vec::new(),
+        todo: new_marker.todo,
+    };
+
+    if context.strict {
+        if let Some(entity) = entities.get(&marker.entity_id) {
+            strict::check_edit(entity, markers.values(), markers.values().chain(std::iter::once(&marker)))?;
+        }
+    }
+
+    markers.insert(marker.id.clone(), marker.clone());
+
+    if let Some(entity) = entities.get_mut(&marker.entity_id) {
+        record_fields(entity, &marker.changes, now);
+    }
+
+    Ok(marker)
+}
+
+pub fn update_marker(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    mut update: MarkerUpdate,
+) -> Result<Marker, String> {
+    if let Some(changes) = update.changes.as_mut() {
+        change_types::resolve_changes(&context.change_types, changes)?;
+    }
+    if let Some(visual) = update.visual.as_mut() {
+        check_visual(visual)?;
+    }
+    if let Some(existing) = markers.get(&update.marker_id) {
+        let entity_id = update.entity_id.as_ref().unwrap_or(&existing.entity_id);
+        let changes = update.changes.as_ref().unwrap_or(&existing.changes);
+        if let Some(entity) = entities.get(entity_id) {
+            arcs::validate_changes(entity, changes)?;
+        }
+    }
+
+    // A marker moved to a new position goes after the markers already there
+    let sequence = update
+        .position
+        .filter(|pos| markers.get(&update.marker_id).is_some_and(|m| m.position != *pos))
+        .map(|pos| next_sequence(markers, pos));
+
+    if context.strict {
+        if let Some(existing) = markers.get(&update.marker_id) {
+            let mut candidate = existing.clone();
+            if let Some(pos) = update.position {
+                candidate.position = pos;
+            }
+            if let Some(sequence) = sequence {
+                candidate.sequence = sequence;
+            }
+            if let Some(ent_id) = &update.entity_id {
+                candidate.entity_id = ent_id.clone();
+            }
+            if let Some(changes) = &update.changes {
+                candidate.changes = changes.clone();
+            }
+
+            // Both the entity losing the marker and the one gaining it
+            let mut entity_ids = vec![&existing.entity_id, &candidate.entity_id];
+            entity_ids.dedup();
+            for entity in entity_ids.into_iter().filter_map(|id| entities.get(id)) {
+                let after = markers
+                    .values()
+                    .filter(|m| m.id != candidate.id)
+                    .chain(std::iter::once(&candidate));
+                strict::check_edit(entity, markers.values(), after)?;
+            }
+        }
+    }
+
+    let marker = markers
+        .get_mut(&update.marker_id)
+        .ok_or("Marker not found")?;
+
+    let now = dates::now();
+
+    if let Some(pos) = update.position {
+        marker.position = pos;
+    }
+    if let Some(sequence) = sequence {
+        marker.sequence = sequence;
+    }
+    if let Some(ent_id) = update.entity_id {
+        marker.entity_id = ent_id;
+    }
+    if let Some(changes) = update.changes {
+        marker.changes = changes;
+
+        if let Some(entity) = entities.get_mut(&marker.entity_id) {
+            record_fields(entity, &marker.changes, now);
+        }
+    }
+    if let Some(vis) = update.visual {
+        marker.visual = vis;
+    }
+    if let Some(desc) = update.description {
+        marker.description = desc;
+    }
+    if let Some(todo) = update.todo {
+        marker.todo = todo;
+    }
+
+    marker.modified_at = now;
+
+    Ok(marker.clone())
+}
+
+// Apply an outcome's changes to a marker, as an ordinary edit of its changes
+fn apply_outcome(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    marker_id: &str,
+    active: MarkerOutcome,
+    others: Vec<MarkerOutcome>,
+) -> Result<Marker, String> {
+    update_marker(
+        entities,
+        markers,
+        context,
+        MarkerUpdate {
+            marker_id: marker_id.to_string(),
+            position: None,
+            entity_id: None,
+            changes: Some(active.changes),
+            visual: None,
+            description: None,
+            todo: None,
+        },
+    )?;
+
+    let marker = markers.get_mut(marker_id).ok_or("Marker not found")?;
+    marker.active_outcome = Some(active.name);
+    marker.outcomes = others;
+    Ok(marker.clone())
+}
+
+/// Give a marker alternative outcomes, applying the one named `active`
+///
+/// Later state follows whichever outcome is active, so an undecided scene can be
+/// drafted both ways. No outcomes makes the marker an ordinary one again, with
+/// the changes it has now.
+pub fn set_marker_outcomes(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    marker_id: &str,
+    mut outcomes: Vec<MarkerOutcome>,
+    active: Option<String>,
+) -> Result<Marker, String> {
+    let entity_id = markers.get(marker_id).ok_or("Marker not found")?.entity_id.clone();
+
+    if outcomes.is_empty() {
+        let marker = markers.get_mut(marker_id).ok_or("Marker not found")?;
+        marker.active_outcome = None;
+        marker.outcomes.clear();
+        marker.modified_at = dates::now();
+        return Ok(marker.clone());
+    }
+
+    for outcome in &mut outcomes {
+        outcome.name = outcome.name.trim().to_string();
+        if outcome.name.is_empty() {
+            return Err("Outcome name cannot be empty".to_string());
+        }
+        change_types::resolve_changes(&context.change_types, &mut outcome.changes)?;
+        if let Some(entity) = entities.get(&entity_id) {
+            arcs::validate_changes(entity, &outcome.changes)?;
+        }
+    }
+    for (index, outcome) in outcomes.iter().enumerate() {
+        if outcomes[..index].iter().any(|o| o.name == outcome.name) {
+            return Err(format!("Outcome \"{}\" is listed twice", outcome.name));
+        }
+    }
+
+    let active = active.ok_or("Choose which outcome is active")?;
+    let index = outcomes
+        .iter()
+        .position(|o| o.name == active.trim())
+        .ok_or_else(|| format!("Outcome not found: {}", active))?;
+    let chosen = outcomes.remove(index);
+
+    apply_outcome(entities, markers, context, marker_id, chosen, outcomes)
+}
+
+/// Make another of a marker's outcomes the active one
+pub fn switch_marker_outcome(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    marker_id: &str,
+    outcome: &str,
+) -> Result<Marker, String> {
+    let marker = markers.get(marker_id).ok_or("Marker not found")?;
+    let index = marker
+        .outcomes
+        .iter()
+        .position(|o| o.name == outcome.trim())
+        .ok_or_else(|| format!("Outcome not found: {}", outcome))?;
+
+    // The outcome being replaced takes the chosen one's place in the list
+    let mut others = marker.outcomes.clone();
+    let chosen = others.remove(index);
+    if let Some(name) = &marker.active_outcome {
+        others.insert(index, MarkerOutcome { name: name.clone(), changes: marker.changes.clone() });
+    }
+
+    apply_outcome(entities, markers, context, marker_id, chosen, others)
+}
+
+/// Declare (or clear) a field's value type
+pub fn set_field_type(
+    entities: &mut HashMap<String, Entity>,
+    entity_id: &str,
+    field_name: &str,
+    field_type: Option<FieldType>,
+) -> Result<Entity, String> {
+    let entity = entities
+        .get_mut(entity_id)
+        .ok_or("Entity not found")?;
+
+    let now = dates::now();
+    if !entity.fields.iter().any(|f| f == field_name) {
+        entity.fields.push(field_name.to_string());
+    }
+    let metadata = entity.field_metadata.entry(field_name.to_string()).or_insert(FieldMetadata {
+        created_at: now,
+        last_modified: now,
+        field_type: None,
+        default_value: None,
+    });
+    metadata.field_type = field_type;
+    metadata.last_modified = now;
+
+    Ok(entity.clone())
+}
+
+/// Set (or clear) a field's default: its value before any marker sets it
+///
+/// The default must fit the field's declared type, if any.
+pub fn set_field_default(
+    entities: &mut HashMap<String, Entity>,
+    entity_id: &str,
+    field_name: &str,
+    default_value: Option<String>,
+) -> Result<Entity, String> {
+    let entity = entities
+        .get_mut(entity_id)
+        .ok_or("Entity not found")?;
+
+    let default_value = default_value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let field_type = entity.field_metadata.get(field_name).and_then(|m| m.field_type);
+    if let (Some(field_type), Some(default)) = (field_type, &default_value) {
+        if !field_type.accepts(default) {
+            let type_name = format!("{:?}", field_type).to_lowercase();
+            return Err(format!("Default \"{}\" of field \"{}\" is not a valid {}", default, field_name, type_name));
+        }
+    }
+
+    let now = dates::now();
+    if !entity.fields.iter().any(|f| f == field_name) {
+        entity.fields.push(field_name.to_string());
+    }
+    let metadata = entity.field_metadata.entry(field_name.to_string()).or_insert(FieldMetadata {
+        created_at: now,
+        last_modified: now,
+        field_type: None,
+        default_value: None,
+    });
+    metadata.default_value = default_value;
+    metadata.last_modified = now;
+
+    Ok(entity.clone())
+}
+
+/// Set (or clear) the distance between two locations
+///
+/// Distances are symmetric and stored once, on the first location.
+pub fn set_location_distance(
+    entities: &mut HashMap<String, Entity>,
+    location_id: &str,
+    other_location_id: &str,
+    distance: Option<f64>,
+) -> Result<(), String> {
+    if location_id == other_location_id {
+        return Err("A location has no distance to itself".to_string());
+    }
+    if distance.is_some_and(|d| !d.is_finite() || d < 0.0) {
+        return Err("Distance must be a non-negative number".to_string());
+    }
+    for id in [location_id, other_location_id] {
+        match entities.get(id) {
+            Some(entity) if entity.kind == EntityKind::Location => {}
+            Some(entity) => return Err(format!("{} is not a location", entity.name)),
+            None => return Err("Location not found".to_string()),
+        }
+    }
+
+    if let Some(other) = entities.get_mut(other_location_id) {
+        other.distances.remove(location_id);
+    }
+    let location = entities.get_mut(location_id).ok_or("Location not found")?;
+    match distance {
+        Some(distance) => location.distances.insert(other_location_id.to_string(), distance),
+        None => location.distances.remove(other_location_id),
+    };
+
+    Ok(())
+}
+
+pub fn delete_marker(
+    entities: &HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    marker_id: &str,
+) -> Result<(), String> {
+    // Deleting a marker can leave later relative changes or removals with nothing to act on
+    if context.strict {
+        if let Some(entity) = markers.get(marker_id).and_then(|m| entities.get(&m.entity_id)) {
+            strict::check_edit(entity, markers.values(), markers.values().filter(|m| m.id != marker_id))?;
+        }
+    }
+
+    markers
+        .remove(marker_id)
+        .ok_or("Marker not found")?;
+
+    Ok(())
+}
+
+/// Pin a marker to a heading at `position` (where the heading is now), or unpin it
+pub fn set_marker_pin(
+    entities: &HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    marker_id: &str,
+    pin: Option<HeadingPin>,
+    position: Option<usize>,
+) -> Result<Marker, String> {
+    // A marker moved to the heading goes after the markers already there
+    let sequence = position
+        .filter(|pos| markers.get(marker_id).is_some_and(|m| m.position != *pos))
+        .map(|pos| next_sequence(markers, pos));
+
+    let mut marker = markers.get(marker_id).ok_or("Marker not found")?.clone();
+    if let Some(pos) = position {
+        marker.position = pos;
+    }
+    if let Some(sequence) = sequence {
+        marker.sequence = sequence;
+    }
+    marker.pin = pin;
+    marker.modified_at = dates::now();
+
+    // Moving the marker changes the order its changes apply in
+    if context.strict {
+        strict::check_batch(entities, markers, std::slice::from_ref(&marker), &[])?;
+    }
+
+    markers.insert(marker.id.clone(), marker.clone());
+
+    Ok(marker)
+}
+
+pub fn set_marker_tags(
+    markers: &mut HashMap<String, Marker>,
+    marker_id: &str,
+    tags: Vec<String>,
+) -> Result<Marker, String> {
+    let marker = markers
+        .get_mut(marker_id)
+        .ok_or("Marker not found")?;
+
+    marker.tags = tags;
+    marker.modified_at = dates::now();
+
+    Ok(marker.clone())
+}
+
+/// Set the order of the markers at a position; `marker_ids` must list each of them once
+pub fn reorder_markers(
+    entities: &HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    position: usize,
+    marker_ids: &[String],
+) -> Result<Vec<Marker>, String> {
+    let mut at_position: Vec<&String> = markers
+        .values()
+        .filter(|m| m.position == position)
+        .map(|m| &m.id)
+        .collect();
+    let mut requested: Vec<&String> = marker_ids.iter().collect();
+    at_position.sort();
+    requested.sort();
+    if at_position != requested {
+        return Err(format!("The marker list must contain each marker at position {} exactly once", position));
+    }
+
+    let now = dates::now();
+    let mut reordered = Vec::with_capacity(marker_ids.len());
+    for (sequence, marker_id) in marker_ids.iter().enumerate() {
+        let mut marker = markers.get(marker_id).ok_or("Marker not found")?.clone();
+        marker.sequence = sequence as u32;
+        marker.modified_at = now;
+        reordered.push(marker);
+    }
+
+    if context.strict {
+        strict::check_batch(entities, markers, &reordered, &[])?;
+    }
+
+    for marker in &reordered {
+        markers.insert(marker.id.clone(), marker.clone());
+    }
+
+    Ok(reordered)
+}
+
+// Story times are compared and subtracted, so NaN and infinities aren't allowed
+fn check_story_time(story_time: Option<f64>) -> Result<(), String> {
+    if story_time.is_some_and(|t| !t.is_finite()) {
+        return Err("Story time must be a finite number".to_string());
+    }
+    Ok(())
+}
+
+pub fn set_marker_story_time(
+    markers: &mut HashMap<String, Marker>,
+    marker_id: &str,
+    story_time: Option<f64>,
+) -> Result<Marker, String> {
+    check_story_time(story_time)?;
+
+    let marker = markers
+        .get_mut(marker_id)
+        .ok_or("Marker not found")?;
+
+    marker.story_time = story_time;
+    marker.modified_at = dates::now();
+
+    Ok(marker.clone())
+}
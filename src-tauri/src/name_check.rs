@@ -0,0 +1,147 @@
+//! QuestScribe - Name Consistency Checker
+//!
+//! Finds near-miss spellings of entity names and aliases in the text
+//! ("Katnis" for "Katniss"), the most common continuity error there is.
+//!
+//! A word (or run of words, for names with several) is a near miss when it's
+//! within a small edit distance of a name without matching any name exactly:
+//! one edit for names up to seven letters, two for longer ones. Names shorter
+//! than four letters are skipped, as are words that don't start with a capital
+//! letter, since names are proper nouns and "and" is one edit from "Ann".
+//! Comparison ignores case and punctuation between words, so "katniss" and
+//! "O Brien" for "O'Brien" aren't reported.
+
+use crate::glossary;
+use crate::positions;
+use crate::state::{Entity, Marker};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+const MIN_NAME_LEN: usize = 4;
+const LONG_NAME_LEN: usize = 8; // Names this long allow two edits
+
+/// A word in the text that looks like a misspelled name
+#[derive(Debug, Clone, Serialize)]
+pub struct NameMisspelling {
+    pub entity_id: String,
+    pub position: usize, // Document position of the first character of the word
+    pub found: String,
+    pub expected: String, // The name or alias it's closest to
+    pub distance: usize,
+}
+
+// A name or alias, with its lowercase words (joined by spaces) for comparing
+struct Spelling<'a> {
+    name: String,
+    lower: Vec<char>,
+    word_count: usize,
+    entity_id: &'a str,
+}
+
+// Levenshtein distance over characters
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn max_distance(name_len: usize) -> usize {
+    if name_len >= LONG_NAME_LEN {
+        2
+    } else {
+        1
+    }
+}
+
+// Words of a text as (char index, word); apostrophes end a word ("Katniss's")
+fn words(chars: &[char]) -> Vec<(usize, String)> {
+    let mut list = Vec::new();
+    let mut start = None;
+    for (index, c) in chars.iter().chain(std::iter::once(&' ')).enumerate() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(from)) => {
+                list.push((from, chars[from..index].iter().collect()));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    list
+}
+
+/// Find near-miss spellings of every entity's name and aliases, in document order
+pub fn find_misspellings(
+    doc: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+) -> Vec<NameMisspelling> {
+    // Every spelling in use, so a name that is another entity's exact name isn't flagged
+    let mut names: Vec<Spelling> = Vec::new();
+    for entity in entities.values() {
+        let aliases = glossary::entity_aliases(entity, markers);
+        for name in std::iter::once(entity.name.trim().to_string()).chain(aliases) {
+            let name_words = words(&name.to_lowercase().chars().collect::<Vec<_>>());
+            let lower = name_words.iter().map(|(_, w)| w.as_str()).collect::<Vec<_>>().join(" ").chars().collect();
+            names.push(Spelling { name, lower, word_count: name_words.len(), entity_id: &entity.id });
+        }
+    }
+    let known: HashSet<Vec<char>> = names.iter().map(|n| n.lower.clone()).collect();
+    names.retain(|n| n.word_count > 0 && n.lower.iter().filter(|c| c.is_alphanumeric()).count() >= MIN_NAME_LEN);
+
+    let mut found = Vec::new();
+    positions::for_each_node(doc, |node, pos| {
+        let Some(text) = node.get("text").and_then(|t| t.as_str()) else {
+            return;
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let words = words(&chars);
+
+        for (index, (start, first)) in words.iter().enumerate() {
+            if !first.chars().next().is_some_and(char::is_uppercase) {
+                continue;
+            }
+
+            // The closest name, comparing as many words as the name has
+            let mut best: Option<(usize, &Spelling, String)> = None;
+            for name in &names {
+                let Some(window) = words.get(index..index + name.word_count) else {
+                    continue;
+                };
+                let candidate = window.iter().map(|(_, w)| w.as_str()).collect::<Vec<_>>().join(" ");
+                let lower: Vec<char> = candidate.to_lowercase().chars().collect();
+                let allowed = max_distance(name.lower.len());
+                if known.contains(&lower) || lower.len().abs_diff(name.lower.len()) > allowed {
+                    continue;
+                }
+
+                let distance = edit_distance(&lower, &name.lower);
+                if (1..=allowed).contains(&distance)
+                    && best.as_ref().is_none_or(|(d, _, _)| distance < *d)
+                {
+                    best = Some((distance, name, candidate));
+                }
+            }
+
+            if let Some((distance, name, candidate)) = best {
+                found.push(NameMisspelling {
+                    entity_id: name.entity_id.to_string(),
+                    position: pos + positions::char_index_to_utf16(text, *start),
+                    found: candidate,
+                    expected: name.name.clone(),
+                    distance,
+                });
+            }
+        }
+    });
+
+    found.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.entity_id.cmp(&b.entity_id)));
+    found
+}
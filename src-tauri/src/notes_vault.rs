@@ -0,0 +1,167 @@
+//! QuestScribe - Notes Vault
+//!
+//! An encrypted notes section inside the document, for planning notes that
+//! must not leak to co-authors or beta readers (the traitor's identity, the
+//! ending). The vault has its own passphrase; it's unrelated to the document
+//! and isn't stored anywhere.
+//!
+//! # Storage
+//!
+//! The document only ever holds the sealed vault: a random salt, a random
+//! nonce, and the notes encrypted with XChaCha20-Poly1305 under a key derived
+//! from the passphrase with Argon2id. Unlocking decrypts the notes into memory
+//! for the session; every edit re-seals the vault right away, so a save (or a
+//! crash) never writes plaintext. Locking drops the key and the notes.
+//!
+//! Exports never include the vault; redacted exports drop even the sealed form.
+
+use crate::base64;
+use crate::dates;
+use crate::ids;
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// The vault as saved in the document (all fields base64)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedVault {
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultNote {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub modified_at: i64,
+}
+
+/// A vault open for the session: the derived key and the decrypted notes
+pub struct UnlockedVault {
+    key: [u8; KEY_LEN],
+    salt: Vec<u8>,
+    pub notes: Vec<VaultNote>,
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Failed to generate random data: {}", e))?;
+    Ok(bytes)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; KEY_LEN], String> {
+    let mut key = [0u8; KEY_LEN];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Failed to derive vault key: {}", e))?;
+    Ok(key)
+}
+
+fn cipher(key: &[u8; KEY_LEN]) -> Result<XChaCha20Poly1305, String> {
+    XChaCha20Poly1305::new_from_slice(key).map_err(|e| format!("Failed to set up vault encryption: {}", e))
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, String> {
+    base64::decode(value).ok_or_else(|| format!("Notes vault is damaged: invalid {}", field))
+}
+
+/// Start an empty vault with a new passphrase
+pub fn create(passphrase: &str) -> Result<UnlockedVault, String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("Vault passphrase must be at least {} characters", MIN_PASSPHRASE_LEN));
+    }
+
+    let salt = random_bytes(SALT_LEN)?;
+    Ok(UnlockedVault {
+        key: derive_key(passphrase, &salt)?,
+        salt,
+        notes: Vec::new(),
+    })
+}
+
+/// Decrypt a sealed vault
+pub fn unlock(sealed: &SealedVault, passphrase: &str) -> Result<UnlockedVault, String> {
+    let salt = decode("salt", &sealed.salt)?;
+    let nonce = decode("nonce", &sealed.nonce)?;
+    let ciphertext = decode("ciphertext", &sealed.ciphertext)?;
+    if nonce.len() != NONCE_LEN {
+        return Err("Notes vault is damaged: invalid nonce".to_string());
+    }
+
+    let key = derive_key(passphrase, &salt)?;
+    // The tag check fails for a wrong passphrase and for tampered data alike
+    let plaintext = cipher(&key)?
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "Wrong vault passphrase".to_string())?;
+    let notes: Vec<VaultNote> = serde_json::from_slice(&plaintext)
+        .map_err(|e| format!("Failed to parse vault notes: {}", e))?;
+
+    Ok(UnlockedVault { key, salt, notes })
+}
+
+impl UnlockedVault {
+    /// Encrypt the notes for saving, with a fresh nonce
+    pub fn seal(&self) -> Result<SealedVault, String> {
+        let plaintext = serde_json::to_vec(&self.notes)
+            .map_err(|e| format!("Failed to serialize vault notes: {}", e))?;
+        let nonce = random_bytes(NONCE_LEN)?;
+        let ciphertext = cipher(&self.key)?
+            .encrypt(XNonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|e| format!("Failed to encrypt vault notes: {}", e))?;
+
+        Ok(SealedVault {
+            salt: base64::encode(&self.salt),
+            nonce: base64::encode(&nonce),
+            ciphertext: base64::encode(&ciphertext),
+        })
+    }
+
+    /// Add a note (no id) or replace one
+    pub fn save_note(&mut self, id: Option<String>, title: String, body: String) -> Result<VaultNote, String> {
+        let title = title.trim().to_string();
+        if title.is_empty() {
+            return Err("Note title cannot be empty".to_string());
+        }
+
+        let note = VaultNote {
+            id: id.clone().unwrap_or_else(ids::new_id),
+            title,
+            body,
+            modified_at: dates::now(),
+        };
+        match id {
+            Some(id) => {
+                let existing = self.notes.iter_mut().find(|n| n.id == id).ok_or("Note not found")?;
+                *existing = note.clone();
+            }
+            None => self.notes.push(note.clone()),
+        }
+
+        Ok(note)
+    }
+
+    pub fn delete_note(&mut self, id: &str) -> Result<(), String> {
+        let before = self.notes.len();
+        self.notes.retain(|n| n.id != id);
+        if self.notes.len() == before {
+            return Err("Note not found".to_string());
+        }
+        Ok(())
+    }
+
+    /// Re-key the vault under a new passphrase (with a new salt)
+    pub fn change_passphrase(&mut self, passphrase: &str) -> Result<(), String> {
+        let fresh = create(passphrase)?;
+        self.key = fresh.key;
+        self.salt = fresh.salt;
+        Ok(())
+    }
+}
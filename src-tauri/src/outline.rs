@@ -0,0 +1,142 @@
+//! QuestScribe - Document Outline
+//!
+//! Builds the heading tree for the navigation pane. Each section runs from its
+//! heading to the next heading of the same or a higher level, so a chapter's
+//! range includes its scenes. Only top-level headings are considered, matching
+//! how the editor inserts them.
+
+use crate::chapters::node_text;
+use crate::engine;
+use crate::positions;
+use crate::state::{Entity, Marker};
+use crate::stats;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// The marker closest to a section, for jumping to the story state there
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineMarker {
+    pub marker_id: String,
+    pub entity_id: String,
+    pub entity_name: String,
+    pub position: usize,
+    pub inside: bool, // false = no marker in the section; this is the last one before it
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineSection {
+    pub level: u64,
+    pub title: String,
+    pub start: usize, // Position of the heading
+    pub end: usize,   // Start of the next heading at this level or above, or the document size
+    pub word_count: usize,     // Whole section, including subsections
+    pub own_word_count: usize, // Text before the first subsection
+    pub marker_count: usize,
+    pub nearest_marker: Option<OutlineMarker>,
+    pub children: Vec<OutlineSection>,
+}
+
+// A heading found while scanning the document
+struct Heading {
+    level: u64,
+    title: String,
+    start: usize,
+    block_index: usize,
+}
+
+/// Build the outline tree of a ProseMirror document
+pub fn document_outline(
+    doc: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+) -> Vec<OutlineSection> {
+    let doc_size = positions::content_size(doc);
+    let blocks: &[serde_json::Value] = doc
+        .get("content")
+        .and_then(|c| c.as_array())
+        .map(|c| c.as_slice())
+        .unwrap_or(&[]);
+
+    // Word counts indexed like `blocks`
+    let mut block_words = Vec::with_capacity(blocks.len());
+    let mut headings = Vec::new();
+
+    let mut pos = 0;
+    for (block_index, block) in blocks.iter().enumerate() {
+        if block.get("type").and_then(|t| t.as_str()) == Some("heading") {
+            headings.push(Heading {
+                level: block.get("attrs").and_then(|a| a.get("level")).and_then(|l| l.as_u64()).unwrap_or(1),
+                title: node_text(block).trim().to_string(),
+                start: pos,
+                block_index,
+            });
+        }
+        block_words.push(stats::block_word_count(block));
+        pos += positions::node_size(block);
+    }
+
+    let mut sorted_markers: Vec<&Marker> = markers.values().collect();
+    sorted_markers.sort_by(|a, b| engine::compare_markers(a, b));
+
+    // Flat sections in document order
+    let mut flat: Vec<OutlineSection> = Vec::with_capacity(headings.len());
+    for (i, heading) in headings.iter().enumerate() {
+        let next = headings[i + 1..].iter().find(|h| h.level <= heading.level);
+        let end_block = next.map(|h| h.block_index).unwrap_or(blocks.len());
+        let own_end_block = headings.get(i + 1).map(|h| h.block_index).unwrap_or(blocks.len()).min(end_block);
+        let end = next.map(|h| h.start).unwrap_or(doc_size);
+
+        let in_section: Vec<&&Marker> = sorted_markers
+            .iter()
+            .filter(|m| m.position >= heading.start && m.position < end)
+            .collect();
+        let nearest = match in_section.first() {
+            Some(marker) => Some((*marker, true)),
+            None => sorted_markers.iter().rev().find(|m| m.position < heading.start).map(|m| (m, false)),
+        };
+
+        flat.push(OutlineSection {
+            level: heading.level,
+            title: heading.title.clone(),
+            start: heading.start,
+            end,
+            word_count: block_words[heading.block_index..end_block].iter().sum(),
+            own_word_count: block_words[heading.block_index..own_end_block].iter().sum(),
+            marker_count: in_section.len(),
+            nearest_marker: nearest.map(|(marker, inside)| OutlineMarker {
+                marker_id: marker.id.clone(),
+                entity_id: marker.entity_id.clone(),
+                entity_name: entities
+                    .get(&marker.entity_id)
+                    .map(|e| e.name.clone())
+                    .unwrap_or_default(),
+                position: marker.position,
+                inside,
+            }),
+            children: Vec::new(),
+        });
+    }
+
+    // Nest each section under the closest preceding section with a lower level
+    let mut roots: Vec<OutlineSection> = Vec::new();
+    let mut stack: Vec<OutlineSection> = Vec::new();
+    for section in flat {
+        while stack.last().is_some_and(|top| top.level >= section.level) {
+            let done = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, done);
+        }
+        stack.push(section);
+    }
+    while let Some(done) = stack.pop() {
+        attach(&mut stack, &mut roots, done);
+    }
+
+    roots
+}
+
+fn attach(stack: &mut [OutlineSection], roots: &mut Vec<OutlineSection>, section: OutlineSection) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(section),
+        None => roots.push(section),
+    }
+}
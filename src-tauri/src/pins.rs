@@ -0,0 +1,113 @@
+//! QuestScribe - Entity State Pins
+//!
+//! A pin is a named, frozen copy of an entity's computed state at some
+//! position ("before the time-skip"). Later edits to markers don't change it,
+//! so comparing the state at another position against the pin shows what has
+//! changed since, even if the markers that led up to the pinned state were
+//! moved or deleted.
+//!
+//! Pins are stored in the document and removed with their entity.
+
+use crate::engine::{self, EntityState};
+use crate::ids;
+use crate::recap;
+use crate::state::{Entity, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatePin {
+    pub id: String,
+    pub entity_id: String,
+    pub label: String, // e.g., "Before the time-skip"
+    pub position: usize, // Where the state was computed (not updated as the text is edited)
+    pub created_at: i64,
+    pub state: EntityState,
+}
+
+/// A field whose value differs between the pin and the compared state
+#[derive(Debug, Clone, Serialize)]
+pub struct PinChange {
+    pub field: String,
+    pub pinned: String,
+    pub current: String,
+}
+
+/// Differences between a pin and an entity's state at a position, by flattened field
+#[derive(Debug, Clone, Serialize)]
+pub struct PinComparison {
+    pub pin_id: String,
+    pub label: String,
+    pub pinned_position: usize,
+    pub position: usize,
+    pub added: BTreeMap<String, String>, // Fields set now but not in the pin
+    pub removed: BTreeMap<String, String>, // Fields in the pin but not set now (pinned values)
+    pub changed: Vec<PinChange>,
+}
+
+// An entity's state at a position, with defaults filled in
+fn state_at(entity: &Entity, markers: &HashMap<String, Marker>, position: usize) -> EntityState {
+    let mut state = engine::entity_state_with_defaults(markers, entity, position);
+    engine::fill_defaults(&mut state, entity);
+    state
+}
+
+/// Freeze an entity's state at a position under a label
+pub fn pin_state(
+    entity: &Entity,
+    markers: &HashMap<String, Marker>,
+    position: usize,
+    label: &str,
+    now: i64,
+) -> Result<StatePin, String> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err("Pin label cannot be empty".to_string());
+    }
+
+    Ok(StatePin {
+        id: ids::new_id(),
+        entity_id: entity.id.clone(),
+        label: label.to_string(),
+        position,
+        created_at: now,
+        state: state_at(entity, markers, position),
+    })
+}
+
+/// Compare an entity's state at a position with one of its pins
+pub fn compare(
+    pin: &StatePin,
+    entity: &Entity,
+    markers: &HashMap<String, Marker>,
+    position: usize,
+) -> Result<PinComparison, String> {
+    if pin.entity_id != entity.id {
+        return Err("The pin belongs to a different entity".to_string());
+    }
+
+    let pinned = recap::flat_values(&pin.state);
+    let mut current = recap::flat_values(&state_at(entity, markers, position));
+
+    let mut removed = BTreeMap::new();
+    let mut changed = Vec::new();
+    for (field, pinned_value) in pinned {
+        match current.remove(&field) {
+            Some(value) if value == pinned_value => {}
+            Some(value) => changed.push(PinChange { field, pinned: pinned_value, current: value }),
+            None => {
+                removed.insert(field, pinned_value);
+            }
+        }
+    }
+
+    Ok(PinComparison {
+        pin_id: pin.id.clone(),
+        label: pin.label.clone(),
+        pinned_position: pin.position,
+        position,
+        added: current,
+        removed,
+        changed,
+    })
+}
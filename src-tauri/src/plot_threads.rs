@@ -0,0 +1,207 @@
+//! QuestScribe - Plot Threads
+//!
+//! Plot threads track storylines rather than entity stats: a thread is opened
+//! somewhere in the text, developed any number of times, and (hopefully)
+//! resolved. Each status change is an event at a document position, optionally
+//! linked to a marker; a linked event follows its marker as the text is edited.
+//!
+//! Threads are stored in the document, separately from entities and markers.
+
+use crate::ids;
+use crate::positions::TextEdit;
+use crate::state::Marker;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadStatus {
+    Open,
+    Developed,
+    Resolved,
+}
+
+/// A status change of a thread
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadEvent {
+    pub id: String,
+    pub status: ThreadStatus,
+    pub position: usize,
+    #[serde(default)]
+    pub marker_id: Option<String>, // Linked marker; its position wins while it exists
+    #[serde(default)]
+    pub note: String, // e.g., "Mira finds the second letter"
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlotThread {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub events: Vec<ThreadEvent>,
+}
+
+/// A thread's status at some position
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadState {
+    pub thread_id: String,
+    pub name: String,
+    pub status: ThreadStatus,
+    pub since_position: usize, // Position of the event that set the status
+}
+
+/// A new event for a thread
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewThreadEvent {
+    pub status: ThreadStatus,
+    pub position: usize,
+    #[serde(default)]
+    pub marker_id: Option<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl ThreadEvent {
+    /// Current position: the linked marker's, or the stored one
+    pub fn effective_position(&self, markers: &HashMap<String, Marker>) -> usize {
+        self.marker_id
+            .as_ref()
+            .and_then(|id| markers.get(id))
+            .map(|m| m.position)
+            .unwrap_or(self.position)
+    }
+}
+
+impl PlotThread {
+    /// Events in story order, with linked events at their marker's position
+    pub fn timeline(&self, markers: &HashMap<String, Marker>) -> Vec<ThreadEvent> {
+        let mut events: Vec<ThreadEvent> = self
+            .events
+            .iter()
+            .map(|event| ThreadEvent {
+                position: event.effective_position(markers),
+                ..event.clone()
+            })
+            .collect();
+        events.sort_by_key(|e| e.position);
+        events
+    }
+
+    /// Status at a position (events at the position are included); None before the thread opens
+    pub fn state_at(&self, markers: &HashMap<String, Marker>, position: usize) -> Option<ThreadState> {
+        self.timeline(markers)
+            .into_iter()
+            .rev()
+            .find(|e| e.position <= position)
+            .map(|event| ThreadState {
+                thread_id: self.id.clone(),
+                name: self.name.clone(),
+                status: event.status,
+                since_position: event.position,
+            })
+    }
+
+    /// Add an event, checking that the thread is opened before anything else happens to it
+    pub fn add_event(&mut self, markers: &HashMap<String, Marker>, new_event: NewThreadEvent) -> Result<ThreadEvent, String> {
+        if let Some(marker_id) = &new_event.marker_id {
+            if !markers.contains_key(marker_id) {
+                return Err("Marker not found".to_string());
+            }
+        }
+
+        let event = ThreadEvent {
+            id: ids::new_id(),
+            status: new_event.status,
+            position: new_event.position,
+            marker_id: new_event.marker_id,
+            note: new_event.note.unwrap_or_default(),
+        };
+
+        let first_status = self
+            .timeline(markers)
+            .into_iter()
+            .chain(std::iter::once(event.clone()))
+            .min_by_key(|e| e.effective_position(markers))
+            .map(|e| e.status);
+        if first_status != Some(ThreadStatus::Open) {
+            return Err("A thread has to be opened before it can be developed or resolved".to_string());
+        }
+
+        self.events.push(event.clone());
+        Ok(event)
+    }
+
+    /// Remove an event; the opening event can only go while it's the thread's only event
+    pub fn remove_event(&mut self, markers: &HashMap<String, Marker>, event_id: &str) -> Result<(), String> {
+        let index = self
+            .events
+            .iter()
+            .position(|e| e.id == event_id)
+            .ok_or("Thread event not found")?;
+        if self.events.len() == 1 {
+            return Err("A thread needs at least one event; delete the thread instead".to_string());
+        }
+
+        let removed = self.events.remove(index);
+        if self.timeline(markers).first().map(|e| e.status) != Some(ThreadStatus::Open) {
+            self.events.insert(index, removed);
+            return Err("A thread has to be opened before it can be developed or resolved".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Create a thread, opened at a position
+pub fn create_thread(
+    markers: &HashMap<String, Marker>,
+    name: String,
+    description: String,
+    opened: NewThreadEvent,
+) -> Result<PlotThread, String> {
+    if name.trim().is_empty() {
+        return Err("Thread name can't be empty".to_string());
+    }
+
+    let mut thread = PlotThread {
+        id: ids::new_id(),
+        name,
+        description,
+        events: Vec::new(),
+    };
+    thread.add_event(markers, NewThreadEvent { status: ThreadStatus::Open, ..opened })?;
+
+    Ok(thread)
+}
+
+/// Threads that aren't resolved at a position, in the order they were opened
+pub fn unresolved_threads(
+    threads: &[PlotThread],
+    markers: &HashMap<String, Marker>,
+    position: usize,
+) -> Vec<ThreadState> {
+    let mut open: Vec<(usize, ThreadState)> = threads
+        .iter()
+        .filter_map(|thread| {
+            let opened_at = thread.timeline(markers).first()?.position;
+            let state = thread.state_at(markers, position)?;
+            (state.status != ThreadStatus::Resolved).then_some((opened_at, state))
+        })
+        .collect();
+
+    open.sort_by_key(|(opened_at, _)| *opened_at);
+    open.into_iter().map(|(_, state)| state).collect()
+}
+
+/// Shift event positions through text edits. Linked events shift too: their markers'
+/// positions win, but the stored one is the fallback once the marker is gone.
+/// Events whose text was deleted move to the start of the edit.
+pub fn shift_positions(threads: &mut [PlotThread], edits: &[TextEdit]) {
+    for event in threads.iter_mut().flat_map(|t| t.events.iter_mut()) {
+        for edit in edits {
+            event.position = edit.map_leaf(event.position).unwrap_or(edit.from);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! QuestScribe - Plottr Timeline Export
+//!
+//! Writes a `.pltr` file Plottr can open, for writers who plot in Plottr but
+//! track state here. The file uses Plottr's 2020 layout (a flat chapter list
+//! and plain-text descriptions), which Plottr upgrades when it opens the file.
+//!
+//! - Every chapter becomes a chapter (beat) of a single book.
+//! - Every entity becomes a character (or a place, for locations) with its
+//!   state at the end of the document as custom attributes, and gets a
+//!   plotline with one card per chapter where its state changes. A card's
+//!   title is the chapter's marker descriptions; its description is the
+//!   chapter's changes as prose (see recap.rs).
+//! - Every plot thread becomes a plotline with one card per event.
+//!
+//! Importing the file back (see tool_import.rs) recreates the cast and threads.
+
+use crate::chapters::Chapter;
+use crate::engine;
+use crate::plot_threads::{PlotThread, ThreadStatus};
+use crate::recap;
+use crate::state::{Entity, EntityKind, Marker};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+
+const FILE_VERSION: &str = "2020.3.4";
+const BOOK_ID: u64 = 1;
+const THREAD_LINE_COLOR: &str = "#6CACE4";
+
+fn chapter_of(chapters: &[Chapter], position: usize) -> Option<usize> {
+    chapters.iter().position(|c| position >= c.start && position < c.end)
+}
+
+fn status_label(status: ThreadStatus) -> &'static str {
+    match status {
+        ThreadStatus::Open => "Opened",
+        ThreadStatus::Developed => "Developed",
+        ThreadStatus::Resolved => "Resolved",
+    }
+}
+
+fn push_card(
+    cards: &mut Vec<Value>,
+    line_id: usize,
+    chapter: usize,
+    within_line: usize,
+    title: String,
+    description: String,
+    character: Option<usize>,
+) {
+    cards.push(json!({
+        "id": cards.len() + 1,
+        "lineId": line_id,
+        "chapterId": chapter + 1,
+        "title": title,
+        "description": description,
+        "tags": [],
+        "characters": character.into_iter().collect::<Vec<_>>(),
+        "places": [],
+        "templates": [],
+        "imageId": null,
+        "fromTemplateId": null,
+        "positionWithinLine": within_line,
+        "positionInChapter": 0,
+    }));
+}
+
+/// Build the Plottr file for a document
+pub fn build_plottr_file(
+    title: &str,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    threads: &[PlotThread],
+    chapters: &[Chapter],
+    locale: &str,
+) -> Value {
+    let mut sorted: Vec<&Entity> = entities.values().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+
+    // Plottr IDs are small integers; chapter N has ID N + 1
+    let chapter_list: Vec<Value> = chapters
+        .iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            json!({
+                "id": index + 1,
+                "bookId": BOOK_ID,
+                "position": index,
+                "title": chapter.title,
+                "time": 0,
+                "autoOutlineSort": true,
+                "templates": [],
+            })
+        })
+        .collect();
+
+    let mut characters = Vec::new();
+    let mut places = Vec::new();
+    let mut character_attributes = BTreeSet::new();
+    let mut place_attributes = BTreeSet::new();
+    let mut lines = Vec::new();
+    let mut cards = Vec::new();
+
+    for (index, entity) in sorted.iter().enumerate() {
+        let sheet_id = index + 1;
+        let line_id = lines.len() + 1;
+
+        let mut end_state = recap::flat_values(&engine::compute_state_with_defaults(
+            markers.values().filter(|m| m.entity_id == entity.id),
+            &engine::field_defaults(entity),
+        ));
+        let mut sheet = json!({
+            "id": sheet_id,
+            "name": entity.name,
+            "description": "",
+            "notes": "",
+            "color": entity.color,
+            "cards": [],
+            "noteIds": [],
+            "templates": [],
+            "tags": [],
+            "categoryId": null,
+            "imageId": null,
+            "bookIds": [BOOK_ID],
+        });
+        // Attributes are stored next to Plottr's own keys, which win
+        end_state.retain(|field, _| sheet.get(field.as_str()).is_none());
+        for (field, value) in &end_state {
+            sheet[field.as_str()] = json!(value);
+        }
+
+        let character = match entity.kind {
+            EntityKind::Character => {
+                character_attributes.extend(end_state.keys().cloned());
+                characters.push(sheet);
+                Some(sheet_id)
+            }
+            EntityKind::Location => {
+                place_attributes.extend(end_state.keys().cloned());
+                places.push(sheet);
+                None
+            }
+        };
+
+        lines.push(json!({
+            "id": line_id,
+            "bookId": BOOK_ID,
+            "color": entity.color,
+            "title": entity.name,
+            "position": line_id - 1,
+            "characterId": character,
+            "expanded": null,
+            "fromTemplateId": null,
+        }));
+
+        let mut within_line = 0;
+        for (chapter_index, chapter) in chapters.iter().enumerate() {
+            let in_chapter: Vec<&Marker> = markers
+                .values()
+                .filter(|m| m.entity_id == entity.id && m.position >= chapter.start && m.position < chapter.end)
+                .collect();
+            if in_chapter.is_empty() {
+                continue;
+            }
+
+            let changes: Vec<_> = recap::generate_recap(entities, markers, chapter.start, chapter.end, locale)
+                .entities
+                .into_iter()
+                .filter(|r| r.entity_id == entity.id)
+                .collect();
+            let mut descriptions: Vec<&str> = in_chapter
+                .iter()
+                .map(|m| m.description.trim())
+                .filter(|d| !d.is_empty())
+                .collect();
+            descriptions.dedup();
+            let title = if descriptions.is_empty() { chapter.title.clone() } else { descriptions.join("; ") };
+
+            push_card(&mut cards, line_id, chapter_index, within_line, title, recap::render_prose(&changes, locale), character);
+            within_line += 1;
+        }
+    }
+
+    for thread in threads {
+        let line_id = lines.len() + 1;
+        lines.push(json!({
+            "id": line_id,
+            "bookId": BOOK_ID,
+            "color": THREAD_LINE_COLOR,
+            "title": thread.name,
+            "position": line_id - 1,
+            "characterId": null,
+            "expanded": null,
+            "fromTemplateId": null,
+        }));
+
+        for (within_line, event) in thread.timeline(markers).iter().enumerate() {
+            let position = event.effective_position(markers);
+            let Some(chapter) = chapter_of(chapters, position) else {
+                continue;
+            };
+            let title = match event.note.trim() {
+                "" => status_label(event.status).to_string(),
+                note => note.to_string(),
+            };
+            push_card(&mut cards, line_id, chapter, within_line, title, String::new(), None);
+        }
+    }
+
+    let attributes = |names: BTreeSet<String>| -> Vec<Value> {
+        names.into_iter().map(|name| json!({ "name": name, "type": "text" })).collect()
+    };
+
+    json!({
+        "file": { "fileName": format!("{}.pltr", title), "loaded": true, "dirty": false, "version": FILE_VERSION },
+        "series": { "name": title, "premise": "", "genre": "", "theme": "", "templates": [] },
+        "books": {
+            "allIds": [BOOK_ID],
+            "1": { "id": BOOK_ID, "title": title, "premise": "", "genre": "", "theme": "", "templates": [] },
+        },
+        "chapters": chapter_list,
+        "lines": lines,
+        "cards": cards,
+        "characters": characters,
+        "places": places,
+        "tags": [],
+        "notes": [],
+        "images": {},
+        "customAttributes": {
+            "characters": attributes(character_attributes),
+            "places": attributes(place_attributes),
+            "cards": [],
+            "scenes": [],
+            "lines": [],
+        },
+        "categories": { "characters": [], "places": [], "notes": [], "tags": [] },
+    })
+}
@@ -0,0 +1,141 @@
+//! QuestScribe - Document Position Model
+//!
+//! Every position exchanged between the frontend and the backend (marker positions,
+//! cursor positions, ranges) is a **ProseMirror document position**. ProseMirror counts:
+//!
+//! - one unit per UTF-16 code unit of text (an emoji like "⚔️" is several units)
+//! - one unit per leaf node (marker nodes, hard breaks, horizontal rules)
+//! - one unit for the opening and one for the closing token of every other node
+//!
+//! Rust strings are UTF-8 and iterate by `char`, so byte offsets and char indices
+//! never match these positions once the text leaves ASCII. Backend code must not use
+//! them as positions; convert with the helpers in this module instead.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Node types that are leaves in the editor schema (size 1, no content)
+pub const LEAF_NODE_TYPES: &[&str] = &["marker", "entity_mention", "hard_break", "horizontal_rule", "image"];
+
+/// Length of a string in UTF-16 code units (what JavaScript's `length` reports)
+pub fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+/// Convert a UTF-16 offset into a char index
+///
+/// Offsets pointing into the middle of a surrogate pair round down to the
+/// start of that character; offsets past the end clamp to the char count.
+pub fn utf16_to_char_index(text: &str, utf16_offset: usize) -> usize {
+    let mut units = 0;
+    for (index, ch) in text.chars().enumerate() {
+        units += ch.len_utf16();
+        if units > utf16_offset {
+            return index;
+        }
+    }
+    text.chars().count()
+}
+
+/// Convert a char index into a UTF-16 offset
+pub fn char_index_to_utf16(text: &str, char_index: usize) -> usize {
+    text.chars().take(char_index).map(char::len_utf16).sum()
+}
+
+fn node_type(node: &serde_json::Value) -> &str {
+    node.get("type").and_then(|t| t.as_str()).unwrap_or("")
+}
+
+/// Size of a ProseMirror node in document positions
+pub fn node_size(node: &serde_json::Value) -> usize {
+    let kind = node_type(node);
+
+    if kind == "text" {
+        return node.get("text").and_then(|t| t.as_str()).map(utf16_len).unwrap_or(0);
+    }
+    if LEAF_NODE_TYPES.contains(&kind) {
+        return 1;
+    }
+
+    2 + content_size(node)
+}
+
+/// Size of a node's content (for the doc node, this is the largest valid position)
+pub fn content_size(node: &serde_json::Value) -> usize {
+    node.get("content")
+        .and_then(|c| c.as_array())
+        .map(|children| children.iter().map(node_size).sum())
+        .unwrap_or(0)
+}
+
+/// Visit every descendant of the doc node with its document position,
+/// like ProseMirror's `doc.descendants((node, pos) => ...)`
+pub fn for_each_node<F>(doc: &serde_json::Value, mut visit: F)
+where
+    F: FnMut(&serde_json::Value, usize),
+{
+    fn walk<F: FnMut(&serde_json::Value, usize)>(parent: &serde_json::Value, start: usize, visit: &mut F) {
+        let mut pos = start;
+        if let Some(children) = parent.get("content").and_then(|c| c.as_array()) {
+            for child in children {
+                visit(child, pos);
+                if node_type(child) != "text" && !LEAF_NODE_TYPES.contains(&node_type(child)) {
+                    walk(child, pos + 1, visit);
+                }
+                pos += node_size(child);
+            }
+        }
+    }
+
+    walk(doc, 0, &mut visit);
+}
+
+/// Positions of all marker nodes embedded in the document, keyed by marker ID
+pub fn marker_node_positions(doc: &serde_json::Value) -> HashMap<String, usize> {
+    let mut positions = HashMap::new();
+
+    for_each_node(doc, |node, pos| {
+        if node_type(node) == "marker" {
+            if let Some(id) = node.get("attrs").and_then(|a| a.get("id")).and_then(|i| i.as_str()) {
+                positions.insert(id.to_string(), pos);
+            }
+        }
+    });
+
+    positions
+}
+
+/// A text edit in document positions: `from..to` was replaced by `inserted_len` positions
+///
+/// Edits in a batch are applied in order, each expressed in the coordinates of the
+/// document produced by the previous one (the same convention as ProseMirror steps).
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TextEdit {
+    pub from: usize,
+    pub to: usize,
+    pub inserted_len: usize,
+}
+
+impl TextEdit {
+    /// Check that the edit describes a valid range
+    pub fn validate(&self) -> Result<(), String> {
+        if self.from > self.to {
+            return Err(format!("Invalid edit range: {}..{}", self.from, self.to));
+        }
+        Ok(())
+    }
+
+    /// Map the position of a leaf node (such as a marker) through this edit
+    ///
+    /// Returns `None` when the node sat inside the replaced range and was deleted.
+    /// Content inserted exactly at a node's position goes before it, so the node shifts.
+    pub fn map_leaf(&self, pos: usize) -> Option<usize> {
+        if pos < self.from {
+            Some(pos)
+        } else if pos < self.to {
+            None
+        } else {
+            Some(pos - (self.to - self.from) + self.inserted_len)
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! QuestScribe - Per-Document Preferences
+//!
+//! Preferences saved inside the document, so a manuscript looks and behaves the
+//! same when reopened or shared with a co-author. Application-wide preferences
+//! (autosave, locale, ...) live in settings.rs instead.
+
+use crate::book_matter::BookMatter;
+use crate::sheets::{self, SheetLayout};
+use crate::status_window::StatusWindowStyle;
+use crate::structure::StructureTemplate;
+use serde::{Deserialize, Serialize};
+
+/// Units the story's measurements are written in (for the frontend's conversions)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MeasurementUnits {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarMonth {
+    pub name: String,
+    pub days: u32,
+}
+
+/// The story world's calendar (no months = the real-world Gregorian calendar)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalendarConfig {
+    pub months: Vec<CalendarMonth>,
+    pub weekdays: Vec<String>,
+    pub start_year: i64, // Year the story begins in
+    pub era: Option<String>, // Suffix for years (e.g., "AE")
+}
+
+/// Typography for exported manuscripts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportStyle {
+    pub font_family: String,
+    pub font_size_pt: u32, // Body text; headings are sized relative to it unless heading sizes are set
+    pub heading_sizes_pt: Option<Vec<u32>>, // Heading sizes from level 1 down; deeper levels use the last one
+    pub blank_line_between_paragraphs: bool,
+}
+
+impl Default for ExportStyle {
+    fn default() -> Self {
+        Self {
+            font_family: "Times New Roman".to_string(),
+            font_size_pt: 12,
+            heading_sizes_pt: None,
+            blank_line_between_paragraphs: true,
+        }
+    }
+}
+
+impl ExportStyle {
+    /// Body font size in half-points (the unit RTF and DOCX use)
+    pub fn body_half_points(&self) -> usize {
+        self.font_size_pt as usize * 2
+    }
+
+    /// Heading font size in half-points (level 1 is largest; no level is treated as level 6)
+    pub fn heading_half_points(&self, level: Option<u32>) -> usize {
+        let level = level.unwrap_or(6).clamp(1, 6) as usize;
+        if let Some(sizes) = self.heading_sizes_pt.as_ref().filter(|s| !s.is_empty()) {
+            return sizes[(level - 1).min(sizes.len() - 1)] as usize * 2;
+        }
+
+        // From body size +4pt at level 1 down to body size -3pt at level 6
+        let body = self.body_half_points();
+        match level {
+            1 => body + 8,
+            2 => body + 4,
+            3 => body,
+            _ => body.saturating_sub(2 * (level - 3)).max(2),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.font_family.trim().is_empty() {
+            return Err("Export font family cannot be empty".to_string());
+        }
+        if !(6..=72).contains(&self.font_size_pt) {
+            return Err("Export font size must be between 6 and 72 points".to_string());
+        }
+        if let Some(sizes) = &self.heading_sizes_pt {
+            if sizes.len() > 6 {
+                return Err("Heading sizes can be set for at most 6 levels".to_string());
+            }
+            if sizes.iter().any(|size| !(6..=72).contains(size)) {
+                return Err("Heading sizes must be between 6 and 72 points".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocumentPreferences {
+    pub title: Option<String>, // For export file names (see export_paths.rs); None = the file name
+    pub default_marker_icon: Option<String>, // Icon when no visual rule matches (None = ⭐)
+    pub units: MeasurementUnits,
+    pub calendar: CalendarConfig,
+    pub export_style: ExportStyle,
+    pub max_travel_speed: Option<f64>, // Distance units per story hour; None = a hard day's ride
+    pub chapter_pattern: Option<String>, // Regex matching chapter titles, for chapter detection (e.g., "Chapter \d+")
+    pub strict_mode: bool, // Make the state engine's silent coercions errors (see strict.rs)
+    pub suggestion_mode: bool, // The editor proposes edits instead of making them (see track_changes.rs)
+    pub structure_template: Option<StructureTemplate>, // Beats to check the manuscript against (see structure.rs); None = three acts
+    pub matter: BookMatter, // Front and back matter added to exports (see book_matter.rs)
+    pub sheet_layouts: Vec<SheetLayout>, // Character sheet layouts by entity kind (see sheets.rs)
+    pub status_window: StatusWindowStyle, // Look of status windows (see status_window.rs)
+}
+
+impl DocumentPreferences {
+    /// Fastest plausible travel, in distance units per story hour (see continuity.rs)
+    pub fn max_travel_speed(&self) -> f64 {
+        self.max_travel_speed.unwrap_or(match self.units {
+            MeasurementUnits::Metric => 15.0,
+            MeasurementUnits::Imperial => 10.0,
+        })
+    }
+
+    /// Abbreviation of the distance unit
+    pub fn distance_unit(&self) -> &'static str {
+        match self.units {
+            MeasurementUnits::Metric => "km",
+            MeasurementUnits::Imperial => "mi",
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.calendar.months.iter().any(|m| m.name.trim().is_empty() || m.days == 0) {
+            return Err("Calendar months need a name and at least one day".to_string());
+        }
+        self.export_style.validate()?;
+        if self.max_travel_speed.is_some_and(|speed| !(speed > 0.0 && speed.is_finite())) {
+            return Err("Maximum travel speed must be a positive number".to_string());
+        }
+        if self.title.as_ref().is_some_and(|t| t.trim().is_empty()) {
+            return Err("Title cannot be empty".to_string());
+        }
+        if let Some(pattern) = &self.chapter_pattern {
+            regex::Regex::new(pattern).map_err(|e| format!("Invalid chapter pattern: {}", e))?;
+        }
+        if let Some(template) = &self.structure_template {
+            template.validate()?;
+        }
+        self.matter.validate()?;
+        sheets::validate_layouts(&self.sheet_layouts)?;
+        self.status_window.validate()?;
+        Ok(())
+    }
+}
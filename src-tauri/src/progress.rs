@@ -0,0 +1,82 @@
+//! QuestScribe - Progress History
+//!
+//! Each save records a snapshot of the manuscript's size (words, markers,
+//! entities) and of how much tracking each entity has (markers and field
+//! changes). Snapshots are kept per calendar day, with the last save of a day
+//! replacing earlier ones, and are stored in the document so the history moves
+//! with the file. They feed charts of how the manuscript and its world grew.
+
+use crate::dates;
+use crate::state::{Entity, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityProgress {
+    pub entity_id: String,
+    pub name: String, // Kept so the history still reads after the entity is deleted
+    pub marker_count: usize,
+    pub change_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProgressSnapshot {
+    pub day: i64,     // Days since 1970-01-01 in the author's time zone
+    pub date: String, // ISO date of `day`
+    pub taken_at: i64,
+    pub word_count: usize,
+    pub marker_count: usize,
+    pub entity_count: usize,
+    pub entities: Vec<EntityProgress>, // Sorted by name
+}
+
+/// Snapshot the document as it is being saved
+pub fn take_snapshot(
+    word_count: usize,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    utc_offset_minutes: i32,
+    now: i64,
+) -> ProgressSnapshot {
+    let mut per_entity: Vec<EntityProgress> = entities
+        .values()
+        .map(|entity| {
+            let own: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity.id).collect();
+            EntityProgress {
+                entity_id: entity.id.clone(),
+                name: entity.name.clone(),
+                marker_count: own.len(),
+                change_count: own.iter().map(|m| m.changes.len()).sum(),
+            }
+        })
+        .collect();
+    per_entity.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.entity_id.cmp(&b.entity_id)));
+
+    let day = dates::day_number(now, utc_offset_minutes);
+    ProgressSnapshot {
+        day,
+        date: dates::format_day(day),
+        taken_at: now,
+        word_count,
+        marker_count: markers.len(),
+        entity_count: entities.len(),
+        entities: per_entity,
+    }
+}
+
+/// Add a snapshot to the history, replacing any earlier one from the same day
+pub fn record_snapshot(history: &mut Vec<ProgressSnapshot>, snapshot: ProgressSnapshot) {
+    history.retain(|s| s.day != snapshot.day);
+    history.push(snapshot);
+    history.sort_by_key(|s| s.day);
+}
+
+/// Snapshots from the last `days` days (all when None), oldest first
+pub fn history_since(history: &[ProgressSnapshot], days: Option<u32>, utc_offset_minutes: i32, now: i64) -> Vec<ProgressSnapshot> {
+    let today = dates::day_number(now, utc_offset_minutes);
+    history
+        .iter()
+        .filter(|s| days.is_none_or(|days| s.day > today - days as i64))
+        .cloned()
+        .collect()
+}
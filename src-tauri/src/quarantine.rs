@@ -0,0 +1,191 @@
+//! QuestScribe - Load Quarantine
+//!
+//! A document file with one bad entity or marker (hand-edited, written by a
+//! newer version, or damaged) still opens. Loading reads entities and markers
+//! one by one; any that can't be read or don't check out is set aside in the
+//! document's quarantine with the reason, instead of failing the whole load or
+//! being dropped:
+//!
+//! - entities and markers that don't parse (e.g., an unknown change type)
+//! - duplicate or empty IDs
+//! - markers of an entity that doesn't exist
+//! - custom changes without a type, or of a type the document doesn't define
+//!   and without the reducer to compute it
+//!
+//! Quarantined items keep their original JSON and are saved with the document,
+//! so nothing is lost until the author discards them. An item can be restored
+//! as it is (after restoring the entity it needs, say) or with corrected JSON.
+
+use crate::change_types::CustomChangeType;
+use crate::ids;
+use crate::legacy;
+use crate::state::{ChangeType, Document, Entity, Marker};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuarantineKind {
+    Entity,
+    Marker,
+}
+
+/// An entity or marker set aside on load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedItem {
+    pub id: String, // ID of the quarantine entry
+    pub kind: QuarantineKind,
+    #[serde(default)]
+    pub item_id: Option<String>, // The entity or marker's own ID, if it has one
+    pub reason: String,
+    pub data: Value, // The item as it was in the file
+}
+
+/// An entity or marker taken out of quarantine
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum RestoredItem {
+    Entity(Entity),
+    Marker(Marker),
+}
+
+fn item_id_of(data: &Value) -> Option<String> {
+    data.get("id").and_then(|id| id.as_str()).map(str::to_string)
+}
+
+fn quarantined(kind: QuarantineKind, data: Value, reason: String) -> QuarantinedItem {
+    QuarantinedItem { id: ids::new_id(), kind, item_id: item_id_of(&data), reason, data }
+}
+
+/// Check an entity against the ones already accepted
+pub fn check_entity(data: &Value, entities: &HashMap<String, Entity>) -> Result<Entity, String> {
+    let entity: Entity = serde_json::from_value(data.clone()).map_err(|e| format!("Unreadable entity: {}", e))?;
+    if entity.id.trim().is_empty() {
+        return Err("Entity has no ID".to_string());
+    }
+    if entities.contains_key(&entity.id) {
+        return Err(format!("Another entity has the ID {}", entity.id));
+    }
+    Ok(entity)
+}
+
+/// Check a marker against the accepted entities and markers and the document's change types
+pub fn check_marker(
+    data: &Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    change_types: &[CustomChangeType],
+) -> Result<Marker, String> {
+    let marker: Marker = serde_json::from_value(data.clone()).map_err(|e| format!("Unreadable marker: {}", e))?;
+    if marker.id.trim().is_empty() {
+        return Err("Marker has no ID".to_string());
+    }
+    if markers.contains_key(&marker.id) {
+        return Err(format!("Another marker has the ID {}", marker.id));
+    }
+    if !entities.contains_key(&marker.entity_id) {
+        return Err(format!("Marker belongs to an entity that doesn't exist ({})", marker.entity_id));
+    }
+
+    let all_changes = marker.changes.iter().chain(marker.outcomes.iter().flat_map(|o| &o.changes));
+    for change in all_changes.filter(|c| c.change_type == ChangeType::Custom) {
+        let Some(custom) = &change.custom else {
+            return Err(format!("Custom change to {} has no change type", change.field_name));
+        };
+        let defined = change_types.iter().any(|t| t.name.trim() == custom.name.trim());
+        if !defined && custom.reducer.trim().is_empty() {
+            return Err(format!("Unknown change type \"{}\" for {}", custom.name, change.field_name));
+        }
+    }
+
+    Ok(marker)
+}
+
+/// Read a document file, quarantining the entities and markers that don't check out.
+/// The returned document's quarantine holds the new items after the ones it was saved with.
+pub fn read_document(json: &str) -> Result<Document, String> {
+    let mut value: Value = serde_json::from_str(json).map_err(|e| format!("Failed to parse document: {}", e))?;
+
+    // Files from before format versions go through the legacy reader first
+    let from_version = legacy::file_version(&value);
+    let legacy_notes = (from_version < legacy::FORMAT_VERSION).then(|| legacy::normalize(&mut value));
+
+    // Read the rest of the document without the entities and markers, then check those one by one
+    let take = |value: &mut Value, key: &str| match value.get_mut(key) {
+        Some(Value::Array(items)) => Ok(std::mem::take(items)),
+        Some(_) => Err(format!("Failed to parse document: \"{}\" is not a list", key)),
+        None => Err(format!("Failed to parse document: missing field `{}`", key)),
+    };
+    let entity_data = take(&mut value, "entities")?;
+    let marker_data = take(&mut value, "markers")?;
+    let mut document: Document =
+        serde_json::from_value(value).map_err(|e| format!("Failed to parse document: {}", e))?;
+
+    let mut entities: HashMap<String, Entity> = HashMap::new();
+    for data in entity_data {
+        match check_entity(&data, &entities) {
+            Ok(entity) => {
+                document.entities.push(entity.clone());
+                entities.insert(entity.id.clone(), entity);
+            }
+            Err(reason) => document.quarantine.push(quarantined(QuarantineKind::Entity, data, reason)),
+        }
+    }
+
+    let mut markers: HashMap<String, Marker> = HashMap::new();
+    for data in marker_data {
+        match check_marker(&data, &entities, &markers, &document.change_types) {
+            Ok(marker) => {
+                document.markers.push(marker.clone());
+                markers.insert(marker.id.clone(), marker);
+            }
+            Err(reason) => document.quarantine.push(quarantined(QuarantineKind::Marker, data, reason)),
+        }
+    }
+
+    if let Some(notes) = legacy_notes {
+        legacy::complete(&mut document, from_version, notes);
+    }
+    Ok(document)
+}
+
+/// Take an item out of quarantine into the document, optionally with corrected JSON.
+/// The item stays quarantined (with the new data and reason) if it still doesn't check out.
+pub fn restore(
+    quarantine: &mut Vec<QuarantinedItem>,
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    change_types: &[CustomChangeType],
+    entry_id: &str,
+    data: Option<Value>,
+) -> Result<RestoredItem, String> {
+    let index = quarantine
+        .iter()
+        .position(|item| item.id == entry_id)
+        .ok_or("Quarantined item not found")?;
+    let item = &mut quarantine[index];
+    if let Some(data) = data {
+        item.item_id = item_id_of(&data);
+        item.data = data;
+    }
+
+    let checked = match item.kind {
+        QuarantineKind::Entity => check_entity(&item.data, entities).map(RestoredItem::Entity),
+        QuarantineKind::Marker => {
+            check_marker(&item.data, entities, markers, change_types).map(RestoredItem::Marker)
+        }
+    };
+    let restored = checked.inspect_err(|reason| item.reason = reason.clone())?;
+
+    match &restored {
+        RestoredItem::Entity(entity) => {
+            entities.insert(entity.id.clone(), entity.clone());
+        }
+        RestoredItem::Marker(marker) => {
+            markers.insert(marker.id.clone(), marker.clone());
+        }
+    }
+    quarantine.remove(index);
+    Ok(restored)
+}
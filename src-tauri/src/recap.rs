@@ -0,0 +1,293 @@
+//! QuestScribe - Chapter Recaps
+//!
+//! Summarizes what changed for each entity between two document positions, for
+//! "previously on..." sections in serialized fiction. The recap compares each
+//! entity's state before the range with its state at the end of the range, so
+//! changes that cancel out (a potion gained and drunk in the same chapter) are
+//! left out.
+//!
+//! Fields under `inventory` are reported as items gained or lost, fields whose
+//! name contains "level" as level changes, and everything else as notable changes.
+
+use crate::engine::{self, EntityState};
+use crate::i18n;
+use crate::llm::{self, TextProvider};
+use crate::state::{Entity, FieldChange, Marker};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// A field whose value differs between the start and end of the range
+#[derive(Debug, Clone, Serialize)]
+pub struct RecapValueChange {
+    pub field: String,
+    pub before: Option<String>, // None = field didn't exist yet
+    pub after: Option<String>,  // None = field was removed
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecapItem {
+    pub name: String, // Path below "inventory" (e.g., "Healing Potion")
+    pub quantity_change: Option<f64>, // Set when the quantities are numeric
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityRecap {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub marker_count: usize,
+    pub level_changes: Vec<RecapValueChange>,
+    pub items_gained: Vec<RecapItem>,
+    pub items_lost: Vec<RecapItem>,
+    pub notable_changes: Vec<RecapValueChange>,
+}
+
+/// Where a recap's prose came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProseSource {
+    Template,
+    Llm,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Recap {
+    pub from_position: usize,
+    pub to_position: usize,
+    pub entities: Vec<EntityRecap>,
+    pub prose: String,
+    pub prose_source: ProseSource,
+    pub llm_error: Option<String>, // Why the provider's prose wasn't used (template prose was kept)
+}
+
+/// Flatten a computed state into field path -> display value
+pub fn flat_values(state: &EntityState) -> BTreeMap<String, String> {
+    let mut changes: Vec<FieldChange> = Vec::new();
+    engine::flatten_state_to_changes(state, String::new(), &mut changes);
+
+    changes
+        .into_iter()
+        .map(|c| (c.field_name, format_value(&c.value)))
+        .collect()
+}
+
+/// Show whole numbers without the ".0" the engine's f64 values carry
+pub fn format_value(value: &str) -> String {
+    match value.parse::<f64>() {
+        Ok(num) if num.fract() == 0.0 && num.abs() < 1e15 => format!("{}", num as i64),
+        _ => value.to_string(),
+    }
+}
+
+pub fn is_level_field(field: &str) -> bool {
+    field
+        .rsplit('.')
+        .next()
+        .is_some_and(|name| name.to_lowercase().contains("level"))
+}
+
+fn inventory_item(field: &str) -> Option<&str> {
+    let (group, item) = field.split_once('.')?;
+    group.eq_ignore_ascii_case("inventory").then_some(item)
+}
+
+fn entity_recap(
+    entity_id: &str,
+    entity_name: &str,
+    markers: &[&Marker],
+    defaults: &engine::FieldDefaults,
+    from: usize,
+    to: usize,
+) -> EntityRecap {
+    let state_before = |end: usize| {
+        engine::compute_state_with_defaults(markers.iter().copied().filter(|m| m.position < end), defaults)
+    };
+    let before = flat_values(&state_before(from));
+    let after = flat_values(&state_before(to));
+
+    let mut recap = EntityRecap {
+        entity_id: entity_id.to_string(),
+        entity_name: entity_name.to_string(),
+        marker_count: markers.iter().filter(|m| m.position >= from && m.position < to).count(),
+        level_changes: Vec::new(),
+        items_gained: Vec::new(),
+        items_lost: Vec::new(),
+        notable_changes: Vec::new(),
+    };
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    for field in fields {
+        let old = before.get(field);
+        let new = after.get(field);
+        if old == new {
+            continue;
+        }
+
+        if let Some(item) = inventory_item(field) {
+            let quantity_change = match (old.map(|v| v.parse::<f64>()), new.map(|v| v.parse::<f64>())) {
+                (Some(Ok(a)), Some(Ok(b))) => Some(b - a),
+                (None, Some(Ok(b))) => Some(b),
+                (Some(Ok(a)), None) => Some(-a),
+                _ => None,
+            };
+            let entry = RecapItem { name: item.to_string(), quantity_change };
+
+            match (old, new, quantity_change) {
+                (_, _, Some(delta)) if delta > 0.0 => recap.items_gained.push(entry),
+                (_, _, Some(delta)) if delta < 0.0 => recap.items_lost.push(entry),
+                (None, Some(_), _) => recap.items_gained.push(entry),
+                (Some(_), None, _) => recap.items_lost.push(entry),
+                _ => recap.notable_changes.push(RecapValueChange {
+                    field: field.clone(),
+                    before: old.cloned(),
+                    after: new.cloned(),
+                }),
+            }
+            continue;
+        }
+
+        let change = RecapValueChange {
+            field: field.clone(),
+            before: old.cloned(),
+            after: new.cloned(),
+        };
+        if is_level_field(field) {
+            recap.level_changes.push(change);
+        } else {
+            recap.notable_changes.push(change);
+        }
+    }
+
+    recap
+}
+
+impl EntityRecap {
+    fn is_empty(&self) -> bool {
+        self.level_changes.is_empty()
+            && self.items_gained.is_empty()
+            && self.items_lost.is_empty()
+            && self.notable_changes.is_empty()
+    }
+}
+
+/// Build the recap of everything that happened in `from..to`
+///
+/// Entities are listed in the order they first appear in the range; entities
+/// whose state ends up unchanged are omitted.
+pub fn generate_recap(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    from: usize,
+    to: usize,
+    locale: &str,
+) -> Recap {
+    let mut by_entity: HashMap<&str, Vec<&Marker>> = HashMap::new();
+    for marker in markers.values() {
+        by_entity.entry(marker.entity_id.as_str()).or_default().push(marker);
+    }
+
+    // Order entities by their first marker inside the range
+    let mut order: Vec<(usize, &str)> = by_entity
+        .iter()
+        .filter_map(|(entity_id, list)| {
+            list.iter()
+                .filter(|m| m.position >= from && m.position < to)
+                .map(|m| m.position)
+                .min()
+                .map(|first| (first, *entity_id))
+        })
+        .collect();
+    order.sort();
+
+    let recaps: Vec<EntityRecap> = order
+        .into_iter()
+        .filter_map(|(_, entity_id)| {
+            let entity = entities.get(entity_id);
+            let name = entity.map(|e| e.name.as_str()).unwrap_or(entity_id);
+            let defaults = entity.map(engine::field_defaults).unwrap_or_default();
+            let recap = entity_recap(entity_id, name, &by_entity[entity_id], &defaults, from, to);
+            (!recap.is_empty()).then_some(recap)
+        })
+        .collect();
+
+    Recap {
+        from_position: from,
+        to_position: to,
+        prose: render_prose(&recaps, locale),
+        entities: recaps,
+        prose_source: ProseSource::Template,
+        llm_error: None,
+    }
+}
+
+/// Replace the template prose with prose from a language model, keeping the
+/// template prose (and recording the error) if the provider fails
+pub fn narrate(recap: &mut Recap, provider: &dyn TextProvider, locale: &str) {
+    if recap.entities.is_empty() {
+        return;
+    }
+
+    let changes = serde_json::to_value(&recap.entities).unwrap_or_default();
+    let prompt = llm::summary_prompt("\"previously on...\" recap", &changes, i18n::language_name(locale));
+
+    match provider.complete(&prompt) {
+        Ok(text) if !text.is_empty() => {
+            recap.prose = text;
+            recap.prose_source = ProseSource::Llm;
+        }
+        Ok(_) => recap.llm_error = Some("Summarization response had no text".to_string()),
+        Err(e) => recap.llm_error = Some(e),
+    }
+}
+
+fn format_item(item: &RecapItem) -> String {
+    match item.quantity_change {
+        Some(delta) if delta.abs() != 1.0 => format!("{} ×{}", item.name, format_value(&delta.abs().to_string())),
+        _ => item.name.clone(),
+    }
+}
+
+/// Render a recap as plain prose, one paragraph per entity
+pub fn render_prose(recaps: &[EntityRecap], locale: &str) -> String {
+    if recaps.is_empty() {
+        return i18n::tr(locale, "recap.nothing", &[]);
+    }
+
+    let mut paragraphs = Vec::new();
+    for recap in recaps {
+        let name = recap.entity_name.as_str();
+        let mut sentences = Vec::new();
+
+        for change in recap.level_changes.iter().chain(&recap.notable_changes) {
+            let sentence = match (&change.before, &change.after) {
+                (Some(before), Some(after)) => i18n::tr(
+                    locale,
+                    "recap.changed",
+                    &[("name", name), ("field", &change.field), ("before", before), ("after", after)],
+                ),
+                (None, Some(after)) => i18n::tr(
+                    locale,
+                    "recap.set",
+                    &[("name", name), ("field", &change.field), ("after", after)],
+                ),
+                _ => i18n::tr(locale, "recap.removed", &[("name", name), ("field", &change.field)]),
+            };
+            sentences.push(sentence);
+        }
+
+        if !recap.items_gained.is_empty() {
+            let items: Vec<String> = recap.items_gained.iter().map(format_item).collect();
+            sentences.push(i18n::tr(locale, "recap.gained", &[("name", name), ("items", &items.join(", "))]));
+        }
+        if !recap.items_lost.is_empty() {
+            let items: Vec<String> = recap.items_lost.iter().map(format_item).collect();
+            sentences.push(i18n::tr(locale, "recap.lost", &[("name", name), ("items", &items.join(", "))]));
+        }
+
+        paragraphs.push(sentences.join(" "));
+    }
+
+    paragraphs.join("\n\n")
+}
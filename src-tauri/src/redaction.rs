@@ -0,0 +1,169 @@
+//! QuestScribe - Spoiler Redaction
+//!
+//! Produces beta-reader-safe copies of a document. Redacted markers are removed
+//! from the marker list and from the ProseMirror content, and everything derived
+//! from them goes too: entity field lists are rebuilt from the remaining markers,
+//! and entities that only appeared in redacted markers are dropped, so a secret
+//! character or a not-yet-found artifact can't leak through the entity panel.
+//! Plot thread events linked to redacted markers are removed the same way.
+
+use crate::plot_threads::{self, PlotThread, ThreadStatus};
+use crate::positions::{self, TextEdit};
+use crate::state::{Entity, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+fn default_spoiler_tags() -> Vec<String> {
+    vec!["spoiler".to_string()]
+}
+
+/// Which markers to redact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionOptions {
+    #[serde(default = "default_spoiler_tags")]
+    pub spoiler_tags: Vec<String>, // Markers with any of these tags (case-insensitive)
+    #[serde(default)]
+    pub after_position: Option<usize>, // Also redact every marker at or after this position
+}
+
+impl Default for RedactionOptions {
+    fn default() -> Self {
+        Self {
+            spoiler_tags: default_spoiler_tags(),
+            after_position: None,
+        }
+    }
+}
+
+impl RedactionOptions {
+    pub fn is_redacted(&self, marker: &Marker) -> bool {
+        if self.after_position.is_some_and(|pos| marker.position >= pos) {
+            return true;
+        }
+
+        marker.tags.iter().any(|tag| {
+            self.spoiler_tags
+                .iter()
+                .any(|spoiler| spoiler.eq_ignore_ascii_case(tag))
+        })
+    }
+}
+
+/// Entities and markers that survive redaction
+pub struct Redacted {
+    pub entities: HashMap<String, Entity>,
+    pub markers: HashMap<String, Marker>,
+}
+
+/// Remove redacted markers and the entity data derived from them
+pub fn redact(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    options: &RedactionOptions,
+) -> Redacted {
+    let kept: HashMap<String, Marker> = markers
+        .iter()
+        .filter(|(_, marker)| !options.is_redacted(marker))
+        .map(|(id, marker)| (id.clone(), marker.clone()))
+        .collect();
+
+    let had_markers: HashSet<&str> = markers.values().map(|m| m.entity_id.as_str()).collect();
+    let has_markers: HashSet<&str> = kept.values().map(|m| m.entity_id.as_str()).collect();
+
+    let mut kept_entities = HashMap::new();
+    for (id, entity) in entities {
+        // Entities without any markers (e.g., just created) are kept as they are
+        if !had_markers.contains(id.as_str()) {
+            kept_entities.insert(id.clone(), entity.clone());
+            continue;
+        }
+
+        // An entity that only appears in redacted markers is itself a spoiler
+        if !has_markers.contains(id.as_str()) {
+            continue;
+        }
+
+        let fields: HashSet<&str> = kept
+            .values()
+            .filter(|m| &m.entity_id == id)
+            .flat_map(|m| m.changes.iter().map(|c| c.field_name.as_str()))
+            .collect();
+
+        let mut entity = entity.clone();
+        entity.fields.retain(|f| fields.contains(f.as_str()));
+        entity.field_metadata.retain(|f, _| fields.contains(f.as_str()));
+        kept_entities.insert(id.clone(), entity);
+    }
+
+    Redacted {
+        entities: kept_entities,
+        markers: kept,
+    }
+}
+
+/// Remove marker nodes that aren't in `keep` from ProseMirror content
+pub fn strip_marker_nodes(node: &mut serde_json::Value, keep: &HashMap<String, Marker>) {
+    if let Some(children) = node.get_mut("content").and_then(|c| c.as_array_mut()) {
+        children.retain(|child| {
+            if child.get("type").and_then(|t| t.as_str()) != Some("marker") {
+                return true;
+            }
+            child
+                .get("attrs")
+                .and_then(|a| a.get("id"))
+                .and_then(|id| id.as_str())
+                .is_some_and(|id| keep.contains_key(id))
+        });
+
+        for child in children.iter_mut() {
+            strip_marker_nodes(child, keep);
+        }
+    }
+}
+
+/// Remove plot thread events that would give redacted markers away
+///
+/// Events linked to a redacted marker go, as do events at or after `after_position`.
+/// A thread whose opening event is gone is left out entirely. `doc` and `markers`
+/// are the content and markers before redaction; unlinked events are shifted for
+/// the marker nodes removed from the content.
+pub fn redact_threads(
+    threads: &[PlotThread],
+    doc: &serde_json::Value,
+    markers: &HashMap<String, Marker>,
+    kept: &HashMap<String, Marker>,
+    options: &RedactionOptions,
+) -> Vec<PlotThread> {
+    let mut redacted: Vec<PlotThread> = threads
+        .iter()
+        .filter_map(|thread| {
+            let mut thread = thread.clone();
+            thread.events.retain(|event| {
+                let linked_redacted = event
+                    .marker_id
+                    .as_ref()
+                    .is_some_and(|id| markers.contains_key(id) && !kept.contains_key(id));
+                let position = event.effective_position(markers);
+                !linked_redacted && options.after_position.is_none_or(|pos| position < pos)
+            });
+
+            let opens = thread.timeline(markers).first().map(|e| e.status) == Some(ThreadStatus::Open);
+            opens.then_some(thread)
+        })
+        .collect();
+
+    // One-position deletions, back to front so each is valid in the previous edit's coordinates
+    let mut removed: Vec<usize> = positions::marker_node_positions(doc)
+        .into_iter()
+        .filter(|(id, _)| !kept.contains_key(id))
+        .map(|(_, pos)| pos)
+        .collect();
+    removed.sort_unstable_by(|a, b| b.cmp(a));
+    let edits: Vec<TextEdit> = removed
+        .into_iter()
+        .map(|pos| TextEdit { from: pos, to: pos + 1, inserted_len: 0 })
+        .collect();
+    plot_threads::shift_positions(&mut redacted, &edits);
+
+    redacted
+}
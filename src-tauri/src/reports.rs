@@ -0,0 +1,241 @@
+//! QuestScribe - Progression Reports
+//!
+//! Summaries of how entities change over the course of the document, grouped
+//! by chapter (or over the whole document), for pacing reviews and spreadsheets.
+
+use crate::chapters::Chapter;
+use crate::csv;
+use crate::engine::{self, EntityState};
+use crate::i18n;
+use crate::state::{Entity, Marker};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// One entity's activity within one group (chapter or whole document)
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeReportRow {
+    pub group: String, // Chapter title, or the "whole document" label
+    pub group_index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub entity_id: String,
+    pub entity_name: String,
+    pub marker_count: usize,
+    pub change_count: usize,
+    pub fields_touched: Vec<String>,
+    pub net_deltas: BTreeMap<String, f64>, // Numeric fields: value at group end minus value at group start
+}
+
+/// Build report rows for every entity with markers in each group, ordered by group then entity name
+pub fn change_report(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    groups: &[Chapter],
+) -> Vec<ChangeReportRow> {
+    let mut sorted_entities: Vec<&Entity> = entities.values().collect();
+    sorted_entities.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut rows = Vec::new();
+
+    for group in groups {
+        for entity in &sorted_entities {
+            let in_group: Vec<&Marker> = markers
+                .values()
+                .filter(|m| m.entity_id == entity.id && m.position >= group.start && m.position < group.end)
+                .collect();
+
+            if in_group.is_empty() {
+                continue;
+            }
+
+            let fields_touched: BTreeSet<String> = in_group
+                .iter()
+                .flat_map(|m| m.changes.iter().map(|c| c.field_name.clone()))
+                .collect();
+
+            // State just before and at the end of the group
+            let defaults = engine::field_defaults(entity);
+            let before = engine::compute_state_with_defaults(
+                markers.values().filter(|m| m.entity_id == entity.id && m.position < group.start),
+                &defaults,
+            );
+            let after = engine::compute_state_with_defaults(
+                markers.values().filter(|m| m.entity_id == entity.id && m.position < group.end),
+                &defaults,
+            );
+
+            let mut net_deltas = BTreeMap::new();
+            for field in &fields_touched {
+                let end_value = engine::get_nested_value(&after, field).and_then(|v| v.as_f64());
+                let start_value = engine::get_nested_value(&before, field).and_then(|v| v.as_f64());
+                if let Some(end_value) = end_value {
+                    net_deltas.insert(field.clone(), end_value - start_value.unwrap_or(0.0));
+                }
+            }
+
+            rows.push(ChangeReportRow {
+                group: group.title.clone(),
+                group_index: group.index,
+                start: group.start,
+                end: group.end,
+                entity_id: entity.id.clone(),
+                entity_name: entity.name.clone(),
+                marker_count: in_group.len(),
+                change_count: in_group.iter().map(|m| m.changes.len()).sum(),
+                fields_touched: fields_touched.into_iter().collect(),
+                net_deltas,
+            });
+        }
+    }
+
+    rows
+}
+
+// Format a delta with an explicit sign ("+5", "-2.5")
+fn format_delta(delta: f64) -> String {
+    if delta >= 0.0 {
+        format!("+{}", delta)
+    } else {
+        delta.to_string()
+    }
+}
+
+/// Render report rows as CSV with localized column headers
+pub fn change_report_csv(rows: &[ChangeReportRow], locale: &str) -> String {
+    let header: Vec<String> = [
+        "report.col.chapter",
+        "report.col.start",
+        "report.col.end",
+        "report.col.entity",
+        "report.col.markers",
+        "report.col.changes",
+        "report.col.fields",
+        "report.col.deltas",
+    ]
+    .iter()
+    .map(|key| i18n::tr(locale, key, &[]))
+    .collect();
+
+    let mut output = csv::format_row(&header);
+
+    for row in rows {
+        let deltas = row
+            .net_deltas
+            .iter()
+            .map(|(field, delta)| format!("{}: {}", field, format_delta(*delta)))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        output.push_str(&csv::format_row(&[
+            row.group.clone(),
+            row.start.to_string(),
+            row.end.to_string(),
+            row.entity_name.clone(),
+            row.marker_count.to_string(),
+            row.change_count.to_string(),
+            row.fields_touched.join("; "),
+            deltas,
+        ]));
+    }
+
+    output
+}
+
+/// One column of the state matrix: entity state as of a chapter's end
+#[derive(Debug, Clone, Serialize)]
+pub struct MatrixColumn {
+    pub label: String, // Chapter title, or the position when the end isn't a chapter's
+    pub end: usize,    // Markers before this position are applied
+}
+
+/// One entity's fields × columns grid
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityMatrix {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub fields: Vec<String>,
+    pub values: Vec<Vec<Option<String>>>, // values[field][column]; None where the field isn't set
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StateMatrix {
+    pub columns: Vec<MatrixColumn>,
+    pub entities: Vec<EntityMatrix>,
+}
+
+/// Columns at the given positions, labeled with the chapter that ends there when there is one
+pub fn matrix_columns(ends: &[usize], chapters: &[Chapter]) -> Vec<MatrixColumn> {
+    ends.iter()
+        .map(|&end| MatrixColumn {
+            label: chapters
+                .iter()
+                .find(|c| c.end == end)
+                .map(|c| c.title.clone())
+                .unwrap_or_else(|| end.to_string()),
+            end,
+        })
+        .collect()
+}
+
+/// Every leaf field of each entity at the end of each column, in one pass per entity
+pub fn state_matrix(entities: &[&Entity], markers: &HashMap<String, Marker>, columns: Vec<MatrixColumn>) -> StateMatrix {
+    let entities = entities
+        .iter()
+        .map(|entity| {
+            let defaults = engine::field_defaults(entity);
+            let mut own: Vec<&Marker> = markers.values().filter(|m| m.entity_id == entity.id && !m.todo).collect();
+            own.sort_by(|a, b| engine::compare_markers(a, b));
+
+            // Replay the markers once, taking a snapshot at each column end in order of position
+            let mut by_end: Vec<usize> = (0..columns.len()).collect();
+            by_end.sort_by_key(|&index| columns[index].end);
+
+            let mut snapshots: Vec<BTreeMap<String, String>> = vec![BTreeMap::new(); columns.len()];
+            let mut state = EntityState::new();
+            let mut pending = own.into_iter().peekable();
+            for index in by_end {
+                while let Some(marker) = pending.next_if(|m| m.position < columns[index].end) {
+                    for change in &marker.changes {
+                        engine::apply_change(&mut state, change, &defaults);
+                    }
+                }
+                let mut changes = Vec::new();
+                engine::flatten_state_to_changes(&state, String::new(), &mut changes);
+                snapshots[index] = changes.into_iter().map(|c| (c.field_name, c.value)).collect();
+            }
+
+            let fields: BTreeSet<String> = snapshots.iter().flat_map(|s| s.keys().cloned()).collect();
+            let values = fields
+                .iter()
+                .map(|field| snapshots.iter().map(|s| s.get(field).cloned()).collect())
+                .collect();
+
+            EntityMatrix {
+                entity_id: entity.id.clone(),
+                entity_name: entity.name.clone(),
+                fields: fields.into_iter().collect(),
+                values,
+            }
+        })
+        .collect();
+
+    StateMatrix { columns, entities }
+}
+
+/// Render the state matrix as CSV: one row per entity field, one column per chapter end
+pub fn state_matrix_csv(matrix: &StateMatrix, locale: &str) -> String {
+    let mut header = vec![i18n::tr(locale, "report.col.entity", &[]), i18n::tr(locale, "report.col.field", &[])];
+    header.extend(matrix.columns.iter().map(|c| c.label.clone()));
+
+    let mut output = csv::format_row(&header);
+
+    for entity in &matrix.entities {
+        for (field, values) in entity.fields.iter().zip(&entity.values) {
+            let mut row = vec![entity.entity_name.clone(), field.clone()];
+            row.extend(values.iter().map(|v| v.clone().unwrap_or_default()));
+            output.push_str(&csv::format_row(&row));
+        }
+    }
+
+    output
+}
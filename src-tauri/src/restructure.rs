@@ -0,0 +1,457 @@
+//! QuestScribe - Marker Restructuring
+//!
+//! Operations that reshape existing markers rather than edit one at a time:
+//! splitting a marker's changes into a second marker, merging markers that
+//! share an entity and position, and copying or reassigning markers to another
+//! entity.
+//! Markers created here are returned to the frontend, which still has to place
+//! their nodes in the text (and remove the nodes of markers merged away).
+
+use crate::arcs;
+use crate::change_types;
+use crate::dates;
+use crate::engine;
+use crate::ids;
+use crate::knowledge;
+use crate::mutations::{self, MutationContext, NewMarker};
+use crate::state::{ChangeType, Entity, FieldChange, Marker, MarkerVisual};
+use crate::strict;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// A marker and the new marker split off from it
+#[derive(Debug, Clone, Serialize)]
+pub struct SplitResult {
+    pub original: Marker,
+    pub created: Marker,
+}
+
+/// Move some of a marker's changes into a new marker for the same entity
+///
+/// The new marker copies the original's visual, description, tags, and story time.
+/// At the original's position it's applied right after the original, so state
+/// doesn't change; at another position it goes after the markers already there.
+pub fn split_marker(
+    markers: &mut HashMap<String, Marker>,
+    marker_id: &str,
+    change_indices: &[usize],
+    new_position: Option<usize>,
+) -> Result<SplitResult, String> {
+    let original = markers.get(marker_id).ok_or("Marker not found")?.clone();
+
+    let selected: HashSet<usize> = change_indices.iter().copied().collect();
+    if selected.is_empty() {
+        return Err("Select at least one change to split off".to_string());
+    }
+    if let Some(index) = selected.iter().find(|&&i| i >= original.changes.len()) {
+        return Err(format!("Change index {} is out of range", index));
+    }
+    if selected.len() == original.changes.len() {
+        return Err("A marker can't be split into an empty marker; move it instead".to_string());
+    }
+
+    let (moved, kept): (Vec<_>, Vec<_>) = original
+        .changes
+        .iter()
+        .cloned()
+        .enumerate()
+        .partition(|(index, _)| selected.contains(index));
+
+    let position = new_position.unwrap_or(original.position);
+    let sequence = if position == original.position {
+        // Renumber the markers at the position, leaving a gap directly after the original
+        let mut ordered: Vec<&Marker> = markers.values().filter(|m| m.position == position).collect();
+        ordered.sort_by(|a, b| engine::compare_markers(a, b));
+        let order: Vec<String> = ordered.into_iter().map(|m| m.id.clone()).collect();
+
+        let mut gap = 0;
+        for (index, id) in order.iter().enumerate() {
+            let offset = if gap > 0 { 1 } else { 0 };
+            if let Some(marker) = markers.get_mut(id) {
+                marker.sequence = (index + offset) as u32;
+            }
+            if *id == original.id {
+                gap = index + 1;
+            }
+        }
+        gap as u32
+    } else {
+        mutations::next_sequence(markers, position)
+    };
+
+    let now = dates::now();
+    let created = Marker {
+        id: ids::new_id(),
+        position,
+        sequence,
+        changes: moved.into_iter().map(|(_, change)| change).collect(),
+        created_at: now,
+        modified_at: now,
+        ..original.clone()
+    };
+
+    let marker = markers.get_mut(marker_id).ok_or("Marker not found")?;
+    marker.changes = kept.into_iter().map(|(_, change)| change).collect();
+    marker.modified_at = now;
+    let original = marker.clone();
+
+    markers.insert(created.id.clone(), created.clone());
+
+    Ok(SplitResult { original, created })
+}
+
+/// How changes to the same field are resolved when markers are merged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    #[default]
+    Combine,   // One change with the same effect (e.g., "HP -3" then "HP -2" becomes "HP -5")
+    KeepFirst, // The change applied first
+    KeepLast,  // The change applied last
+}
+
+/// The merged marker and the markers merged into it (now deleted)
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeResult {
+    pub marker: Marker,
+    pub removed_marker_ids: Vec<String>,
+}
+
+/// Markers that could be merged: same entity, same position
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeCandidate {
+    pub entity_id: String,
+    pub position: usize,
+    pub marker_ids: Vec<String>,         // In application order
+    pub conflicting_fields: Vec<String>, // Fields changed by more than one of the markers
+}
+
+// Show whole numbers without a trailing ".0"
+fn format_number(num: f64) -> String {
+    if num.fract() == 0.0 && num.abs() < 1e15 {
+        format!("{}", num as i64)
+    } else {
+        num.to_string()
+    }
+}
+
+// A single change with the effect of `previous` followed by `next` (as engine.rs applies them),
+// or None when a custom change is involved and the two can't be collapsed into one
+fn combine_changes(previous: &FieldChange, next: &FieldChange) -> Option<FieldChange> {
+    let absolute_base = (previous.change_type == ChangeType::Absolute)
+        .then(|| previous.value.parse::<f64>().ok())
+        .flatten();
+
+    if next.change_type == ChangeType::Custom {
+        // After a numeric value the reducer can be worked out now; otherwise both changes stand
+        let value = change_types::apply(absolute_base?, next).ok()?;
+        return Some(FieldChange {
+            field_name: next.field_name.clone(),
+            change_type: ChangeType::Absolute,
+            value: format_number(value),
+            custom: None,
+        });
+    }
+
+    let Some(delta) = (next.change_type == ChangeType::Relative)
+        .then(|| next.value.parse::<f64>().ok())
+        .flatten()
+    else {
+        // Anything but a numeric relative change overwrites what came before
+        let mut combined = next.clone();
+        if next.change_type == ChangeType::Relative {
+            combined.change_type = ChangeType::Absolute;
+        }
+        return Some(combined);
+    };
+
+    if previous.change_type == ChangeType::Custom {
+        // The delta applies to whatever the reducer produces
+        return None;
+    }
+
+    let base = previous.value.parse::<f64>().ok();
+    let (change_type, value) = match (&previous.change_type, base) {
+        (ChangeType::Relative, Some(base)) => (ChangeType::Relative, base + delta),
+        (ChangeType::Absolute, Some(base)) => (ChangeType::Absolute, base + delta),
+        // A relative change on a missing or non-numeric value starts from 0
+        _ => (ChangeType::Absolute, delta),
+    };
+
+    Some(FieldChange {
+        field_name: next.field_name.clone(),
+        change_type,
+        value: format_number(value),
+        custom: None,
+    })
+}
+
+// Resolve changes (in application order) to one change per field, keeping first-seen field order
+//
+// Combining keeps a field's changes apart where a custom change can't be collapsed
+// (see `combine_changes`), so a field can then have more than one change, in order.
+fn resolve_changes(changes: Vec<FieldChange>, strategy: MergeStrategy) -> Vec<FieldChange> {
+    let mut resolved: Vec<(String, FieldChange)> = Vec::new();
+
+    for change in changes {
+        let path = knowledge::change_path(&change);
+        let Some(index) = resolved.iter().rposition(|(p, _)| *p == path) else {
+            resolved.push((path, change));
+            continue;
+        };
+        match strategy {
+            MergeStrategy::Combine => match combine_changes(&resolved[index].1, &change) {
+                Some(combined) => resolved[index].1 = combined,
+                None => resolved.push((path, change)),
+            },
+            MergeStrategy::KeepFirst => {}
+            MergeStrategy::KeepLast => resolved[index].1 = change,
+        }
+    }
+
+    resolved.into_iter().map(|(_, change)| change).collect()
+}
+
+// Fields changed by more than one marker
+fn conflicting_fields(markers: &[&Marker]) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for marker in markers {
+        let paths: HashSet<String> = marker.changes.iter().map(knowledge::change_path).collect();
+        for path in paths {
+            *seen.entry(path).or_insert(0) += 1;
+        }
+    }
+
+    let mut conflicts: Vec<String> = seen.into_iter().filter(|(_, n)| *n > 1).map(|(p, _)| p).collect();
+    conflicts.sort();
+    conflicts
+}
+
+/// Merge markers of one entity at one position into the first of them (in application order)
+///
+/// Descriptions are joined and tags combined; the other markers are deleted.
+pub fn merge_markers(
+    entities: &HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    marker_ids: &[String],
+    strategy: MergeStrategy,
+) -> Result<MergeResult, String> {
+    let unique: HashSet<&String> = marker_ids.iter().collect();
+    if unique.len() < 2 {
+        return Err("Select at least two markers to merge".to_string());
+    }
+
+    let mut selected: Vec<&Marker> = unique
+        .iter()
+        .map(|id| markers.get(*id).ok_or_else(|| format!("Marker not found: {}", id)))
+        .collect::<Result<_, _>>()?;
+    selected.sort_by(|a, b| engine::compare_markers(a, b));
+
+    let first = selected[0];
+    if selected.iter().any(|m| m.entity_id != first.entity_id) {
+        return Err("Only markers of the same entity can be merged".to_string());
+    }
+    if selected.iter().any(|m| m.position != first.position) {
+        return Err("Only markers at the same position can be merged".to_string());
+    }
+
+    let mut changes = resolve_changes(
+        selected.iter().flat_map(|m| m.changes.iter().cloned()).collect(),
+        strategy,
+    );
+    if let Some(entity) = entities.get(&first.entity_id) {
+        // A combined arc value past the scale has the clamped value's effect
+        for change in changes.iter_mut().filter(|c| c.change_type == ChangeType::Absolute) {
+            if !arcs::is_arc_field(entity, &change.field_name) {
+                continue;
+            }
+            if let Ok(value) = change.value.parse::<f64>() {
+                change.value = format_number(arcs::clamp(value));
+            }
+        }
+        arcs::validate_changes(entity, &changes)?;
+    }
+
+    let mut descriptions: Vec<&str> = Vec::new();
+    let mut tags: Vec<String> = Vec::new();
+    for marker in &selected {
+        if !marker.description.is_empty() && !descriptions.contains(&marker.description.as_str()) {
+            descriptions.push(&marker.description);
+        }
+        for tag in &marker.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+    }
+
+    let merged = Marker {
+        changes,
+        description: descriptions.join("; "),
+        tags,
+        story_time: selected.iter().find_map(|m| m.story_time),
+        modified_at: dates::now(),
+        ..first.clone()
+    };
+    let removed_marker_ids: Vec<String> = selected[1..].iter().map(|m| m.id.clone()).collect();
+
+    // Combined changes can act differently than the separate ones did
+    if context.strict {
+        strict::check_batch(entities, markers, std::slice::from_ref(&merged), &removed_marker_ids)?;
+    }
+
+    for id in &removed_marker_ids {
+        markers.remove(id);
+    }
+    markers.insert(merged.id.clone(), merged.clone());
+
+    Ok(MergeResult {
+        marker: merged,
+        removed_marker_ids,
+    })
+}
+
+/// Find groups of markers sharing an entity and a position, sorted by position
+pub fn find_merge_candidates(markers: &HashMap<String, Marker>) -> Vec<MergeCandidate> {
+    let mut groups: HashMap<(&str, usize), Vec<&Marker>> = HashMap::new();
+    for marker in markers.values() {
+        groups.entry((marker.entity_id.as_str(), marker.position)).or_default().push(marker);
+    }
+
+    let mut candidates: Vec<MergeCandidate> = groups
+        .into_iter()
+        .filter(|(_, group)| group.len() > 1)
+        .map(|((entity_id, position), mut group)| {
+            group.sort_by(|a, b| engine::compare_markers(a, b));
+            MergeCandidate {
+                entity_id: entity_id.to_string(),
+                position,
+                marker_ids: group.iter().map(|m| m.id.clone()).collect(),
+                conflicting_fields: conflicting_fields(&group),
+            }
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.entity_id.cmp(&b.entity_id)));
+    candidates
+}
+
+// A marker colored like its entity takes the new entity's color; custom colors stay
+fn recolor(visual: &MarkerVisual, entity_color: Option<&str>, target_color: &str) -> MarkerVisual {
+    if entity_color == Some(visual.color.as_str()) {
+        MarkerVisual {
+            color: target_color.to_string(),
+            ..visual.clone()
+        }
+    } else {
+        visual.clone()
+    }
+}
+
+/// Copy markers to another entity, shifted by `position_offset`, in application order
+///
+/// Copies keep their changes, description, tags, story time, and icon; a marker
+/// colored like its entity takes the target entity's color.
+/// A copy that fails validation fails the whole call, with the earlier copies
+/// already inserted; callers work on copies of the maps.
+pub fn copy_markers(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    marker_ids: &[String],
+    target_entity_id: &str,
+    position_offset: i64,
+) -> Result<Vec<Marker>, String> {
+    let target_color = entities
+        .get(target_entity_id)
+        .map(|e| e.color.clone())
+        .ok_or("Target entity not found")?;
+
+    let mut sources: Vec<Marker> = marker_ids
+        .iter()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(|id| markers.get(id).cloned().ok_or_else(|| format!("Marker not found: {}", id)))
+        .collect::<Result<_, _>>()?;
+    sources.sort_by(engine::compare_markers);
+
+    // Check every new position before creating anything
+    let mut copies = Vec::with_capacity(sources.len());
+    for source in sources {
+        let position = usize::try_from(source.position as i64 + position_offset)
+            .map_err(|_| format!("Marker at position {} would be moved before the start of the document", source.position))?;
+
+        let source_color = entities.get(&source.entity_id).map(|e| e.color.as_str());
+        let visual = recolor(&source.visual, source_color, &target_color);
+
+        copies.push(NewMarker {
+            position,
+            entity_id: target_entity_id.to_string(),
+            changes: source.changes,
+            visual: Some(visual),
+            description: Some(source.description),
+            tags: Some(source.tags),
+            story_time: source.story_time,
+            todo: source.todo,
+        });
+    }
+
+    copies
+        .into_iter()
+        .map(|copy| mutations::insert_marker(entities, markers, context, copy))
+        .collect()
+}
+
+/// Move an entity's markers in `start..end` to another entity, in application order
+///
+/// The target entity gains the fields the markers change; the source entity keeps
+/// its field list, since other markers may still use those fields.
+pub fn reassign_markers_in_range(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    context: &MutationContext,
+    start: usize,
+    end: usize,
+    from_entity_id: &str,
+    to_entity_id: &str,
+) -> Result<Vec<Marker>, String> {
+    if start > end {
+        return Err(format!("Invalid range: {}..{}", start, end));
+    }
+    if from_entity_id == to_entity_id {
+        return Err("Markers are already assigned to this entity".to_string());
+    }
+    let from_color = entities.get(from_entity_id).map(|e| e.color.clone()).ok_or("Source entity not found")?;
+    let to_color = entities.get(to_entity_id).map(|e| e.color.clone()).ok_or("Target entity not found")?;
+
+    let in_range = |m: &Marker| m.entity_id == from_entity_id && m.position >= start && m.position < end;
+    if let Some(target) = entities.get(to_entity_id) {
+        for marker in markers.values().filter(|m| in_range(m)) {
+            arcs::validate_changes(target, &marker.changes)?;
+        }
+    }
+
+    let mut reassigned: Vec<Marker> = markers.values().filter(|m| in_range(m)).cloned().collect();
+    reassigned.sort_by(engine::compare_markers);
+
+    let now = dates::now();
+    for marker in reassigned.iter_mut() {
+        marker.entity_id = to_entity_id.to_string();
+        marker.visual = recolor(&marker.visual, Some(&from_color), &to_color);
+        marker.modified_at = now;
+    }
+
+    // Both entities' histories change: one loses the markers, the other gains them
+    if context.strict {
+        strict::check_batch(entities, markers, &reassigned, &[])?;
+    }
+
+    let target = entities.get_mut(to_entity_id).ok_or("Target entity not found")?;
+    for marker in &reassigned {
+        mutations::record_fields(target, &marker.changes, now);
+        markers.insert(marker.id.clone(), marker.clone());
+    }
+
+    Ok(reassigned)
+}
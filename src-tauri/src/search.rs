@@ -0,0 +1,143 @@
+//! QuestScribe - Quick-Open Search
+//!
+//! Fuzzy search over entity names, field paths, and marker descriptions and
+//! tags, for a command-palette style quick-open. Exact, prefix, and substring
+//! matches rank first; anything else is scored by trigram similarity, so typos
+//! and partial words ("fierbolt", "strngth") still find their target.
+
+use crate::state::{Entity, Marker};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Results scoring below this are left out
+const MIN_SCORE: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchResultKind {
+    Entity,
+    Field,
+    Marker,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub kind: SearchResultKind,
+    pub id: String,        // Entity ID, field path, or marker ID
+    pub entity_id: String, // The entity itself, the field's entity, or the marker's entity
+    pub label: String,
+    pub detail: Option<String>, // Owning entity's name for fields and markers
+    pub position: Option<usize>, // Markers only
+    pub score: f64,              // 0 to 1
+}
+
+// Trigrams of each word, padded so that word starts weigh more ("  h", " hp", "hp ")
+fn trigrams(text: &str) -> HashSet<[char; 3]> {
+    let mut grams = HashSet::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        let padded: Vec<char> = "  ".chars().chain(word.chars()).chain(" ".chars()).collect();
+        for window in padded.windows(3) {
+            grams.insert([window[0], window[1], window[2]]);
+        }
+    }
+    grams
+}
+
+/// How well `text` matches the (lowercased) query, from 0 to 1
+fn match_score(query: &str, query_grams: &HashSet<[char; 3]>, text: &str) -> f64 {
+    let text = text.to_lowercase();
+    if text == query {
+        return 1.0;
+    }
+    if text.starts_with(query) {
+        return 0.9;
+    }
+    if let Some(index) = text.find(query) {
+        let at_word_start = text[..index].chars().next_back().is_none_or(|c| !c.is_alphanumeric());
+        return if at_word_start { 0.85 } else { 0.75 };
+    }
+
+    if query_grams.is_empty() {
+        return 0.0;
+    }
+    let text_grams = trigrams(&text);
+    let shared = query_grams.intersection(&text_grams).count() as f64;
+    // Mostly how much of the query is found, slightly favoring shorter texts
+    let similarity = shared / query_grams.len() as f64 * 0.8 + shared / text_grams.len().max(1) as f64 * 0.2;
+    similarity * 0.7
+}
+
+/// Search everything, best matches first
+pub fn fuzzy_search(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    query: &str,
+    limit: usize,
+) -> Vec<SearchResult> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let query_grams = trigrams(&query);
+    let score = |text: &str| match_score(&query, &query_grams, text);
+
+    let mut results = Vec::new();
+
+    for entity in entities.values() {
+        results.push(SearchResult {
+            kind: SearchResultKind::Entity,
+            id: entity.id.clone(),
+            entity_id: entity.id.clone(),
+            label: entity.name.clone(),
+            detail: None,
+            position: None,
+            score: score(&entity.name),
+        });
+
+        for field in &entity.fields {
+            // "stats.Strength" is found by "strength" as well as by "stats.str"
+            let leaf = field.rsplit('.').next().unwrap_or(field);
+            results.push(SearchResult {
+                kind: SearchResultKind::Field,
+                id: field.clone(),
+                entity_id: entity.id.clone(),
+                label: field.clone(),
+                detail: Some(entity.name.clone()),
+                position: None,
+                score: score(field).max(score(leaf)),
+            });
+        }
+    }
+
+    for marker in markers.values() {
+        let description_score = if marker.description.is_empty() { 0.0 } else { score(&marker.description) };
+        let tag_score = marker.tags.iter().map(|tag| score(tag)).fold(0.0, f64::max);
+        let entity_name = entities.get(&marker.entity_id).map(|e| e.name.clone());
+        let label = if marker.description.is_empty() {
+            marker.tags.join(", ")
+        } else {
+            marker.description.clone()
+        };
+
+        results.push(SearchResult {
+            kind: SearchResultKind::Marker,
+            id: marker.id.clone(),
+            entity_id: marker.entity_id.clone(),
+            label,
+            detail: entity_name,
+            position: Some(marker.position),
+            score: description_score.max(tag_score),
+        });
+    }
+
+    results.retain(|r| r.score >= MIN_SCORE);
+    results.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.label.len().cmp(&b.label.len()))
+            .then_with(|| a.label.cmp(&b.label))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    results.truncate(limit);
+    results
+}
@@ -0,0 +1,171 @@
+//! QuestScribe - Writing Sessions
+//!
+//! A session runs from `start_writing_session` to `end_writing_session` and records
+//! how the document's word count changed in between. Finished sessions are appended
+//! to a history file in the app data directory, shared by all documents, which feeds
+//! streak and productivity charts.
+
+use crate::dates;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// History file name inside the app data directory
+pub const HISTORY_FILE: &str = "writing_history.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WritingSession {
+    pub id: String,
+    #[serde(default)]
+    pub document_path: Option<String>,
+    pub started_at: i64,
+    pub ended_at: i64, // Equal to started_at while the session is active
+    pub start_word_count: usize,
+    pub end_word_count: usize,
+}
+
+impl WritingSession {
+    /// Net words written (negative when the session was spent cutting)
+    pub fn word_delta(&self) -> i64 {
+        self.end_word_count as i64 - self.start_word_count as i64
+    }
+
+    pub fn duration_secs(&self) -> i64 {
+        (self.ended_at - self.started_at).max(0)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WritingHistory {
+    #[serde(default)]
+    pub sessions: Vec<WritingSession>,
+}
+
+/// Writing totals for one calendar day
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyWriting {
+    pub date: String, // "YYYY-MM-DD" in the user's time zone
+    pub words: i64,
+    pub minutes: i64,
+    pub sessions: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WritingHistorySummary {
+    pub sessions: Vec<WritingSession>,
+    pub daily: Vec<DailyWriting>,
+    pub current_streak: usize, // Consecutive days with words written, ending today or yesterday
+    pub longest_streak: usize,
+    pub total_words: i64,
+    pub total_minutes: i64,
+}
+
+/// Load the history file (a missing file is an empty history)
+pub fn load_history(path: &Path) -> Result<WritingHistory, String> {
+    if !path.exists() {
+        return Ok(WritingHistory::default());
+    }
+
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read writing history: {}", e))?;
+
+    serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse writing history: {}", e))
+}
+
+pub fn save_history(path: &Path, history: &WritingHistory) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create app data folder: {}", e))?;
+    }
+
+    let json = serde_json::to_string_pretty(history)
+        .map_err(|e| format!("Failed to serialize writing history: {}", e))?;
+
+    fs::write(path, json)
+        .map_err(|e| format!("Failed to write writing history: {}", e))
+}
+
+/// Per-day totals keyed by day number, for sessions that started on or after `since`
+pub fn daily_totals(
+    sessions: &[WritingSession],
+    utc_offset_minutes: i32,
+    since: Option<i64>,
+) -> BTreeMap<i64, DailyWriting> {
+    let mut days: BTreeMap<i64, DailyWriting> = BTreeMap::new();
+
+    for session in sessions.iter().filter(|s| since.is_none_or(|t| s.started_at >= t)) {
+        let day = dates::day_number(session.started_at, utc_offset_minutes);
+        let entry = days.entry(day).or_insert_with(|| DailyWriting {
+            date: dates::format_day(day),
+            words: 0,
+            minutes: 0,
+            sessions: 0,
+        });
+        entry.words += session.word_delta();
+        entry.minutes += session.duration_secs() / 60;
+        entry.sessions += 1;
+    }
+
+    days
+}
+
+/// Summarize the history for charts; `days` limits sessions and daily totals to the
+/// most recent N days, while streaks always consider the full history
+pub fn summarize(
+    history: &WritingHistory,
+    utc_offset_minutes: i32,
+    days: Option<i64>,
+    now: i64,
+) -> WritingHistorySummary {
+    let today = dates::day_number(now, utc_offset_minutes);
+
+    // Streaks count days where the net word count went up
+    let productive_days: Vec<i64> = daily_totals(&history.sessions, utc_offset_minutes, None)
+        .into_iter()
+        .filter(|(_, d)| d.words > 0)
+        .map(|(day, _)| day)
+        .collect();
+
+    let mut longest_streak = 0;
+    let mut run = 0;
+    let mut previous: Option<i64> = None;
+    for &day in &productive_days {
+        run = if previous == Some(day - 1) { run + 1 } else { 1 };
+        longest_streak = longest_streak.max(run);
+        previous = Some(day);
+    }
+
+    // The current streak may end yesterday if nothing has been written yet today
+    let mut current_streak = 0;
+    let mut expected = if productive_days.last() == Some(&today) { today } else { today - 1 };
+    for &day in productive_days.iter().rev() {
+        if day != expected {
+            break;
+        }
+        current_streak += 1;
+        expected -= 1;
+    }
+
+    // Window for the detailed data
+    let since = days.map(|d| (today - d + 1) * 86_400 - utc_offset_minutes as i64 * 60);
+    let sessions: Vec<WritingSession> = history
+        .sessions
+        .iter()
+        .filter(|s| since.is_none_or(|t| s.started_at >= t))
+        .cloned()
+        .collect();
+    let daily: Vec<DailyWriting> = daily_totals(&history.sessions, utc_offset_minutes, since)
+        .into_values()
+        .collect();
+
+    WritingHistorySummary {
+        total_words: sessions.iter().map(|s| s.word_delta()).sum(),
+        total_minutes: sessions.iter().map(|s| s.duration_secs()).sum::<i64>() / 60,
+        sessions,
+        daily,
+        current_streak,
+        longest_streak,
+    }
+}
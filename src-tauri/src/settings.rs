@@ -0,0 +1,213 @@
+//! QuestScribe - Application Settings
+//!
+//! User preferences that apply to every document, persisted as `settings.json`
+//! in the app config directory. Settings are loaded once at startup and written
+//! back whenever they change. Unknown or missing keys fall back to defaults, so
+//! older settings files keep working as new preferences are added.
+
+use crate::colors;
+use crate::i18n;
+use crate::preferences::ExportStyle;
+use crate::redaction::RedactionOptions;
+use crate::typography::TypographyOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Settings file name inside the app config directory
+pub const FILE_NAME: &str = "settings.json";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Txt,
+    Rtf,
+    #[default]
+    Docx,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Txt => "txt",
+            ExportFormat::Rtf => "rtf",
+            ExportFormat::Docx => "docx",
+        }
+    }
+}
+
+/// A named set of export options for a recurring export ("Patreon post", "Agent submission")
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportProfile {
+    pub format: ExportFormat,
+    pub style: Option<ExportStyle>, // None = the document's export style
+    pub endnotes: bool,
+    pub append_sheets: bool, // Character sheets of every entity, as of the end of the exported text
+    pub redaction: Option<RedactionOptions>, // Hide spoiler markers from endnotes and sheets
+    pub marker_comments: bool, // Marker descriptions as Word comments (DOCX only)
+    pub typography: TypographyOptions, // Smart quotes, em dashes and ellipses (see typography.rs)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub autosave_enabled: bool,
+    pub autosave_interval_secs: u64,
+    pub backup_count: u32, // Backups kept per document; 0 disables backups
+    pub default_export_format: ExportFormat,
+    pub default_entity_color: String, // Hex color for new entities
+    pub locale: String, // Locale for backend-generated text
+    pub export_profiles: BTreeMap<String, ExportProfile>,
+    pub palettes: BTreeMap<String, Vec<String>>, // Entity color palettes by name (see colors.rs)
+    pub diagnostic_logging: bool, // Structured log of commands for bug reports (see logging.rs)
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            autosave_enabled: false,
+            autosave_interval_secs: 180,
+            backup_count: 5,
+            default_export_format: ExportFormat::Docx,
+            default_entity_color: "#FFD700".to_string(),
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+            export_profiles: BTreeMap::new(),
+            palettes: colors::builtin_palettes(),
+            diagnostic_logging: false,
+        }
+    }
+}
+
+/// A partial update from the settings dialog (None = leave unchanged)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SettingsUpdate {
+    pub autosave_enabled: Option<bool>,
+    pub autosave_interval_secs: Option<u64>,
+    pub backup_count: Option<u32>,
+    pub default_export_format: Option<ExportFormat>,
+    pub default_entity_color: Option<String>,
+    pub locale: Option<String>,
+    pub diagnostic_logging: Option<bool>,
+}
+
+impl AppSettings {
+    /// Apply an update, validating every provided value before changing anything
+    pub fn apply(&mut self, update: SettingsUpdate) -> Result<(), String> {
+        if let Some(interval) = update.autosave_interval_secs {
+            if !(30..=3600).contains(&interval) {
+                return Err("Autosave interval must be between 30 seconds and 1 hour".to_string());
+            }
+        }
+        if let Some(count) = update.backup_count {
+            if count > 100 {
+                return Err("Backup count must be 100 or less".to_string());
+            }
+        }
+        let color = update.default_entity_color.as_deref().map(colors::parse_color).transpose()?;
+        let locale = match &update.locale {
+            Some(locale) => Some(
+                i18n::normalize_locale(locale).ok_or_else(|| format!("Unsupported locale: {}", locale))?,
+            ),
+            None => None,
+        };
+
+        if let Some(enabled) = update.autosave_enabled {
+            self.autosave_enabled = enabled;
+        }
+        if let Some(interval) = update.autosave_interval_secs {
+            self.autosave_interval_secs = interval;
+        }
+        if let Some(count) = update.backup_count {
+            self.backup_count = count;
+        }
+        if let Some(format) = update.default_export_format {
+            self.default_export_format = format;
+        }
+        if let Some(color) = color {
+            self.default_entity_color = color;
+        }
+        if let Some(locale) = locale {
+            self.locale = locale.to_string();
+        }
+        if let Some(enabled) = update.diagnostic_logging {
+            self.diagnostic_logging = enabled;
+        }
+
+        Ok(())
+    }
+
+    /// Add or replace an export profile
+    pub fn set_export_profile(&mut self, name: &str, profile: ExportProfile) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Export profile name cannot be empty".to_string());
+        }
+        if let Some(style) = &profile.style {
+            style.validate()?;
+        }
+
+        self.export_profiles.insert(name.to_string(), profile);
+        Ok(())
+    }
+
+    pub fn remove_export_profile(&mut self, name: &str) -> Result<(), String> {
+        self.export_profiles
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| format!("Export profile not found: {}", name))
+    }
+
+    /// Add or replace a color palette
+    pub fn set_palette(&mut self, name: &str, palette: &[String]) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Palette name cannot be empty".to_string());
+        }
+
+        self.palettes.insert(name.to_string(), colors::parse_palette(palette)?);
+        Ok(())
+    }
+
+    pub fn remove_palette(&mut self, name: &str) -> Result<(), String> {
+        self.palettes
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| format!("Palette not found: {}", name))
+    }
+}
+
+/// Load settings (a missing file means defaults)
+pub fn load_settings(path: &Path) -> Result<AppSettings, String> {
+    if !path.exists() {
+        return Ok(AppSettings::default());
+    }
+
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read settings: {}", e))?;
+
+    let mut settings: AppSettings = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse settings: {}", e))?;
+
+    // A hand-edited file may name a locale we don't bundle
+    settings.locale = i18n::normalize_locale(&settings.locale)
+        .unwrap_or(i18n::DEFAULT_LOCALE)
+        .to_string();
+
+    Ok(settings)
+}
+
+pub fn save_settings(path: &Path, settings: &AppSettings) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create settings folder: {}", e))?;
+    }
+
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+    fs::write(path, json)
+        .map_err(|e| format!("Failed to write settings: {}", e))
+}
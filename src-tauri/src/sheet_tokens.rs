@@ -0,0 +1,110 @@
+//! QuestScribe - Embedded Sheets
+//!
+//! A paragraph holding nothing but a sheet token, `{{sheet:Hero}}`, is replaced
+//! in exports by Hero's status window (see status_window.rs) as of that
+//! paragraph, always matching the markers before it. The token names the
+//! entity by name (ignoring case) or by ID, and the sheet follows the entity
+//! kind's layout (see sheets.rs). The window is one paragraph of code text,
+//! a line per hard break, so it keeps its monospace alignment.
+//!
+//! Exports can slice the document to a range before rendering, so each token
+//! paragraph is stamped with its position in the full document first. Tokens
+//! naming no entity the export includes (unknown, or redacted) stay as written,
+//! for the author to spot.
+
+use crate::chapters;
+use crate::positions;
+use crate::sheets::{self, SheetLayout};
+use crate::status_window::{self, StatusWindowStyle};
+use crate::state::{Entity, Marker};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+const TOKEN_PREFIX: &str = "{{sheet:";
+const TOKEN_SUFFIX: &str = "}}";
+// Paragraph attribute holding the token's position in the full document
+const POSITION_ATTR: &str = "sheet_position";
+
+/// The entity a paragraph's sheet token names (None if the paragraph isn't just a token)
+pub fn token_entity(block: &Value) -> Option<String> {
+    if block.get("type").and_then(|t| t.as_str()) != Some("paragraph") {
+        return None;
+    }
+    let text = chapters::node_text(block);
+    let name = text.trim().strip_prefix(TOKEN_PREFIX)?.strip_suffix(TOKEN_SUFFIX)?.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+fn find_entity<'a>(entities: &'a HashMap<String, Entity>, name: &str) -> Option<&'a Entity> {
+    entities
+        .get(name)
+        .or_else(|| entities.values().find(|e| e.name.trim().eq_ignore_ascii_case(name)))
+}
+
+/// Record the position of each token paragraph, before the document is sliced
+pub fn stamp_positions(doc: &mut Value) {
+    let Some(blocks) = doc.get_mut("content").and_then(|c| c.as_array_mut()) else {
+        return;
+    };
+
+    let mut pos = 0;
+    for block in blocks {
+        let size = positions::node_size(block);
+        if token_entity(block).is_some() {
+            let attrs = block.as_object_mut().unwrap().entry("attrs".to_string()).or_insert_with(|| json!({}));
+            if let Some(attrs) = attrs.as_object_mut() {
+                attrs.insert(POSITION_ATTR.to_string(), json!(pos));
+            }
+        }
+        pos += size;
+    }
+}
+
+// A paragraph of code text, with hard breaks between the lines
+fn window_paragraph(lines: Vec<String>) -> Value {
+    let mut content = Vec::new();
+    for line in lines {
+        if !content.is_empty() {
+            content.push(json!({ "type": "hard_break" }));
+        }
+        content.push(json!({ "type": "text", "text": line, "marks": [{ "type": "code" }] }));
+    }
+    json!({ "type": "paragraph", "content": content })
+}
+
+/// Replace the token paragraphs with the sheets they name
+pub fn expand(
+    doc: &mut Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    layouts: &[SheetLayout],
+    style: &StatusWindowStyle,
+) {
+    let Some(blocks) = doc.get_mut("content").and_then(|c| c.as_array_mut()) else {
+        return;
+    };
+
+    let mut expanded = Vec::with_capacity(blocks.len());
+    let mut pos = 0;
+    for block in blocks.drain(..) {
+        let size = positions::node_size(&block);
+        let entity = token_entity(&block).and_then(|name| find_entity(entities, &name));
+        let Some(entity) = entity else {
+            expanded.push(block);
+            pos += size;
+            continue;
+        };
+
+        let position = block
+            .get("attrs")
+            .and_then(|a| a.get(POSITION_ATTR))
+            .and_then(|p| p.as_u64())
+            .map_or(pos, |p| p as usize);
+        let sheet = sheets::build(entity, markers, position, sheets::layout_for(layouts, entity));
+
+        expanded.push(window_paragraph(status_window::render(&sheet, style)));
+        pos += size;
+    }
+
+    *blocks = expanded;
+}
@@ -0,0 +1,276 @@
+//! QuestScribe - Character Sheets
+//!
+//! An entity's state at a position as a tree the frontend and exporters can
+//! render however they like: groups (e.g., "stats") holding fields and other
+//! groups, and for each field its value, the value formatted for display (arc
+//! fields as their scale, whole numbers without ".0"), its declared type, and
+//! the marker that last changed it. Fields showing their declared default have
+//! no last change.
+//!
+//! A document can give each entity kind a sheet layout (stored in its
+//! preferences): the order of the sheet's top-level sections, and the fields
+//! shown as headline stats above the rest. Sections the layout doesn't name
+//! follow in their usual order, and a headline field is left out of the
+//! details below it.
+//!
+//! `format_character_sheet`'s text and the sheets appended to exports are
+//! rendered from the same tree.
+
+use crate::arcs;
+use crate::engine::{self, EntityState};
+use crate::knowledge;
+use crate::recap;
+use crate::state::{ChangeType, Entity, EntityKind, FieldType, Marker};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// The marker that last changed a field
+#[derive(Debug, Clone, Serialize)]
+pub struct LastChange {
+    pub marker_id: String,
+    pub position: usize,
+    pub change_type: ChangeType,
+    pub description: String, // The marker's description (may be empty)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SheetItem {
+    Group {
+        name: String,
+        path: String,
+        items: Vec<SheetItem>,
+    },
+    Field {
+        name: String,
+        path: String,
+        value: Value,
+        formatted: String,
+        field_type: Option<FieldType>,
+        last_changed: Option<LastChange>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CharacterSheet {
+    pub entity_id: String,
+    pub name: String,
+    pub position: usize,
+    pub headline: Vec<SheetItem>, // Headline stats (fields only), in the layout's order
+    pub items: Vec<SheetItem>, // The details
+}
+
+/// How an entity kind's sheets are laid out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SheetLayout {
+    pub kind: EntityKind,
+    #[serde(default)]
+    pub sections: Vec<String>, // Top-level groups and fields, in order (e.g., "stats", "inventory")
+    #[serde(default)]
+    pub headline: Vec<String>, // Field paths shown as headline stats (e.g., "stats.HP", "level")
+}
+
+impl SheetLayout {
+    pub fn validate(&self) -> Result<(), String> {
+        let names = self.sections.iter().chain(&self.headline);
+        if names.clone().any(|name| name.trim().is_empty()) {
+            return Err("Sheet layout sections and headline fields cannot be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Check a document's layouts (at most one per entity kind)
+pub fn validate_layouts(layouts: &[SheetLayout]) -> Result<(), String> {
+    for (index, layout) in layouts.iter().enumerate() {
+        layout.validate()?;
+        if layouts[..index].iter().any(|other| other.kind == layout.kind) {
+            return Err("Each entity kind can have only one sheet layout".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// The layout for an entity's kind, if the document defines one
+pub fn layout_for<'a>(layouts: &'a [SheetLayout], entity: &Entity) -> Option<&'a SheetLayout> {
+    layouts.iter().find(|layout| layout.kind == entity.kind)
+}
+
+// The last change to each path written by the entity's markers up to a position
+fn last_changes(entity: &Entity, markers: &HashMap<String, Marker>, position: usize) -> HashMap<String, LastChange> {
+    let mut applied: Vec<&Marker> = markers
+        .values()
+        .filter(|m| m.entity_id == entity.id && m.position <= position && !m.todo)
+        .collect();
+    applied.sort_by(|a, b| engine::compare_markers(a, b));
+
+    let mut last = HashMap::new();
+    for marker in applied {
+        for change in &marker.changes {
+            let path = knowledge::change_path(change);
+            // A change to a group replaces (or removes) what was set inside it
+            last.retain(|field: &String, _| !field.starts_with(&format!("{}.", path)));
+            last.insert(
+                path,
+                LastChange {
+                    marker_id: marker.id.clone(),
+                    position: marker.position,
+                    change_type: change.change_type.clone(),
+                    description: marker.description.clone(),
+                },
+            );
+        }
+    }
+    last
+}
+
+// The last change to a field: to the field itself, or else to the closest group containing it
+fn last_change_of(path: &str, last: &HashMap<String, LastChange>) -> Option<LastChange> {
+    let mut prefix = path;
+    loop {
+        if let Some(change) = last.get(prefix) {
+            return Some(change.clone());
+        }
+        prefix = &prefix[..prefix.rfind('.')?];
+    }
+}
+
+fn build_items(
+    state: &serde_json::Map<String, Value>,
+    entity: &Entity,
+    prefix: &str,
+    last: &HashMap<String, LastChange>,
+) -> Vec<SheetItem> {
+    state
+        .iter()
+        .map(|(key, value)| {
+            let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            if let Some(obj) = value.as_object() {
+                return SheetItem::Group {
+                    name: key.clone(),
+                    items: build_items(obj, entity, &path, last),
+                    path,
+                };
+            }
+
+            let formatted = match value {
+                Value::Number(n) if arcs::is_arc_field(entity, &path) => arcs::render_scale(n.as_f64().unwrap_or(0.0)),
+                Value::String(s) => s.clone(),
+                other => recap::format_value(&other.to_string()),
+            };
+            SheetItem::Field {
+                name: key.clone(),
+                value: value.clone(),
+                formatted,
+                field_type: entity.field_metadata.get(&path).and_then(|m| m.field_type),
+                last_changed: last_change_of(&path, last),
+                path,
+            }
+        })
+        .collect()
+}
+
+// Take the field at a path out of the items, dropping groups it leaves empty
+fn take_field(items: &mut Vec<SheetItem>, path: &str) -> Option<SheetItem> {
+    for index in 0..items.len() {
+        match &mut items[index] {
+            SheetItem::Field { path: field_path, .. } if field_path == path => return Some(items.remove(index)),
+            SheetItem::Group { path: group_path, items: children, .. } if path.starts_with(&format!("{}.", group_path)) => {
+                let taken = take_field(children, path);
+                if children.is_empty() {
+                    items.remove(index);
+                }
+                return taken;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn apply_layout(sheet: &mut CharacterSheet, layout: &SheetLayout) {
+    sheet.headline = layout
+        .headline
+        .iter()
+        .filter_map(|path| take_field(&mut sheet.items, path.trim()))
+        .collect();
+
+    // Named sections first, in the layout's order; a stable sort keeps the rest as they were
+    let rank = |item: &SheetItem| {
+        let path = match item {
+            SheetItem::Group { path, .. } | SheetItem::Field { path, .. } => path,
+        };
+        layout.sections.iter().position(|s| s.trim() == path).unwrap_or(layout.sections.len())
+    };
+    sheet.items.sort_by_key(rank);
+}
+
+/// An entity's character sheet at a position, with defaults for fields not yet set,
+/// laid out by its kind's layout
+pub fn build(
+    entity: &Entity,
+    markers: &HashMap<String, Marker>,
+    position: usize,
+    layout: Option<&SheetLayout>,
+) -> CharacterSheet {
+    let mut state: EntityState = engine::entity_state_with_defaults(markers, entity, position);
+    engine::fill_defaults(&mut state, entity);
+
+    let mut sheet = CharacterSheet {
+        entity_id: entity.id.clone(),
+        name: entity.name.clone(),
+        position,
+        headline: Vec::new(),
+        items: build_items(&state, entity, "", &last_changes(entity, markers, position)),
+    };
+    if let Some(layout) = layout {
+        apply_layout(&mut sheet, layout);
+    }
+    sheet
+}
+
+/// Each line of a sheet with its depth: group names, and fields as "name: value"
+pub fn lines(items: &[SheetItem]) -> Vec<(usize, &SheetItem, String)> {
+    fn walk<'a>(items: &'a [SheetItem], depth: usize, out: &mut Vec<(usize, &'a SheetItem, String)>) {
+        for item in items {
+            match item {
+                SheetItem::Group { name, items: children, .. } => {
+                    out.push((depth, item, name.clone()));
+                    walk(children, depth + 1, out);
+                }
+                SheetItem::Field { name, formatted, .. } => out.push((depth, item, format!("{}: {}", name, formatted))),
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    walk(items, 0, &mut out);
+    out
+}
+
+/// Each line of a sheet with its depth and whether it's bold (headline stats first, and group names)
+pub fn styled_lines(sheet: &CharacterSheet) -> Vec<(usize, bool, String)> {
+    let headline = lines(&sheet.headline).into_iter().map(|(depth, _, line)| (depth, true, line));
+    let details = lines(&sheet.items)
+        .into_iter()
+        .map(|(depth, item, line)| (depth, matches!(item, SheetItem::Group { .. }), line));
+    headline.chain(details).collect()
+}
+
+/// A sheet as indented text, headline stats first
+pub fn render_text(sheet: &CharacterSheet) -> String {
+    let indented = |items: &[SheetItem]| {
+        lines(items)
+            .into_iter()
+            .map(|(depth, _, line)| format!("{}{}", "  ".repeat(depth), line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    match (sheet.headline.is_empty(), sheet.items.is_empty()) {
+        (true, _) => indented(&sheet.items),
+        (false, true) => indented(&sheet.headline),
+        (false, false) => format!("{}\n\n{}", indented(&sheet.headline), indented(&sheet.items)),
+    }
+}
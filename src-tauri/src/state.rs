@@ -10,9 +10,29 @@
 //! - **FieldChange**: A single state modification (e.g., HP +10, Level = 5)
 //! - **Document**: The complete saved state including text content, entities, and markers
 
+use crate::arcs;
+use crate::change_types::CustomChangeType;
+use crate::chapters::HeadingPin;
+use crate::dates;
+use crate::goals::WordGoals;
+use crate::icons::IconPack;
+use crate::ids;
+use crate::legacy::Migration;
+use crate::notes_vault::{SealedVault, UnlockedVault};
+use crate::pins::StatePin;
+use crate::plot_threads::PlotThread;
+use crate::preferences::DocumentPreferences;
+use crate::progress::ProgressSnapshot;
+use crate::quarantine::QuarantinedItem;
+use crate::sessions::WritingSession;
+use crate::settings::AppSettings;
+use crate::track_changes::Suggestion;
+use crate::visibility::VisibilityFilters;
+use crate::visual_rules::VisualRule;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 /// Represents a character or object being tracked in the story
 ///
@@ -28,12 +48,65 @@ pub struct Entity {
     pub color: String, // Hex color for this entity's markers
     #[serde(default)]
     pub field_metadata: HashMap<String, FieldMetadata>, // Track creation/modification times
+    #[serde(default)]
+    pub portrait: Option<Portrait>,
+    #[serde(default)]
+    pub kind: EntityKind,
+    #[serde(default)]
+    pub distances: HashMap<String, f64>, // Locations only: distance to other locations by ID, in the document's units
+}
+
+/// What an entity represents
+///
+/// Location entities are places other entities can be at (see locations.rs).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    #[default]
+    Character,
+    Location,
+}
+
+/// Picture of an entity, stored base64-encoded like icon pack images
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Portrait {
+    pub mime_type: String, // "image/png", "image/jpeg", or "image/webp"
+    pub data: String,      // Base64-encoded file contents
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldMetadata {
     pub created_at: i64,
     pub last_modified: i64,
+    #[serde(default)]
+    pub field_type: Option<FieldType>, // Declared type (e.g., from an entity import); None = untyped
+    #[serde(default)]
+    pub default_value: Option<String>, // Value of the field before any marker sets it
+}
+
+/// Declared value type of a field
+///
+/// Values are still stored as strings in markers; the type documents intent
+/// and lets imports validate defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Number,
+    Text,
+    Boolean,
+    Arc, // Emotional scale from -5 to +5 (see arcs.rs)
+}
+
+impl FieldType {
+    /// Whether a (marker or default) value fits the type
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            FieldType::Number => value.parse::<f64>().is_ok(),
+            FieldType::Boolean => value.parse::<bool>().is_ok(),
+            FieldType::Text => true,
+            FieldType::Arc => value.parse::<f64>().is_ok_and(|v| (arcs::ARC_MIN..=arcs::ARC_MAX).contains(&v)),
+        }
+    }
 }
 
 fn default_entity_color() -> String {
@@ -47,7 +120,7 @@ fn default_entity_color() -> String {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Marker {
     pub id: String,
-    pub position: usize, // Character position in the document
+    pub position: usize, // ProseMirror document position (UTF-16 based, see positions.rs)
     pub entity_id: String,
     pub changes: Vec<FieldChange>,
     pub visual: MarkerVisual,
@@ -57,13 +130,31 @@ pub struct Marker {
     pub created_at: i64,
     #[serde(default = "default_timestamp")]
     pub modified_at: i64,
+    #[serde(default)]
+    pub tags: Vec<String>, // Free-form labels (e.g., "combat", "spoiler")
+    #[serde(default)]
+    pub story_time: Option<f64>, // In-world time in hours (see chronology.rs); None = follows the narrative
+    #[serde(default)]
+    pub sequence: u32, // Order among markers at the same position (lower applies first)
+    #[serde(default)]
+    pub pin: Option<HeadingPin>, // Heading the position follows (see chapters.rs); None = placed in the text
+    #[serde(default)]
+    pub active_outcome: Option<String>, // Name of the outcome `changes` belongs to, for markers with alternatives
+    #[serde(default)]
+    pub outcomes: Vec<MarkerOutcome>, // The other outcomes, not applied (e.g., for an undecided scene)
+    #[serde(default)]
+    pub todo: bool, // A reminder for the writer ("decide how much gold here"); its changes aren't applied
+}
+
+/// An alternative set of changes for a marker (e.g., "duel lost" instead of "duel won")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkerOutcome {
+    pub name: String,
+    pub changes: Vec<FieldChange>,
 }
 
 fn default_timestamp() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64
+    dates::now()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,6 +162,16 @@ pub struct FieldChange {
     pub field_name: String,
     pub change_type: ChangeType,
     pub value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<CustomChange>, // For ChangeType::Custom
+}
+
+/// The custom change type a change uses, with its reducer (see change_types.rs)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomChange {
+    pub name: String,
+    #[serde(default)]
+    pub reducer: String, // Filled in from the document's definition when the marker is saved
 }
 
 /// Types of state changes that can be applied
@@ -78,19 +179,25 @@ pub struct FieldChange {
 /// - **Absolute**: Set field to exact value (e.g., "Level = 5")
 /// - **Relative**: Add/subtract from current value (e.g., "HP +10")
 /// - **Remove**: Delete field from state entirely
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// - **Learn**: Entity learns the fact named by the field (see knowledge.rs)
+/// - **Custom**: A change type the document defines (see change_types.rs)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ChangeType {
     Absolute,
     Relative,
     Remove,
+    Learn,
+    Custom,
 }
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MarkerVisual {
-    pub icon: String,  // Emoji string like "⭐"
+    pub icon: String,  // Emoji string like "⭐" (also the fallback when icon_ref can't be resolved)
     pub color: String, // Hex color like "#FFD700"
+    #[serde(default)]
+    pub icon_ref: Option<String>, // Icon pack image as "<pack_id>/<icon_name>" (see icons.rs)
 }
 
 // Document structure for saving/loading
@@ -99,19 +206,139 @@ pub struct Document {
     pub content: String,  // The text content
     pub entities: Vec<Entity>,
     pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub language: Option<String>, // Locale for generated text (e.g., "es"); None = use app locale
+    #[serde(default)]
+    pub icon_packs: Vec<IconPack>, // Icon packs embedded in this document
+    #[serde(default)]
+    pub visual_rules: Option<Vec<VisualRule>>, // None = built-in default rules
+    #[serde(default)]
+    pub goals: WordGoals,
+    #[serde(default)]
+    pub preferences: DocumentPreferences,
+    #[serde(default)]
+    pub plot_threads: Vec<PlotThread>,
+    #[serde(default)]
+    pub progress: Vec<ProgressSnapshot>, // One snapshot per day the document was saved (see progress.rs)
+    #[serde(default)]
+    pub visibility: VisibilityFilters, // What the author has hidden from view (see visibility.rs)
+    #[serde(default)]
+    pub suggestions: Vec<Suggestion>, // Pending tracked changes (see track_changes.rs)
+    #[serde(default)]
+    pub change_types: Vec<CustomChangeType>, // Custom change types (see change_types.rs)
+    #[serde(default)]
+    pub notes_vault: Option<SealedVault>, // Encrypted planning notes (see notes_vault.rs)
+    #[serde(default)]
+    pub pins: Vec<StatePin>, // Frozen entity states to compare against (see pins.rs)
+    #[serde(default)]
+    pub quarantine: Vec<QuarantinedItem>, // Entities and markers set aside on load (see quarantine.rs)
+    #[serde(default)]
+    pub format_version: u32, // 0 = saved before versions were written (see legacy.rs)
+    #[serde(default)]
+    pub migrations: Vec<Migration>, // What reading older formats changed, oldest first
 }
 
-// Application state
-pub struct AppState {
+/// Session used by commands that don't pass a session ID (single-window use)
+pub const DEFAULT_SESSION: &str = "main";
+
+/// State of one open document
+///
+/// Each window editing a document has its own session, so two projects can be
+/// open side by side without their entities and markers mixing.
+pub struct DocumentState {
     pub entities: Mutex<HashMap<String, Entity>>,
     pub markers: Mutex<HashMap<String, Marker>>,
+    pub content: Mutex<Option<serde_json::Value>>, // Canonical ProseMirror document (see content.rs); None until sent
+    pub document_language: Mutex<Option<String>>,
+    pub icon_packs: Mutex<Vec<IconPack>>, // Document-level icon packs
+    pub visual_rules: Mutex<Option<Vec<VisualRule>>>,
+    pub writing_session: Mutex<Option<WritingSession>>, // Active writing session, if any
+    pub goals: Mutex<WordGoals>,
+    pub preferences: Mutex<DocumentPreferences>,
+    pub plot_threads: Mutex<Vec<PlotThread>>,
+    pub progress: Mutex<Vec<ProgressSnapshot>>,
+    pub visibility: Mutex<VisibilityFilters>,
+    pub suggestions: Mutex<Vec<Suggestion>>,
+    pub change_types: Mutex<Vec<CustomChangeType>>,
+    pub notes_vault: Mutex<Option<SealedVault>>,
+    pub unlocked_vault: Mutex<Option<UnlockedVault>>, // Decrypted notes while the vault is unlocked
+    pub pins: Mutex<Vec<StatePin>>,
+    pub quarantine: Mutex<Vec<QuarantinedItem>>,
+    pub migrations: Mutex<Vec<Migration>>,
+    pub locked_path: Mutex<Option<PathBuf>>, // File this session holds the lock for (see lockfile.rs)
+    pub read_only: Mutex<bool>, // Opened for review; mutating commands are refused
 }
 
-impl AppState {
+impl DocumentState {
     pub fn new() -> Self {
         Self {
             entities: Mutex::new(HashMap::new()),
             markers: Mutex::new(HashMap::new()),
+            content: Mutex::new(None),
+            document_language: Mutex::new(None),
+            icon_packs: Mutex::new(Vec::new()),
+            visual_rules: Mutex::new(None),
+            writing_session: Mutex::new(None),
+            goals: Mutex::new(WordGoals::default()),
+            preferences: Mutex::new(DocumentPreferences::default()),
+            plot_threads: Mutex::new(Vec::new()),
+            progress: Mutex::new(Vec::new()),
+            visibility: Mutex::new(VisibilityFilters::default()),
+            suggestions: Mutex::new(Vec::new()),
+            change_types: Mutex::new(Vec::new()),
+            notes_vault: Mutex::new(None),
+            unlocked_vault: Mutex::new(None),
+            pins: Mutex::new(Vec::new()),
+            quarantine: Mutex::new(Vec::new()),
+            migrations: Mutex::new(Vec::new()),
+            locked_path: Mutex::new(None),
+            read_only: Mutex::new(false),
+        }
+    }
+
+    /// Fail with a clear message when the document was opened read-only
+    pub fn ensure_writable(&self) -> Result<(), String> {
+        if *self.read_only.lock().unwrap() {
+            return Err("This document is open in read-only mode".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Application state
+pub struct AppState {
+    pub settings: Mutex<AppSettings>, // Application-wide preferences (settings.json)
+    pub documents: Mutex<HashMap<String, Arc<DocumentState>>>, // Keyed by document session ID
+    pub instance_id: String, // Identifies this app instance in document lock files
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        Self {
+            settings: Mutex::new(AppSettings::default()),
+            documents: Mutex::new(HashMap::new()),
+            instance_id: ids::new_id(),
+        }
+    }
+
+    /// Get the document state for a session, creating an empty one on first use
+    /// (None = the default session)
+    pub fn document(&self, session_id: Option<&str>) -> Arc<DocumentState> {
+        let session_id = session_id.unwrap_or(DEFAULT_SESSION);
+
+        self.documents
+            .lock()
+            .unwrap()
+            .entry(session_id.to_string())
+            .or_insert_with(|| Arc::new(DocumentState::new()))
+            .clone()
+    }
+
+    /// Locale for backend-generated text: the document language if set, else the app locale
+    pub fn locale_for(&self, document: &DocumentState) -> String {
+        if let Some(language) = document.document_language.lock().unwrap().clone() {
+            return language;
         }
+        self.settings.lock().unwrap().locale.clone()
     }
 }
\ No newline at end of file
@@ -0,0 +1,35 @@
+//! QuestScribe - Document Statistics
+//!
+//! Word counting over ProseMirror content. A word is a run of characters
+//! containing at least one letter or digit, separated by whitespace, matching
+//! what writers expect from a word processor's count.
+
+use crate::chapters::node_text;
+
+/// Count the words in a piece of text
+pub fn count_words(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|w| w.chars().any(|c| c.is_alphanumeric()))
+        .count()
+}
+
+/// Count the words in a ProseMirror document (blocks are counted separately so
+/// words on either side of a paragraph break never merge)
+pub fn document_word_count(doc: &serde_json::Value) -> usize {
+    doc.get("content")
+        .and_then(|c| c.as_array())
+        .map(|blocks| blocks.iter().map(block_word_count).sum())
+        .unwrap_or(0)
+}
+
+pub fn block_word_count(node: &serde_json::Value) -> usize {
+    match node.get("type").and_then(|t| t.as_str()) {
+        Some("paragraph") | Some("heading") | Some("code_block") => count_words(&node_text(node)),
+        // Containers such as blockquotes: count their blocks individually
+        _ => node
+            .get("content")
+            .and_then(|c| c.as_array())
+            .map(|children| children.iter().map(block_word_count).sum())
+            .unwrap_or(0),
+    }
+}
@@ -0,0 +1,236 @@
+//! QuestScribe - Status Windows
+//!
+//! The boxed "status window" LitRPG serials show when a character checks their
+//! stats, drawn from the entity's sheet (see sheets.rs):
+//!
+//! ```text
+//! ╔══════════════════╗
+//! ║   STATUS: Hero   ║
+//! ╠══════════════════╣
+//! ║ Level:  12       ║
+//! ║ Class:  Ranger   ║
+//! ╟──────────────────╢
+//! ║ stats            ║
+//! ║   HP:   140      ║
+//! ║   MP:   35       ║
+//! ╚══════════════════╝
+//! ```
+//!
+//! The document's style (in its preferences) picks the border, the title, and
+//! which fields or groups to show, in order; without a field list the window
+//! shows the headline stats, then the rest of the sheet. Values line up in a
+//! column. Widths count characters, so the box only lines up in a monospace
+//! font; exports render it in one (see sheet_tokens.rs).
+
+use crate::sheets::{CharacterSheet, SheetItem};
+use serde::{Deserialize, Serialize};
+
+/// Most characters inside the box
+const MAX_WIDTH: usize = 120;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BorderStyle {
+    #[default]
+    Double,
+    Single,
+    Rounded,
+    Heavy,
+    Ascii,
+}
+
+// Box-drawing characters of a border style
+struct Border {
+    top_left: char,
+    top_right: char,
+    bottom_left: char,
+    bottom_right: char,
+    horizontal: char,
+    vertical: char,
+    title_left: char, // Joins the line under the title to the sides
+    title_right: char,
+    rule: char, // Line between the headline stats and the details
+    rule_left: char,
+    rule_right: char,
+}
+
+impl BorderStyle {
+    fn chars(self) -> Border {
+        let (corners, horizontal, vertical, title_joins, rule, rule_joins) = match self {
+            BorderStyle::Double => (['╔', '╗', '╚', '╝'], '═', '║', ['╠', '╣'], '─', ['╟', '╢']),
+            BorderStyle::Single => (['┌', '┐', '└', '┘'], '─', '│', ['├', '┤'], '─', ['├', '┤']),
+            BorderStyle::Rounded => (['╭', '╮', '╰', '╯'], '─', '│', ['├', '┤'], '─', ['├', '┤']),
+            BorderStyle::Heavy => (['┏', '┓', '┗', '┛'], '━', '┃', ['┣', '┫'], '─', ['┠', '┨']),
+            BorderStyle::Ascii => (['+', '+', '+', '+'], '-', '|', ['+', '+'], '-', ['+', '+']),
+        };
+        Border {
+            top_left: corners[0],
+            top_right: corners[1],
+            bottom_left: corners[2],
+            bottom_right: corners[3],
+            horizontal,
+            vertical,
+            title_left: title_joins[0],
+            title_right: title_joins[1],
+            rule,
+            rule_left: rule_joins[0],
+            rule_right: rule_joins[1],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatusWindowStyle {
+    pub border: BorderStyle,
+    pub title: String, // "{name}" is replaced with the entity's name; empty = no title
+    pub fields: Vec<String>, // Field or group paths to show, in order; empty = the whole sheet
+    pub min_width: usize, // Characters inside the box, at least
+}
+
+impl Default for StatusWindowStyle {
+    fn default() -> Self {
+        Self {
+            border: BorderStyle::Double,
+            title: "STATUS: {name}".to_string(),
+            fields: Vec::new(),
+            min_width: 0,
+        }
+    }
+}
+
+impl StatusWindowStyle {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_width > MAX_WIDTH {
+            return Err(format!("Status window width can be at most {} characters", MAX_WIDTH));
+        }
+        if self.fields.iter().any(|f| f.trim().is_empty()) {
+            return Err("Status window fields cannot be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+// A line inside the box
+enum Row {
+    Pair { label: String, value: String, depth: usize },
+    Group { name: String, depth: usize },
+    Rule,
+}
+
+fn add_rows(items: &[SheetItem], depth: usize, rows: &mut Vec<Row>) {
+    for item in items {
+        match item {
+            SheetItem::Field { name, formatted, .. } => {
+                rows.push(Row::Pair { label: name.clone(), value: formatted.clone(), depth })
+            }
+            SheetItem::Group { name, items, .. } => {
+                rows.push(Row::Group { name: name.clone(), depth });
+                add_rows(items, depth + 1, rows);
+            }
+        }
+    }
+}
+
+// The field or group at a path, among the headline stats or the details
+fn find_item<'a>(items: &'a [SheetItem], path: &str) -> Option<&'a SheetItem> {
+    items.iter().find_map(|item| match item {
+        SheetItem::Field { path: p, .. } if p == path => Some(item),
+        SheetItem::Group { path: p, .. } if p == path => Some(item),
+        SheetItem::Group { path: p, items, .. } if path.starts_with(&format!("{}.", p)) => find_item(items, path),
+        _ => None,
+    })
+}
+
+fn rows(sheet: &CharacterSheet, style: &StatusWindowStyle) -> Vec<Row> {
+    let mut rows = Vec::new();
+    if style.fields.is_empty() {
+        add_rows(&sheet.headline, 0, &mut rows);
+        if !sheet.headline.is_empty() && !sheet.items.is_empty() {
+            rows.push(Row::Rule);
+        }
+        add_rows(&sheet.items, 0, &mut rows);
+        return rows;
+    }
+
+    for path in &style.fields {
+        let path = path.trim();
+        let found = find_item(&sheet.headline, path).or_else(|| find_item(&sheet.items, path));
+        if let Some(item) = found {
+            add_rows(std::slice::from_ref(item), 0, &mut rows);
+        }
+    }
+    rows
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+// Cut text to a width, ending with "…" when it didn't fit
+fn fit(text: &str, max: usize) -> String {
+    if width(text) <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// The lines of an entity's status window
+pub fn render(sheet: &CharacterSheet, style: &StatusWindowStyle) -> Vec<String> {
+    let border = style.border.chars();
+    let title = style.title.replace("{name}", &sheet.name).trim().to_string();
+    let rows = rows(sheet, style);
+
+    // Labels (with their indentation) in one column, values lined up after them
+    let label_width = rows
+        .iter()
+        .filter_map(|row| match row {
+            Row::Pair { label, depth, .. } => Some(depth * 2 + width(label) + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let texts: Vec<Option<String>> = rows
+        .iter()
+        .map(|row| match row {
+            Row::Pair { label, value, depth } => {
+                let label = format!("{}{}:", "  ".repeat(*depth), label);
+                Some(format!("{:<width$}  {}", label, value, width = label_width))
+            }
+            Row::Group { name, depth } => Some(format!("{}{}", "  ".repeat(*depth), name)),
+            Row::Rule => None,
+        })
+        .collect();
+
+    let content_width = texts.iter().flatten().map(|t| width(t)).max().unwrap_or(0);
+    let inner = (content_width + 2).max(width(&title) + 4).max(style.min_width).min(MAX_WIDTH);
+    let line = |text: &str| {
+        let text = fit(text, inner - 2);
+        format!("{} {}{} {}", border.vertical, text, " ".repeat(inner - 2 - width(&text)), border.vertical)
+    };
+    let horizontal = |left: char, fill: char, right: char| {
+        format!("{}{}{}", left, fill.to_string().repeat(inner), right)
+    };
+
+    let mut lines = vec![horizontal(border.top_left, border.horizontal, border.top_right)];
+    if !title.is_empty() {
+        let title = fit(&title, inner - 2);
+        let left = (inner - width(&title)) / 2;
+        let right = inner - width(&title) - left;
+        lines.push(format!("{}{}{}{}{}", border.vertical, " ".repeat(left), title, " ".repeat(right), border.vertical));
+        if !texts.is_empty() {
+            lines.push(horizontal(border.title_left, border.horizontal, border.title_right));
+        }
+    }
+    for text in &texts {
+        match text {
+            Some(text) => lines.push(line(text)),
+            None => lines.push(horizontal(border.rule_left, border.rule, border.rule_right)),
+        }
+    }
+    lines.push(horizontal(border.bottom_left, border.horizontal, border.bottom_right));
+
+    lines
+}
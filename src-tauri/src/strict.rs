@@ -0,0 +1,170 @@
+//! QuestScribe - Strict Mode
+//!
+//! By default the engine coerces whatever the markers say: a relative change to
+//! an unset field starts from 0 (or the field's default), "+2" on a text value
+//! overwrites it, and removing a field that isn't there does nothing. A document
+//! in strict mode (see `DocumentPreferences::strict_mode`) treats these as
+//! errors instead:
+//!
+//! - A relative change to a field with no value and no numeric default
+//! - A relative change that isn't a number, or that targets a non-numeric value
+//!   or a field declared as text or boolean
+//! - An absolute value that doesn't fit the field's declared type
+//! - Removing a field that has no value
+//! - A custom change (see change_types.rs) its reducer can't compute, or that
+//!   targets a non-numeric value or a text or boolean field
+//!
+//! Edits are refused only when they introduce a new violation, so a document
+//! switched to strict mode with existing problems can still be worked on (and
+//! `violations` lists what to fix).
+
+use crate::change_types;
+use crate::engine::{self, EntityState};
+use crate::state::{ChangeType, Entity, FieldChange, FieldType, Marker};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StrictViolation {
+    pub marker_id: String,
+    pub entity_id: String,
+    pub position: usize,
+    pub field: String,
+    pub message: String,
+}
+
+// What's wrong with applying the change to the state, if anything
+fn check_change(entity: &Entity, state: &EntityState, change: &FieldChange, defaults: &engine::FieldDefaults) -> Option<String> {
+    let field = &change.field_name;
+    let field_type = entity.field_metadata.get(field).and_then(|m| m.field_type);
+    let current = engine::get_nested_value(state, field);
+
+    match change.change_type {
+        ChangeType::Relative => {
+            if change.value.parse::<f64>().is_err() {
+                return Some(format!("Relative change \"{}\" to \"{}\" is not a number", change.value, field));
+            }
+            if let Some(declared @ (FieldType::Text | FieldType::Boolean)) = field_type {
+                let type_name = format!("{:?}", declared).to_lowercase();
+                return Some(format!("\"{}\" is a {} field and can't take a relative change", field, type_name));
+            }
+            match current {
+                None if defaults.get(field).is_none() => Some(format!("\"{}\" has no value to change", field)),
+                Some(value) if value.as_f64().is_none() => {
+                    Some(format!("\"{}\" holds {}, not a number, and can't take a relative change", field, value))
+                }
+                _ => None,
+            }
+        }
+        ChangeType::Absolute => {
+            let field_type = field_type?;
+            (!field_type.accepts(&change.value)).then(|| {
+                let type_name = format!("{:?}", field_type).to_lowercase();
+                format!("\"{}\" is not a valid {} for \"{}\"", change.value, type_name, field)
+            })
+        }
+        ChangeType::Custom => {
+            let type_name = change.custom.as_ref().map_or("custom", |c| c.name.as_str());
+            if let Some(declared @ (FieldType::Text | FieldType::Boolean)) = field_type {
+                let declared_name = format!("{:?}", declared).to_lowercase();
+                return Some(format!("\"{}\" is a {} field and can't take a {} change", field, declared_name, type_name));
+            }
+            match current {
+                Some(value) if value.as_f64().is_none() => {
+                    Some(format!("\"{}\" holds {}, not a number, and can't take a {} change", field, value, type_name))
+                }
+                _ => {
+                    let start = current.and_then(|v| v.as_f64()).or_else(|| defaults.get(field)).unwrap_or(0.0);
+                    change_types::apply(start, change).err()
+                }
+            }
+        }
+        ChangeType::Remove => current.is_none().then(|| format!("\"{}\" has no value to remove", field)),
+        ChangeType::Learn => None,
+    }
+}
+
+/// Every violation in the entity's markers, in application order (TODO markers aren't applied, so they're skipped)
+pub fn entity_violations<'a>(entity: &Entity, markers: impl IntoIterator<Item = &'a Marker>) -> Vec<StrictViolation> {
+    let mut ordered: Vec<&Marker> = markers.into_iter().filter(|m| m.entity_id == entity.id && !m.todo).collect();
+    ordered.sort_by(|a, b| engine::compare_markers(a, b));
+
+    let defaults = engine::field_defaults(entity);
+    let mut state = EntityState::new();
+    let mut violations = Vec::new();
+
+    for marker in ordered {
+        for change in &marker.changes {
+            if let Some(message) = check_change(entity, &state, change, &defaults) {
+                violations.push(StrictViolation {
+                    marker_id: marker.id.clone(),
+                    entity_id: entity.id.clone(),
+                    position: marker.position,
+                    field: change.field_name.clone(),
+                    message,
+                });
+            }
+            engine::apply_change(&mut state, change, &defaults);
+        }
+    }
+
+    violations
+}
+
+/// Every violation in the document, by position
+pub fn violations(entities: &HashMap<String, Entity>, markers: &HashMap<String, Marker>) -> Vec<StrictViolation> {
+    let mut all: Vec<StrictViolation> = entities
+        .values()
+        .flat_map(|entity| entity_violations(entity, markers.values()))
+        .collect();
+    all.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.marker_id.cmp(&b.marker_id)));
+    all
+}
+
+/// Refuse an edit to the entity's markers that introduces a violation
+///
+/// `before` and `after` are the markers without and with the edit; other
+/// entities' markers are ignored.
+pub fn check_edit<'a>(
+    entity: &Entity,
+    before: impl IntoIterator<Item = &'a Marker>,
+    after: impl IntoIterator<Item = &'a Marker>,
+) -> Result<(), String> {
+    let existing = entity_violations(entity, before);
+    let introduced = entity_violations(entity, after).into_iter().find(|v| {
+        !existing
+            .iter()
+            .any(|e| e.marker_id == v.marker_id && e.field == v.field && e.message == v.message)
+    });
+
+    match introduced {
+        Some(violation) => Err(format!("Strict mode: {} (at position {})", violation.message, violation.position)),
+        None => Ok(()),
+    }
+}
+
+/// `check_edit` for an edit that replaces the `changed` markers (by ID) and deletes
+/// the `removed` ones, for every entity those markers belong to before or after
+pub fn check_batch(
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    changed: &[Marker],
+    removed: &[String],
+) -> Result<(), String> {
+    let replaced_ids: HashSet<&String> = changed.iter().map(|m| &m.id).chain(removed).collect();
+    let replaced = |m: &Marker| replaced_ids.contains(&m.id);
+
+    let mut entity_ids: Vec<&String> = changed
+        .iter()
+        .map(|m| &m.entity_id)
+        .chain(markers.values().filter(|m| replaced(m)).map(|m| &m.entity_id))
+        .collect();
+    entity_ids.sort();
+    entity_ids.dedup();
+
+    for entity in entity_ids.into_iter().filter_map(|id| entities.get(id)) {
+        let after = markers.values().filter(|m| !replaced(m)).chain(changed.iter());
+        check_edit(entity, markers.values(), after)?;
+    }
+    Ok(())
+}
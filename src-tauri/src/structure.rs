@@ -0,0 +1,212 @@
+//! QuestScribe - Story Structure Templates
+//!
+//! A structure template places beats at percentages of the manuscript: three
+//! acts split 25/50/25, or the fifteen beats of Save the Cat. Percentages are
+//! of the word count, so a long descriptive chapter takes up as much of the
+//! story as a writer would expect, not as much as its markup.
+//!
+//! Checking a document maps every beat onto a position range and reports the
+//! chapters it falls in, how much text and how many markers it holds, and
+//! whether it lacks either. The document's template is stored in its
+//! preferences; without one, the three-act template is used.
+
+use crate::chapters::Chapter;
+use crate::positions;
+use crate::state::Marker;
+use crate::stats;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One beat, as a range of the manuscript in percent (a point beat has start == end)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Beat {
+    pub name: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StructureTemplate {
+    pub name: String,
+    pub beats: Vec<Beat>,
+}
+
+// Point beats are checked over this much of the manuscript on either side, in percent
+const POINT_BEAT_WINDOW: f64 = 2.0;
+
+fn template(name: &str, beats: &[(&str, f64, f64)]) -> StructureTemplate {
+    StructureTemplate {
+        name: name.to_string(),
+        beats: beats
+            .iter()
+            .map(|(beat, start, end)| Beat { name: beat.to_string(), start: *start, end: *end })
+            .collect(),
+    }
+}
+
+/// The bundled templates
+pub fn builtin_templates() -> Vec<StructureTemplate> {
+    vec![
+        template(
+            "Three-Act Structure",
+            &[
+                ("Act I: Setup", 0.0, 25.0),
+                ("Inciting Incident", 12.0, 12.0),
+                ("First Plot Point", 25.0, 25.0),
+                ("Act II: Confrontation", 25.0, 75.0),
+                ("Midpoint", 50.0, 50.0),
+                ("Second Plot Point", 75.0, 75.0),
+                ("Act III: Resolution", 75.0, 100.0),
+                ("Climax", 90.0, 90.0),
+            ],
+        ),
+        template(
+            "Save the Cat",
+            &[
+                ("Opening Image", 0.0, 1.0),
+                ("Theme Stated", 5.0, 5.0),
+                ("Set-Up", 1.0, 10.0),
+                ("Catalyst", 10.0, 10.0),
+                ("Debate", 10.0, 20.0),
+                ("Break into Two", 20.0, 20.0),
+                ("B Story", 22.0, 22.0),
+                ("Fun and Games", 20.0, 50.0),
+                ("Midpoint", 50.0, 50.0),
+                ("Bad Guys Close In", 50.0, 75.0),
+                ("All Is Lost", 75.0, 75.0),
+                ("Dark Night of the Soul", 75.0, 80.0),
+                ("Break into Three", 80.0, 80.0),
+                ("Finale", 80.0, 99.0),
+                ("Final Image", 99.0, 100.0),
+            ],
+        ),
+    ]
+}
+
+impl StructureTemplate {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Structure template name cannot be empty".to_string());
+        }
+        if self.beats.is_empty() {
+            return Err(format!("Structure template '{}' has no beats", self.name));
+        }
+        for beat in &self.beats {
+            if beat.name.trim().is_empty() {
+                return Err("Beat name cannot be empty".to_string());
+            }
+            if !(0.0..=100.0).contains(&beat.start) || !(0.0..=100.0).contains(&beat.end) || beat.start > beat.end {
+                return Err(format!("Beat '{}' must span 0 to 100 percent, start before end", beat.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where a beat landed in the document
+#[derive(Debug, Clone, Serialize)]
+pub struct BeatReport {
+    pub name: String,
+    pub start_percent: f64,
+    pub end_percent: f64,
+    pub start: usize, // Document positions covered by the beat (including a point beat's window)
+    pub end: usize,
+    pub chapters: Vec<String>, // Titles of the chapters the beat overlaps
+    pub word_count: usize,
+    pub marker_count: usize,
+    pub lacks_content: bool,
+    pub lacks_markers: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StructureReport {
+    pub template: String,
+    pub word_count: usize,
+    pub beats: Vec<BeatReport>,
+}
+
+// Top-level blocks as (start, end, words)
+fn blocks(doc: &serde_json::Value) -> Vec<(usize, usize, usize)> {
+    let mut pos = 0;
+    let mut list = Vec::new();
+    if let Some(children) = doc.get("content").and_then(|c| c.as_array()) {
+        for child in children {
+            let size = positions::node_size(child);
+            list.push((pos, pos + size, stats::block_word_count(child)));
+            pos += size;
+        }
+    }
+    list
+}
+
+// Position where the given share of the words has been written
+fn position_at(blocks: &[(usize, usize, usize)], total_words: usize, percent: f64) -> usize {
+    let target = total_words as f64 * percent / 100.0;
+    let mut words = 0.0;
+    for (start, end, count) in blocks {
+        let next = words + *count as f64;
+        if next > target {
+            // Interpolate inside the block
+            let share = if *count == 0 { 0.0 } else { (target - words) / *count as f64 };
+            return start + ((end - start) as f64 * share) as usize;
+        }
+        words = next;
+    }
+    blocks.last().map(|(_, end, _)| *end).unwrap_or(0)
+}
+
+/// Map a template onto a document
+pub fn check_structure(
+    template: &StructureTemplate,
+    doc: &serde_json::Value,
+    chapters: &[Chapter],
+    markers: &HashMap<String, Marker>,
+) -> StructureReport {
+    let blocks = blocks(doc);
+    let total_words: usize = blocks.iter().map(|(_, _, words)| words).sum();
+
+    let beats = template
+        .beats
+        .iter()
+        .map(|beat| {
+            let (from, to) = if beat.start == beat.end {
+                ((beat.start - POINT_BEAT_WINDOW).max(0.0), (beat.end + POINT_BEAT_WINDOW).min(100.0))
+            } else {
+                (beat.start, beat.end)
+            };
+            let start = position_at(&blocks, total_words, from);
+            let end = position_at(&blocks, total_words, to).max(start);
+
+            // Blocks are counted whole when they overlap the beat
+            let word_count = blocks
+                .iter()
+                .filter(|(block_start, block_end, _)| *block_start < end && *block_end > start)
+                .map(|(_, _, words)| words)
+                .sum();
+            let marker_count = markers.values().filter(|m| m.position >= start && m.position < end).count();
+
+            BeatReport {
+                name: beat.name.clone(),
+                start_percent: beat.start,
+                end_percent: beat.end,
+                start,
+                end,
+                chapters: chapters
+                    .iter()
+                    .filter(|c| c.start < end && c.end > start)
+                    .map(|c| c.title.clone())
+                    .collect(),
+                word_count,
+                marker_count,
+                lacks_content: word_count == 0,
+                lacks_markers: marker_count == 0,
+            }
+        })
+        .collect();
+
+    StructureReport {
+        template: template.name.clone(),
+        word_count: total_words,
+        beats,
+    }
+}
@@ -0,0 +1,154 @@
+//! QuestScribe - Field Value Suggestions
+//!
+//! Autocomplete for the marker editor's value box, drawn from what the document
+//! already contains:
+//!
+//! - **Previous**: values this entity's field has been set to, most recent first
+//! - **Delta**: the field's current number adjusted by the relative changes
+//!   most often used for fields of that name (e.g., "+1" for a level)
+//! - **Known**: values other entities use for a field of that name (e.g., the
+//!   factions a character can belong to), plus true/false for boolean fields
+
+use crate::engine;
+use crate::state::{ChangeType, Entity, FieldType, Marker};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Delta suggestions offered at most
+const MAX_DELTAS: usize = 5;
+/// Known values offered at most
+const MAX_KNOWN: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionSource {
+    Previous,
+    Delta,
+    Known,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValueSuggestion {
+    pub value: String,
+    pub change_type: ChangeType,
+    pub source: SuggestionSource,
+    pub result: Option<String>, // Value after a relative change (e.g., "+5" on 20 gives "25")
+    pub uses: usize,            // Times the value (or delta) appears in the document
+}
+
+// Count values, keeping the order of first appearance
+fn tally(values: impl Iterator<Item = String>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(v, _)| *v == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    counts
+}
+
+// Values of absolute changes to `field` in the given markers, in order
+fn absolute_values(markers: &[&Marker], field: &str, include: impl Fn(&Marker) -> bool) -> Vec<String> {
+    markers
+        .iter()
+        .filter(|m| include(m))
+        .flat_map(|m| m.changes.iter())
+        .filter(|c| c.field_name == field && c.change_type == ChangeType::Absolute)
+        .map(|c| c.value.clone())
+        .collect()
+}
+
+// "+5", "-2.5"
+fn signed(delta: f64) -> String {
+    if delta >= 0.0 {
+        format!("+{}", delta)
+    } else {
+        delta.to_string()
+    }
+}
+
+/// Suggestions for the value of `field` in a new marker for the entity at `position`
+pub fn suggest_field_values(
+    entity: &Entity,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    field: &str,
+    position: usize,
+) -> Vec<ValueSuggestion> {
+    let mut suggestions = Vec::new();
+
+    let mut ordered: Vec<&Marker> = markers.values().collect();
+    ordered.sort_by(|a, b| engine::compare_markers(a, b));
+
+    // This entity's own values, most recent first
+    let own = absolute_values(&ordered, field, |m| m.entity_id == entity.id);
+    for (value, uses) in tally(own.into_iter().rev()) {
+        suggestions.push(ValueSuggestion {
+            value,
+            change_type: ChangeType::Absolute,
+            source: SuggestionSource::Previous,
+            result: None,
+            uses,
+        });
+    }
+
+    // Common steps from the current number
+    let state = engine::entity_state_with_defaults(markers, entity, position);
+    let current = engine::get_nested_value(&state, field).and_then(|v| v.as_f64());
+    if let Some(current) = current {
+        let deltas = ordered
+            .iter()
+            .flat_map(|m| m.changes.iter())
+            .filter(|c| c.field_name == field && c.change_type == ChangeType::Relative)
+            .filter_map(|c| c.value.parse::<f64>().ok())
+            .filter(|delta| *delta != 0.0)
+            .map(signed);
+        let mut deltas = tally(deltas);
+        deltas.sort_by_key(|(_, uses)| Reverse(*uses));
+
+        for (value, uses) in deltas.into_iter().take(MAX_DELTAS) {
+            let delta = value.parse::<f64>().unwrap_or(0.0);
+            suggestions.push(ValueSuggestion {
+                value,
+                change_type: ChangeType::Relative,
+                source: SuggestionSource::Delta,
+                result: Some((current + delta).to_string()),
+                uses,
+            });
+        }
+    }
+
+    // Values the field takes elsewhere
+    let mut known = tally(absolute_values(&ordered, field, |m| m.entity_id != entity.id).into_iter());
+    if entities.values().any(|e| {
+        e.field_metadata
+            .get(field)
+            .is_some_and(|meta| meta.field_type == Some(FieldType::Boolean))
+    }) {
+        for value in ["true", "false"] {
+            if !known.iter().any(|(v, _)| v == value) {
+                known.push((value.to_string(), 0));
+            }
+        }
+    }
+    known.retain(|(value, _)| !suggestions.iter().any(|s| s.change_type == ChangeType::Absolute && s.value == *value));
+    // Numbers are better served by deltas than by other entities' values
+    if current.is_some() {
+        known.retain(|(value, _)| value.parse::<f64>().is_err());
+    }
+    known.sort_by_key(|(_, uses)| Reverse(*uses));
+
+    for (value, uses) in known.into_iter().take(MAX_KNOWN) {
+        suggestions.push(ValueSuggestion {
+            value,
+            change_type: ChangeType::Absolute,
+            source: SuggestionSource::Known,
+            result: None,
+            uses,
+        });
+    }
+
+    suggestions
+}
@@ -0,0 +1,177 @@
+//! QuestScribe - Chapter Synopses
+//!
+//! A synopsis document with one entry per chapter: the chapter heading, the
+//! chapter's opening paragraph, and what changed for each entity over the
+//! chapter (the same aggregation as recaps, see recap.rs). Written as Markdown
+//! or DOCX for agents, editors and co-writers who want the shape of the book
+//! without reading it.
+//!
+//! The summaries are template prose; on request, a configured language model
+//! rewrites them (see llm.rs), keeping the template prose where it fails.
+
+use crate::chapters::{self, Chapter};
+use crate::i18n;
+use crate::llm::{self, TextProvider};
+use crate::positions;
+use crate::preferences::ExportStyle;
+use crate::recap::{self, EntityRecap, ProseSource};
+use crate::state::{Entity, Marker};
+use docx_rs::{Docx, Paragraph, Run, RunFonts};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SynopsisFormat {
+    Markdown,
+    Docx,
+}
+
+impl SynopsisFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(SynopsisFormat::Markdown),
+            "docx" => Ok(SynopsisFormat::Docx),
+            _ => Err(format!("Unknown synopsis format: {}", format)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChapterSynopsis {
+    pub index: usize,
+    pub title: String,
+    pub start: usize,
+    pub end: usize,
+    pub opening: Option<String>, // First non-empty paragraph of the chapter
+    pub changes: Vec<EntityRecap>,
+    pub summary: String, // The changes as prose
+    pub summary_source: ProseSource,
+    pub llm_error: Option<String>, // Why the provider's prose wasn't used (template prose was kept)
+}
+
+// Text of the first non-empty paragraph in start..end
+fn opening_paragraph(doc: &serde_json::Value, start: usize, end: usize) -> Option<String> {
+    let children = doc.get("content").and_then(|c| c.as_array())?;
+    let mut pos = 0;
+    for child in children {
+        let size = positions::node_size(child);
+        if pos >= end {
+            break;
+        }
+        if pos >= start && child.get("type").and_then(|t| t.as_str()) == Some("paragraph") {
+            let text = chapters::node_text(child).trim().to_string();
+            if !text.is_empty() {
+                return Some(text);
+            }
+        }
+        pos += size;
+    }
+    None
+}
+
+/// Build a synopsis for every chapter, in document order
+pub fn generate_chapter_synopses(
+    doc: &serde_json::Value,
+    entities: &HashMap<String, Entity>,
+    markers: &HashMap<String, Marker>,
+    chapters: &[Chapter],
+    locale: &str,
+) -> Vec<ChapterSynopsis> {
+    chapters
+        .iter()
+        .map(|chapter| {
+            let changes = recap::generate_recap(entities, markers, chapter.start, chapter.end, locale).entities;
+            ChapterSynopsis {
+                index: chapter.index,
+                title: chapter.title.clone(),
+                start: chapter.start,
+                end: chapter.end,
+                opening: opening_paragraph(doc, chapter.start, chapter.end),
+                summary: recap::render_prose(&changes, locale),
+                summary_source: ProseSource::Template,
+                llm_error: None,
+                changes,
+            }
+        })
+        .collect()
+}
+
+/// Replace the template summaries with prose from a language model. After a
+/// failed request the remaining chapters keep their template prose, so an
+/// unreachable provider doesn't time out once per chapter.
+pub fn narrate(synopses: &mut [ChapterSynopsis], provider: &dyn TextProvider, locale: &str) {
+    let mut failed: Option<String> = None;
+    for synopsis in synopses.iter_mut().filter(|s| !s.changes.is_empty()) {
+        if let Some(error) = &failed {
+            synopsis.llm_error = Some(error.clone());
+            continue;
+        }
+
+        let changes = serde_json::to_value(&synopsis.changes).unwrap_or_default();
+        let prompt = llm::summary_prompt("chapter synopsis", &changes, i18n::language_name(locale));
+        match provider.complete(&prompt) {
+            Ok(text) if !text.is_empty() => {
+                synopsis.summary = text;
+                synopsis.summary_source = ProseSource::Llm;
+            }
+            Ok(_) => synopsis.llm_error = Some("Summarization response had no text".to_string()),
+            Err(e) => {
+                synopsis.llm_error = Some(e.clone());
+                failed = Some(e);
+            }
+        }
+    }
+}
+
+pub fn render_markdown(synopses: &[ChapterSynopsis], locale: &str) -> String {
+    let mut out = format!("# {}\n", i18n::tr(locale, "synopsis.heading", &[]));
+
+    for synopsis in synopses {
+        out.push_str(&format!("\n## {}\n\n", synopsis.title));
+        if let Some(opening) = &synopsis.opening {
+            out.push_str(&format!("> {}\n\n", opening));
+        }
+        out.push_str(&synopsis.summary);
+        out.push('\n');
+    }
+
+    out
+}
+
+/// A run in the export style's font
+pub fn styled_run(text: &str, size: usize, style: &ExportStyle) -> Run {
+    Run::new().add_text(text).size(size).fonts(
+        RunFonts::new()
+            .ascii(&style.font_family)
+            .hi_ansi(&style.font_family)
+            .cs(&style.font_family),
+    )
+}
+
+/// Write the synopses as a DOCX file, in the document's export style
+pub fn render_docx(synopses: &[ChapterSynopsis], style: &ExportStyle, locale: &str) -> Result<Vec<u8>, String> {
+    let body_size = style.body_half_points();
+    let heading = i18n::tr(locale, "synopsis.heading", &[]);
+    let mut docx = Docx::new().add_paragraph(
+        Paragraph::new().add_run(styled_run(&heading, style.heading_half_points(Some(1)), style).bold()),
+    );
+
+    for synopsis in synopses {
+        docx = docx.add_paragraph(
+            Paragraph::new().add_run(styled_run(&synopsis.title, style.heading_half_points(Some(2)), style).bold()),
+        );
+        if let Some(opening) = &synopsis.opening {
+            docx = docx.add_paragraph(Paragraph::new().add_run(styled_run(opening, body_size, style).italic()));
+        }
+        for paragraph in synopsis.summary.split("\n\n") {
+            docx = docx.add_paragraph(Paragraph::new().add_run(styled_run(paragraph, body_size, style)));
+        }
+    }
+
+    let mut buf = Cursor::new(Vec::new());
+    docx.build()
+        .pack(&mut buf)
+        .map_err(|e| format!("Failed to pack DOCX: {}", e))?;
+    Ok(buf.into_inner())
+}
@@ -0,0 +1,332 @@
+//! QuestScribe - Import from Other Writing Tools
+//!
+//! Brings a cast and an outline over from Plottr and Campfire Write, so
+//! switching doesn't mean retyping every character sheet.
+//!
+//! # Plottr (`.pltr`)
+//!
+//! - Characters become character entities and places become location entities.
+//!   The description, notes, and every custom attribute with a value become
+//!   fields, set by a starting marker (see entity_import.rs).
+//! - Plotlines become plot threads, and their cards become thread events: the
+//!   first card opens the thread and the rest develop it, with the card's title
+//!   and description as the note. QuestScribe has no separate bookmarks, so a
+//!   card keeps its place in the timeline as the event position: the start of
+//!   the chapter at the same place in the outline as the card's beat (or the
+//!   start of the document when there's no such chapter).
+//! - Plotlines named like an existing thread are skipped, so importing the same
+//!   file twice doesn't duplicate them.
+//!
+//! # Campfire Write (JSON export)
+//!
+//! A list of character sheets, or an object with `characters` and `locations`
+//! lists. Each sheet needs a `name` (or `title`); its other text, number and
+//! yes/no properties become fields, as do the label/value pairs of any
+//! `attributes`, `fields` or `panels` list.
+
+use crate::chapters::Chapter;
+use crate::entity_import::{self, EntityDefinition, EntityImport, FieldEntry, START_POSITION};
+use crate::mutations::MutationContext;
+use crate::plot_threads::{self, NewThreadEvent, PlotThread, ThreadStatus};
+use crate::state::{Entity, EntityKind, Marker};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceTool {
+    Plottr,
+    Campfire,
+}
+
+impl SourceTool {
+    pub fn parse(tool: &str) -> Result<Self, String> {
+        match tool.to_lowercase().as_str() {
+            "plottr" => Ok(SourceTool::Plottr),
+            "campfire" => Ok(SourceTool::Campfire),
+            _ => Err(format!("Unknown source tool: {}", tool)),
+        }
+    }
+}
+
+/// What an import created or changed
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolImport {
+    pub entities: EntityImport,
+    pub threads: Vec<PlotThread>,
+}
+
+// Sheet properties that aren't story data
+const CAMPFIRE_SKIPPED: &[&str] = &[
+    "id", "name", "title", "color", "image", "attributes", "fields", "panels", "createdAt", "updatedAt",
+];
+
+// Text of a plain string or Slate rich text (paragraphs joined by newlines)
+fn rich_text(value: &Value) -> String {
+    fn leaves(value: &Value, out: &mut String) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(text)) = map.get("text") {
+                    out.push_str(text);
+                }
+                if let Some(children) = map.get("children") {
+                    leaves(children, out);
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| leaves(item, out)),
+            _ => {}
+        }
+    }
+
+    match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Array(blocks) => blocks
+            .iter()
+            .map(|block| {
+                let mut text = String::new();
+                leaves(block, &mut text);
+                text.trim().to_string()
+            })
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+// A field for a label/value pair, skipping empty values ("." would nest the field)
+fn field_entry(label: &str, value: &Value, seen: &mut HashSet<String>) -> Option<FieldEntry> {
+    let name = label.trim().replace('.', " ");
+    let default = match value {
+        Value::Number(_) | Value::Bool(_) => value.clone(),
+        _ => {
+            let text = rich_text(value);
+            if text.is_empty() {
+                return None;
+            }
+            Value::String(text)
+        }
+    };
+    if name.is_empty() || !seen.insert(name.clone()) {
+        return None;
+    }
+
+    Some(FieldEntry::Definition { name, field_type: None, default: Some(default) })
+}
+
+fn id_of(value: &Value) -> Option<String> {
+    match value {
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn list<'a>(file: &'a Value, key: &str) -> &'a [Value] {
+    file.get(key).and_then(|v| v.as_array()).map(|v| v.as_slice()).unwrap_or(&[])
+}
+
+// Names of a Plottr custom attribute list (plain names in older files)
+fn plottr_attributes(file: &Value, kind: &str) -> Vec<String> {
+    file.get("customAttributes")
+        .map(|attributes| list(attributes, kind))
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|attribute| match attribute {
+            Value::String(name) => Some(name.clone()),
+            other => other.get("name").and_then(|n| n.as_str()).map(str::to_string),
+        })
+        .collect()
+}
+
+fn plottr_definitions(file: &Value) -> Vec<EntityDefinition> {
+    let mut definitions = Vec::new();
+
+    for (key, kind) in [("characters", EntityKind::Character), ("places", EntityKind::Location)] {
+        let attributes = plottr_attributes(file, key);
+        for sheet in list(file, key) {
+            let Some(name) = sheet.get("name").and_then(|n| n.as_str()).filter(|n| !n.trim().is_empty()) else {
+                continue;
+            };
+
+            let mut seen = HashSet::new();
+            let fields = [("description", "Description"), ("notes", "Notes")]
+                .iter()
+                .map(|(key, label)| (label.to_string(), sheet.get(*key)))
+                .chain(attributes.iter().map(|attribute| (attribute.clone(), sheet.get(attribute))))
+                .filter_map(|(label, value)| field_entry(&label, value?, &mut seen))
+                .collect();
+
+            definitions.push(EntityDefinition {
+                name: name.trim().to_string(),
+                color: sheet.get("color").and_then(|c| c.as_str()).map(str::to_string),
+                kind: Some(kind),
+                fields,
+            });
+        }
+    }
+
+    definitions
+}
+
+// Beat (chapter) IDs in outline order; newer files keep beats in a tree per book
+fn plottr_beat_order(file: &Value) -> HashMap<String, usize> {
+    let mut beats: Vec<&Value> = list(file, "chapters").iter().collect();
+    if beats.is_empty() {
+        if let Some(books) = file.get("beats").and_then(|b| b.as_object()) {
+            for book in books.values() {
+                match book.get("index") {
+                    Some(Value::Object(index)) => beats.extend(index.values()),
+                    _ => beats.extend(book.as_array().into_iter().flatten()),
+                }
+            }
+        }
+    }
+
+    let position = |beat: &Value| beat.get("position").and_then(|p| p.as_f64()).unwrap_or(0.0);
+    beats.sort_by(|a, b| position(a).total_cmp(&position(b)));
+
+    beats
+        .iter()
+        .filter_map(|beat| beat.get("id").and_then(id_of))
+        .enumerate()
+        .map(|(order, id)| (id, order))
+        .collect()
+}
+
+fn plottr_threads(
+    file: &Value,
+    markers: &HashMap<String, Marker>,
+    existing: &[PlotThread],
+    chapters: &[Chapter],
+) -> Result<Vec<PlotThread>, String> {
+    let beat_order = plottr_beat_order(file);
+    let position_of = |card: &Value| {
+        card.get("beatId")
+            .or_else(|| card.get("chapterId"))
+            .and_then(id_of)
+            .and_then(|beat| beat_order.get(&beat))
+            .and_then(|order| chapters.get(*order))
+            .map(|chapter| chapter.start)
+            .unwrap_or(START_POSITION)
+    };
+
+    let mut threads = Vec::new();
+    for line in list(file, "lines") {
+        let Some(name) = line.get("title").and_then(|t| t.as_str()).map(str::trim).filter(|t| !t.is_empty()) else {
+            continue;
+        };
+        if existing.iter().chain(&threads).any(|t: &PlotThread| t.name.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+
+        let line_id = line.get("id").and_then(id_of);
+        let mut cards: Vec<&Value> = list(file, "cards")
+            .iter()
+            .filter(|card| card.get("lineId").and_then(id_of) == line_id)
+            .collect();
+        let within_line = |card: &Value| card.get("positionWithinLine").and_then(|p| p.as_f64()).unwrap_or(0.0);
+        cards.sort_by(|a, b| position_of(a).cmp(&position_of(b)).then(within_line(a).total_cmp(&within_line(b))));
+
+        let mut events = cards.iter().enumerate().map(|(index, card)| {
+            let title = card.get("title").and_then(|t| t.as_str()).unwrap_or("").trim().to_string();
+            let description = card.get("description").map(rich_text).unwrap_or_default();
+            NewThreadEvent {
+                status: if index == 0 { ThreadStatus::Open } else { ThreadStatus::Developed },
+                position: position_of(card),
+                marker_id: None,
+                note: Some(match (title.is_empty(), description.is_empty()) {
+                    (false, false) => format!("{}: {}", title, description),
+                    (false, true) => title,
+                    _ => description,
+                }),
+            }
+        });
+
+        let Some(opened) = events.next() else {
+            continue;
+        };
+        let mut thread = plot_threads::create_thread(markers, name.to_string(), String::new(), opened)?;
+        for event in events {
+            thread.add_event(markers, event)?;
+        }
+        threads.push(thread);
+    }
+
+    Ok(threads)
+}
+
+fn campfire_definitions(file: &Value) -> Vec<EntityDefinition> {
+    let sheets: Vec<(&Value, EntityKind)> = match file {
+        Value::Array(sheets) => sheets.iter().map(|s| (s, EntityKind::Character)).collect(),
+        _ => list(file, "characters")
+            .iter()
+            .map(|s| (s, EntityKind::Character))
+            .chain(list(file, "locations").iter().map(|s| (s, EntityKind::Location)))
+            .collect(),
+    };
+
+    sheets
+        .into_iter()
+        .filter_map(|(sheet, kind)| {
+            let name = sheet.get("name").or_else(|| sheet.get("title"))?.as_str()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+
+            let mut seen = HashSet::new();
+            let mut fields: Vec<FieldEntry> = sheet
+                .as_object()?
+                .iter()
+                .filter(|(key, value)| !CAMPFIRE_SKIPPED.contains(&key.as_str()) && !value.is_object() && !value.is_array())
+                .filter_map(|(key, value)| field_entry(key, value, &mut seen))
+                .collect();
+
+            for key in ["attributes", "fields", "panels"] {
+                for pair in list(sheet, key) {
+                    let label = ["name", "label", "title"].iter().find_map(|k| pair.get(*k)?.as_str());
+                    let value = ["value", "text", "content"].iter().find_map(|k| pair.get(*k));
+                    if let (Some(label), Some(value)) = (label, value) {
+                        fields.extend(field_entry(label, value, &mut seen));
+                    }
+                }
+            }
+
+            Some(EntityDefinition {
+                name: name.to_string(),
+                color: sheet.get("color").and_then(|c| c.as_str()).map(str::to_string),
+                kind: Some(kind),
+                fields,
+            })
+        })
+        .collect()
+}
+
+/// Import a Plottr or Campfire export (already read into `text`)
+///
+/// `chapters` places Plottr cards; pass the document's chapters, or none.
+pub fn import_from_tool(
+    entities: &mut HashMap<String, Entity>,
+    markers: &mut HashMap<String, Marker>,
+    threads: &mut Vec<PlotThread>,
+    context: &MutationContext,
+    tool: SourceTool,
+    text: &str,
+    chapters: &[Chapter],
+) -> Result<ToolImport, String> {
+    let file: Value = serde_json::from_str(text)
+        .map_err(|e| format!("Failed to parse export file: {}", e))?;
+
+    let (definitions, new_threads) = match tool {
+        SourceTool::Plottr => (plottr_definitions(&file), plottr_threads(&file, markers, threads, chapters)?),
+        SourceTool::Campfire => (campfire_definitions(&file), Vec::new()),
+    };
+    if definitions.is_empty() && new_threads.is_empty() {
+        return Err("Nothing to import: the file has no characters, places or plotlines".to_string());
+    }
+
+    let imported = entity_import::import_definitions(entities, markers, context, &definitions)?;
+    threads.extend(new_threads.iter().cloned());
+
+    Ok(ToolImport { entities: imported, threads: new_threads })
+}
@@ -0,0 +1,199 @@
+//! QuestScribe - Tracked Changes
+//!
+//! For author–editor workflows, edits can be proposed rather than made. A
+//! suggested deletion leaves its text in place until it's accepted; a suggested
+//! insertion goes into the text but comes out again if it's rejected. Each
+//! suggestion records who proposed it and when, and its range follows the text
+//! through later edits, like a marker's position does.
+//!
+//! Suggestions are stored in the document. Exports show the pending ones, with
+//! deletions struck through and insertions underlined (see `mark_for_export`).
+
+use crate::content::{self, AppliedSteps};
+use crate::ids;
+use crate::positions::TextEdit;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+
+/// Mark types for pending suggestions in exported content (not part of the editor's schema)
+pub const DELETION_MARK: &str = "suggested_deletion";
+pub const INSERTION_MARK: &str = "suggested_insertion";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    Insertion,
+    Deletion,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub id: String,
+    pub kind: SuggestionKind,
+    pub from: usize,
+    pub to: usize,
+    pub text: String, // Text of the range when it was proposed
+    pub author: String,
+    pub created_at: i64,
+}
+
+/// A proposed edit: the content with any inserted text, and the new suggestions
+pub struct Proposed {
+    pub applied: AppliedSteps,
+    pub suggestions: Vec<Suggestion>,
+}
+
+// Map a range through an edit. Text inserted at either end stays outside the range,
+// and a range whose text is all replaced collapses.
+fn map_range(from: usize, to: usize, edit: &TextEdit) -> (usize, usize) {
+    let shift = |pos: usize| pos - (edit.to - edit.from) + edit.inserted_len;
+    let start = if from < edit.from {
+        from
+    } else if from >= edit.to {
+        shift(from)
+    } else {
+        edit.from + edit.inserted_len
+    };
+    let end = if to <= edit.from {
+        to
+    } else if to > edit.to {
+        shift(to)
+    } else {
+        edit.from
+    };
+    (start, end.max(start))
+}
+
+/// Map suggestion ranges through edits, dropping suggestions whose text is gone
+pub fn shift_ranges(suggestions: &mut Vec<Suggestion>, edits: &[TextEdit]) {
+    for suggestion in suggestions.iter_mut() {
+        for edit in edits {
+            (suggestion.from, suggestion.to) = map_range(suggestion.from, suggestion.to, edit);
+        }
+    }
+    suggestions.retain(|s| s.from < s.to);
+}
+
+fn remove_step(from: usize, to: usize) -> Value {
+    json!({ "stepType": "replace", "from": from, "to": to })
+}
+
+/// Propose replacing from..to with `text`
+///
+/// The range must be text within one paragraph or heading, and must not overlap
+/// another suggestion. The old text becomes a deletion and `text`, inserted right
+/// after it, an insertion (either may be empty, but not both).
+pub fn propose(
+    doc: &Value,
+    suggestions: &mut Vec<Suggestion>,
+    from: usize,
+    to: usize,
+    text: &str,
+    author: &str,
+    now: i64,
+) -> Result<Proposed, String> {
+    let author = author.trim();
+    if author.is_empty() {
+        return Err("Suggestion author cannot be empty".to_string());
+    }
+    if from == to && text.is_empty() {
+        return Err("Suggestion changes nothing".to_string());
+    }
+    let deleted = content::text_between(doc, from, to)?;
+    if suggestions.iter().any(|s| s.from < to && from < s.to) {
+        return Err("Text already has a pending suggestion".to_string());
+    }
+
+    let steps = if text.is_empty() {
+        Vec::new()
+    } else {
+        vec![json!({
+            "stepType": "replace",
+            "from": to,
+            "to": to,
+            "slice": { "content": [{ "type": "text", "text": text }] },
+        })]
+    };
+    let applied = content::apply_steps(doc, &steps)?;
+    shift_ranges(suggestions, &applied.edits);
+
+    let suggestion = |kind, from, to, text: &str| Suggestion {
+        id: ids::new_id(),
+        kind,
+        from,
+        to,
+        text: text.to_string(),
+        author: author.to_string(),
+        created_at: now,
+    };
+    let mut proposed = Vec::new();
+    if from < to {
+        proposed.push(suggestion(SuggestionKind::Deletion, from, to, &deleted));
+    }
+    if !text.is_empty() {
+        let inserted_len = text.encode_utf16().count();
+        proposed.push(suggestion(SuggestionKind::Insertion, to, to + inserted_len, text));
+    }
+    suggestions.extend(proposed.iter().cloned());
+
+    Ok(Proposed { applied, suggestions: proposed })
+}
+
+// Whether resolving the suggestion takes its text out of the document
+fn removes_text(suggestion: &Suggestion, accept: bool) -> bool {
+    matches!(
+        (suggestion.kind, accept),
+        (SuggestionKind::Deletion, true) | (SuggestionKind::Insertion, false)
+    )
+}
+
+/// Accept or reject a suggestion
+pub fn resolve(doc: &Value, suggestions: &mut Vec<Suggestion>, suggestion_id: &str, accept: bool) -> Result<AppliedSteps, String> {
+    let index = suggestions
+        .iter()
+        .position(|s| s.id == suggestion_id)
+        .ok_or("Suggestion not found")?;
+
+    let suggestion = &suggestions[index];
+    let steps = if removes_text(suggestion, accept) {
+        vec![remove_step(suggestion.from, suggestion.to)]
+    } else {
+        Vec::new()
+    };
+    let applied = content::apply_steps(doc, &steps)?;
+
+    suggestions.remove(index);
+    shift_ranges(suggestions, &applied.edits);
+    Ok(applied)
+}
+
+/// Accept or reject every suggestion
+pub fn resolve_all(doc: &Value, suggestions: &mut Vec<Suggestion>, accept: bool) -> Result<AppliedSteps, String> {
+    // Last first, so each removal leaves the earlier positions as they are
+    let mut removed: Vec<&Suggestion> = suggestions.iter().filter(|s| removes_text(s, accept)).collect();
+    removed.sort_by_key(|s| Reverse(s.from));
+    let steps: Vec<Value> = removed.iter().map(|s| remove_step(s.from, s.to)).collect();
+    let applied = content::apply_steps(doc, &steps)?;
+
+    suggestions.clear();
+    Ok(applied)
+}
+
+/// The stored content with pending suggestions marked (`DELETION_MARK`, `INSERTION_MARK`), for export
+///
+/// Suggestion ranges are positions in the stored content; a suggestion that doesn't
+/// fit it is left unmarked rather than failing the export.
+pub fn mark_for_export(doc: &Value, suggestions: &[Suggestion]) -> Value {
+    suggestions.iter().fold(doc.clone(), |marked, s| {
+        let mark = match s.kind {
+            SuggestionKind::Deletion => DELETION_MARK,
+            SuggestionKind::Insertion => INSERTION_MARK,
+        };
+        let step = json!({ "stepType": "addMark", "from": s.from, "to": s.to, "mark": { "type": mark } });
+        match content::apply_steps(&marked, &[step]) {
+            Ok(applied) => applied.doc,
+            Err(_) => marked,
+        }
+    })
+}
@@ -0,0 +1,89 @@
+//! QuestScribe - Typographic Cleanup
+//!
+//! An optional pass over an export's text, after the manuscript is assembled
+//! and before any format renders it, so every format gets the same result:
+//!
+//! - straight quotes become curly ones, opening or closing by what comes
+//!   before them (an apostrophe inside a word is a closing single quote)
+//! - "--" and "---" become an em dash
+//! - "..." and ". . ." become an ellipsis
+//!
+//! Quotes are decided across the runs of a paragraph, so a quote at the start
+//! of an italic run still closes the word before it. Endnote reference numbers
+//! and monospace text (status windows, whose borders may be drawn with "-")
+//! are left alone.
+
+use crate::exporters::FormattedParagraph;
+use serde::{Deserialize, Serialize};
+
+/// Which cleanups to apply (all off = the text as written)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TypographyOptions {
+    pub smart_quotes: bool,
+    pub em_dashes: bool,
+    pub ellipses: bool,
+}
+
+impl TypographyOptions {
+    pub fn is_empty(&self) -> bool {
+        !(self.smart_quotes || self.em_dashes || self.ellipses)
+    }
+}
+
+// Whether a quote after this character opens (start of text, space, or opening punctuation)
+fn opens_after(previous: Option<char>) -> bool {
+    match previous {
+        None => true,
+        Some(c) => c.is_whitespace() || matches!(c, '(' | '[' | '{' | '—' | '–' | '-' | '“' | '‘'),
+    }
+}
+
+// Curl the quotes of a run, given the character before it
+fn curl_quotes(text: &str, mut previous: Option<char>) -> String {
+    let mut curled = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        let replacement = match ch {
+            '"' if opens_after(previous) => '“',
+            '"' => '”',
+            '\'' if opens_after(previous) => '‘',
+            '\'' => '’',
+            c => c,
+        };
+        curled.push(replacement);
+        previous = Some(replacement);
+    }
+
+    curled
+}
+
+/// Apply the cleanups to a piece of text, given the character before it (for quotes)
+pub fn clean_text(text: &str, previous: Option<char>, options: &TypographyOptions) -> String {
+    let mut text = text.to_string();
+    if options.em_dashes {
+        text = text.replace("---", "—").replace("--", "—");
+    }
+    if options.ellipses {
+        text = text.replace(". . .", "…").replace("...", "…");
+    }
+    if options.smart_quotes {
+        text = curl_quotes(&text, previous);
+    }
+    text
+}
+
+/// Apply the cleanups to every paragraph of a manuscript
+pub fn clean_paragraphs(paragraphs: &mut [FormattedParagraph], options: &TypographyOptions) {
+    if options.is_empty() {
+        return;
+    }
+
+    for para in paragraphs {
+        let mut previous = None;
+        for run in para.runs.iter_mut().filter(|run| !run.note && !run.monospace) {
+            run.text = clean_text(&run.text, previous, options);
+            previous = run.text.chars().last().or(previous);
+        }
+    }
+}
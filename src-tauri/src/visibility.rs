@@ -0,0 +1,53 @@
+//! QuestScribe - Visibility Filters
+//!
+//! What the author has hidden from view: entities, marker tags, and collapsed
+//! field groups in each entity's state panel. The filters are saved in the
+//! document so they survive restarts, and backend outputs that feed the same
+//! views (the marker density map, the state matrix) can apply them on request.
+
+use crate::state::{Entity, Marker};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisibilityFilters {
+    pub hidden_entities: Vec<String>, // Entity IDs
+    pub hidden_tags: Vec<String>,
+    pub collapsed_groups: BTreeMap<String, Vec<String>>, // Entity ID -> collapsed field group paths (e.g., "stats")
+}
+
+impl VisibilityFilters {
+    pub fn is_entity_visible(&self, entity_id: &str) -> bool {
+        !self.hidden_entities.iter().any(|id| id == entity_id)
+    }
+
+    /// Whether a marker shows: its entity isn't hidden and none of its tags are
+    pub fn is_marker_visible(&self, marker: &Marker) -> bool {
+        self.is_entity_visible(&marker.entity_id) && !marker.tags.iter().any(|tag| self.hidden_tags.contains(tag))
+    }
+
+    /// Drop unknown entities, blank tags and groups, and duplicates
+    pub fn normalize(&mut self, entities: &HashMap<String, Entity>) {
+        self.hidden_entities.retain(|id| entities.contains_key(id));
+        self.hidden_entities.sort();
+        self.hidden_entities.dedup();
+
+        self.hidden_tags = self
+            .hidden_tags
+            .iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        self.hidden_tags.sort();
+        self.hidden_tags.dedup();
+
+        self.collapsed_groups.retain(|entity_id, _| entities.contains_key(entity_id));
+        for groups in self.collapsed_groups.values_mut() {
+            groups.retain(|group| !group.trim().is_empty());
+            groups.sort();
+            groups.dedup();
+        }
+        self.collapsed_groups.retain(|_, groups| !groups.is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! QuestScribe - Marker Visual Rules
+//!
+//! Rules pick a marker's icon and color from the changes it contains, so the
+//! frontend can insert a marker without choosing visuals (e.g., "HP -12" becomes
+//! a red 💔, "inventory.Gold +50" a gold 💰).
+//!
+//! Rules are stored per document and evaluated in order; the first rule matched
+//! by any change in the marker wins. A document without its own rules uses
+//! `default_rules()`.
+
+use crate::state::{ChangeType, FieldChange, MarkerVisual};
+use serde::{Deserialize, Serialize};
+
+/// Direction of a relative numeric change
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeDirection {
+    Increase,
+    Decrease,
+}
+
+/// A rule assigning visuals to markers whose changes match all of its conditions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VisualRule {
+    pub name: String,
+    #[serde(default)]
+    pub field_pattern: Option<String>, // Case-insensitive, '*' matches anything (e.g., "inventory.*")
+    #[serde(default)]
+    pub change_type: Option<ChangeType>,
+    #[serde(default)]
+    pub direction: Option<ChangeDirection>, // Only matches relative changes with a numeric value
+    pub icon: String,
+    #[serde(default)]
+    pub color: Option<String>, // None = use the entity's color
+    #[serde(default)]
+    pub icon_ref: Option<String>,
+}
+
+/// Rules used by documents that haven't configured their own
+pub fn default_rules() -> Vec<VisualRule> {
+    let rule = |name: &str, pattern: &str, direction: Option<ChangeDirection>, icon: &str, color: Option<&str>| VisualRule {
+        name: name.to_string(),
+        field_pattern: Some(pattern.to_string()),
+        change_type: None,
+        direction,
+        icon: icon.to_string(),
+        color: color.map(|c| c.to_string()),
+        icon_ref: None,
+    };
+
+    vec![
+        rule("Damage", "*hp*", Some(ChangeDirection::Decrease), "💔", Some("#DC143C")),
+        rule("Damage", "*health*", Some(ChangeDirection::Decrease), "💔", Some("#DC143C")),
+        rule("Healing", "*hp*", Some(ChangeDirection::Increase), "💚", Some("#2E8B57")),
+        rule("Level up", "*level*", None, "⭐", Some("#FFD700")),
+        rule("Loot", "*gold*", None, "💰", Some("#DAA520")),
+        rule("Loot", "inventory.*", None, "🎒", Some("#DAA520")),
+        rule("Skill", "skills.*", None, "📘", None),
+        rule("Spell", "spells.*", None, "✨", None),
+    ]
+}
+
+// Case-insensitive wildcard match where '*' matches any run of characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Classic two-pointer glob match with backtracking to the last '*'
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] != '*' && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl VisualRule {
+    fn matches(&self, change: &FieldChange) -> bool {
+        if let Some(pattern) = &self.field_pattern {
+            if !wildcard_match(pattern, &change.field_name) {
+                return false;
+            }
+        }
+        if let Some(change_type) = &self.change_type {
+            if *change_type != change.change_type {
+                return false;
+            }
+        }
+        if let Some(direction) = self.direction {
+            let delta = match (&change.change_type, change.value.trim().parse::<f64>()) {
+                (ChangeType::Relative, Ok(delta)) => delta,
+                _ => return false,
+            };
+            let actual = if delta < 0.0 { ChangeDirection::Decrease } else { ChangeDirection::Increase };
+            if actual != direction {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Choose visuals for a marker from the rules, falling back to the document's default
+/// icon (or a star) in the entity color
+pub fn apply_rules(
+    rules: &[VisualRule],
+    changes: &[FieldChange],
+    entity_color: &str,
+    default_icon: Option<&str>,
+) -> MarkerVisual {
+    let matched = rules
+        .iter()
+        .find(|rule| changes.iter().any(|change| rule.matches(change)));
+
+    match matched {
+        Some(rule) => MarkerVisual {
+            icon: rule.icon.clone(),
+            color: rule.color.clone().unwrap_or_else(|| entity_color.to_string()),
+            icon_ref: rule.icon_ref.clone(),
+        },
+        None => MarkerVisual {
+            icon: default_icon.unwrap_or("⭐").to_string(),
+            color: entity_color.to_string(),
+            icon_ref: None,
+        },
+    }
+}